@@ -0,0 +1,43 @@
+/* C API of cochange-tool (see src/ffi.rs). Link against the cdylib built by
+ * `cargo build --release` (e.g. libcochange_tool.so). */
+
+#ifndef COCHANGE_H
+#define COCHANGE_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct CochangeDb CochangeDb;
+
+typedef struct {
+    char *entity1;
+    char *entity2;
+    double support;
+    double confidence;
+} CochangePair;
+
+typedef struct {
+    CochangePair *pairs;
+    size_t len;
+} CochangePairs;
+
+void cochange_init(int verbosity);
+const char *cochange_last_error(void);
+
+int cochange_extract(const char *repo_path, const char *db_path);
+
+CochangeDb *cochange_open(const char *db_path);
+void cochange_close(CochangeDb *db);
+
+CochangePairs *cochange_query_pairs(const CochangeDb *db, double min_support,
+                                    double min_confidence);
+void cochange_pairs_free(CochangePairs *pairs);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,20 @@
+; Decision points (i.e. each adds one to the cyclomatic complexity)
+(if_statement) @branch
+(for_statement) @branch
+(enhanced_for_statement) @branch
+(while_statement) @branch
+(do_statement) @branch
+(catch_clause) @branch
+(switch_label) @branch
+(ternary_expression) @branch
+
+; Statements which nest their bodies
+(if_statement) @nesting
+(for_statement) @nesting
+(enhanced_for_statement) @nesting
+(while_statement) @nesting
+(do_statement) @nesting
+(try_statement) @nesting
+
+(formal_parameter) @parameter
+(spread_parameter) @parameter
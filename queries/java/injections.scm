@@ -0,0 +1,6 @@
+; Strings passed to the JDBC and JPA methods which take SQL (or JPQL)
+(method_invocation
+  name: (identifier) @_method
+  arguments: (argument_list . (string_literal) @injection.content)
+  (#match? @_method "^(prepareStatement|prepareCall|execute|executeQuery|executeUpdate|addBatch|createQuery|createNativeQuery)$")
+  (#set! injection.language "sql"))
@@ -30,4 +30,29 @@
 
 (field_declaration
   declarator: (variable_declarator
-    name: (identifier) @name)) @tag.field
\ No newline at end of file
+    name: (identifier) @name)) @tag.field
+(constant_declaration
+  declarator: (variable_declarator
+    name: (identifier) @name)) @tag.field
+
+(enum_constant
+  name: (identifier) @name) @tag.constant
+
+(annotation_type_element_declaration
+  name: (identifier) @name) @tag.element
+
+; Initializers, anonymous classes, and lambdas have no name, so one is given to
+; them. Those of the same parent are numbered in order (replacing any {}).
+((static_initializer) @tag.initializer
+  (#set! name "static"))
+
+(class_body
+  (block) @tag.initializer
+  (#set! name "instance"))
+
+(object_creation_expression
+  (class_body) @tag.anonymous
+  (#set! name "${}"))
+
+((lambda_expression) @tag.lambda
+  (#set! name "lambda#{}"))
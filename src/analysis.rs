@@ -0,0 +1,2637 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::bail;
+use anyhow::Result;
+use rusqlite::Connection;
+use time::OffsetDateTime;
+
+use crate::db::has_table;
+use crate::db::Id;
+use crate::export::read_csv;
+use crate::issues::PULL_REQUEST_KIND;
+use crate::parsing::unquote_path;
+
+/// A set of entities that changed together (i.e. in the same commit).
+#[derive(Debug, Clone)]
+pub struct ChangeSet {
+    pub commit_id: Id,
+    pub commit_time: i64,
+    pub weight: f64,
+    /// The changed entities along with their churn (adds + dels)
+    pub entities: Vec<(Id, usize)>,
+}
+
+impl ChangeSet {
+    pub fn entity_ids(&self) -> impl Iterator<Item = Id> + '_ {
+        self.entities.iter().map(|&(id, _)| id)
+    }
+}
+
+pub fn load_change_sets(conn: &Connection) -> Result<Vec<ChangeSet>> {
+    let mut stmt = conn.prepare(
+        "SELECT CH.commit_id, CO.commit_date, CH.entity_id, MAX(CH.adds + CH.dels)
+        FROM changes CH
+        JOIN commits CO ON CO.id = CH.commit_id
+        GROUP BY CH.commit_id, CH.entity_id
+        ORDER BY CH.commit_id",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, Id>(0)?,
+            row.get::<_, i64>(1)?,
+            row.get::<_, Id>(2)?,
+            row.get::<_, usize>(3)?,
+        ))
+    })?;
+
+    let mut change_sets: Vec<ChangeSet> = Vec::new();
+
+    for row in rows {
+        let (commit_id, commit_time, entity_id, churn) = row?;
+
+        match change_sets.last_mut() {
+            Some(last) if last.commit_id == commit_id => last.entities.push((entity_id, churn)),
+            _ => change_sets.push(ChangeSet {
+                commit_id,
+                commit_time,
+                weight: 1.0,
+                entities: vec![(entity_id, churn)],
+            }),
+        }
+    }
+
+    Ok(change_sets)
+}
+
+/// The level to which changes are rolled up (along the parents of the changed
+/// entities) before co-changes are counted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Aggregation {
+    /// Count co-changes of the changed entities themselves (e.g. methods)
+    Entity,
+    /// Roll changes up to the innermost enclosing class (or interface, enum,
+    /// record, or annotation), or to the file outside of any class
+    Class,
+    /// Roll changes up to their file
+    File,
+    /// Roll changes up to the directory of their file
+    Package,
+}
+
+/// Maps each entity to the entity its changes are rolled up into. Entities
+/// without an ancestor at the given level are not rolled up.
+pub fn to_rollup(
+    parents: &HashMap<Id, Option<Id>>,
+    kinds: &HashMap<Id, String>,
+    aggregation: Aggregation,
+) -> HashMap<Id, Id> {
+    let is_target = |id: &Id| match (aggregation, kinds[id].as_str()) {
+        (Aggregation::Entity, _) => true,
+        (Aggregation::Class, kind) => {
+            matches!(kind, "class" | "interface" | "enum" | "record" | "annotation" | "file")
+        }
+        (Aggregation::File, kind) => kind == "file",
+        (Aggregation::Package, kind) => kind == "dir",
+    };
+
+    parents
+        .keys()
+        .map(|&id| (id, ancestors_or_self(parents, id).into_iter().find(is_target).unwrap_or(id)))
+        .collect()
+}
+
+/// Replaces the entities of each change set with the entities they are rolled
+/// up into. The churn of a rolled up entity is the largest churn among the
+/// entities rolled into it because a change to an entity is also a change to
+/// each of its ancestors.
+pub fn roll_up(change_sets: &mut [ChangeSet], rollup: &HashMap<Id, Id>) {
+    for change_set in change_sets {
+        let mut entities: Vec<(Id, usize)> = Vec::new();
+
+        for &(entity_id, churn) in &change_set.entities {
+            let entity_id = rollup.get(&entity_id).copied().unwrap_or(entity_id);
+
+            match entities.iter_mut().find(|(id, _)| *id == entity_id) {
+                Some((_, max)) => *max = churn.max(*max),
+                None => entities.push((entity_id, churn)),
+            }
+        }
+
+        change_set.entities = entities;
+    }
+}
+
+/// Reads a CSV file of entity aliases, where each row holds the path of an
+/// entity (e.g. from before a refactoring the tracking missed) and the path it
+/// is merged into. Blank lines, lines starting with `#`, and a `from,to` header
+/// are skipped.
+pub fn read_aliases<P: AsRef<Path>>(path: P) -> Result<Vec<(String, String)>> {
+    let mut aliases = Vec::new();
+
+    for (i, row) in read_csv(path.as_ref())?.into_iter().enumerate() {
+        match row.as_slice() {
+            [from, to] if i == 0 && from == "from" && to == "to" => {}
+            [blank] if blank.trim().is_empty() => {}
+            [first, ..] if first.starts_with('#') => {}
+            [from, to] => aliases.push((unquote_path(from.trim()), unquote_path(to.trim()))),
+            _ => bail!(
+                "expected two columns (from,to) on line {} of {}",
+                i + 1,
+                path.as_ref().to_string_lossy()
+            ),
+        }
+    }
+
+    Ok(aliases)
+}
+
+/// Maps each entity to the entity it is an alias of (see `read_aliases`). The
+/// entities below an aliased path (e.g. the files of a renamed directory or the
+/// methods of a renamed class) are mapped to their counterparts below the new
+/// path where there are any. Aliases may be chained.
+pub fn to_aliases(
+    paths: &HashMap<Id, String>,
+    aliases: &[(String, String)],
+) -> Result<HashMap<Id, Id>> {
+    let ids = paths.iter().map(|(&id, path)| (path.as_str(), id)).collect::<HashMap<_, _>>();
+    let mut mapped = HashMap::new();
+
+    for (from, to) in aliases {
+        let mut found = false;
+
+        for (&id, path) in paths {
+            let rest = match path.strip_prefix(from.as_str()) {
+                Some(rest)
+                    if rest.is_empty() || rest.starts_with('/') || rest.starts_with("::") =>
+                {
+                    rest
+                }
+                _ => continue,
+            };
+
+            found = true;
+
+            if let Some(&to_id) = ids.get(format!("{}{}", to, rest).as_str()) {
+                if to_id != id {
+                    mapped.insert(id, to_id);
+                }
+            }
+        }
+
+        if !found {
+            bail!("no entity matches alias '{}'", from);
+        }
+    }
+
+    // Follow chains (e.g. A to B and B to C) to their end, stopping at cycles
+    let mut resolved = HashMap::new();
+
+    for &id in mapped.keys() {
+        let mut target = id;
+        let mut seen = HashSet::from([id]);
+
+        while let Some(&next) = mapped.get(&target) {
+            if !seen.insert(next) {
+                break;
+            }
+
+            target = next;
+        }
+
+        if target != id {
+            resolved.insert(id, target);
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Maps each commit to the email address of its author.
+pub fn load_commit_authors(conn: &Connection) -> Result<HashMap<Id, String>> {
+    let mut stmt = conn.prepare("SELECT id, author_mail FROM commits")?;
+    let authors =
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_, _>>()?;
+    Ok(authors)
+}
+
+/// Loads the ids of the commits which duplicate the patch of an earlier commit.
+pub fn load_duplicate_ids(conn: &Connection) -> Result<HashSet<Id>> {
+    let mut stmt = conn.prepare("SELECT commit_id FROM duplicate_commits")?;
+    let ids = stmt.query_map([], |row| row.get(0))?.collect::<Result<_, _>>()?;
+    Ok(ids)
+}
+
+/// Merges the change sets of each author into sessions. A change set joins the
+/// previous session of its author if it was committed within `gap` of the last
+/// change set in that session.
+///
+/// Each session is identified by its last commit. The churn of an entity which
+/// changed more than once within a session is summed.
+pub fn group_sessions(
+    mut change_sets: Vec<ChangeSet>,
+    authors: &HashMap<Id, String>,
+    gap: Duration,
+) -> Vec<ChangeSet> {
+    let gap = gap.as_secs() as i64;
+    change_sets.sort_by_key(|c| (authors.get(&c.commit_id), c.commit_time, c.commit_id));
+
+    let mut sessions: Vec<ChangeSet> = Vec::new();
+
+    for change_set in change_sets {
+        match sessions.last_mut() {
+            Some(last)
+                if authors.get(&last.commit_id) == authors.get(&change_set.commit_id)
+                    && change_set.commit_time - last.commit_time <= gap =>
+            {
+                merge_change_set(last, change_set)
+            }
+            _ => sessions.push(change_set),
+        }
+    }
+
+    sessions.sort_by_key(|c| c.commit_id);
+    sessions
+}
+
+/// Loads the merged pull request of each commit which belongs to one. A commit
+/// which belongs to more than one is given the earliest. Pull requests which
+/// were not merged are ignored, as their commits (if any made it into the
+/// history) were shipped some other way.
+pub fn load_pull_request_ids(conn: &Connection) -> Result<HashMap<Id, Id>> {
+    let mut stmt = conn.prepare(
+        "SELECT PRC.commit_id, MIN(PRC.pull_request_id)
+        FROM pull_request_commits PRC
+        JOIN pull_requests PR ON PR.id = PRC.pull_request_id
+        WHERE PRC.commit_id IS NOT NULL AND PR.state = 'merged'
+        GROUP BY PRC.commit_id",
+    )?;
+    let ids =
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_, _>>()?;
+    Ok(ids)
+}
+
+/// Merges the change sets of the commits of each pull request (including the
+/// commit which merged it) into a single logical change set. Commits which do
+/// not belong to a pull request are kept as is.
+///
+/// Each pull request is identified by its last commit and weighted as one
+/// change set, however many commits it was made of. The churn of an entity
+/// which changed more than once within a pull request is summed.
+pub fn group_pull_requests(
+    mut change_sets: Vec<ChangeSet>,
+    pull_requests: &HashMap<Id, Id>,
+) -> Vec<ChangeSet> {
+    change_sets.sort_by_key(|c| (pull_requests.get(&c.commit_id), c.commit_time, c.commit_id));
+
+    let mut grouped: Vec<ChangeSet> = Vec::new();
+
+    for change_set in change_sets {
+        match (grouped.last_mut(), pull_requests.get(&change_set.commit_id)) {
+            (Some(last), Some(pull_request))
+                if pull_requests.get(&last.commit_id) == Some(pull_request) =>
+            {
+                merge_change_set(last, change_set)
+            }
+            _ => grouped.push(change_set),
+        }
+    }
+
+    grouped.sort_by_key(|c| c.commit_id);
+    grouped
+}
+
+/// Merges a later change set into an earlier one, which takes on its commit.
+fn merge_change_set(last: &mut ChangeSet, change_set: ChangeSet) {
+    for (entity_id, churn) in change_set.entities {
+        match last.entities.iter_mut().find(|(id, _)| *id == entity_id) {
+            Some((_, total)) => *total += churn,
+            None => last.entities.push((entity_id, churn)),
+        }
+    }
+
+    last.commit_id = change_set.commit_id;
+    last.commit_time = change_set.commit_time;
+}
+
+/// Weights each change set by its age (relative to the most recent change
+/// set) such that the weight halves every `half_life`.
+pub fn apply_half_life(change_sets: &mut [ChangeSet], half_life: Duration) {
+    let newest = change_sets.iter().map(|c| c.commit_time).max().unwrap_or_default();
+    let half_life = half_life.as_secs_f64().max(1.0);
+
+    for change_set in change_sets {
+        let age = (newest - change_set.commit_time).max(0) as f64;
+        change_set.weight *= 0.5f64.powf(age / half_life);
+    }
+}
+
+/// Weighted co-change counts of entities and of (unordered) entity pairs.
+///
+/// Each change set contributes its weight (1.0 unless otherwise adjusted) to
+/// every entity and pair of entities it contains.
+#[derive(Debug, Default)]
+pub struct Coupling {
+    pub changes: HashMap<Id, f64>,
+    pub pairs: HashMap<(Id, Id), f64>,
+}
+
+impl Coupling {
+    pub fn new(change_sets: &[ChangeSet]) -> Self {
+        let mut coupling = Self::default();
+
+        for change_set in change_sets {
+            let mut ids = change_set.entity_ids().collect::<Vec<_>>();
+            ids.sort();
+            ids.dedup();
+
+            for (i, &a) in ids.iter().enumerate() {
+                *coupling.changes.entry(a).or_default() += change_set.weight;
+
+                for &b in &ids[i + 1..] {
+                    *coupling.pairs.entry((a, b)).or_default() += change_set.weight;
+                }
+            }
+        }
+
+        coupling
+    }
+
+    /// The (weighted) number of change sets containing both `a` and `b`.
+    pub fn support(&self, a: Id, b: Id) -> f64 {
+        let key = if a < b { (a, b) } else { (b, a) };
+        self.pairs.get(&key).copied().unwrap_or_default()
+    }
+
+    /// The fraction of the changes to `a` which also changed `b`.
+    pub fn confidence(&self, a: Id, b: Id) -> f64 {
+        match self.changes.get(&a) {
+            Some(&n) if n > 0.0 => self.support(a, b) / n,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Maps each entity to its parent (if any).
+pub fn load_parents(conn: &Connection) -> Result<HashMap<Id, Option<Id>>> {
+    let mut stmt = conn.prepare("SELECT id, parent_id FROM entities")?;
+    let parents =
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_, _>>()?;
+    Ok(parents)
+}
+
+/// Returns the given entity followed by all of its ancestors.
+pub fn ancestors_or_self(parents: &HashMap<Id, Option<Id>>, id: Id) -> Vec<Id> {
+    let mut ids = vec![id];
+    let mut curr = parents.get(&id).copied().flatten();
+
+    while let Some(parent_id) = curr {
+        ids.push(parent_id);
+        curr = parents.get(&parent_id).copied().flatten();
+    }
+
+    ids
+}
+
+/// Builds a human-readable path (e.g. `Foo.java::Foo::bar`) for every entity.
+/// Directories are left out because the names of files already contain them.
+pub fn load_entity_paths(conn: &Connection) -> Result<HashMap<Id, String>> {
+    let mut stmt = conn.prepare("SELECT id, parent_id, name, kind FROM entities ORDER BY id")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, Id>(0)?,
+            row.get::<_, Option<Id>>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+        ))
+    })?;
+
+    // Parents always have a smaller id than their children
+    let mut paths: HashMap<Id, String> = HashMap::new();
+    let mut dirs = HashSet::new();
+
+    for row in rows {
+        let (id, parent_id, name, kind) = row?;
+        let parent_id = parent_id.filter(|p| !dirs.contains(p));
+
+        if kind == "dir" {
+            dirs.insert(id);
+        }
+
+        let path = match parent_id.and_then(|p| paths.get(&p)) {
+            Some(parent_path) => format!("{}::{}", parent_path, name),
+            None => name,
+        };
+
+        paths.insert(id, path);
+    }
+
+    Ok(paths)
+}
+
+/// The id of each entity by the (name, kind, disc) of it and of its ancestors.
+pub type EntityKeys = HashMap<Vec<(String, String, String)>, Id>;
+
+/// Maps the (name, kind, disc) of each entity and of its ancestors (outermost
+/// first) to its id. This matches the order of `ir::Entity::to_vec`, so
+/// directories are left out.
+pub fn load_entity_keys(conn: &Connection) -> Result<EntityKeys> {
+    let mut stmt =
+        conn.prepare("SELECT id, parent_id, name, kind, disc FROM entities ORDER BY id")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, Id>(0)?,
+            row.get::<_, Option<Id>>(1)?,
+            (row.get::<_, String>(2)?, row.get::<_, String>(3)?, row.get::<_, String>(4)?),
+        ))
+    })?;
+
+    // Parents always have a smaller id than their children
+    let mut keys: HashMap<Id, Vec<(String, String, String)>> = HashMap::new();
+
+    let mut dirs = HashSet::new();
+
+    for row in rows {
+        let (id, parent_id, tag) = row?;
+        let parent_id = parent_id.filter(|p| !dirs.contains(p));
+
+        if tag.1 == "dir" {
+            dirs.insert(id);
+        }
+
+        let mut key = parent_id.and_then(|p| keys.get(&p)).cloned().unwrap_or_default();
+        key.push(tag);
+        keys.insert(id, key);
+    }
+
+    Ok(keys.into_iter().map(|(id, key)| (key, id)).collect())
+}
+
+/// Finds the entity with the given path (see `load_entity_paths`). The leading
+/// part of the path may be omitted so long as the remainder is unambiguous,
+/// e.g. `Foo.java::Foo::bar` for `src/main/java/Foo.java::Foo::bar`. Paths
+/// quoted by git (see `unquote_path`) are accepted as well.
+pub fn resolve_entity(paths: &HashMap<Id, String>, query: &str) -> Result<Id> {
+    let query = unquote_path(query);
+    let query = query.as_str();
+
+    if let Some((&id, _)) = paths.iter().find(|(_, path)| path.as_str() == query) {
+        return Ok(id);
+    }
+
+    let mut matches = paths
+        .iter()
+        .filter(|(_, path)| {
+            path.strip_suffix(query)
+                .is_some_and(|prefix| prefix.ends_with('/') || prefix.ends_with("::"))
+        })
+        .collect::<Vec<_>>();
+    matches.sort_by_key(|&(_, path)| path);
+
+    match matches.as_slice() {
+        [] => bail!("no entity matches '{}'", query),
+        [(&id, _)] => Ok(id),
+        _ => {
+            let candidates = matches.iter().take(10).map(|(_, p)| format!("\n  {}", p));
+            bail!("'{}' is ambiguous; candidates:{}", query, candidates.collect::<String>())
+        }
+    }
+}
+
+/// The ids of the entities present at the given commit.
+pub fn load_present_ids(conn: &Connection, commit_id: Id) -> Result<HashSet<Id>> {
+    let mut stmt = conn.prepare("SELECT entity_id FROM presence WHERE commit_id = :commit_id")?;
+    let ids = stmt
+        .query_map(&[(":commit_id", &commit_id)], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    Ok(ids)
+}
+
+/// Loads the dependencies (source, target, and kind) extracted from a commit.
+pub fn load_deps(conn: &Connection, commit_id: Id) -> Result<Vec<(Id, Id, String)>> {
+    let mut stmt = conn.prepare(
+        "SELECT src_id, tgt_id, kind FROM deps WHERE commit_id = ? ORDER BY src_id, tgt_id, kind",
+    )?;
+    let deps = stmt
+        .query_map([commit_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<Result<_, _>>()?;
+    Ok(deps)
+}
+
+/// A directed graph of structural dependencies between entities.
+///
+/// Each dependency is "lifted" to the ancestors of both of its endpoints, so a
+/// class depends on another class if any of its members depends on any member
+/// of the other.
+#[derive(Debug, Default)]
+pub struct DepGraph {
+    edges: HashMap<Id, HashSet<Id>>,
+}
+
+impl DepGraph {
+    pub fn load(
+        conn: &Connection,
+        commit_id: Id,
+        parents: &HashMap<Id, Option<Id>>,
+    ) -> Result<Self> {
+        let mut stmt =
+            conn.prepare("SELECT src_id, tgt_id FROM deps WHERE commit_id = :commit_id")?;
+        let rows = stmt.query_map(&[(":commit_id", &commit_id)], |row| {
+            Ok((row.get::<_, Id>(0)?, row.get::<_, Id>(1)?))
+        })?;
+
+        let mut graph = Self::default();
+
+        for row in rows {
+            let (src_id, tgt_id) = row?;
+            let tgt_ids = ancestors_or_self(parents, tgt_id);
+
+            for src in ancestors_or_self(parents, src_id) {
+                for &tgt in &tgt_ids {
+                    if src != tgt {
+                        graph.edges.entry(src).or_default().insert(tgt);
+                    }
+                }
+            }
+        }
+
+        if graph.edges.is_empty() {
+            bail!("no dependencies found for this commit (did you run add-deps?)");
+        }
+
+        Ok(graph)
+    }
+
+    pub fn depends_on(&self, src: Id, tgt: Id) -> bool {
+        self.edges.get(&src).map(|tgts| tgts.contains(&tgt)).unwrap_or(false)
+    }
+
+    /// The same graph with every dependency reversed (i.e. from each entity to
+    /// the entities which depend on it).
+    pub fn reversed(&self) -> Self {
+        let mut graph = Self::default();
+
+        for (&src, tgts) in &self.edges {
+            for &tgt in tgts {
+                graph.edges.entry(tgt).or_default().insert(src);
+            }
+        }
+
+        graph
+    }
+
+    /// The entities which `src` depends on, either directly or (if
+    /// `transitive`) through any number of dependencies.
+    pub fn reachable(&self, src: Id, transitive: bool) -> HashSet<Id> {
+        let mut visited = HashSet::from([src]);
+        let mut queue = VecDeque::from([src]);
+
+        while let Some(curr) = queue.pop_front() {
+            for &next in self.edges.get(&curr).into_iter().flatten() {
+                if visited.insert(next) && transitive {
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        visited.remove(&src);
+        visited
+    }
+
+    /// Whether `tgt` can be reached from `src` by following any number of
+    /// dependencies.
+    pub fn has_path(&self, src: Id, tgt: Id) -> bool {
+        let mut visited = HashSet::from([src]);
+        let mut queue = VecDeque::from([src]);
+
+        while let Some(curr) = queue.pop_front() {
+            for &next in self.edges.get(&curr).into_iter().flatten() {
+                if next == tgt {
+                    return true;
+                }
+
+                if visited.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        false
+    }
+}
+
+/// A pair of co-changing entities. The confidence is the larger of the
+/// confidences in either direction.
+#[derive(Debug, Clone)]
+pub struct Pair {
+    pub entity1_id: Id,
+    pub entity2_id: Id,
+    pub support: f64,
+    pub confidence: f64,
+}
+
+/// Finds the pairs of entities which meet the given support and confidence
+/// thresholds, sorted by support and then by confidence.
+///
+/// Pairs where one entity contains the other are skipped, as they trivially
+/// co-change.
+pub fn find_coupled_pairs(
+    coupling: &Coupling,
+    parents: &HashMap<Id, Option<Id>>,
+    min_support: f64,
+    min_confidence: f64,
+) -> Vec<Pair> {
+    let mut pairs = Vec::new();
+
+    for (&(a, b), &support) in &coupling.pairs {
+        if support < min_support {
+            continue;
+        }
+
+        let confidence = coupling.confidence(a, b).max(coupling.confidence(b, a));
+
+        if confidence < min_confidence {
+            continue;
+        }
+
+        if ancestors_or_self(parents, a).contains(&b) || ancestors_or_self(parents, b).contains(&a)
+        {
+            continue;
+        }
+
+        pairs.push(Pair { entity1_id: a, entity2_id: b, support, confidence });
+    }
+
+    pairs.sort_by(|x, y| {
+        (y.support.total_cmp(&x.support))
+            .then(y.confidence.total_cmp(&x.confidence))
+            .then((x.entity1_id, x.entity2_id).cmp(&(y.entity1_id, y.entity2_id)))
+    });
+
+    pairs
+}
+
+/// Finds pairs of entities which frequently change together but have no
+/// structural dependency between them (in either direction). These are
+/// candidate modularity violations.
+///
+/// Only entities present at the commit the dependencies were extracted from
+/// are considered.
+pub fn find_hidden_deps(
+    conn: &Connection,
+    mut change_sets: Vec<ChangeSet>,
+    commit_id: Id,
+    min_support: f64,
+    min_confidence: f64,
+    transitive: bool,
+) -> Result<Vec<Pair>> {
+    let parents = load_parents(conn)?;
+    let graph = DepGraph::load(conn, commit_id, &parents)?;
+    let present = load_present_ids(conn, commit_id)?;
+
+    for change_set in &mut change_sets {
+        change_set.entities.retain(|(id, _)| present.contains(id));
+    }
+
+    let coupling = Coupling::new(&change_sets);
+    let mut pairs = find_coupled_pairs(&coupling, &parents, min_support, min_confidence);
+
+    pairs.retain(|p| {
+        let (a, b) = (p.entity1_id, p.entity2_id);
+
+        let is_dependent = if transitive {
+            graph.has_path(a, b) || graph.has_path(b, a)
+        } else {
+            graph.depends_on(a, b) || graph.depends_on(b, a)
+        };
+
+        !is_dependent
+    });
+
+    Ok(pairs)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum InterfaceClass {
+    Fragile,
+    Stable,
+}
+
+impl InterfaceClass {
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            InterfaceClass::Fragile => "fragile",
+            InterfaceClass::Stable => "stable",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DepCochange {
+    pub src_id: Id,
+    pub tgt_id: Id,
+    pub src_changes: f64,
+    pub tgt_changes: f64,
+    pub support: f64,
+    pub tgt_fan_in: usize,
+    pub class: Option<InterfaceClass>,
+}
+
+impl DepCochange {
+    /// The fraction of the changes to the target which also changed the source.
+    /// This estimates how often a change propagates along this dependency.
+    pub fn propagation(&self) -> f64 {
+        match self.tgt_changes > 0.0 {
+            true => self.support / self.tgt_changes,
+            false => 0.0,
+        }
+    }
+}
+
+/// Reports, for each structural dependency at the given commit, how often its
+/// endpoints co-change.
+///
+/// Targets with a fan-in of at least `min_fan_in` are classified as interfaces.
+/// An interface is fragile if, on average, at least `min_propagation` of its
+/// changes propagate to its dependents. Otherwise it is stable.
+pub fn find_dep_cochanges(
+    conn: &Connection,
+    change_sets: Vec<ChangeSet>,
+    commit_id: Id,
+    min_fan_in: usize,
+    min_propagation: f64,
+) -> Result<Vec<DepCochange>> {
+    let mut stmt =
+        conn.prepare("SELECT DISTINCT src_id, tgt_id FROM deps WHERE commit_id = :commit_id")?;
+    let edges = stmt
+        .query_map(&[(":commit_id", &commit_id)], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<(Id, Id)>, _>>()?;
+
+    if edges.is_empty() {
+        bail!("no dependencies found for this commit (did you run add-deps?)");
+    }
+
+    let endpoints = edges.iter().flat_map(|&(s, t)| [s, t]).collect::<HashSet<_>>();
+    let mut commits: HashMap<Id, HashSet<Id>> = HashMap::new();
+    let mut weights: HashMap<Id, f64> = HashMap::new();
+
+    for change_set in change_sets {
+        weights.insert(change_set.commit_id, change_set.weight);
+
+        for entity_id in change_set.entity_ids() {
+            if endpoints.contains(&entity_id) {
+                commits.entry(entity_id).or_default().insert(change_set.commit_id);
+            }
+        }
+    }
+
+    let mut fan_in: HashMap<Id, usize> = HashMap::new();
+
+    for &(src_id, tgt_id) in &edges {
+        if src_id != tgt_id {
+            *fan_in.entry(tgt_id).or_default() += 1;
+        }
+    }
+
+    let empty = HashSet::new();
+    let mut dep_cochanges = Vec::new();
+
+    for (src_id, tgt_id) in edges {
+        if src_id == tgt_id {
+            continue;
+        }
+
+        let src_commits = commits.get(&src_id).unwrap_or(&empty);
+        let tgt_commits = commits.get(&tgt_id).unwrap_or(&empty);
+
+        dep_cochanges.push(DepCochange {
+            src_id,
+            tgt_id,
+            src_changes: src_commits.iter().map(|c| weights[c]).sum(),
+            tgt_changes: tgt_commits.iter().map(|c| weights[c]).sum(),
+            support: src_commits.intersection(tgt_commits).map(|c| weights[c]).sum(),
+            tgt_fan_in: fan_in[&tgt_id],
+            class: None,
+        });
+    }
+
+    // Classify each interface by the mean propagation over its incoming edges
+    let mut propagations: HashMap<Id, Vec<f64>> = HashMap::new();
+
+    for dep_cochange in &dep_cochanges {
+        if dep_cochange.tgt_fan_in >= min_fan_in {
+            propagations.entry(dep_cochange.tgt_id).or_default().push(dep_cochange.propagation());
+        }
+    }
+
+    let classes = propagations
+        .into_iter()
+        .map(|(tgt_id, ps)| {
+            let mean = ps.iter().sum::<f64>() / ps.len() as f64;
+            let class = match mean >= min_propagation {
+                true => InterfaceClass::Fragile,
+                false => InterfaceClass::Stable,
+            };
+            (tgt_id, class)
+        })
+        .collect::<HashMap<_, _>>();
+
+    for dep_cochange in &mut dep_cochanges {
+        dep_cochange.class = classes.get(&dep_cochange.tgt_id).copied();
+    }
+
+    dep_cochanges.sort_by_key(|d| (d.src_id, d.tgt_id));
+    Ok(dep_cochanges)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Volatility {
+    Volatile,
+    Stable,
+}
+
+impl Volatility {
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            Volatility::Volatile => "volatile",
+            Volatility::Stable => "stable",
+        }
+    }
+}
+
+/// An interface (an entity with many dependents) along with how often it
+/// changed.
+#[derive(Debug, Clone)]
+pub struct Interface {
+    pub entity_id: Id,
+    /// The number of distinct entities which depend on this one
+    pub fan_in: usize,
+    pub changes: f64,
+    pub churn: usize,
+    pub volatility: Volatility,
+}
+
+/// Finds the entities with at least `min_fan_in` dependents at the given
+/// commit and classifies each as volatile if it changed at least `min_changes`
+/// (weighted) times and as stable otherwise. Volatile interfaces come first,
+/// each sorted by fan-in and then by changes.
+pub fn find_interfaces(
+    conn: &Connection,
+    change_sets: &[ChangeSet],
+    commit_id: Id,
+    min_fan_in: usize,
+    min_changes: f64,
+) -> Result<Vec<Interface>> {
+    let deps = load_deps(conn, commit_id)?;
+
+    if deps.is_empty() {
+        bail!("no dependencies found for this commit (did you run add-deps?)");
+    }
+
+    let mut dependents: HashMap<Id, HashSet<Id>> = HashMap::new();
+
+    for (src_id, tgt_id, _) in deps.into_iter().filter(|(src_id, tgt_id, _)| src_id != tgt_id) {
+        dependents.entry(tgt_id).or_default().insert(src_id);
+    }
+
+    let mut interfaces = dependents
+        .into_iter()
+        .filter(|(_, srcs)| srcs.len() >= min_fan_in)
+        .map(|(entity_id, srcs)| {
+            (
+                entity_id,
+                Interface {
+                    entity_id,
+                    fan_in: srcs.len(),
+                    changes: 0.0,
+                    churn: 0,
+                    volatility: Volatility::Stable,
+                },
+            )
+        })
+        .collect::<HashMap<_, _>>();
+
+    for change_set in change_sets {
+        for &(entity_id, churn) in &change_set.entities {
+            if let Some(interface) = interfaces.get_mut(&entity_id) {
+                interface.changes += change_set.weight;
+                interface.churn += churn;
+            }
+        }
+    }
+
+    let mut interfaces = interfaces.into_values().collect::<Vec<_>>();
+
+    for interface in &mut interfaces {
+        if interface.changes >= min_changes {
+            interface.volatility = Volatility::Volatile;
+        }
+    }
+
+    interfaces.sort_by(|x, y| {
+        (x.volatility.cmp(&y.volatility))
+            .then(y.fan_in.cmp(&x.fan_in))
+            .then(y.changes.total_cmp(&x.changes))
+            .then(x.entity_id.cmp(&y.entity_id))
+    });
+    Ok(interfaces)
+}
+
+/// The lines (adds + dels) each author contributed to each entity, along with
+/// the most recently used name of each author (keyed by email). The changes to
+/// each parent of a merge are counted once, by their largest churn.
+#[derive(Debug, Default)]
+pub struct Contributions {
+    pub lines: HashMap<Id, HashMap<String, usize>>,
+    pub names: HashMap<String, String>,
+}
+
+pub fn load_contributions(conn: &Connection) -> Result<Contributions> {
+    let mut stmt = conn.prepare(
+        "SELECT CH.entity_id, CO.author_name, CO.author_mail, MAX(CH.adds + CH.dels)
+        FROM changes CH
+        JOIN commits CO ON CO.id = CH.commit_id
+        GROUP BY CH.commit_id, CH.entity_id
+        ORDER BY CO.author_date",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, Id>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, usize>(3)?,
+        ))
+    })?;
+
+    let mut contributions = Contributions::default();
+
+    for row in rows {
+        let (entity_id, name, mail, lines) = row?;
+        *contributions.lines.entry(entity_id).or_default().entry(mail.clone()).or_default() +=
+            lines;
+        contributions.names.insert(mail, name);
+    }
+
+    Ok(contributions)
+}
+
+/// Maps each entity to its kind.
+pub fn load_kinds(conn: &Connection) -> Result<HashMap<Id, String>> {
+    let mut stmt = conn.prepare("SELECT id, kind FROM entities")?;
+    let kinds =
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_, _>>()?;
+    Ok(kinds)
+}
+
+/// Maps each file entity to its path.
+pub fn load_files(conn: &Connection) -> Result<HashMap<Id, String>> {
+    let mut stmt = conn.prepare("SELECT id, name FROM entities WHERE kind = 'file'")?;
+    let files =
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_, _>>()?;
+    Ok(files)
+}
+
+/// The package (i.e. directory) of the given file path.
+pub fn to_package(path: &str) -> String {
+    match path.rsplit_once('/') {
+        Some((dir, _)) => dir.to_string(),
+        None => ".".to_string(),
+    }
+}
+
+/// The share of each author, sorted from largest to smallest.
+pub fn to_shares(lines: &HashMap<String, usize>) -> Vec<(String, f64)> {
+    let total = lines.values().sum::<usize>().max(1) as f64;
+    let mut shares = lines.iter().map(|(a, &n)| (a.clone(), n as f64 / total)).collect::<Vec<_>>();
+    shares.sort_by(|x, y| y.1.total_cmp(&x.1).then(x.0.cmp(&y.0)));
+    shares
+}
+
+/// The smallest number of authors which together contributed more than
+/// `coverage` of the lines. Losing these authors would leave most of the code
+/// without anyone familiar with it.
+pub fn bus_factor(shares: &[(String, f64)], coverage: f64) -> usize {
+    let mut covered = 0.0;
+
+    for (i, (_, share)) in shares.iter().enumerate() {
+        covered += share;
+
+        if covered > coverage {
+            return i + 1;
+        }
+    }
+
+    shares.len()
+}
+
+#[derive(Debug, Clone)]
+pub struct BusFactor {
+    pub kind: &'static str,
+    pub name: String,
+    pub entity_id: Option<Id>,
+    pub shares: Vec<(String, f64)>,
+    pub bus_factor: usize,
+}
+
+/// Computes the bus factor of every file and of every package.
+pub fn find_bus_factors(
+    contributions: &Contributions,
+    files: &HashMap<Id, String>,
+    coverage: f64,
+) -> Vec<BusFactor> {
+    let mut bus_factors = Vec::new();
+    let mut packages: HashMap<String, HashMap<String, usize>> = HashMap::new();
+
+    for (&entity_id, path) in files {
+        let lines = match contributions.lines.get(&entity_id) {
+            Some(lines) => lines,
+            None => continue,
+        };
+
+        let package = packages.entry(to_package(path)).or_default();
+
+        for (author, &n) in lines {
+            *package.entry(author.clone()).or_default() += n;
+        }
+
+        let shares = to_shares(lines);
+        let bus_factor = bus_factor(&shares, coverage);
+        let name = path.clone();
+        bus_factors.push(BusFactor {
+            kind: "file",
+            name,
+            entity_id: Some(entity_id),
+            shares,
+            bus_factor,
+        });
+    }
+
+    for (name, lines) in packages {
+        let shares = to_shares(&lines);
+        let bus_factor = bus_factor(&shares, coverage);
+        bus_factors.push(BusFactor { kind: "package", name, entity_id: None, shares, bus_factor });
+    }
+
+    bus_factors.sort_by(|x, y| (x.kind, &x.name).cmp(&(y.kind, &y.name)));
+    bus_factors
+}
+
+/// The number of commits in which each author touched each entity. If `kind`
+/// is given, only entities of that kind are considered.
+pub fn load_touches(
+    conn: &Connection,
+    kind: Option<&str>,
+) -> Result<HashMap<String, HashMap<Id, usize>>> {
+    let mut stmt = conn.prepare(
+        "SELECT CO.author_mail, CH.entity_id, COUNT(DISTINCT CH.commit_id)
+        FROM changes CH
+        JOIN commits CO ON CO.id = CH.commit_id
+        JOIN entities E ON E.id = CH.entity_id
+        WHERE :kind IS NULL OR E.kind = :kind
+        GROUP BY CO.author_mail, CH.entity_id",
+    )?;
+    let rows = stmt.query_map(&[(":kind", &kind)], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, Id>(1)?, row.get::<_, usize>(2)?))
+    })?;
+
+    let mut touches: HashMap<String, HashMap<Id, usize>> = HashMap::new();
+
+    for row in rows {
+        let (mail, entity_id, n) = row?;
+        touches.entry(mail).or_default().insert(entity_id, n);
+    }
+
+    Ok(touches)
+}
+
+/// Weights each pair of authors by the number of entities they both touched
+/// (each in at least `min_touches` commits).
+pub fn find_dev_pairs(
+    touches: &HashMap<String, HashMap<Id, usize>>,
+    min_touches: usize,
+) -> HashMap<(String, String), usize> {
+    let mut authors: HashMap<Id, Vec<&String>> = HashMap::new();
+
+    for (mail, entities) in touches {
+        for (&entity_id, &n) in entities {
+            if n >= min_touches {
+                authors.entry(entity_id).or_default().push(mail);
+            }
+        }
+    }
+
+    let mut pairs: HashMap<(String, String), usize> = HashMap::new();
+
+    for mut mails in authors.into_values() {
+        mails.sort();
+
+        for (i, a) in mails.iter().enumerate() {
+            for b in &mails[i + 1..] {
+                *pairs.entry(((*a).clone(), (*b).clone())).or_default() += 1;
+            }
+        }
+    }
+
+    pairs
+}
+
+/// A (half-open) interval of time given as unix timestamps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Window {
+    pub start: i64,
+    pub end: i64,
+}
+
+impl Window {
+    pub fn contains(&self, time: i64) -> bool {
+        self.start <= time && time < self.end
+    }
+}
+
+/// Splits the span of the given change sets into windows of length `size`
+/// whose starts are `step` apart. If no size is given, a single window covering
+/// every change set is returned.
+pub fn to_windows(
+    change_sets: &[ChangeSet],
+    size: Option<Duration>,
+    step: Option<Duration>,
+) -> Vec<Window> {
+    let first = change_sets.iter().map(|c| c.commit_time).min().unwrap_or_default();
+    let last = change_sets.iter().map(|c| c.commit_time).max().unwrap_or_default();
+
+    let size = match size {
+        Some(size) => size.as_secs().max(1) as i64,
+        None => return vec![Window { start: first, end: last + 1 }],
+    };
+
+    let step = step.map(|s| s.as_secs().max(1) as i64).unwrap_or(size);
+    let mut windows = Vec::new();
+    let mut start = first;
+
+    while start <= last {
+        windows.push(Window { start, end: start + size });
+        start += step;
+    }
+
+    windows
+}
+
+/// The tags matching the given glob pattern (e.g. `v*`) along with the commit
+/// time of each, ordered from oldest to newest.
+pub fn load_releases(conn: &Connection, glob: &str) -> Result<Vec<(String, i64)>> {
+    let mut stmt = conn.prepare(
+        "SELECT T.name, C.commit_date
+        FROM tags T
+        JOIN commits C ON C.id = T.commit_id
+        WHERE T.name GLOB ?
+        ORDER BY C.commit_date, T.name",
+    )?;
+    let releases =
+        stmt.query_map([glob], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_, _>>()?;
+    Ok(releases)
+}
+
+/// Splits the history into the intervals between consecutive releases. The
+/// window of an interval contains the commits after the older release up to
+/// and including the newer release. Each window is labeled by its releases.
+pub fn to_release_windows(releases: &[(String, i64)]) -> Vec<(String, Window)> {
+    releases
+        .windows(2)
+        .map(|pair| (&pair[0], &pair[1]))
+        .filter(|((_, older), (_, newer))| older < newer)
+        .map(|((older_name, older), (newer_name, newer))| {
+            let label = format!("{}..{}", older_name, newer_name);
+            (label, Window { start: older + 1, end: newer + 1 })
+        })
+        .collect()
+}
+
+/// The change sets which fall within the given window.
+pub fn in_window(change_sets: &[ChangeSet], window: &Window) -> Vec<ChangeSet> {
+    change_sets.iter().filter(|c| window.contains(c.commit_time)).cloned().collect()
+}
+
+/// The number of change sets and the churn of each month (given as YYYY-MM) from
+/// the first to the last change set. Months without changes are included. The
+/// churn of a change set is the lines its commit changed in files (see
+/// `find_commit_metrics`), as each changed line also changes every entity
+/// around it.
+pub fn find_churn_trend(
+    change_sets: &[ChangeSet],
+    commit_metrics: &[CommitMetrics],
+) -> Vec<(String, usize, usize)> {
+    let lines: HashMap<Id, usize> = commit_metrics.iter().map(|m| (m.commit_id, m.lines)).collect();
+    let mut months: BTreeMap<(i32, u8), (usize, usize)> = BTreeMap::new();
+
+    for change_set in change_sets {
+        if let Ok(datetime) = OffsetDateTime::from_unix_timestamp(change_set.commit_time) {
+            let month = months.entry((datetime.year(), datetime.month() as u8)).or_default();
+            month.0 += 1;
+            month.1 += lines.get(&change_set.commit_id).copied().unwrap_or_default();
+        }
+    }
+
+    let (first, last) = match (months.keys().next(), months.keys().next_back()) {
+        (Some(&first), Some(&last)) => (first, last),
+        _ => return Vec::new(),
+    };
+
+    let mut trend = Vec::new();
+    let (mut year, mut month) = first;
+
+    while (year, month) <= last {
+        let (commits, churn) = months.get(&(year, month)).copied().unwrap_or_default();
+        trend.push((format!("{:04}-{:02}", year, month), commits, churn));
+        (year, month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+    }
+
+    trend
+}
+
+/// The size (in lines) and complexity (branches + 1) of each entity which is
+/// still present (see `entity_metrics`).
+pub fn load_measures(conn: &Connection) -> Result<HashMap<Id, (usize, usize)>> {
+    let mut stmt =
+        conn.prepare("SELECT entity_id, loc, branches + 1 FROM entity_metrics WHERE loc > 0")?;
+    let measures = stmt
+        .query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?
+        .collect::<Result<_, _>>()?;
+    Ok(measures)
+}
+
+/// What the number of changes to an entity is multiplied by to find hotspots.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum HotspotMeasure {
+    Size,
+    Complexity,
+}
+
+#[derive(Debug, Clone)]
+pub struct Hotspot {
+    pub entity_id: Id,
+    pub changes: f64,
+    pub churn: usize,
+    pub size: usize,
+    pub complexity: usize,
+    pub score: f64,
+}
+
+/// Ranks entities by their (weighted) number of changes times their size or
+/// complexity. Entities which are no longer present are given a size and
+/// complexity of zero.
+pub fn find_hotspots(
+    change_sets: &[ChangeSet],
+    measures: &HashMap<Id, (usize, usize)>,
+    measure: HotspotMeasure,
+) -> Vec<Hotspot> {
+    let mut hotspots: HashMap<Id, Hotspot> = HashMap::new();
+
+    for change_set in change_sets {
+        for &(entity_id, churn) in &change_set.entities {
+            let (size, complexity) = measures.get(&entity_id).copied().unwrap_or_default();
+            let hotspot = hotspots.entry(entity_id).or_insert_with(|| Hotspot {
+                entity_id,
+                changes: 0.0,
+                churn: 0,
+                size,
+                complexity,
+                score: 0.0,
+            });
+
+            hotspot.changes += change_set.weight;
+            hotspot.churn += churn;
+        }
+    }
+
+    let mut hotspots = hotspots.into_values().collect::<Vec<_>>();
+
+    for hotspot in &mut hotspots {
+        hotspot.score = hotspot.changes
+            * match measure {
+                HotspotMeasure::Size => hotspot.size as f64,
+                HotspotMeasure::Complexity => hotspot.complexity as f64,
+            };
+    }
+
+    hotspots.sort_by(|x, y| y.score.total_cmp(&x.score).then(x.entity_id.cmp(&y.entity_id)));
+    hotspots
+}
+
+/// Key metrics of the coupling within a window, to track over time how the
+/// architecture erodes.
+#[derive(Debug, Clone, Default)]
+pub struct TrendPoint {
+    /// The (weighted) number of change sets
+    pub commits: f64,
+    /// The number of strongly coupled pairs
+    pub pairs: usize,
+    /// The total support of the strongly coupled pairs
+    pub total_support: f64,
+    pub mean_confidence: f64,
+    /// The support of the most strongly coupled pair
+    pub top_support: f64,
+    /// The largest number of strongly coupled pairs which one entity belongs to
+    pub top_degree: usize,
+    /// The score of the top hotspot (by size)
+    pub top_hotspot: f64,
+}
+
+/// Computes the metrics of a trend from the change sets of one window.
+pub fn to_trend_point(
+    change_sets: &[ChangeSet],
+    parents: &HashMap<Id, Option<Id>>,
+    measures: &HashMap<Id, (usize, usize)>,
+    min_support: f64,
+    min_confidence: f64,
+) -> TrendPoint {
+    let coupling = Coupling::new(change_sets);
+    let pairs = find_coupled_pairs(&coupling, parents, min_support, min_confidence);
+    let mut degrees: HashMap<Id, usize> = HashMap::new();
+
+    for pair in &pairs {
+        *degrees.entry(pair.entity1_id).or_default() += 1;
+        *degrees.entry(pair.entity2_id).or_default() += 1;
+    }
+
+    let total_support = pairs.iter().map(|p| p.support).sum::<f64>();
+    let total_confidence = pairs.iter().map(|p| p.confidence).sum::<f64>();
+    let hotspots = find_hotspots(change_sets, measures, HotspotMeasure::Size);
+
+    TrendPoint {
+        commits: change_sets.iter().map(|c| c.weight).sum(),
+        pairs: pairs.len(),
+        total_support,
+        mean_confidence: if pairs.is_empty() { 0.0 } else { total_confidence / pairs.len() as f64 },
+        top_support: pairs.first().map_or(0.0, |p| p.support),
+        top_degree: degrees.values().copied().max().unwrap_or_default(),
+        top_hotspot: hotspots.first().map_or(0.0, |h| h.score),
+    }
+}
+
+/// Per-entity metrics within a period, used to compare two periods.
+#[derive(Debug, Clone, Default)]
+pub struct PeriodMetrics {
+    pub changes: f64,
+    pub churn: usize,
+    pub degree: usize,
+}
+
+pub fn to_period_metrics(
+    change_sets: &[ChangeSet],
+    parents: &HashMap<Id, Option<Id>>,
+    min_support: f64,
+    min_confidence: f64,
+) -> (HashMap<Id, PeriodMetrics>, usize) {
+    let mut metrics: HashMap<Id, PeriodMetrics> = HashMap::new();
+
+    for change_set in change_sets {
+        for &(entity_id, churn) in &change_set.entities {
+            let m = metrics.entry(entity_id).or_default();
+            m.changes += change_set.weight;
+            m.churn += churn;
+        }
+    }
+
+    let coupling = Coupling::new(change_sets);
+    let pairs = find_coupled_pairs(&coupling, parents, min_support, min_confidence);
+
+    for pair in &pairs {
+        metrics.entry(pair.entity1_id).or_default().degree += 1;
+        metrics.entry(pair.entity2_id).or_default().degree += 1;
+    }
+
+    (metrics, pairs.len())
+}
+
+/// The files changed by a commit, used to detect bulk commits. The changes to
+/// each parent of a merge are counted once, by their largest churn.
+#[derive(Debug, Clone, Default)]
+pub struct CommitShape {
+    pub commit_id: Id,
+    pub num_entities: usize,
+    /// The path, kind of change ('A', 'D', or 'M'), adds, and dels of each
+    /// changed file
+    pub files: Vec<(String, char, usize, usize)>,
+}
+
+pub fn load_commit_shapes(conn: &Connection) -> Result<Vec<CommitShape>> {
+    let mut stmt = conn.prepare(
+        "SELECT CH.commit_id, E.kind, E.name, CH.kind, CH.adds, CH.dels, MAX(CH.adds + CH.dels)
+        FROM changes CH
+        JOIN entities E ON E.id = CH.entity_id
+        GROUP BY CH.commit_id, CH.entity_id
+        ORDER BY CH.commit_id",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, Id>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, usize>(4)?,
+            row.get::<_, usize>(5)?,
+        ))
+    })?;
+
+    let mut shapes: Vec<CommitShape> = Vec::new();
+
+    for row in rows {
+        let (commit_id, entity_kind, name, kind, adds, dels) = row?;
+
+        if shapes.last().map(|s| s.commit_id) != Some(commit_id) {
+            shapes.push(CommitShape { commit_id, ..Default::default() });
+        }
+
+        let shape = shapes.last_mut().unwrap();
+        shape.num_entities += 1;
+
+        if entity_kind == "file" {
+            let kind = kind.chars().next().unwrap_or('M');
+            shape.files.push((name, kind, adds, dels));
+        }
+    }
+
+    Ok(shapes)
+}
+
+/// Why a commit was classified as a bulk commit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BulkKind {
+    /// Exceeds the maximum number of files or entities
+    Large,
+    /// Makes the same number of adds and dels to most files (e.g. updating a
+    /// license header)
+    Uniform,
+    /// Moves most files (i.e. deletes them and adds files of the same name)
+    Move,
+    /// Makes a balanced number of adds and dels to most files (e.g. reformatting)
+    Reformat,
+}
+
+impl BulkKind {
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            BulkKind::Large => "large",
+            BulkKind::Uniform => "uniform",
+            BulkKind::Move => "move",
+            BulkKind::Reformat => "reformat",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BulkThresholds {
+    pub max_files: Option<usize>,
+    pub max_entities: Option<usize>,
+    /// Commits which change fewer files than this are never classified as
+    /// uniform, move, or reformat commits
+    pub min_files: usize,
+}
+
+/// Classifies a commit as a bulk commit (or not) with a few simple heuristics.
+/// A heuristic matches when it holds for at least 80% of the changed files.
+pub fn classify_commit(shape: &CommitShape, thresholds: &BulkThresholds) -> Option<BulkKind> {
+    let num_files = shape.files.len();
+
+    if thresholds.max_files.is_some_and(|max| num_files > max)
+        || thresholds.max_entities.is_some_and(|max| shape.num_entities > max)
+    {
+        return Some(BulkKind::Large);
+    }
+
+    if num_files == 0 || num_files < thresholds.min_files {
+        return None;
+    }
+
+    let quorum = (num_files * 4).div_ceil(5);
+
+    let mut counts: HashMap<(usize, usize), usize> = HashMap::new();
+
+    for &(_, _, adds, dels) in &shape.files {
+        *counts.entry((adds, dels)).or_default() += 1;
+    }
+
+    if counts.values().copied().max().unwrap_or_default() >= quorum {
+        return Some(BulkKind::Uniform);
+    }
+
+    let basename = |path: &str| path.rsplit('/').next().unwrap_or(path).to_string();
+    let deleted = shape
+        .files
+        .iter()
+        .filter(|(_, kind, _, _)| *kind == 'D')
+        .map(|(path, _, _, _)| basename(path))
+        .collect::<HashSet<_>>();
+    let moved = shape
+        .files
+        .iter()
+        .filter(|(path, kind, _, _)| *kind == 'A' && deleted.contains(&basename(path)))
+        .count();
+
+    if moved * 2 >= quorum {
+        return Some(BulkKind::Move);
+    }
+
+    let balanced = shape
+        .files
+        .iter()
+        .filter(|&&(_, kind, adds, dels)| kind == 'M' && adds.abs_diff(dels) * 10 <= adds.max(dels))
+        .count();
+
+    if balanced >= quorum {
+        return Some(BulkKind::Reformat);
+    }
+
+    None
+}
+
+/// Maps each commit to its hash.
+pub fn load_commit_shas(conn: &Connection) -> Result<HashMap<Id, String>> {
+    let mut stmt = conn.prepare("SELECT id, sha1 FROM commits")?;
+    let shas =
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_, _>>()?;
+    Ok(shas)
+}
+
+/// A change to a single entity.
+#[derive(Debug, Clone)]
+pub struct EntityChange {
+    pub sha1: String,
+    pub commit_time: i64,
+    pub kind: String,
+    pub adds: usize,
+    pub dels: usize,
+}
+
+/// Loads the changes to the given entity, most recent first.
+pub fn load_entity_log(conn: &Connection, entity_id: Id) -> Result<Vec<EntityChange>> {
+    let mut stmt = conn.prepare(
+        "SELECT CO.sha1, CO.commit_date, CH.kind, CH.adds, CH.dels
+        FROM changes CH
+        JOIN commits CO ON CO.id = CH.commit_id
+        WHERE CH.entity_id = :entity_id
+        ORDER BY CO.commit_date DESC, CO.id DESC",
+    )?;
+    let changes = stmt
+        .query_map(&[(":entity_id", &entity_id)], |row| {
+            Ok(EntityChange {
+                sha1: row.get(0)?,
+                commit_time: row.get(1)?,
+                kind: row.get(2)?,
+                adds: row.get(3)?,
+                dels: row.get(4)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(changes)
+}
+
+/// An entity which historically co-changed with one of the changed entities.
+#[derive(Debug, Clone)]
+pub struct Suggestion {
+    pub entity_id: Id,
+    /// The changed entity which this suggestion is most confident in
+    pub because_id: Id,
+    pub support: f64,
+    pub confidence: f64,
+}
+
+/// Suggests the entities which are likely to also need changing given that
+/// the `changed` entities changed, sorted by confidence and then by support.
+///
+/// The confidence of a suggestion is the fraction of the changes to one of the
+/// changed entities which also changed the suggested entity. Ancestors and
+/// descendants of the changed entities are never suggested.
+pub fn find_suggestions(
+    coupling: &Coupling,
+    parents: &HashMap<Id, Option<Id>>,
+    changed: &HashSet<Id>,
+    min_support: f64,
+    min_confidence: f64,
+) -> Vec<Suggestion> {
+    let related = |id: Id| {
+        changed.iter().any(|&c| {
+            ancestors_or_self(parents, id).contains(&c)
+                || ancestors_or_self(parents, c).contains(&id)
+        })
+    };
+
+    let mut suggestions: HashMap<Id, Suggestion> = HashMap::new();
+
+    for (&(a, b), &support) in &coupling.pairs {
+        for (src, tgt) in [(a, b), (b, a)] {
+            if support < min_support || !changed.contains(&src) || related(tgt) {
+                continue;
+            }
+
+            let confidence = coupling.confidence(src, tgt);
+
+            if confidence < min_confidence {
+                continue;
+            }
+
+            let suggestion = Suggestion { entity_id: tgt, because_id: src, support, confidence };
+
+            match suggestions.get(&tgt) {
+                Some(prev) if prev.confidence >= confidence => {}
+                _ => {
+                    suggestions.insert(tgt, suggestion);
+                }
+            }
+        }
+    }
+
+    let mut suggestions = suggestions.into_values().collect::<Vec<_>>();
+    suggestions.sort_by(|x, y| {
+        (y.confidence.total_cmp(&x.confidence))
+            .then(y.support.total_cmp(&x.support))
+            .then(x.entity_id.cmp(&y.entity_id))
+    });
+    suggestions
+}
+
+/// When an entity was first and last seen along with the commits which
+/// created and deleted it (if known).
+#[derive(Debug, Clone)]
+pub struct Lifespan {
+    pub entity_id: Id,
+    /// The commit of the first change which added the entity
+    pub birth_commit_id: Option<Id>,
+    /// The commit of the last change which deleted the entity (unless it was
+    /// later re-added)
+    pub death_commit_id: Option<Id>,
+    pub first_seen: i64,
+    pub last_seen: i64,
+}
+
+/// Finds the lifespan of every entity which was changed or seen present.
+pub fn find_lifespans(conn: &Connection) -> Result<Vec<Lifespan>> {
+    let mut stmt = conn.prepare(
+        "SELECT CH.entity_id, CH.commit_id, CO.commit_date, CH.kind
+        FROM changes CH
+        JOIN commits CO ON CO.id = CH.commit_id
+        UNION ALL
+        SELECT P.entity_id, P.commit_id, CO.commit_date, NULL
+        FROM presence P
+        JOIN commits CO ON CO.id = P.commit_id
+        ORDER BY 3, 2",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, Id>(0)?,
+            row.get::<_, Id>(1)?,
+            row.get::<_, i64>(2)?,
+            row.get::<_, Option<String>>(3)?,
+        ))
+    })?;
+
+    let mut lifespans: HashMap<Id, Lifespan> = HashMap::new();
+
+    for row in rows {
+        let (entity_id, commit_id, time, kind) = row?;
+        let lifespan = lifespans.entry(entity_id).or_insert_with(|| Lifespan {
+            entity_id,
+            birth_commit_id: None,
+            death_commit_id: None,
+            first_seen: time,
+            last_seen: time,
+        });
+
+        // Rows are sorted by time so only the end of the lifespan may move
+        lifespan.last_seen = time;
+
+        match kind.as_deref() {
+            Some("A") => {
+                lifespan.birth_commit_id.get_or_insert(commit_id);
+                lifespan.death_commit_id = None;
+            }
+            Some("D") => lifespan.death_commit_id = Some(commit_id),
+            Some(_) => {}
+            None => lifespan.death_commit_id = None,
+        }
+    }
+
+    let mut lifespans = lifespans.into_values().collect::<Vec<_>>();
+    lifespans.sort_by_key(|l| l.entity_id);
+    Ok(lifespans)
+}
+
+/// Size and churn metrics of an entity over the whole history.
+#[derive(Debug, Clone, Default)]
+pub struct EntityMetrics {
+    pub entity_id: Id,
+    /// The size (in lines) at the most recent commit the entity was present
+    pub loc: usize,
+    /// The complexity at the most recent commit the entity was present
+    pub nesting: usize,
+    pub branches: usize,
+    pub params: usize,
+    pub adds: usize,
+    pub dels: usize,
+    pub commits: usize,
+    pub authors: usize,
+    /// The mean number of files changed by the commits which changed the entity
+    pub scattering: f64,
+    /// The entropy of each commit which changed the entity weighted by the
+    /// share of the lines of the commit in the file of the entity, summed
+    pub history_complexity: f64,
+}
+
+pub fn find_entity_metrics(conn: &Connection) -> Result<Vec<EntityMetrics>> {
+    let mut metrics: HashMap<Id, EntityMetrics> = HashMap::new();
+
+    let mut stmt = conn.prepare(
+        "SELECT P.entity_id, P.end_row - P.start_row + 1, P.nesting, P.branches, P.params
+        FROM presence P
+        JOIN commits CO ON CO.id = P.commit_id
+        ORDER BY CO.commit_date, CO.id",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, Id>(0)?,
+            row.get::<_, usize>(1)?,
+            row.get::<_, usize>(2)?,
+            row.get::<_, usize>(3)?,
+            row.get::<_, usize>(4)?,
+        ))
+    })?;
+
+    for row in rows {
+        let (entity_id, loc, nesting, branches, params) = row?;
+        let m = metrics
+            .entry(entity_id)
+            .or_insert_with(|| EntityMetrics { entity_id, ..Default::default() });
+        m.loc = loc;
+        m.nesting = nesting;
+        m.branches = branches;
+        m.params = params;
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT CH.entity_id
+              , SUM(CH.adds)
+              , SUM(CH.dels)
+              , COUNT(DISTINCT CH.commit_id)
+              , COUNT(DISTINCT CO.author_mail)
+        FROM changes CH
+        JOIN commits CO ON CO.id = CH.commit_id
+        GROUP BY CH.entity_id",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, Id>(0)?,
+            row.get::<_, usize>(1)?,
+            row.get::<_, usize>(2)?,
+            row.get::<_, usize>(3)?,
+            row.get::<_, usize>(4)?,
+        ))
+    })?;
+
+    for row in rows {
+        let (entity_id, adds, dels, commits, authors) = row?;
+        let m = metrics
+            .entry(entity_id)
+            .or_insert_with(|| EntityMetrics { entity_id, ..Default::default() });
+        m.adds = adds;
+        m.dels = dels;
+        m.commits = commits;
+        m.authors = authors;
+    }
+
+    let commit_metrics = find_commit_metrics(conn)?;
+    let commit_metrics = commit_metrics.iter().map(|c| (c.commit_id, c)).collect::<HashMap<_, _>>();
+    let file_ids = to_file_ids(&load_parents(conn)?, &load_kinds(conn)?);
+    let mut stmt = conn.prepare("SELECT DISTINCT commit_id, entity_id FROM changes")?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, Id>(0)?, row.get::<_, Id>(1)?)))?;
+
+    for row in rows {
+        let (commit_id, entity_id) = row?;
+        let (c, m) = match (commit_metrics.get(&commit_id), metrics.get_mut(&entity_id)) {
+            (Some(c), Some(m)) if m.commits > 0 => (c, m),
+            _ => continue,
+        };
+        let lines = file_ids.get(&entity_id).and_then(|f| c.file_lines.get(f)).copied();
+
+        m.scattering += c.files as f64 / m.commits as f64;
+
+        if c.lines > 0 {
+            m.history_complexity += c.entropy * lines.unwrap_or_default() as f64 / c.lines as f64;
+        }
+    }
+
+    let mut metrics = metrics.into_values().collect::<Vec<_>>();
+    metrics.sort_by_key(|m| m.entity_id);
+    Ok(metrics)
+}
+
+/// How many files and entities a commit changed and how evenly the changed
+/// lines are spread across the files.
+#[derive(Debug, Clone, Default)]
+pub struct CommitMetrics {
+    pub commit_id: Id,
+    pub files: usize,
+    /// The number of changed entities (including files)
+    pub entities: usize,
+    /// The lines (adds + dels) changed in files
+    pub lines: usize,
+    /// The Shannon entropy of the changed lines across files, normalized by the
+    /// largest possible entropy (so 0 if one file changed and 1 if every file
+    /// changed equally)
+    pub entropy: f64,
+    /// The lines changed in each file
+    pub file_lines: HashMap<Id, usize>,
+}
+
+/// Derives the change entropy (as in Hassan's "Predicting Faults Using the
+/// Complexity of Code Changes") and size of each commit from its changes. The
+/// changes to each parent of a merge are counted once, by their largest churn.
+pub fn find_commit_metrics(conn: &Connection) -> Result<Vec<CommitMetrics>> {
+    let kinds = load_kinds(conn)?;
+    let mut metrics: BTreeMap<Id, CommitMetrics> = BTreeMap::new();
+
+    let mut stmt = conn.prepare(
+        "SELECT commit_id, entity_id, MAX(adds + dels)
+        FROM changes
+        GROUP BY commit_id, entity_id",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, Id>(0)?, row.get::<_, Id>(1)?, row.get::<_, usize>(2)?))
+    })?;
+
+    for row in rows {
+        let (commit_id, entity_id, lines) = row?;
+        let m = metrics
+            .entry(commit_id)
+            .or_insert_with(|| CommitMetrics { commit_id, ..Default::default() });
+        m.entities += 1;
+
+        if kinds.get(&entity_id).is_some_and(|k| k == "file") {
+            m.files += 1;
+            m.lines += lines;
+            m.file_lines.insert(entity_id, lines);
+        }
+    }
+
+    for m in metrics.values_mut() {
+        let changed = m.file_lines.values().filter(|&&n| n > 0).count();
+
+        if changed > 1 {
+            let entropy = m.file_lines.values().filter(|&&n| n > 0).map(|&n| {
+                let p = n as f64 / m.lines as f64;
+                -p * p.log2()
+            });
+            m.entropy = entropy.sum::<f64>() / (changed as f64).log2();
+        }
+    }
+
+    Ok(metrics.into_values().collect())
+}
+
+/// Whether a commit message suggests the commit fixes a bug. This is the usual
+/// keyword heuristic, so expect some false positives and negatives.
+pub fn is_fix_message(message: &str) -> bool {
+    const KEYWORDS: [&str; 5] = ["fix", "bug", "defect", "fault", "crash"];
+
+    message
+        .split(|c: char| !c.is_alphanumeric())
+        .map(|word| word.to_lowercase())
+        .any(|word| KEYWORDS.iter().any(|k| word.starts_with(k)))
+}
+
+pub fn load_commit_messages(conn: &Connection) -> Result<HashMap<Id, String>> {
+    let mut stmt = conn.prepare("SELECT id, message FROM commits")?;
+    let messages =
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_, _>>()?;
+    Ok(messages)
+}
+
+/// Loads whether each commit which refers to an imported issue (see
+/// import-issues) fixes a defect, i.e. whether any of its issues is a defect.
+/// Only issues the tracker classified (by a kind or labels) are considered, so
+/// pull requests and bare issues are left to the message heuristic.
+pub fn load_defect_commits(conn: &Connection) -> Result<HashMap<Id, bool>> {
+    if !has_table(conn, "commit_issues")? {
+        return Ok(HashMap::new());
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT CI.commit_id, MAX(I.is_defect)
+        FROM commit_issues CI
+        JOIN issues I ON I.id = CI.issue_id
+        WHERE I.kind IS NOT ? AND (I.kind IS NOT NULL OR I.labels <> '')
+        GROUP BY CI.commit_id",
+    )?;
+    let defects = stmt
+        .query_map([PULL_REQUEST_KIND], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    Ok(defects)
+}
+
+/// Counts the commits which changed each entity and fix bugs. A commit which
+/// refers to a classified issue (see `load_defect_commits`) fixes a bug if any
+/// of its issues is a defect. Otherwise, it is judged by its message (see
+/// `is_fix_message`).
+pub fn load_fix_counts(conn: &Connection) -> Result<HashMap<Id, usize>> {
+    let defects = load_defect_commits(conn)?;
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT CH.entity_id, CO.id, CO.message
+        FROM changes CH
+        JOIN commits CO ON CO.id = CH.commit_id",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, Id>(0)?, row.get::<_, Id>(1)?, row.get::<_, String>(2)?))
+    })?;
+
+    let mut counts: HashMap<Id, usize> = HashMap::new();
+
+    for row in rows {
+        let (entity_id, commit_id, message) = row?;
+
+        if defects.get(&commit_id).copied().unwrap_or_else(|| is_fix_message(&message)) {
+            *counts.entry(entity_id).or_default() += 1;
+        }
+    }
+
+    Ok(counts)
+}
+
+/// The Shannon entropy (in bits) of the distribution of lines among authors.
+/// It is zero when a single author wrote every line.
+pub fn entropy(lines: &HashMap<String, usize>) -> f64 {
+    to_shares(lines).iter().filter(|(_, p)| *p > 0.0).map(|(_, p)| -p * p.log2()).sum()
+}
+
+/// A row of the feature matrix used for defect prediction.
+#[derive(Debug, Clone, Default)]
+pub struct Features {
+    pub metrics: EntityMetrics,
+    pub author_entropy: f64,
+    pub coupling_degree: usize,
+    /// Days between the first time the entity was seen and the newest commit
+    pub age_days: f64,
+    pub fix_commits: usize,
+}
+
+/// Collects the features of every entity in `entity_metrics`. The coupling
+/// degree is the number of pairs (see `find_coupled_pairs`) the entity is in.
+pub fn find_features(
+    conn: &Connection,
+    change_sets: &[ChangeSet],
+    min_support: f64,
+    min_confidence: f64,
+) -> Result<Vec<Features>> {
+    let parents = load_parents(conn)?;
+    let coupling = Coupling::new(change_sets);
+    let mut degrees: HashMap<Id, usize> = HashMap::new();
+
+    for pair in find_coupled_pairs(&coupling, &parents, min_support, min_confidence) {
+        *degrees.entry(pair.entity1_id).or_default() += 1;
+        *degrees.entry(pair.entity2_id).or_default() += 1;
+    }
+
+    let mut stmt = conn.prepare("SELECT entity_id, first_seen FROM entity_lifespans")?;
+    let first_seen: HashMap<Id, i64> =
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_, _>>()?;
+    let newest = conn.query_row("SELECT MAX(commit_date) FROM commits", [], |row| {
+        row.get::<_, Option<i64>>(0)
+    })?;
+
+    let contributions = load_contributions(conn)?;
+    let fix_counts = load_fix_counts(conn)?;
+
+    let features = find_entity_metrics(conn)?
+        .into_iter()
+        .map(|metrics| {
+            let id = metrics.entity_id;
+            let age = match (newest, first_seen.get(&id)) {
+                (Some(newest), Some(&first)) => (newest - first).max(0) as f64 / 86400.0,
+                _ => 0.0,
+            };
+
+            Features {
+                author_entropy: contributions.lines.get(&id).map(entropy).unwrap_or_default(),
+                coupling_degree: degrees.get(&id).copied().unwrap_or_default(),
+                age_days: age,
+                fix_commits: fix_counts.get(&id).copied().unwrap_or_default(),
+                metrics,
+            }
+        })
+        .collect();
+
+    Ok(features)
+}
+
+/// Loads the hashes of the commits whose changes were extracted.
+pub fn load_extracted_commits(conn: &Connection) -> Result<HashSet<String>> {
+    let mut stmt = conn.prepare("SELECT sha1 FROM commits WHERE has_change_info")?;
+    let shas = stmt.query_map([], |row| row.get(0))?.collect::<Result<_, _>>()?;
+    Ok(shas)
+}
+
+/// Loads the entities which were marked as generated code when the database
+/// was dumped (if any were).
+pub fn load_generated_ids(conn: &Connection) -> Result<HashSet<Id>> {
+    if !has_table(conn, "generated_entities")? {
+        return Ok(HashSet::new());
+    }
+
+    let mut stmt = conn.prepare("SELECT entity_id FROM generated_entities")?;
+    let ids = stmt.query_map([], |row| row.get(0))?.collect::<Result<_, _>>()?;
+    Ok(ids)
+}
+
+/// Loads the files whose path matches any of the given glob patterns (e.g.
+/// `*Test.java`).
+pub fn load_matching_files(conn: &Connection, patterns: &[String]) -> Result<HashSet<Id>> {
+    let mut stmt = conn.prepare("SELECT id FROM entities WHERE kind = 'file' AND name GLOB ?")?;
+    let mut ids = HashSet::new();
+
+    for pattern in patterns {
+        for id in stmt.query_map([pattern], |row| row.get(0))? {
+            ids.insert(id?);
+        }
+    }
+
+    Ok(ids)
+}
+
+/// Loads the methods present at the given commit along with their name and
+/// first row.
+pub fn load_present_methods(conn: &Connection, commit_id: Id) -> Result<Vec<(Id, String, usize)>> {
+    let mut stmt = conn.prepare(
+        "SELECT E.id, E.name, P.start_row
+        FROM presence P
+        JOIN entities E ON E.id = P.entity_id
+        WHERE P.commit_id = ? AND E.kind = 'method'",
+    )?;
+    let methods = stmt
+        .query_map([commit_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<Result<_, _>>()?;
+    Ok(methods)
+}
+
+/// Loads the first and last line of each entity present at the given commit.
+/// These are only known if the presence of entities was extracted for it.
+pub fn load_locations(conn: &Connection, commit_id: Id) -> Result<HashMap<Id, (usize, usize)>> {
+    let mut stmt =
+        conn.prepare("SELECT entity_id, start_row, end_row FROM presence WHERE commit_id = ?")?;
+    let locations = stmt
+        .query_map([commit_id], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?
+        .collect::<Result<_, _>>()?;
+    Ok(locations)
+}
+
+/// Loads the most recent commit at which the presence of entities was
+/// extracted (if any).
+pub fn load_latest_presence_commit(conn: &Connection) -> Result<Option<Id>> {
+    let mut stmt = conn.prepare(
+        "SELECT id FROM commits WHERE has_presence_info ORDER BY commit_date DESC, id DESC LIMIT 1",
+    )?;
+    let commit_id = stmt.query_map([], |row| row.get(0))?.next().transpose()?;
+    Ok(commit_id)
+}
+
+/// Maps each entity to the file it belongs to (directories belong to none).
+pub fn to_file_ids(
+    parents: &HashMap<Id, Option<Id>>,
+    kinds: &HashMap<Id, String>,
+) -> HashMap<Id, Id> {
+    parents
+        .keys()
+        .filter_map(|&id| {
+            let file_id = ancestors_or_self(parents, id).into_iter().find(|a| kinds[a] == "file");
+            file_id.map(|file_id| (id, file_id))
+        })
+        .collect()
+}
+
+/// The name of the file without its directory or extension.
+fn to_stem(path: &str) -> &str {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    name.rsplit_once('.').map_or(name, |(stem, _)| stem)
+}
+
+/// The stem of the file which a test file is named after by convention, e.g.
+/// `Foo` for `FooTest.java`, `FooTests.java`, `FooIT.java`, or `TestFoo.java`.
+pub fn to_tested_stem(path: &str) -> Option<&str> {
+    let stem = to_stem(path);
+    ["Tests", "Test", "IT"]
+        .iter()
+        .find_map(|suffix| stem.strip_suffix(suffix))
+        .or_else(|| stem.strip_prefix("Test"))
+        .filter(|s| !s.is_empty())
+}
+
+/// A link from a test entity to a production entity which it (likely) tests.
+#[derive(Debug, Clone)]
+pub struct TestLink {
+    pub test_id: Id,
+    pub entity_id: Id,
+    /// Whether the file of the test is named after the production file
+    pub by_name: bool,
+    pub support: f64,
+    /// The fraction of the changes to the production entity which also changed
+    /// the test
+    pub confidence: f64,
+}
+
+/// Links test entities (the entities of the given test files) to production
+/// entities, sorted by confidence and then by support.
+///
+/// Each test file is linked to the production files it is named after. Each
+/// test entity is also linked to the production entities which it co-changes
+/// with often enough.
+pub fn find_test_links(
+    coupling: &Coupling,
+    files: &HashMap<Id, String>,
+    file_ids: &HashMap<Id, Id>,
+    test_files: &HashSet<Id>,
+    min_support: f64,
+    min_confidence: f64,
+) -> Vec<TestLink> {
+    let is_test = |id: &Id| file_ids.get(id).is_some_and(|f| test_files.contains(f));
+    let mut links: HashMap<(Id, Id), TestLink> = HashMap::new();
+    let mut link = |test_id: Id, entity_id: Id, by_name: bool| {
+        let support = coupling.support(test_id, entity_id);
+        let confidence = coupling.confidence(entity_id, test_id);
+        let link = links.entry((test_id, entity_id)).or_insert(TestLink {
+            test_id,
+            entity_id,
+            by_name,
+            support,
+            confidence,
+        });
+        link.by_name |= by_name;
+    };
+
+    let mut by_stem: HashMap<&str, Vec<Id>> = HashMap::new();
+
+    for (&id, path) in files.iter().filter(|(id, _)| !test_files.contains(id)) {
+        by_stem.entry(to_stem(path)).or_default().push(id);
+    }
+
+    for &test_id in test_files {
+        let stem = files.get(&test_id).and_then(|path| to_tested_stem(path));
+
+        for &entity_id in stem.and_then(|stem| by_stem.get(stem)).into_iter().flatten() {
+            link(test_id, entity_id, true);
+        }
+    }
+
+    for (&(a, b), &support) in &coupling.pairs {
+        let (test_id, entity_id) = match (is_test(&a), is_test(&b)) {
+            (true, false) => (a, b),
+            (false, true) => (b, a),
+            _ => continue,
+        };
+
+        if support >= min_support && coupling.confidence(entity_id, test_id) >= min_confidence {
+            link(test_id, entity_id, false);
+        }
+    }
+
+    let mut links = links.into_values().collect::<Vec<_>>();
+    links.sort_by(|x, y| {
+        y.confidence
+            .total_cmp(&x.confidence)
+            .then(y.support.total_cmp(&x.support))
+            .then((x.test_id, x.entity_id).cmp(&(y.test_id, y.entity_id)))
+    });
+    links
+}
+
+/// Loads each link of the `test_links` table as the test, the production
+/// entity, the support, and the confidence.
+pub fn load_test_links(conn: &Connection) -> Result<Vec<(Id, Id, f64, f64)>> {
+    let mut stmt =
+        conn.prepare("SELECT test_id, entity_id, support, confidence FROM test_links")?;
+    let links = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?
+        .collect::<Result<_, _>>()?;
+    Ok(links)
+}
+
+/// An entity which may be affected by a change to one of the changed entities.
+#[derive(Debug, Clone)]
+pub struct Impact {
+    pub entity_id: Id,
+    /// The changed entity with the strongest link to the affected entity
+    pub because_id: Id,
+    /// Whether the entity is a test linked to the changed entity (rather than
+    /// a dependent of it)
+    pub is_test: bool,
+    pub support: f64,
+    pub confidence: f64,
+}
+
+/// Finds the tests linked to the changed entities (or to their ancestors or
+/// descendants) and the entities which depend on the changed entities, sorted
+/// by confidence and then by support.
+///
+/// The confidence of a test is that of its link. The confidence of a dependent
+/// is the fraction of the changes to the changed entity which also changed the
+/// dependent, so dependents which never co-changed are kept but ranked last.
+pub fn find_impact(
+    coupling: &Coupling,
+    parents: &HashMap<Id, Option<Id>>,
+    dependents: Option<&DepGraph>,
+    test_links: &[(Id, Id, f64, f64)],
+    changed: &HashSet<Id>,
+    transitive: bool,
+) -> Vec<Impact> {
+    let related = |id: Id, c: Id| {
+        ancestors_or_self(parents, id).contains(&c) || ancestors_or_self(parents, c).contains(&id)
+    };
+
+    let mut impacts: HashMap<(Id, bool), Impact> = HashMap::new();
+    let mut add = |impact: Impact| match impacts.get(&(impact.entity_id, impact.is_test)) {
+        Some(prev) if prev.confidence >= impact.confidence => {}
+        _ => {
+            impacts.insert((impact.entity_id, impact.is_test), impact);
+        }
+    };
+
+    for &(test_id, entity_id, support, confidence) in test_links {
+        for &c in changed.iter().filter(|&&c| related(entity_id, c)) {
+            add(Impact { entity_id: test_id, because_id: c, is_test: true, support, confidence });
+        }
+    }
+
+    for &c in changed {
+        for entity_id in dependents.map(|d| d.reachable(c, transitive)).unwrap_or_default() {
+            if changed.iter().any(|&c| related(entity_id, c)) {
+                continue;
+            }
+
+            let support = coupling.support(c, entity_id);
+            let confidence = coupling.confidence(c, entity_id);
+            add(Impact { entity_id, because_id: c, is_test: false, support, confidence });
+        }
+    }
+
+    let mut impacts = impacts.into_values().collect::<Vec<_>>();
+    impacts.sort_by(|x, y| {
+        (y.confidence.total_cmp(&x.confidence))
+            .then(y.support.total_cmp(&x.support))
+            .then(x.entity_id.cmp(&y.entity_id))
+            .then(y.is_test.cmp(&x.is_test))
+    });
+    impacts
+}
+
+/// Loads the hash and number of lines of the source of each entity present at
+/// the given commit (see dump --store-sources).
+pub fn load_source_hashes(conn: &Connection, commit_id: Id) -> Result<Vec<(Id, String, usize)>> {
+    if !has_table(conn, "entity_sources")? {
+        bail!("the database has no entity sources (dump it with --store-sources)");
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT ES.entity_id, ES.sha1, P.end_row - P.start_row + 1
+        FROM entity_sources ES
+        JOIN presence P ON P.commit_id = ES.commit_id AND P.entity_id = ES.entity_id
+        WHERE ES.commit_id = :commit_id",
+    )?;
+    let hashes = stmt
+        .query_map(&[(":commit_id", &commit_id)], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(hashes)
+}
+
+/// A pair of entities in different files with the same source, along with how
+/// often they changed together and apart.
+#[derive(Debug, Clone)]
+pub struct ClonePair {
+    pub entity1_id: Id,
+    pub entity2_id: Id,
+    /// The number of lines of either entity
+    pub lines: usize,
+    /// The (weighted) number of commits which changed both entities
+    pub support: f64,
+    /// The (weighted) number of commits which changed only one of them
+    pub divergent: f64,
+}
+
+/// Finds the pairs of entities in different files whose sources have the same
+/// hash and span at least `min_lines` lines, sorted by how often they changed
+/// apart (i.e. how often one was changed without its twin).
+///
+/// A pair is skipped if the parents of its entities are a pair as well (e.g.
+/// the methods of two identical classes), so that only the outermost clones are
+/// reported.
+pub fn find_clones(
+    coupling: &Coupling,
+    parents: &HashMap<Id, Option<Id>>,
+    file_ids: &HashMap<Id, Id>,
+    hashes: &[(Id, String, usize)],
+    min_lines: usize,
+) -> Vec<ClonePair> {
+    let mut groups: BTreeMap<&str, Vec<(Id, usize)>> = BTreeMap::new();
+
+    for (id, sha1, lines) in hashes.iter().filter(|(_, _, lines)| *lines >= min_lines) {
+        groups.entry(sha1).or_default().push((*id, *lines));
+    }
+
+    // The lines of each pair, keyed by the smaller id first
+    let mut pairs = HashMap::new();
+
+    for group in groups.values_mut() {
+        group.sort();
+
+        for (i, &(a, lines)) in group.iter().enumerate() {
+            for &(b, _) in &group[i + 1..] {
+                if file_ids.get(&a) != file_ids.get(&b) {
+                    pairs.insert((a, b), lines);
+                }
+            }
+        }
+    }
+
+    let is_pair = |a: Option<Id>, b: Option<Id>| match (a, b) {
+        (Some(a), Some(b)) => pairs.contains_key(&(a.min(b), a.max(b))),
+        _ => false,
+    };
+    let parent = |id: Id| parents.get(&id).copied().flatten();
+    let changes = |id: Id| coupling.changes.get(&id).copied().unwrap_or_default();
+
+    let mut clones = pairs
+        .iter()
+        .filter(|&(&(a, b), _)| !is_pair(parent(a), parent(b)))
+        .map(|(&(a, b), &lines)| {
+            let support = coupling.support(a, b);
+            let divergent = changes(a) + changes(b) - 2.0 * support;
+            ClonePair { entity1_id: a, entity2_id: b, lines, support, divergent }
+        })
+        .collect::<Vec<_>>();
+
+    clones.sort_by(|x, y| {
+        (y.divergent.total_cmp(&x.divergent))
+            .then(y.lines.cmp(&x.lines))
+            .then((x.entity1_id, x.entity2_id).cmp(&(y.entity1_id, y.entity2_id)))
+    });
+    clones
+}
+
+/// An entity whose co-change partners fall into groups which do not co-change
+/// with each other, i.e. an entity which changes for unrelated reasons.
+#[derive(Debug, Clone)]
+pub struct DivergentChange {
+    pub entity_id: Id,
+    /// The partners of the entity grouped by whether they co-change with each
+    /// other (directly or through other partners), the largest group first
+    pub clusters: Vec<Vec<Id>>,
+}
+
+impl DivergentChange {
+    pub fn partners(&self) -> usize {
+        self.clusters.iter().map(Vec::len).sum()
+    }
+}
+
+/// Finds the entities whose partners (the entities they are coupled with
+/// according to the given pairs) form at least `min_clusters` groups, sorted by
+/// the number of groups and then by the number of partners.
+///
+/// This is the opposite of shotgun surgery: rather than one change touching
+/// many entities, one entity is touched by many unrelated changes.
+pub fn find_divergent_changes(pairs: &[Pair], min_clusters: usize) -> Vec<DivergentChange> {
+    let mut partners: BTreeMap<Id, BTreeSet<Id>> = BTreeMap::new();
+
+    for pair in pairs {
+        partners.entry(pair.entity1_id).or_default().insert(pair.entity2_id);
+        partners.entry(pair.entity2_id).or_default().insert(pair.entity1_id);
+    }
+
+    let mut divergent = Vec::new();
+
+    for (&entity_id, own) in &partners {
+        let mut clusters = Vec::new();
+        let mut seen = HashSet::new();
+
+        for &start in own {
+            if !seen.insert(start) {
+                continue;
+            }
+
+            // Only partners of the entity are followed
+            let mut cluster = Vec::new();
+            let mut queue = VecDeque::from([start]);
+
+            while let Some(id) = queue.pop_front() {
+                cluster.push(id);
+
+                for &next in &partners[&id] {
+                    if own.contains(&next) && seen.insert(next) {
+                        queue.push_back(next);
+                    }
+                }
+            }
+
+            cluster.sort();
+            clusters.push(cluster);
+        }
+
+        if clusters.len() >= min_clusters {
+            clusters.sort_by(|x, y| y.len().cmp(&x.len()).then(x.cmp(y)));
+            divergent.push(DivergentChange { entity_id, clusters });
+        }
+    }
+
+    divergent.sort_by(|x, y| {
+        (y.clusters.len().cmp(&x.clusters.len()))
+            .then(y.partners().cmp(&x.partners()))
+            .then(x.entity_id.cmp(&y.entity_id))
+    });
+    divergent
+}
+
+/// Groups the entities of the given pairs into clusters by propagating labels
+/// over the co-change graph, where each pair is an edge weighted by its
+/// support. Returns the cluster of each entity, numbered from 0 by decreasing
+/// size.
+///
+/// Each entity starts in a cluster of its own and then repeatedly joins the
+/// cluster with the most weight among its neighbors (keeping its own on ties)
+/// until no entity moves or `max_iterations` is reached. Entities are visited in
+/// order of id so that the result is deterministic.
+pub fn find_clusters(pairs: &[Pair], max_iterations: usize) -> HashMap<Id, usize> {
+    let mut neighbors: BTreeMap<Id, Vec<(Id, f64)>> = BTreeMap::new();
+
+    for pair in pairs {
+        neighbors.entry(pair.entity1_id).or_default().push((pair.entity2_id, pair.support));
+        neighbors.entry(pair.entity2_id).or_default().push((pair.entity1_id, pair.support));
+    }
+
+    let mut labels = neighbors.keys().map(|&id| (id, id)).collect::<HashMap<_, _>>();
+
+    for _ in 0..max_iterations {
+        let mut moved = false;
+
+        for (&id, edges) in &neighbors {
+            let mut weights: BTreeMap<Id, f64> = BTreeMap::new();
+
+            for &(other, weight) in edges {
+                *weights.entry(labels[&other]).or_default() += weight;
+            }
+
+            let own = weights.get(&labels[&id]).copied().unwrap_or_default();
+            let best =
+                weights.iter().fold(
+                    None,
+                    |best: Option<(Id, f64)>, (&label, &weight)| match best {
+                        Some((_, w)) if w >= weight => best,
+                        _ => Some((label, weight)),
+                    },
+                );
+
+            if let Some((label, weight)) = best {
+                if weight > own {
+                    labels.insert(id, label);
+                    moved = true;
+                }
+            }
+        }
+
+        if !moved {
+            break;
+        }
+    }
+
+    let mut members: BTreeMap<Id, Vec<Id>> = BTreeMap::new();
+
+    for (&id, &label) in &labels {
+        members.entry(label).or_default().push(id);
+    }
+
+    let mut clusters = members.into_values().collect::<Vec<_>>();
+    clusters.iter_mut().for_each(|c| c.sort());
+    clusters.sort_by(|x, y| y.len().cmp(&x.len()).then(x.cmp(y)));
+
+    let clusters = clusters.into_iter().enumerate();
+    clusters.flat_map(|(i, ids)| ids.into_iter().map(move |id| (id, i))).collect()
+}
+
+/// How well a cluster matches the package structure.
+#[derive(Debug, Clone)]
+pub struct ClusterAlignment {
+    pub cluster: usize,
+    pub size: usize,
+    /// The package most members of the cluster belong to (if any has one)
+    pub package: Option<String>,
+    /// The fraction of the members (with a package) which belong to it
+    pub purity: f64,
+}
+
+/// Compares the given clusters with the package of each entity (if it has
+/// one), ordered by cluster. Also returns the purity of the clusters overall
+/// (the fraction of entities in the dominant package of their cluster) and
+/// that of the packages (the fraction of entities in the dominant cluster of
+/// their package), which are both 1.0 if the clusters match the packages.
+pub fn align_clusters(
+    clusters: &HashMap<Id, usize>,
+    packages: &HashMap<Id, String>,
+) -> (Vec<ClusterAlignment>, f64, f64) {
+    let mut counts: BTreeMap<usize, BTreeMap<&str, usize>> = BTreeMap::new();
+    let mut by_package: BTreeMap<&str, BTreeMap<usize, usize>> = BTreeMap::new();
+
+    for (id, &cluster) in clusters {
+        let count = counts.entry(cluster).or_default();
+
+        if let Some(package) = packages.get(id) {
+            *count.entry(package).or_default() += 1;
+            *by_package.entry(package).or_default().entry(cluster).or_default() += 1;
+        }
+    }
+
+    let mut sizes: HashMap<usize, usize> = HashMap::new();
+    clusters.values().for_each(|&cluster| *sizes.entry(cluster).or_default() += 1);
+
+    let mut alignments = Vec::new();
+    let (mut total, mut dominant) = (0, 0);
+
+    for (cluster, count) in &counts {
+        let members = count.values().sum::<usize>();
+        // The first package wins ties
+        let top = count.iter().fold(None, |top: Option<(&str, usize)>, (&p, &n)| match top {
+            Some((_, m)) if m >= n => top,
+            _ => Some((p, n)),
+        });
+        let n = top.map_or(0, |(_, n)| n);
+        total += members;
+        dominant += n;
+        alignments.push(ClusterAlignment {
+            cluster: *cluster,
+            size: sizes[cluster],
+            package: top.map(|(p, _)| p.to_string()),
+            purity: if members > 0 { n as f64 / members as f64 } else { 0.0 },
+        });
+    }
+
+    let packaged = by_package.values().map(|c| c.values().max().unwrap_or(&0)).sum::<usize>();
+    let ratio = |n: usize| if total > 0 { n as f64 / total as f64 } else { 0.0 };
+    (alignments, ratio(dominant), ratio(packaged))
+}
+
+/// The share of each author (name and email) of each entity.
+pub type Ownership = HashMap<Id, Vec<(String, String, f64)>>;
+
+/// Loads the share of each author (name and email) of each entity from the
+/// `ownership` table.
+pub fn load_ownership(conn: &Connection) -> Result<Ownership> {
+    if !has_table(conn, "ownership")? {
+        bail!("the database has no ownership (run `analyze ownership` first)");
+    }
+
+    let mut stmt =
+        conn.prepare("SELECT entity_id, author_name, author_mail, share FROM ownership")?;
+    let mut rows = stmt.query([])?;
+    let mut ownership: HashMap<Id, Vec<_>> = HashMap::new();
+
+    while let Some(row) = rows.next()? {
+        ownership.entry(row.get(0)?).or_default().push((row.get(1)?, row.get(2)?, row.get(3)?));
+    }
+
+    Ok(ownership)
+}
+
+/// An entity which would be left with too little knowledge if some authors
+/// left.
+#[derive(Debug, Clone)]
+pub struct KnowledgeLoss {
+    pub entity_id: Id,
+    /// The share of the lines of the entity which the departing authors changed
+    pub lost: f64,
+    /// The share of the remaining authors (i.e. 1 - lost)
+    pub surviving: f64,
+}
+
+/// Simulates the departure of the authors with the given emails, finding the
+/// entities they contributed to whose surviving share falls below
+/// `min_surviving`, sorted by surviving share (lowest first).
+pub fn find_knowledge_loss(
+    ownership: &HashMap<Id, Vec<(String, String, f64)>>,
+    departed: &HashSet<String>,
+    min_surviving: f64,
+) -> Vec<KnowledgeLoss> {
+    let mut losses = Vec::new();
+
+    for (&entity_id, shares) in ownership {
+        let lost = shares.iter().filter(|(_, mail, _)| departed.contains(mail));
+        let lost = lost.map(|(_, _, share)| share).sum::<f64>().min(1.0);
+        let surviving = 1.0 - lost;
+
+        if lost > 0.0 && surviving < min_surviving {
+            losses.push(KnowledgeLoss { entity_id, lost, surviving });
+        }
+    }
+
+    losses.sort_by(|x, y| x.surviving.total_cmp(&y.surviving).then(x.entity_id.cmp(&y.entity_id)));
+    losses
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use ratatui::crossterm::event;
+use ratatui::crossterm::event::Event;
+use ratatui::crossterm::event::KeyCode;
+use ratatui::crossterm::event::KeyEventKind;
+use ratatui::crossterm::event::KeyModifiers;
+use ratatui::layout::Constraint;
+use ratatui::layout::Layout;
+use ratatui::style::Modifier;
+use ratatui::style::Style;
+use ratatui::widgets::Block;
+use ratatui::widgets::Borders;
+use ratatui::widgets::List;
+use ratatui::widgets::ListState;
+use ratatui::widgets::Paragraph;
+use ratatui::widgets::Row;
+use ratatui::widgets::Table;
+use ratatui::DefaultTerminal;
+use ratatui::Frame;
+use rusqlite::Connection;
+use time::OffsetDateTime;
+
+use crate::analysis::load_entity_log;
+use crate::analysis::Coupling;
+use crate::analysis::EntityChange;
+use crate::db::Id;
+
+/// The history and co-change partners of the selected entity.
+struct Details {
+    entity_id: Id,
+    history: Vec<EntityChange>,
+    /// Partners along with the support and confidence of the pair
+    partners: Vec<(Id, f64, f64)>,
+}
+
+struct Browser<'a> {
+    conn: &'a Connection,
+    coupling: &'a Coupling,
+    paths: &'a HashMap<Id, String>,
+    limit: usize,
+    /// Every entity, ordered by path
+    entities: Vec<Id>,
+    /// The entities each entity changed with at least once
+    neighbors: HashMap<Id, Vec<Id>>,
+    query: String,
+    /// The entities whose path contains the query
+    visible: Vec<Id>,
+    state: ListState,
+    details: Option<Details>,
+}
+
+impl<'a> Browser<'a> {
+    fn new(
+        conn: &'a Connection,
+        coupling: &'a Coupling,
+        paths: &'a HashMap<Id, String>,
+        limit: usize,
+    ) -> Self {
+        let mut entities = paths.keys().copied().collect::<Vec<_>>();
+        entities.sort_by(|a, b| paths[a].cmp(&paths[b]));
+
+        let mut neighbors: HashMap<Id, Vec<Id>> = HashMap::new();
+
+        for &(a, b) in coupling.pairs.keys() {
+            neighbors.entry(a).or_default().push(b);
+            neighbors.entry(b).or_default().push(a);
+        }
+
+        let mut browser = Self {
+            conn,
+            coupling,
+            paths,
+            limit,
+            entities,
+            neighbors,
+            query: String::new(),
+            visible: Vec::new(),
+            state: ListState::default(),
+            details: None,
+        };
+
+        browser.filter();
+        browser
+    }
+
+    fn selected(&self) -> Option<Id> {
+        self.state.selected().and_then(|i| self.visible.get(i).copied())
+    }
+
+    /// Shows only the entities whose path contains the query (ignoring case).
+    fn filter(&mut self) {
+        let query = self.query.to_lowercase();
+        self.visible = self
+            .entities
+            .iter()
+            .copied()
+            .filter(|id| self.paths[id].to_lowercase().contains(&query))
+            .collect();
+        self.state.select(if self.visible.is_empty() { None } else { Some(0) });
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        if let Some(i) = self.state.selected() {
+            let last = self.visible.len().saturating_sub(1) as isize;
+            self.state.select(Some((i as isize + delta).clamp(0, last) as usize));
+        }
+    }
+
+    /// Loads the details of the selected entity unless they are already loaded.
+    fn load_details(&mut self) -> Result<()> {
+        let entity_id = match self.selected() {
+            Some(entity_id) => entity_id,
+            None => {
+                self.details = None;
+                return Ok(());
+            }
+        };
+
+        if self.details.as_ref().map(|d| d.entity_id) == Some(entity_id) {
+            return Ok(());
+        }
+
+        let mut partners = self
+            .neighbors
+            .get(&entity_id)
+            .into_iter()
+            .flatten()
+            .map(|&other| {
+                let support = self.coupling.support(entity_id, other);
+                (other, support, self.coupling.confidence(entity_id, other))
+            })
+            .collect::<Vec<_>>();
+        partners.sort_by(|x, y| y.2.total_cmp(&x.2).then(y.1.total_cmp(&x.1)).then(x.0.cmp(&y.0)));
+        partners.truncate(self.limit);
+
+        let history = load_entity_log(self.conn, entity_id)?;
+        self.details = Some(Details { entity_id, history, partners });
+        Ok(())
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        loop {
+            self.load_details()?;
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let key = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                _ => continue,
+            };
+
+            match key.code {
+                KeyCode::Esc => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(())
+                }
+                KeyCode::Up => self.move_selection(-1),
+                KeyCode::Down => self.move_selection(1),
+                KeyCode::PageUp => self.move_selection(-10),
+                KeyCode::PageDown => self.move_selection(10),
+                KeyCode::Home => self.move_selection(isize::MIN / 2),
+                KeyCode::End => self.move_selection(isize::MAX / 2),
+                KeyCode::Enter => self.jump_to_partner(),
+                KeyCode::Backspace => {
+                    self.query.pop();
+                    self.filter();
+                }
+                KeyCode::Char(c) => {
+                    self.query.push(c);
+                    self.filter();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Selects the strongest co-change partner of the selected entity.
+    fn jump_to_partner(&mut self) {
+        let partner = match self.details.as_ref().and_then(|d| d.partners.first()) {
+            Some(&(partner, _, _)) => partner,
+            None => return,
+        };
+
+        self.query.clear();
+        self.filter();
+        self.state.select(self.visible.iter().position(|&id| id == partner));
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [search_area, body_area] =
+            Layout::vertical([Constraint::Length(3), Constraint::Min(0)]).areas(frame.area());
+        let [list_area, details_area] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+                .areas(body_area);
+        let [history_area, partners_area] =
+            Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .areas(details_area);
+
+        let title = "Search (type to filter, ↑/↓ to move, Enter for top partner, Esc to quit)";
+        let search = Paragraph::new(self.query.as_str())
+            .block(Block::default().borders(Borders::ALL).title(title));
+        frame.render_widget(search, search_area);
+
+        let title = format!("Entities ({})", self.visible.len());
+        let list = List::new(self.visible.iter().map(|id| self.paths[id].as_str()))
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, list_area, &mut self.state);
+
+        let (history, partners) = match &self.details {
+            Some(details) => (&details.history[..], &details.partners[..]),
+            None => (&[][..], &[][..]),
+        };
+
+        let rows = history.iter().map(|c| {
+            Row::new(vec![
+                to_date_string(c.commit_time),
+                c.sha1.chars().take(10).collect(),
+                c.kind.clone(),
+                format!("+{}", c.adds),
+                format!("-{}", c.dels),
+            ])
+        });
+        let widths = [
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(1),
+            Constraint::Length(7),
+            Constraint::Length(7),
+        ];
+        let title = format!("History ({} changes)", history.len());
+        let table = Table::new(rows, widths)
+            .header(Row::new(vec!["Date", "Commit", "", "Adds", "Dels"]))
+            .block(Block::default().borders(Borders::ALL).title(title));
+        frame.render_widget(table, history_area);
+
+        let rows = partners.iter().map(|&(id, support, confidence)| {
+            Row::new(vec![
+                format!("{:.0}%", confidence * 100.0),
+                format!("{:.1}", support),
+                self.paths[&id].clone(),
+            ])
+        });
+        let widths = [Constraint::Length(5), Constraint::Length(7), Constraint::Min(0)];
+        let table = Table::new(rows, widths)
+            .header(Row::new(vec!["Conf", "Support", "Partner"]))
+            .block(Block::default().borders(Borders::ALL).title("Strongest Partners"));
+        frame.render_widget(table, partners_area);
+    }
+}
+
+fn to_date_string(time: i64) -> String {
+    match OffsetDateTime::from_unix_timestamp(time) {
+        Ok(datetime) => datetime.date().to_string(),
+        Err(_) => time.to_string(),
+    }
+}
+
+/// Runs the interactive explorer until the user quits. The terminal is restored
+/// even if an error occurs.
+pub fn browse(
+    conn: &Connection,
+    coupling: &Coupling,
+    paths: &HashMap<Id, String>,
+    limit: usize,
+) -> Result<()> {
+    let mut browser = Browser::new(conn, coupling, paths, limit);
+    let mut terminal = ratatui::init();
+    let result = browser.run(&mut terminal);
+    ratatui::restore();
+    result
+}
@@ -0,0 +1,197 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::ir::Complexity;
+use crate::ir::Entity;
+use crate::ir::Interval;
+use crate::ir::LocEntity;
+use crate::parsing::normalize_path;
+use crate::parsing::to_file_entity;
+use crate::parsing::EntityParser;
+
+/// The fields ctags must write for each tag: its line, end line, and signature.
+const CTAGS_FIELDS: &str = "--fields=+neS";
+
+/// Numbers the directories of the parsers of this process, since each thread
+/// which parses files has its own parser.
+static PARSERS: AtomicUsize = AtomicUsize::new(0);
+
+/// A single line of the JSON output of universal-ctags.
+#[derive(Debug, Deserialize)]
+struct Tag {
+    /// Either "tag" or "ptag" (for pseudo-tags)
+    #[serde(rename = "_type")]
+    r#type: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    path: String,
+    kind: Option<String>,
+    line: Option<usize>,
+    end: Option<usize>,
+    signature: Option<String>,
+}
+
+enum TagSource {
+    /// A universal-ctags executable which is run on each file
+    Program(PathBuf),
+    /// The tags of each file, read from a tags file up front
+    TagsFile(HashMap<String, Vec<Tag>>),
+}
+
+/// Derives entities from the tags universal-ctags finds in a file. This works
+/// for any language ctags supports, but its entities have no complexity and
+/// are nested only by their line ranges.
+pub struct CtagsParser {
+    source: TagSource,
+    /// Where files are written for ctags to read
+    dir: PathBuf,
+}
+
+impl CtagsParser {
+    /// Runs the given executable, which must be universal-ctags built with JSON
+    /// support, on each file.
+    pub fn new(program: PathBuf) -> Self {
+        let n = PARSERS.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("cochange-ctags-{}-{}", std::process::id(), n));
+        Self { source: TagSource::Program(program), dir }
+    }
+
+    /// Reads the tags of every file from a tags file generated by running
+    /// `ctags -R --output-format=json --fields=+neS` at the root of the
+    /// repository. Its line numbers only match the revision it was generated
+    /// from, so it is best used with a single commit.
+    pub fn from_tags_file(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        let mut tags: HashMap<String, Vec<Tag>> = HashMap::new();
+
+        for line in BufReader::new(file).lines() {
+            if let Some(tag) = parse_tag(&line?)? {
+                let path = normalize_path(tag.path.trim_start_matches("./"));
+                tags.entry(path).or_default().push(tag);
+            }
+        }
+
+        log::info!("Read the tags of {} files from {}", tags.len(), path.display());
+        Ok(Self { source: TagSource::TagsFile(tags), dir: PathBuf::new() })
+    }
+
+    fn run(&self, program: &Path, source: &[u8], filename: &str) -> Result<Vec<Tag>> {
+        // ctags guesses the language from the name of the file, so keep it
+        let name = Path::new(filename).file_name().context("file has no name")?;
+        let path = self.dir.join(name);
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(&path, source)?;
+
+        let output = Command::new(program)
+            .args(["--output-format=json", CTAGS_FIELDS, "-f", "-"])
+            .arg(&path)
+            .output();
+        std::fs::remove_file(&path)?;
+
+        let output = output.with_context(|| {
+            format!("failed to run {} (is universal-ctags installed?)", program.display())
+        })?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("{} failed ({}): {}", program.display(), output.status, stderr.trim());
+        }
+
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| parse_tag(line).transpose())
+            .collect()
+    }
+}
+
+impl EntityParser for CtagsParser {
+    fn parse(&mut self, source: &[u8], filename: &str) -> Result<Vec<LocEntity>> {
+        let file = to_file_entity(source, filename);
+        let ran;
+
+        let tags = match &self.source {
+            TagSource::Program(program) => {
+                ran = self.run(program, source, filename)?;
+                &ran[..]
+            }
+            TagSource::TagsFile(tags) => {
+                tags.get(&normalize_path(filename)).map_or(&[][..], |t| &t[..])
+            }
+        };
+
+        Ok(to_loc_entities(file, tags))
+    }
+}
+
+impl Drop for CtagsParser {
+    fn drop(&mut self) {
+        if self.dir.exists() {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+/// Parses a line of JSON output, skipping pseudo-tags and tags without a line.
+fn parse_tag(line: &str) -> Result<Option<Tag>> {
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let tag: Tag = serde_json::from_str(line)
+        .context("failed to parse tag (was ctags run with --output-format=json?)")?;
+    Ok(Some(tag).filter(|t| t.r#type == "tag" && t.line.is_some()))
+}
+
+fn to_interval(tag: &Tag) -> Interval {
+    let line = tag.line.unwrap_or(1);
+    Interval(line, tag.end.unwrap_or(line).max(line))
+}
+
+/// Nests each tag under the innermost tag whose lines contain its own (or under
+/// the file if there is none).
+fn to_loc_entities(file: LocEntity, tags: &[Tag]) -> Vec<LocEntity> {
+    let mut tags = tags.iter().map(|t| (to_interval(t), t)).collect::<Vec<_>>();
+    tags.sort_by_key(|(loc, _)| (loc.0, Reverse(loc.1)));
+
+    let mut kinds: HashMap<&str, Arc<String>> = HashMap::new();
+    let mut enclosing: Vec<LocEntity> = Vec::new();
+    let mut loc_entities = Vec::new();
+
+    for (loc, tag) in tags {
+        while let Some(outer) = enclosing.last() {
+            if outer.loc.0 <= loc.0 && loc.1 <= outer.loc.1 {
+                break;
+            }
+
+            enclosing.pop();
+        }
+
+        let parent = enclosing.last().unwrap_or(&file).entity.clone();
+        let kind = tag.kind.as_deref().unwrap_or("tag");
+        let kind = kinds.entry(kind).or_insert_with(|| Arc::new(kind.to_string())).clone();
+        let disc = tag.signature.clone().unwrap_or_default();
+        let entity = Entity::new(parent, tag.name.clone(), kind, disc);
+        let loc_entity = LocEntity::new(Arc::new(entity), loc, Complexity::default());
+        enclosing.push(loc_entity.clone());
+        loc_entities.push(loc_entity);
+    }
+
+    loc_entities.push(file);
+    loc_entities
+}
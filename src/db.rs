@@ -1,30 +1,56 @@
 use std::borrow::Borrow;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::hash::Hash;
+use std::io::Write;
 use std::sync::Arc;
 
+use anyhow::bail;
 use anyhow::Result;
 use derive_new::new;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
 use rusqlite::params;
 use rusqlite::CachedStatement;
+use rusqlite::Connection;
 use rusqlite::Transaction;
 
+use crate::analysis::find_commit_metrics;
+use crate::analysis::find_entity_metrics;
+use crate::analysis::find_lifespans;
 use crate::ir::*;
 
+pub mod reader;
+
 pub type Id = usize;
 
 pub trait SqlWriter<'a, K: Hash + Eq, E> {
     fn create_table_script() -> &'static str;
-    fn prepare(tx: &'a Transaction) -> Result<Self>
+    fn prepare(conn: &'a Connection) -> Result<Self>
     where
         Self: Sized;
     fn execute(&mut self, id: Id, key: &K, extra: &E) -> Result<usize>;
 }
 
+/// What a [`VirtualTable`] does when a key is inserted again.
+#[derive(Debug, Default)]
+pub enum Conflict<E> {
+    /// Keep the extra which was inserted first
+    #[default]
+    KeepFirst,
+    /// Replace the extra with the one inserted last
+    KeepLast,
+    /// Combine the inserted extra into the existing one (e.g. by summing)
+    Merge(fn(&mut E, E)),
+}
+
 #[derive(Debug, Default)]
 pub struct VirtualTable<K: Default + Hash + Eq, E: Default> {
     map: HashMap<K, (E, Id)>,
     next_id: Id,
+    conflict: Conflict<E>,
+    /// Whether rows were already written (see `flush`), so the table exists
+    is_flushed: bool,
 }
 
 impl<K: Default + Hash + Eq, E: Default> VirtualTable<K, E> {
@@ -34,41 +60,113 @@ impl<K: Default + Hash + Eq, E: Default> VirtualTable<K, E> {
         Self::default()
     }
 
+    /// Creates a new [`VirtualTable<K, E>`] which resolves repeated keys with
+    /// the given strategy (rather than keeping the first extra).
+    pub fn with_conflict(conflict: Conflict<E>) -> Self {
+        Self { conflict, ..Self::default() }
+    }
+
     /// Returns the length of this [`VirtualTable<K, E>`].
     #[allow(dead_code)]
     pub fn len(&self) -> usize {
         self.map.len()
     }
 
+    /// Returns whether this [`VirtualTable<K, E>`] has no rows.
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
     #[allow(dead_code)]
     pub fn get_id(&self, key: &K) -> Option<Id> {
         self.map.get(key).map(|(_, id)| *id)
     }
 
+    /// Inserts a row, returning its id. If the key is already present, its id
+    /// is returned and the extras are resolved according to the table's
+    /// [`Conflict`] strategy.
     pub fn insert(&mut self, key: K, extra: E) -> Id {
-        let (_, id) = self.map.entry(key).or_insert_with(|| {
-            let id = self.next_id;
-            self.next_id += 1;
-            (extra, id)
-        });
+        match self.map.entry(key) {
+            Entry::Occupied(mut entry) => {
+                let (existing, id) = entry.get_mut();
+
+                match self.conflict {
+                    Conflict::KeepFirst => {}
+                    Conflict::KeepLast => *existing = extra,
+                    Conflict::Merge(merge) => merge(existing, extra),
+                }
+
+                *id
+            }
+            Entry::Vacant(entry) => {
+                let id = self.next_id;
+                self.next_id += 1;
+                entry.insert((extra, id));
+                id
+            }
+        }
+    }
 
-        *id
+    /// Inserts a row unless the key is already present, in which case the
+    /// existing extra is kept whatever the table's [`Conflict`] strategy.
+    pub fn insert_first(&mut self, key: K, extra: E) -> Id {
+        match self.get_id(&key) {
+            Some(id) => id,
+            None => self.insert(key, extra),
+        }
     }
 
     pub fn write<'a, W: SqlWriter<'a, K, E>>(self, tx: &'a Transaction) -> Result<()> {
+        self.write_chunked::<W>(tx, None)
+    }
+
+    /// Like `write`, but if a `txn_size` is given, the rows are written in
+    /// transactions of that many rows so that a failure keeps the rows which
+    /// were committed before it. The connection must then not be in a
+    /// transaction already.
+    pub fn write_chunked<'a, W: SqlWriter<'a, K, E>>(
+        mut self,
+        conn: &'a Connection,
+        txn_size: Option<usize>,
+    ) -> Result<()> {
+        self.flush::<W>(conn, txn_size)
+    }
+
+    /// Writes the rows inserted so far (creating the table first unless it was
+    /// flushed before) and forgets them, while the rows inserted later keep
+    /// getting new ids. A key inserted again after a flush is written again,
+    /// so only flush once none of the flushed keys can repeat.
+    pub fn flush<'a, W: SqlWriter<'a, K, E>>(
+        &mut self,
+        conn: &'a Connection,
+        txn_size: Option<usize>,
+    ) -> Result<()> {
         // Create table
-        tx.execute(W::create_table_script(), params![])?;
+        if !self.is_flushed {
+            conn.execute(W::create_table_script(), params![])?;
+            self.is_flushed = true;
+        }
 
         // Sorting is required for the entities table to maintain the "parent_id"
         // constraint
-        let mut rows = self.map.into_iter().collect::<Vec<_>>();
+        let mut rows = std::mem::take(&mut self.map).into_iter().collect::<Vec<_>>();
         rows.sort_by_key(|(_, (_, id))| *id);
 
         // Insert all
-        let mut writer = W::prepare(tx)?;
+        let mut writer = W::prepare(conn)?;
+        let mut rows = rows.into_iter().peekable();
+
+        while rows.peek().is_some() {
+            let tx = txn_size.map(|_| conn.unchecked_transaction()).transpose()?;
 
-        for (key, (extra, id)) in rows {
-            writer.execute(id, &key, &extra)?;
+            for (key, (extra, id)) in rows.by_ref().take(txn_size.unwrap_or(usize::MAX)) {
+                writer.execute(id, &key, &extra)?;
+            }
+
+            if let Some(tx) = tx {
+                tx.commit()?;
+            }
         }
 
         Ok(())
@@ -90,35 +188,42 @@ pub struct EntityKey {
     disc: String,
 }
 
-pub type EntityVirtualTable = VirtualTable<EntityKey, NullExtra>;
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntityExtra {
+    key: String,
+}
+
+pub type EntityVirtualTable = VirtualTable<EntityKey, EntityExtra>;
 
 pub struct EntityWriter<'a> {
     stmt: CachedStatement<'a>,
 }
 
-impl<'a> SqlWriter<'a, EntityKey, NullExtra> for EntityWriter<'a> {
+impl<'a> SqlWriter<'a, EntityKey, EntityExtra> for EntityWriter<'a> {
     fn create_table_script() -> &'static str {
-        "CREATE TABLE entities (
+        "CREATE TABLE entities ( -- Files, directories, and the classes, methods, etc. within them
             id INT NOT NULL PRIMARY KEY,
-            parent_id INT,
+            parent_id INT, -- The enclosing entity (NULL for files and top-level directories)
             name TEXT NOT NULL,
-            kind TEXT NOT NULL,
-            disc TEXT NOT NULL,
+            kind TEXT NOT NULL, -- e.g. file, dir, class, or method
+            disc TEXT NOT NULL, -- Tells apart entities with the same name (e.g. overloads)
+            key CHAR(40) NOT NULL UNIQUE, -- A hash of the path which is the same in every database
             
             FOREIGN KEY(parent_id) REFERENCES entities(id),
-            CHECK((kind == 'file' AND parent_id IS NULL) OR
-                  (kind != 'file' AND parent_id IS NOT NULL)),
+            CHECK(kind == 'file' OR kind == 'dir' OR parent_id IS NOT NULL),
             UNIQUE(parent_id, name, kind, disc)
         ) WITHOUT ROWID;"
     }
 
-    fn prepare(tx: &'a Transaction) -> Result<Self> {
-        let sql = "INSERT INTO entities (id, parent_id, name, kind, disc) VALUES (?, ?, ?, ?, ?);";
-        Ok(Self { stmt: tx.prepare_cached(sql)? })
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql =
+            "INSERT INTO entities (id, parent_id, name, kind, disc, key) VALUES (?, ?, ?, ?, \
+                   ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
     }
 
-    fn execute(&mut self, id: Id, key: &EntityKey, _: &NullExtra) -> Result<usize> {
-        Ok(self.stmt.execute(params![id, key.parent_id, key.name, key.kind, key.disc])?)
+    fn execute(&mut self, id: Id, key: &EntityKey, e: &EntityExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![id, key.parent_id, key.name, key.kind, key.disc, e.key])?)
     }
 }
 
@@ -134,11 +239,21 @@ pub struct CommitKey {
 #[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct CommitExtra {
     is_merge: bool,
+    author_name: String,
+    author_mail: String,
     author_time: i64,
     commit_time: i64,
+    message: String,
     commit_info: CommitInfo,
 }
 
+impl CommitExtra {
+    /// Keeps the phases which ran whenever the commit was inserted.
+    pub fn merge(&mut self, other: Self) {
+        self.commit_info |= other.commit_info;
+    }
+}
+
 pub type CommitVirtualTable = VirtualTable<CommitKey, CommitExtra>;
 
 pub struct CommitWriter<'a> {
@@ -147,34 +262,38 @@ pub struct CommitWriter<'a> {
 
 impl<'a> SqlWriter<'a, CommitKey, CommitExtra> for CommitWriter<'a> {
     fn create_table_script() -> &'static str {
-        "CREATE TABLE commits (
+        "CREATE TABLE commits ( -- Every extracted commit
             id INT NOT NULL PRIMARY KEY,
-            sha1 CHAR(40) NOT NULL UNIQUE,
+            sha1 CHAR(40) NOT NULL UNIQUE, -- The full hash
             is_merge BOOLEAN NOT NULL,
-            -- author_name TEXT,
-            -- author_mail TEXT,
-            author_date INT NOT NULL,
+            author_name TEXT NOT NULL,
+            author_mail TEXT NOT NULL,
+            author_date INT NOT NULL, -- Seconds since the Unix epoch
             -- commit_name TEXT,
             -- commit_mail TEXT,
-            commit_date INT NOT NULL,
+            commit_date INT NOT NULL, -- Seconds since the Unix epoch
+            message TEXT NOT NULL,
         
-            has_change_info BOOLEAN NOT NULL,
-            has_presence_info BOOLEAN NOT NULL,
+            has_change_info BOOLEAN NOT NULL, -- Whether every change of the commit is known
+            has_presence_info BOOLEAN NOT NULL, -- Whether every entity present is known
             has_reachability_info BOOLEAN NOT NULL
         ) WITHOUT ROWID;"
     }
 
-    fn prepare(tx: &'a Transaction) -> Result<Self> {
+    fn prepare(conn: &'a Connection) -> Result<Self> {
         let sql = "INSERT INTO commits (id
                                       , sha1
                                       , is_merge
+                                      , author_name
+                                      , author_mail
                                       , author_date
                                       , commit_date
+                                      , message
                                       , has_change_info
                                       , has_presence_info
                                       , has_reachability_info)
-                   VALUES (?, ?, ?, ?, ?, ?, ?, ?);";
-        Ok(Self { stmt: tx.prepare_cached(sql)? })
+                   VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
     }
 
     fn execute(&mut self, id: Id, k: &CommitKey, e: &CommitExtra) -> Result<usize> {
@@ -182,8 +301,11 @@ impl<'a> SqlWriter<'a, CommitKey, CommitExtra> for CommitWriter<'a> {
             id,
             k.sha1,
             e.is_merge,
+            e.author_name,
+            e.author_mail,
             e.author_time,
             e.commit_time,
+            e.message,
             e.commit_info.contains(CommitInfo::CHANGES),
             e.commit_info.contains(CommitInfo::PRESENCE),
             e.commit_info.contains(CommitInfo::REACHABILITY),
@@ -213,7 +335,7 @@ pub struct RefWriter<'a> {
 
 impl<'a> SqlWriter<'a, RefKey, RefExtra> for RefWriter<'a> {
     fn create_table_script() -> &'static str {
-        "CREATE TABLE refs (
+        "CREATE TABLE refs ( -- The branches and tags the extraction started from
             id INT NOT NULL PRIMARY KEY,
             commit_id INT NOT NULL,
             name TEXT NOT NULL UNIQUE,
@@ -222,9 +344,9 @@ impl<'a> SqlWriter<'a, RefKey, RefExtra> for RefWriter<'a> {
         ) WITHOUT ROWID;"
     }
 
-    fn prepare(tx: &'a Transaction) -> Result<Self> {
+    fn prepare(conn: &'a Connection) -> Result<Self> {
         let sql = "INSERT INTO refs (id, commit_id, name) VALUES (?, ?, ?);";
-        Ok(Self { stmt: tx.prepare_cached(sql)? })
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
     }
 
     fn execute(&mut self, id: Id, k: &RefKey, e: &RefExtra) -> Result<usize> {
@@ -240,6 +362,7 @@ impl<'a> SqlWriter<'a, RefKey, RefExtra> for RefWriter<'a> {
 pub struct ChangeKey {
     commit_id: Id,
     entity_id: Id,
+    parent: usize,
 }
 
 #[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -249,6 +372,20 @@ pub struct ChangeExtra {
     dels: usize,
 }
 
+impl ChangeExtra {
+    /// Sums the lines of the same change found more than once. Different kinds
+    /// (e.g. an entity deleted under one path and added under another which
+    /// normalizes to the same name) make a modification.
+    pub fn merge(&mut self, other: Self) {
+        if self.kind != other.kind {
+            self.kind = ChangeKind::Modified;
+        }
+
+        self.adds += other.adds;
+        self.dels += other.dels;
+    }
+}
+
 pub type ChangeVirtualTable = VirtualTable<ChangeKey, ChangeExtra>;
 
 pub struct ChangeWriter<'a> {
@@ -257,31 +394,35 @@ pub struct ChangeWriter<'a> {
 
 impl<'a> SqlWriter<'a, ChangeKey, ChangeExtra> for ChangeWriter<'a> {
     fn create_table_script() -> &'static str {
-        "CREATE TABLE changes (
+        "CREATE TABLE changes ( -- A change to an entity by a commit
             id INT NOT NULL PRIMARY KEY,
             commit_id INT NOT NULL,
             entity_id INT NOT NULL,
-            kind CHAR NOT NULL,
-            adds INT NOT NULL,
-            dels INT NOT NULL,
+            kind CHAR NOT NULL, -- A (added), D (deleted), or M (modified)
+            adds INT NOT NULL, -- Lines added within the entity
+            dels INT NOT NULL, -- Lines deleted within the entity
+            parent INT NOT NULL, -- The 0-based index of the parent diffed against
+            semantic_kind CHAR NOT NULL, -- Like kind, or R, V, S, C, X, or L for refined kinds
         
             FOREIGN KEY(commit_id) REFERENCES commits(id),
             FOREIGN KEY(entity_id) REFERENCES entities(id),
-            UNIQUE(commit_id, entity_id),
+            UNIQUE(commit_id, entity_id, parent),
             CHECK(kind = 'A' OR kind = 'D' or kind = 'M')
             -- CHECK(adds > 0 OR dels > 0)
         ) WITHOUT ROWID;"
     }
 
-    fn prepare(tx: &'a Transaction) -> Result<Self> {
+    fn prepare(conn: &'a Connection) -> Result<Self> {
         let sql = "INSERT INTO changes (id
                                       , commit_id
                                       , entity_id
                                       , kind
                                       , adds
-                                      , dels)
-                   VALUES (?, ?, ?, ?, ?, ?);";
-        Ok(Self { stmt: tx.prepare_cached(sql)? })
+                                      , dels
+                                      , parent
+                                      , semantic_kind)
+                   VALUES (?, ?, ?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
     }
 
     fn execute(&mut self, id: Id, k: &ChangeKey, e: &ChangeExtra) -> Result<usize> {
@@ -289,9 +430,11 @@ impl<'a> SqlWriter<'a, ChangeKey, ChangeExtra> for ChangeWriter<'a> {
             id,
             k.commit_id,
             k.entity_id,
-            e.kind.to_string(),
+            e.kind.to_basic().to_string(),
             e.adds,
-            e.dels
+            e.dels,
+            k.parent,
+            e.kind.to_string()
         ])?)
     }
 }
@@ -310,6 +453,20 @@ pub struct PresenceKey {
 pub struct PresenceExtra {
     start_row: usize,
     end_row: usize,
+    nesting: usize,
+    branches: usize,
+    params: usize,
+}
+
+impl PresenceExtra {
+    /// Widens the range to cover both and keeps the greater complexity.
+    pub fn merge(&mut self, other: Self) {
+        self.start_row = self.start_row.min(other.start_row);
+        self.end_row = self.end_row.max(other.end_row);
+        self.nesting = self.nesting.max(other.nesting);
+        self.branches = self.branches.max(other.branches);
+        self.params = self.params.max(other.params);
+    }
 }
 
 pub type PresenceVirtualTable = VirtualTable<PresenceKey, PresenceExtra>;
@@ -320,12 +477,15 @@ pub struct PresenceWriter<'a> {
 
 impl<'a> SqlWriter<'a, PresenceKey, PresenceExtra> for PresenceWriter<'a> {
     fn create_table_script() -> &'static str {
-        "CREATE TABLE presence (
+        "CREATE TABLE presence ( -- The entities present at a commit and where
             id INT NOT NULL PRIMARY KEY,
             commit_id INT NOT NULL,
             entity_id INT NOT NULL,
-            start_row INT NOT NULL,
-            end_row INT NOT NULL,
+            start_row INT NOT NULL, -- The first line (1-based, inclusive)
+            end_row INT NOT NULL, -- The last line (1-based, inclusive)
+            nesting INT NOT NULL, -- The deepest nesting of control flow statements
+            branches INT NOT NULL, -- The number of branches and loops
+            params INT NOT NULL, -- The number of parameters
         
             FOREIGN KEY(commit_id) REFERENCES commits(id),
             FOREIGN KEY(entity_id) REFERENCES entities(id),
@@ -333,14 +493,30 @@ impl<'a> SqlWriter<'a, PresenceKey, PresenceExtra> for PresenceWriter<'a> {
         ) WITHOUT ROWID;"
     }
 
-    fn prepare(tx: &'a Transaction) -> Result<Self> {
-        let sql = "INSERT INTO presence (id, commit_id, entity_id, start_row, end_row) VALUES (?, \
-                   ?, ?, ?, ?);";
-        Ok(Self { stmt: tx.prepare_cached(sql)? })
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO presence (id
+                                       , commit_id
+                                       , entity_id
+                                       , start_row
+                                       , end_row
+                                       , nesting
+                                       , branches
+                                       , params)
+                   VALUES (?, ?, ?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
     }
 
     fn execute(&mut self, id: Id, k: &PresenceKey, e: &PresenceExtra) -> Result<usize> {
-        Ok(self.stmt.execute(params![id, k.commit_id, k.entity_id, e.start_row, e.end_row])?)
+        Ok(self.stmt.execute(params![
+            id,
+            k.commit_id,
+            k.entity_id,
+            e.start_row,
+            e.end_row,
+            e.nesting,
+            e.branches,
+            e.params
+        ])?)
     }
 }
 
@@ -369,13 +545,13 @@ pub struct DepWriter<'a> {
 
 impl<'a> SqlWriter<'a, DepKey, DepExtra> for DepWriter<'a> {
     fn create_table_script() -> &'static str {
-        "CREATE TABLE deps (
+        "CREATE TABLE deps ( -- Structural dependencies at a commit (see add-deps)
             id INT NOT NULL PRIMARY KEY,
             commit_id INT NOT NULL,
             src_id INT NOT NULL,
             tgt_id INT NOT NULL,
             kind TEXT NOT NULL,
-            lineno INT NOT NULL,
+            lineno INT NOT NULL, -- The line of the dependency within the source
         
             FOREIGN KEY(commit_id) REFERENCES commits(id),
             FOREIGN KEY(src_id) REFERENCES entities(id),
@@ -384,10 +560,10 @@ impl<'a> SqlWriter<'a, DepKey, DepExtra> for DepWriter<'a> {
         ) WITHOUT ROWID;"
     }
 
-    fn prepare(tx: &'a Transaction) -> Result<Self> {
+    fn prepare(conn: &'a Connection) -> Result<Self> {
         let sql = "INSERT INTO deps (id, commit_id, src_id, tgt_id, kind, lineno) VALUES (?, ?, \
                    ?, ?, ?, ?);";
-        Ok(Self { stmt: tx.prepare_cached(sql)? })
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
     }
 
     fn execute(&mut self, id: Id, k: &DepKey, e: &DepExtra) -> Result<usize> {
@@ -396,125 +572,3624 @@ impl<'a> SqlWriter<'a, DepKey, DepExtra> for DepWriter<'a> {
 }
 
 // ========================================================
-// Reachability -------------------------------------------
+// Pull Request -------------------------------------------
 // ========================================================
 
 #[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
-pub struct ReachabilityKey {
-    source_id: Id,
-    target_id: Id,
+pub struct PullRequestKey {
+    number: u64,
 }
 
-pub type ReachabilityVirtualTable = VirtualTable<ReachabilityKey, NullExtra>;
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PullRequestExtra {
+    title: String,
+    author: String,
+    state: String,
+    created_at: i64,
+    merged_at: Option<i64>,
+    url: String,
+}
 
-pub struct ReachabilityWriter<'a> {
+pub type PullRequestVirtualTable = VirtualTable<PullRequestKey, PullRequestExtra>;
+
+pub struct PullRequestWriter<'a> {
     stmt: CachedStatement<'a>,
 }
 
-impl<'a> SqlWriter<'a, ReachabilityKey, NullExtra> for ReachabilityWriter<'a> {
+impl<'a> SqlWriter<'a, PullRequestKey, PullRequestExtra> for PullRequestWriter<'a> {
     fn create_table_script() -> &'static str {
-        "CREATE TABLE reachability (
+        "CREATE TABLE pull_requests ( -- Pull or merge requests (see import-prs)
             id INT NOT NULL PRIMARY KEY,
-            source_id INT NOT NULL,
-            target_id INT NOT NULL,
-        
-            FOREIGN KEY(source_id) REFERENCES commits(id),
-            FOREIGN KEY(target_id) REFERENCES commits(id),
-            UNIQUE(source_id, target_id)
+            number INT NOT NULL, -- The number on the platform
+            title TEXT NOT NULL,
+            author TEXT NOT NULL,
+            state TEXT NOT NULL, -- open, closed, or merged
+            created_at INT NOT NULL, -- Seconds since the Unix epoch
+            merged_at INT, -- Seconds since the Unix epoch
+            url TEXT NOT NULL,
+
+            UNIQUE(number)
         ) WITHOUT ROWID;"
     }
 
-    fn prepare(tx: &'a Transaction) -> Result<Self> {
-        let sql = "INSERT INTO reachability (id, source_id, target_id) VALUES (?, ?, ?);";
-        Ok(Self { stmt: tx.prepare_cached(sql)? })
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO pull_requests (id, number, title, author, state, created_at, \
+                   merged_at, url) VALUES (?, ?, ?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
     }
 
-    fn execute(&mut self, id: Id, k: &ReachabilityKey, _: &NullExtra) -> Result<usize> {
-        Ok(self.stmt.execute(params![id, k.source_id, k.target_id])?)
+    fn execute(&mut self, id: Id, k: &PullRequestKey, e: &PullRequestExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![
+            id,
+            k.number,
+            e.title,
+            e.author,
+            e.state,
+            e.created_at,
+            e.merged_at,
+            e.url
+        ])?)
     }
 }
 
-// ========================================================
-// Database -----------------------------------------------
-// ========================================================
+/// A commit of a pull request. Its kind is either 'C' for a commit of the pull
+/// request itself or 'M' for a commit which merged it (e.g. a squash commit).
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PullRequestCommitKey {
+    pull_request_id: Id,
+    sha1: String,
+}
+
+/// The commit is only known if it is in the database.
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PullRequestCommitExtra {
+    commit_id: Option<Id>,
+    kind: char,
+}
+
+pub type PullRequestCommitVirtualTable = VirtualTable<PullRequestCommitKey, PullRequestCommitExtra>;
+
+pub struct PullRequestCommitWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, PullRequestCommitKey, PullRequestCommitExtra>
+    for PullRequestCommitWriter<'a>
+{
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE pull_request_commits ( -- The commits of each pull request
+            id INT NOT NULL PRIMARY KEY,
+            pull_request_id INT NOT NULL,
+            sha1 CHAR(40) NOT NULL,
+            commit_id INT, -- NULL unless the commit was extracted
+            kind CHAR NOT NULL, -- C (a commit of the request) or M (the commit which merged it)
+
+            FOREIGN KEY(pull_request_id) REFERENCES pull_requests(id),
+            FOREIGN KEY(commit_id) REFERENCES commits(id),
+            UNIQUE(pull_request_id, sha1)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO pull_request_commits (id, pull_request_id, sha1, commit_id, kind) \
+                   VALUES (?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(
+        &mut self,
+        id: Id,
+        k: &PullRequestCommitKey,
+        e: &PullRequestCommitExtra,
+    ) -> Result<usize> {
+        let kind = e.kind.to_string();
+        Ok(self.stmt.execute(params![id, k.pull_request_id, k.sha1, e.commit_id, kind])?)
+    }
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PullRequestLabelKey {
+    pull_request_id: Id,
+    label: String,
+}
+
+pub type PullRequestLabelVirtualTable = VirtualTable<PullRequestLabelKey, NullExtra>;
+
+pub struct PullRequestLabelWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, PullRequestLabelKey, NullExtra> for PullRequestLabelWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE pull_request_labels ( -- The labels of each pull request
+            id INT NOT NULL PRIMARY KEY,
+            pull_request_id INT NOT NULL,
+            label TEXT NOT NULL,
+
+            FOREIGN KEY(pull_request_id) REFERENCES pull_requests(id),
+            UNIQUE(pull_request_id, label)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO pull_request_labels (id, pull_request_id, label) VALUES (?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &PullRequestLabelKey, _: &NullExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![id, k.pull_request_id, k.label])?)
+    }
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PullRequestReviewerKey {
+    pull_request_id: Id,
+    reviewer: String,
+}
+
+pub type PullRequestReviewerVirtualTable = VirtualTable<PullRequestReviewerKey, NullExtra>;
+
+pub struct PullRequestReviewerWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, PullRequestReviewerKey, NullExtra> for PullRequestReviewerWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE pull_request_reviewers ( -- The reviewers of each pull request
+            id INT NOT NULL PRIMARY KEY,
+            pull_request_id INT NOT NULL,
+            reviewer TEXT NOT NULL,
+
+            FOREIGN KEY(pull_request_id) REFERENCES pull_requests(id),
+            UNIQUE(pull_request_id, reviewer)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO pull_request_reviewers (id, pull_request_id, reviewer) VALUES (?, \
+                   ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &PullRequestReviewerKey, _: &NullExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![id, k.pull_request_id, k.reviewer])?)
+    }
+}
 
+/// The tables written by import-prs. Unlike the rest, these are written to an
+/// existing database and replace any which were imported before.
 #[derive(Debug, Default)]
-pub struct VirtualDb {
-    pub entity_vt: EntityVirtualTable,
-    pub commit_vt: CommitVirtualTable,
-    pub ref_vt: RefVirtualTable,
-    pub change_vt: ChangeVirtualTable,
-    // pub range_vt: RangeVirtualTable,
-    pub presence_vt: PresenceVirtualTable,
-    pub reachability_vt: ReachabilityVirtualTable,
+pub struct PullRequestDb {
+    pub pull_request_vt: PullRequestVirtualTable,
+    pub commit_vt: PullRequestCommitVirtualTable,
+    pub label_vt: PullRequestLabelVirtualTable,
+    pub reviewer_vt: PullRequestReviewerVirtualTable,
 }
 
-impl VirtualDb {
+impl PullRequestDb {
     pub fn new() -> Self {
         Self::default()
     }
 
-    pub fn write<'a>(self, tx: &'a Transaction) -> Result<()> {
-        self.entity_vt.write::<EntityWriter>(&tx)?;
-        self.commit_vt.write::<CommitWriter>(&tx)?;
-        self.ref_vt.write::<RefWriter>(&tx)?;
-        self.change_vt.write::<ChangeWriter>(&tx)?;
-        // self.range_vt.write::<RangeWriter>(&tx)?;
-        self.presence_vt.write::<PresenceWriter>(&tx)?;
-        self.reachability_vt.write::<ReachabilityWriter>(&tx)?;
+    pub fn write(self, tx: &Transaction) -> Result<()> {
+        for table in [
+            "pull_request_reviewers",
+            "pull_request_labels",
+            "pull_request_commits",
+            "pull_requests",
+        ] {
+            drop_table(tx, table)?;
+        }
+
+        self.pull_request_vt.write::<PullRequestWriter>(tx)?;
+        self.commit_vt.write::<PullRequestCommitWriter>(tx)?;
+        self.label_vt.write::<PullRequestLabelWriter>(tx)?;
+        self.reviewer_vt.write::<PullRequestReviewerWriter>(tx)?;
         Ok(())
     }
 }
 
-pub fn insert_entity<E: Borrow<Entity>>(db: &mut VirtualDb, entity: E) -> Result<Id> {
-    let mut prev_id = None;
+/// Inserts a pull request along with its commits, labels, and reviewers. Its
+/// commits are linked to the commits of the database by their hash.
+pub fn insert_pull_request(
+    db: &mut PullRequestDb,
+    pull_request: &PullRequest,
+    commit_ids: &HashMap<String, Id>,
+) -> Result<Id> {
+    let key = PullRequestKey::new(pull_request.number);
+    let extra = PullRequestExtra::new(
+        pull_request.title.clone(),
+        pull_request.author.clone(),
+        pull_request.state.clone(),
+        pull_request.created_at.unix_timestamp(),
+        pull_request.merged_at.map(|d| d.unix_timestamp()),
+        pull_request.url.clone(),
+    );
+    let id = db.pull_request_vt.insert(key, extra);
 
-    for (name, kind, disc) in entity.borrow().to_vec() {
-        let key = EntityKey::new(prev_id, name, kind, disc);
-        prev_id = Some(db.entity_vt.insert(key, NullExtra));
+    let commits = pull_request.commits.iter().map(|c| (c, 'C'));
+    let merge_commits = pull_request.merge_commits.iter().map(|c| (c, 'M'));
+
+    for (commit, kind) in commits.chain(merge_commits) {
+        let sha1 = commit.to_string();
+        let extra = PullRequestCommitExtra::new(commit_ids.get(&sha1).copied(), kind);
+        db.commit_vt.insert(PullRequestCommitKey::new(id, sha1), extra);
     }
 
-    Ok(prev_id.unwrap())
+    for label in &pull_request.labels {
+        db.label_vt.insert(PullRequestLabelKey::new(id, label.clone()), NullExtra);
+    }
+
+    for reviewer in &pull_request.reviewers {
+        db.reviewer_vt.insert(PullRequestReviewerKey::new(id, reviewer.clone()), NullExtra);
+    }
+
+    Ok(id)
 }
 
-pub fn insert_commit(db: &mut VirtualDb, commit: &Commit) -> Result<Id> {
-    let key = CommitKey::new(commit.sha1.to_string());
-    let extra = CommitExtra::new(
-        commit.is_merge,
-        commit.author_date.unix_timestamp(),
-        commit.commit_date.unix_timestamp(),
-        CommitInfo::empty(),
-    );
-    Ok(db.commit_vt.insert(key, extra))
+// ========================================================
+// Issue --------------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct IssueKey {
+    key: String,
 }
 
-pub fn insert_change(db: &mut VirtualDb, change: &Change) -> Result<Id> {
-    let commit_id = insert_commit(db, &change.commit)?;
-    let entity_id = insert_entity(db, change.entity.clone())?;
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct IssueExtra {
+    kind: Option<String>,
+    title: String,
+    status: String,
+    resolution: Option<String>,
+    /// The labels joined by newlines
+    labels: String,
+    is_defect: bool,
+}
 
-    let change_key = ChangeKey::new(commit_id, entity_id);
-    let change_extra = ChangeExtra::new(change.kind, change.adds, change.dels);
+pub type IssueVirtualTable = VirtualTable<IssueKey, IssueExtra>;
 
-    Ok(db.change_vt.insert(change_key, change_extra))
+pub struct IssueWriter<'a> {
+    stmt: CachedStatement<'a>,
 }
 
-pub fn insert_presence(db: &mut VirtualDb, presence: &Presence) -> Result<Id> {
-    let commit_id = insert_commit(db, &presence.commit)?;
-    let entity_id = insert_entity(db, presence.loc_entity.entity.clone())?;
+impl<'a> SqlWriter<'a, IssueKey, IssueExtra> for IssueWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE issues ( -- Issues referred to by commit messages (see import-issues)
+            id INT NOT NULL PRIMARY KEY,
+            key TEXT NOT NULL UNIQUE, -- e.g. PROJ-123 or #123
+            kind TEXT,
+            title TEXT NOT NULL,
+            status TEXT NOT NULL,
+            resolution TEXT,
+            labels TEXT NOT NULL, -- Separated by newlines
+            is_defect BOOLEAN NOT NULL -- Whether the issue is a defect (e.g. a bug)
+        ) WITHOUT ROWID;"
+    }
 
-    let interval = presence.loc_entity.loc;
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO issues (id, key, kind, title, status, resolution, labels, \
+                   is_defect) VALUES (?, ?, ?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
 
-    let presence_key = PresenceKey::new(commit_id, entity_id);
-    let presence_extra = PresenceExtra::new(interval.0, interval.1);
+    fn execute(&mut self, id: Id, k: &IssueKey, e: &IssueExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![
+            id,
+            k.key,
+            e.kind,
+            e.title,
+            e.status,
+            e.resolution,
+            e.labels,
+            e.is_defect
+        ])?)
+    }
+}
 
-    Ok(db.presence_vt.insert(presence_key, presence_extra))
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CommitIssueKey {
+    commit_id: Id,
+    issue_id: Id,
 }
 
-pub fn insert_ref<'r>(db: &mut VirtualDb, r#ref: &Ref) -> Result<Id> {
-    let commit_id = insert_commit(db, &r#ref.commit)?;
+pub type CommitIssueVirtualTable = VirtualTable<CommitIssueKey, NullExtra>;
 
-    let ref_key = RefKey::new(r#ref.name.clone());
-    let ref_extra = RefExtra::new(commit_id);
+pub struct CommitIssueWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
 
-    Ok(db.ref_vt.insert(ref_key, ref_extra))
+impl<'a> SqlWriter<'a, CommitIssueKey, NullExtra> for CommitIssueWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE commit_issues ( -- The issues each commit refers to
+            id INT NOT NULL PRIMARY KEY,
+            commit_id INT NOT NULL,
+            issue_id INT NOT NULL,
+
+            FOREIGN KEY(commit_id) REFERENCES commits(id),
+            FOREIGN KEY(issue_id) REFERENCES issues(id),
+            UNIQUE(commit_id, issue_id)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO commit_issues (id, commit_id, issue_id) VALUES (?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &CommitIssueKey, _: &NullExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![id, k.commit_id, k.issue_id])?)
+    }
+}
+
+/// The tables written by import-issues, which (like those of import-prs)
+/// replace any which were imported before.
+#[derive(Debug, Default)]
+pub struct IssueDb {
+    pub issue_vt: IssueVirtualTable,
+    pub commit_vt: CommitIssueVirtualTable,
+}
+
+impl IssueDb {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write(self, tx: &Transaction) -> Result<()> {
+        for table in ["commit_issues", "issues"] {
+            drop_table(tx, table)?;
+        }
+
+        self.issue_vt.write::<IssueWriter>(tx)?;
+        self.commit_vt.write::<CommitIssueWriter>(tx)?;
+        Ok(())
+    }
+}
+
+/// Inserts an issue along with the commits which refer to it.
+pub fn insert_issue(db: &mut IssueDb, issue: &Issue, commit_ids: &[Id]) -> Id {
+    let extra = IssueExtra::new(
+        issue.kind.clone(),
+        issue.title.clone(),
+        issue.status.clone(),
+        issue.resolution.clone(),
+        issue.labels.join("\n"),
+        issue.is_defect,
+    );
+    let id = db.issue_vt.insert(IssueKey::new(issue.key.clone()), extra);
+
+    for &commit_id in commit_ids {
+        db.commit_vt.insert(CommitIssueKey::new(commit_id, id), NullExtra);
+    }
+
+    id
+}
+
+// ========================================================
+// Reachability -------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ReachabilityKey {
+    source_id: Id,
+    target_id: Id,
+}
+
+pub type ReachabilityVirtualTable = VirtualTable<ReachabilityKey, NullExtra>;
+
+pub struct ReachabilityWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, ReachabilityKey, NullExtra> for ReachabilityWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE reachability ( -- Pairs of commits where the source can reach the target
+            id INT NOT NULL PRIMARY KEY,
+            source_id INT NOT NULL,
+            target_id INT NOT NULL,
+        
+            FOREIGN KEY(source_id) REFERENCES commits(id),
+            FOREIGN KEY(target_id) REFERENCES commits(id),
+            UNIQUE(source_id, target_id)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO reachability (id, source_id, target_id) VALUES (?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &ReachabilityKey, _: &NullExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![id, k.source_id, k.target_id])?)
+    }
+}
+
+// ========================================================
+// Tag ----------------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TagKey {
+    name: String,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TagExtra {
+    commit_id: Id,
+    tagger_name: Option<String>,
+    tagger_mail: Option<String>,
+    tag_date: Option<i64>,
+    message: Option<String>,
+}
+
+pub type TagVirtualTable = VirtualTable<TagKey, TagExtra>;
+
+pub struct TagWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, TagKey, TagExtra> for TagWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE tags ( -- Every tag and its annotation (if any)
+            id INT NOT NULL PRIMARY KEY,
+            commit_id INT NOT NULL,
+            name TEXT NOT NULL UNIQUE,
+            tagger_name TEXT,
+            tagger_mail TEXT,
+            tag_date INT, -- Seconds since the Unix epoch
+            message TEXT,
+
+            FOREIGN KEY(commit_id) REFERENCES commits(id)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO tags (id
+                                   , commit_id
+                                   , name
+                                   , tagger_name
+                                   , tagger_mail
+                                   , tag_date
+                                   , message)
+                   VALUES (?, ?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &TagKey, e: &TagExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![
+            id,
+            e.commit_id,
+            k.name,
+            e.tagger_name,
+            e.tagger_mail,
+            e.tag_date,
+            e.message
+        ])?)
+    }
+}
+
+// ========================================================
+// Ref History --------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RefHistoryKey {
+    name: String,
+    seq: usize,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RefHistoryExtra {
+    old_sha1: Option<String>,
+    new_sha1: String,
+    time: i64,
+    message: Option<String>,
+}
+
+pub type RefHistoryVirtualTable = VirtualTable<RefHistoryKey, RefHistoryExtra>;
+
+pub struct RefHistoryWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, RefHistoryKey, RefHistoryExtra> for RefHistoryWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE ref_history ( -- The reflog of each ref
+            id INT NOT NULL PRIMARY KEY,
+            name TEXT NOT NULL,
+            seq INT NOT NULL, -- 0 for the oldest entry
+            old_sha1 CHAR(40), -- NULL if the entry created the ref
+            new_sha1 CHAR(40) NOT NULL,
+            time INT NOT NULL, -- Seconds since the Unix epoch
+            message TEXT,
+
+            UNIQUE(name, seq)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO ref_history (id
+                                          , name
+                                          , seq
+                                          , old_sha1
+                                          , new_sha1
+                                          , time
+                                          , message)
+                   VALUES (?, ?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &RefHistoryKey, e: &RefHistoryExtra) -> Result<usize> {
+        Ok(self
+            .stmt
+            .execute(params![id, k.name, k.seq, e.old_sha1, e.new_sha1, e.time, e.message])?)
+    }
+}
+
+// ========================================================
+// Commit Refs --------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CommitRefKey {
+    commit_id: Id,
+    ref_id: Id,
+}
+
+pub type CommitRefVirtualTable = VirtualTable<CommitRefKey, NullExtra>;
+
+pub struct CommitRefWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, CommitRefKey, NullExtra> for CommitRefWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE commit_refs ( -- The refs each commit is reachable from
+            id INT NOT NULL PRIMARY KEY,
+            commit_id INT NOT NULL,
+            ref_id INT NOT NULL,
+
+            FOREIGN KEY(commit_id) REFERENCES commits(id),
+            FOREIGN KEY(ref_id) REFERENCES refs(id),
+            UNIQUE(commit_id, ref_id)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO commit_refs (id, commit_id, ref_id) VALUES (?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &CommitRefKey, _: &NullExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![id, k.commit_id, k.ref_id])?)
+    }
+}
+
+// ========================================================
+// Commit Coverage ----------------------------------------
+// ========================================================
+
+/// A phase which ran for a commit (C for changes or P for presence) and the
+/// settings it ran with (see `Coverage`).
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CommitCoverageKey {
+    commit_id: Id,
+    phase: char,
+    files: String,
+    granularity: String,
+    /// The parsed languages separated by commas
+    languages: String,
+    /// The vendored directories which were left out separated by commas
+    excluded: String,
+}
+
+pub type CommitCoverageVirtualTable = VirtualTable<CommitCoverageKey, NullExtra>;
+
+pub struct CommitCoverageWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, CommitCoverageKey, NullExtra> for CommitCoverageWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE commit_coverage ( -- Which phases ran for each commit and with what settings
+            id INT NOT NULL PRIMARY KEY,
+            commit_id INT NOT NULL,
+            phase CHAR NOT NULL, -- C (changes) or P (presence)
+            files TEXT NOT NULL, -- The glob of the processed files (e.g. *.java)
+            granularity TEXT NOT NULL, -- e.g. entity or file
+            languages TEXT NOT NULL, -- The parsed languages separated by commas
+            excluded TEXT NOT NULL, -- The vendored directories left out separated by commas
+
+            FOREIGN KEY(commit_id) REFERENCES commits(id),
+            UNIQUE(commit_id, phase, files, granularity, languages, excluded),
+            CHECK(phase = 'C' OR phase = 'P')
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO commit_coverage (id, commit_id, phase, files, granularity, \
+                   languages, excluded) VALUES (?, ?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &CommitCoverageKey, _: &NullExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![
+            id,
+            k.commit_id,
+            k.phase.to_string(),
+            k.files,
+            k.granularity,
+            k.languages,
+            k.excluded
+        ])?)
+    }
+}
+
+// ========================================================
+// Duplicate ----------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DuplicateKey {
+    commit_id: Id,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DuplicateExtra {
+    original_id: Id,
+    patch_id: String,
+}
+
+pub type DuplicateVirtualTable = VirtualTable<DuplicateKey, DuplicateExtra>;
+
+pub struct DuplicateWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, DuplicateKey, DuplicateExtra> for DuplicateWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE duplicate_commits ( -- Commits with the same patch as an earlier one
+            id INT NOT NULL PRIMARY KEY,
+            commit_id INT NOT NULL UNIQUE,
+            original_id INT NOT NULL, -- The earliest commit with the patch
+            patch_id CHAR(40) NOT NULL, -- As computed by git patch-id
+
+            FOREIGN KEY(commit_id) REFERENCES commits(id),
+            FOREIGN KEY(original_id) REFERENCES commits(id)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO duplicate_commits (id, commit_id, original_id, patch_id)
+                   VALUES (?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &DuplicateKey, e: &DuplicateExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![id, k.commit_id, e.original_id, e.patch_id])?)
+    }
+}
+
+// ========================================================
+// Revert -------------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RevertKey {
+    commit_id: Id,
+    reverted_id: Id,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RevertExtra {
+    kind: &'static str,
+}
+
+pub type RevertVirtualTable = VirtualTable<RevertKey, RevertExtra>;
+
+pub struct RevertWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, RevertKey, RevertExtra> for RevertWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE reverts ( -- Commits which revert others
+            id INT NOT NULL PRIMARY KEY,
+            commit_id INT NOT NULL,
+            reverted_id INT NOT NULL,
+            kind TEXT NOT NULL, -- Found by the message or by the patch
+
+            FOREIGN KEY(commit_id) REFERENCES commits(id),
+            FOREIGN KEY(reverted_id) REFERENCES commits(id),
+            UNIQUE(commit_id, reverted_id),
+            CHECK(kind = 'message' OR kind = 'patch')
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO reverts (id, commit_id, reverted_id, kind) VALUES (?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &RevertKey, e: &RevertExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![id, k.commit_id, k.reverted_id, e.kind])?)
+    }
+}
+
+// ========================================================
+// Submodule Update ---------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SubmoduleUpdateKey {
+    commit_id: Id,
+    parent: usize,
+    path: String,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SubmoduleUpdateExtra {
+    old_sha1: Option<String>,
+    new_sha1: Option<String>,
+}
+
+pub type SubmoduleUpdateVirtualTable = VirtualTable<SubmoduleUpdateKey, SubmoduleUpdateExtra>;
+
+pub struct SubmoduleUpdateWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, SubmoduleUpdateKey, SubmoduleUpdateExtra> for SubmoduleUpdateWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE submodule_updates ( -- Submodules added, updated, or removed by a commit
+            id INT NOT NULL PRIMARY KEY,
+            commit_id INT NOT NULL,
+            parent INT NOT NULL, -- The 0-based index of the parent diffed against
+            path TEXT NOT NULL,
+            old_sha1 CHAR(40), -- NULL if the submodule was added
+            new_sha1 CHAR(40), -- NULL if the submodule was removed
+
+            FOREIGN KEY(commit_id) REFERENCES commits(id),
+            UNIQUE(commit_id, parent, path)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO submodule_updates (id, commit_id, parent, path, old_sha1, new_sha1)
+                   VALUES (?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(
+        &mut self,
+        id: Id,
+        k: &SubmoduleUpdateKey,
+        e: &SubmoduleUpdateExtra,
+    ) -> Result<usize> {
+        Ok(self.stmt.execute(params![id, k.commit_id, k.parent, k.path, e.old_sha1, e.new_sha1])?)
+    }
+}
+
+// ========================================================
+// Symlink Update -----------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SymlinkUpdateKey {
+    commit_id: Id,
+    parent: usize,
+    path: String,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SymlinkUpdateExtra {
+    old_target: Option<String>,
+    new_target: Option<String>,
+}
+
+pub type SymlinkUpdateVirtualTable = VirtualTable<SymlinkUpdateKey, SymlinkUpdateExtra>;
+
+pub struct SymlinkUpdateWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, SymlinkUpdateKey, SymlinkUpdateExtra> for SymlinkUpdateWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE symlink_updates ( -- Symlinks added, retargeted, or removed by a commit
+            id INT NOT NULL PRIMARY KEY,
+            commit_id INT NOT NULL,
+            parent INT NOT NULL, -- The 0-based index of the parent diffed against
+            path TEXT NOT NULL,
+            old_target TEXT, -- NULL if the symlink was added
+            new_target TEXT, -- NULL if the symlink was removed
+
+            FOREIGN KEY(commit_id) REFERENCES commits(id),
+            UNIQUE(commit_id, parent, path)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql =
+            "INSERT INTO symlink_updates (id, commit_id, parent, path, old_target, new_target)
+                   VALUES (?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &SymlinkUpdateKey, e: &SymlinkUpdateExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![
+            id,
+            k.commit_id,
+            k.parent,
+            k.path,
+            e.old_target,
+            e.new_target
+        ])?)
+    }
+}
+
+// ========================================================
+// Diagnostic ---------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DiagnosticKey {
+    commit_id: Option<Id>,
+    kind: String,
+    subject: String,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DiagnosticExtra {
+    message: String,
+}
+
+pub type DiagnosticVirtualTable = VirtualTable<DiagnosticKey, DiagnosticExtra>;
+
+pub struct DiagnosticWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, DiagnosticKey, DiagnosticExtra> for DiagnosticWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE diagnostics ( -- Limitations of the extracted data
+            id INT NOT NULL PRIMARY KEY,
+            commit_id INT, -- The affected commit (if any)
+            kind TEXT NOT NULL, -- e.g. missing_parents
+            subject TEXT NOT NULL, -- What is affected (e.g. a file)
+            message TEXT NOT NULL,
+
+            FOREIGN KEY(commit_id) REFERENCES commits(id),
+            UNIQUE(commit_id, kind, subject)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO diagnostics (id, commit_id, kind, subject, message)
+                   VALUES (?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &DiagnosticKey, e: &DiagnosticExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![id, k.commit_id, k.kind, k.subject, e.message])?)
+    }
+}
+
+// ========================================================
+// Change Lines -------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChangeLinesKey {
+    commit_id: Id,
+    entity_id: Id,
+    parent: usize,
+}
+
+/// The added and deleted lines as lists such as `3-5,9` (see `to_line_list`).
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChangeLinesExtra {
+    added: String,
+    deleted: String,
+}
+
+pub type ChangeLinesVirtualTable = VirtualTable<ChangeLinesKey, ChangeLinesExtra>;
+
+pub struct ChangeLinesWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, ChangeLinesKey, ChangeLinesExtra> for ChangeLinesWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE change_lines ( -- The lines each change added and deleted (see --store-lines)
+            id INT NOT NULL PRIMARY KEY,
+            commit_id INT NOT NULL,
+            entity_id INT NOT NULL,
+            parent INT NOT NULL,
+            added TEXT NOT NULL, -- Lines of the new blob as inclusive 1-based ranges (e.g. 3-5,9)
+            deleted TEXT NOT NULL, -- Lines of the old blob as inclusive 1-based ranges
+
+            FOREIGN KEY(commit_id) REFERENCES commits(id),
+            FOREIGN KEY(entity_id) REFERENCES entities(id),
+            UNIQUE(commit_id, entity_id, parent)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO change_lines (id, commit_id, entity_id, parent, added, deleted)
+                   VALUES (?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &ChangeLinesKey, e: &ChangeLinesExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![
+            id,
+            k.commit_id,
+            k.entity_id,
+            k.parent,
+            e.added,
+            e.deleted
+        ])?)
+    }
+}
+
+// ========================================================
+// Doc Change ---------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DocChangeKey {
+    commit_id: Id,
+    entity_id: Id,
+    parent: usize,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DocChangeExtra {
+    doc_adds: usize,
+    doc_dels: usize,
+    touched: Touched,
+}
+
+impl DocChangeExtra {
+    pub fn merge(&mut self, other: Self) {
+        self.doc_adds += other.doc_adds;
+        self.doc_dels += other.doc_dels;
+
+        if self.touched != other.touched {
+            self.touched = Touched::Both;
+        }
+    }
+}
+
+pub type DocChangeVirtualTable = VirtualTable<DocChangeKey, DocChangeExtra>;
+
+pub struct DocChangeWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, DocChangeKey, DocChangeExtra> for DocChangeWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE doc_changes ( -- How each change touched doc comments (see --store-docs)
+            id INT NOT NULL PRIMARY KEY,
+            commit_id INT NOT NULL,
+            entity_id INT NOT NULL,
+            parent INT NOT NULL,
+            doc_adds INT NOT NULL, -- Lines added to the doc comment
+            doc_dels INT NOT NULL, -- Lines deleted from the doc comment
+            touched CHAR NOT NULL, -- C (only code), D (only the doc comment), or B (both)
+
+            FOREIGN KEY(commit_id) REFERENCES commits(id),
+            FOREIGN KEY(entity_id) REFERENCES entities(id),
+            UNIQUE(commit_id, entity_id, parent),
+            CHECK(touched = 'C' OR touched = 'D' OR touched = 'B')
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO doc_changes (id
+                                          , commit_id
+                                          , entity_id
+                                          , parent
+                                          , doc_adds
+                                          , doc_dels
+                                          , touched)
+                   VALUES (?, ?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &DocChangeKey, e: &DocChangeExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![
+            id,
+            k.commit_id,
+            k.entity_id,
+            k.parent,
+            e.doc_adds,
+            e.doc_dels,
+            e.touched.to_char().to_string()
+        ])?)
+    }
+}
+
+// ========================================================
+// Hunk ---------------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct HunkKey {
+    commit_id: Id,
+    parent: usize,
+    filename: String,
+    ordinal: usize,
+}
+
+/// The lines of each side of a hunk, from the first to past the last. Hunks
+/// which cover a whole side (of binary files and type changes) have no end.
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct HunkExtra {
+    old_start: usize,
+    old_end: Option<usize>,
+    new_start: usize,
+    new_end: Option<usize>,
+}
+
+pub type HunkVirtualTable = VirtualTable<HunkKey, HunkExtra>;
+
+pub struct HunkWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, HunkKey, HunkExtra> for HunkWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE hunks ( -- The hunks of each diffed file (see --store-hunks)
+            id INT NOT NULL PRIMARY KEY,
+            commit_id INT NOT NULL,
+            parent INT NOT NULL,
+            filename TEXT NOT NULL,
+            ordinal INT NOT NULL, -- The 0-based position of the hunk within the diff
+            old_start INT NOT NULL, -- The first line of the old side (1-based)
+            old_end INT, -- Past the last line (NULL for the whole side)
+            new_start INT NOT NULL, -- The first line of the new side (1-based)
+            new_end INT, -- Past the last line (NULL for the whole side)
+
+            FOREIGN KEY(commit_id) REFERENCES commits(id),
+            UNIQUE(commit_id, parent, filename, ordinal)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO hunks (id
+                                    , commit_id
+                                    , parent
+                                    , filename
+                                    , ordinal
+                                    , old_start
+                                    , old_end
+                                    , new_start
+                                    , new_end)
+                   VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &HunkKey, e: &HunkExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![
+            id,
+            k.commit_id,
+            k.parent,
+            k.filename,
+            k.ordinal,
+            e.old_start,
+            e.old_end,
+            e.new_start,
+            e.new_end
+        ])?)
+    }
+}
+
+// ========================================================
+// Hunk Entity --------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct HunkEntityKey {
+    hunk_id: Id,
+    entity_id: Id,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct HunkEntityExtra {
+    adds: usize,
+    dels: usize,
+}
+
+impl HunkEntityExtra {
+    pub fn merge(&mut self, other: Self) {
+        self.adds += other.adds;
+        self.dels += other.dels;
+    }
+}
+
+pub type HunkEntityVirtualTable = VirtualTable<HunkEntityKey, HunkEntityExtra>;
+
+pub struct HunkEntityWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, HunkEntityKey, HunkEntityExtra> for HunkEntityWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE hunk_entities ( -- The lines of each hunk within each entity
+            id INT NOT NULL PRIMARY KEY,
+            hunk_id INT NOT NULL,
+            entity_id INT NOT NULL,
+            adds INT NOT NULL,
+            dels INT NOT NULL,
+
+            FOREIGN KEY(hunk_id) REFERENCES hunks(id),
+            FOREIGN KEY(entity_id) REFERENCES entities(id),
+            UNIQUE(hunk_id, entity_id)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO hunk_entities (id, hunk_id, entity_id, adds, dels)
+                   VALUES (?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &HunkEntityKey, e: &HunkEntityExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![id, k.hunk_id, k.entity_id, e.adds, e.dels])?)
+    }
+}
+
+// ========================================================
+// Parse Error --------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ParseErrorKey {
+    filename: String,
+    sha1: String,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ParseErrorExtra {
+    errors: usize,
+    error_bytes: usize,
+    bytes: usize,
+    skipped: bool,
+}
+
+pub type ParseErrorVirtualTable = VirtualTable<ParseErrorKey, ParseErrorExtra>;
+
+pub struct ParseErrorWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, ParseErrorKey, ParseErrorExtra> for ParseErrorWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE parse_errors ( -- Blobs which could not be fully parsed
+            id INT NOT NULL PRIMARY KEY,
+            filename TEXT NOT NULL,
+            sha1 CHAR(40) NOT NULL, -- The hash of the blob
+            errors INT NOT NULL, -- The number of ERROR and MISSING nodes
+            error_bytes INT NOT NULL, -- The number of bytes within ERROR nodes
+            bytes INT NOT NULL,
+            skipped BOOLEAN NOT NULL, -- Whether the file was recorded as a single entity
+
+            UNIQUE(filename, sha1)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql =
+            "INSERT INTO parse_errors (id, filename, sha1, errors, error_bytes, bytes, skipped)
+                   VALUES (?, ?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &ParseErrorKey, e: &ParseErrorExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![
+            id,
+            k.filename,
+            k.sha1,
+            e.errors,
+            e.error_bytes,
+            e.bytes,
+            e.skipped
+        ])?)
+    }
+}
+
+// ========================================================
+// Generated Entity ---------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GeneratedEntityKey {
+    entity_id: Id,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GeneratedEntityExtra {
+    reason: String,
+}
+
+pub type GeneratedEntityVirtualTable = VirtualTable<GeneratedEntityKey, GeneratedEntityExtra>;
+
+pub struct GeneratedEntityWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, GeneratedEntityKey, GeneratedEntityExtra> for GeneratedEntityWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE generated_entities ( -- Entities marked as generated code
+            id INT NOT NULL PRIMARY KEY,
+            entity_id INT NOT NULL,
+            reason TEXT NOT NULL, -- header or annotation
+
+            FOREIGN KEY(entity_id) REFERENCES entities(id),
+            UNIQUE(entity_id)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO generated_entities (id, entity_id, reason) VALUES (?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(
+        &mut self,
+        id: Id,
+        k: &GeneratedEntityKey,
+        e: &GeneratedEntityExtra,
+    ) -> Result<usize> {
+        Ok(self.stmt.execute(params![id, k.entity_id, e.reason])?)
+    }
+}
+
+// ========================================================
+// Entity Source ------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntitySourceKey {
+    commit_id: Id,
+    entity_id: Id,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntitySourceExtra {
+    sha1: String,
+    source: Option<Vec<u8>>,
+}
+
+pub type EntitySourceVirtualTable = VirtualTable<EntitySourceKey, EntitySourceExtra>;
+
+pub struct EntitySourceWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, EntitySourceKey, EntitySourceExtra> for EntitySourceWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE entity_sources ( -- The source text of each present entity
+            id INT NOT NULL PRIMARY KEY,
+            commit_id INT NOT NULL,
+            entity_id INT NOT NULL,
+            sha1 CHAR(40) NOT NULL, -- The hash of the text as a git blob
+            source BLOB, -- The zlib-compressed text (NULL if only hashes were stored)
+
+            FOREIGN KEY(commit_id) REFERENCES commits(id),
+            FOREIGN KEY(entity_id) REFERENCES entities(id),
+            UNIQUE(commit_id, entity_id)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO entity_sources (id, commit_id, entity_id, sha1, source)
+                   VALUES (?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &EntitySourceKey, e: &EntitySourceExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![id, k.commit_id, k.entity_id, e.sha1, e.source])?)
+    }
+}
+
+// ========================================================
+// Hidden Deps --------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct HiddenDepKey {
+    entity1_id: Id,
+    entity2_id: Id,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, PartialOrd)]
+pub struct HiddenDepExtra {
+    support: f64,
+    confidence: f64,
+}
+
+pub type HiddenDepVirtualTable = VirtualTable<HiddenDepKey, HiddenDepExtra>;
+
+pub struct HiddenDepWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, HiddenDepKey, HiddenDepExtra> for HiddenDepWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE hidden_deps ( -- Coupled pairs without a structural dependency
+            id INT NOT NULL PRIMARY KEY,
+            entity1_id INT NOT NULL,
+            entity2_id INT NOT NULL,
+            support REAL NOT NULL,
+            confidence REAL NOT NULL,
+
+            FOREIGN KEY(entity1_id) REFERENCES entities(id),
+            FOREIGN KEY(entity2_id) REFERENCES entities(id),
+            UNIQUE(entity1_id, entity2_id)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql =
+            "INSERT INTO hidden_deps (id, entity1_id, entity2_id, support, confidence) VALUES \
+                   (?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &HiddenDepKey, e: &HiddenDepExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![id, k.entity1_id, k.entity2_id, e.support, e.confidence])?)
+    }
+}
+
+// ========================================================
+// Dep Co-change ------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DepCochangeKey {
+    commit_id: Id,
+    src_id: Id,
+    tgt_id: Id,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, PartialOrd)]
+pub struct DepCochangeExtra {
+    src_changes: f64,
+    tgt_changes: f64,
+    support: f64,
+    propagation: f64,
+    tgt_fan_in: usize,
+    class: Option<&'static str>,
+}
+
+pub type DepCochangeVirtualTable = VirtualTable<DepCochangeKey, DepCochangeExtra>;
+
+pub struct DepCochangeWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, DepCochangeKey, DepCochangeExtra> for DepCochangeWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE dep_cochange ( -- How often the endpoints of each dependency co-change
+            id INT NOT NULL PRIMARY KEY,
+            commit_id INT NOT NULL,
+            src_id INT NOT NULL,
+            tgt_id INT NOT NULL,
+            src_changes REAL NOT NULL,
+            tgt_changes REAL NOT NULL,
+            support REAL NOT NULL,
+            propagation REAL NOT NULL, -- Fraction of target changes which also changed the source
+            tgt_fan_in INT NOT NULL, -- The structural fan-in of the target
+            class TEXT, -- fragile or stable (NULL unless the target is an interface)
+
+            FOREIGN KEY(commit_id) REFERENCES commits(id),
+            FOREIGN KEY(src_id) REFERENCES entities(id),
+            FOREIGN KEY(tgt_id) REFERENCES entities(id),
+            UNIQUE(commit_id, src_id, tgt_id),
+            CHECK(class IS NULL OR class = 'fragile' OR class = 'stable')
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO dep_cochange (id
+                                           , commit_id
+                                           , src_id
+                                           , tgt_id
+                                           , src_changes
+                                           , tgt_changes
+                                           , support
+                                           , propagation
+                                           , tgt_fan_in
+                                           , class)
+                   VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &DepCochangeKey, e: &DepCochangeExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![
+            id,
+            k.commit_id,
+            k.src_id,
+            k.tgt_id,
+            e.src_changes,
+            e.tgt_changes,
+            e.support,
+            e.propagation,
+            e.tgt_fan_in,
+            e.class
+        ])?)
+    }
+}
+
+// ========================================================
+// Ownership ----------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OwnershipKey {
+    entity_id: Id,
+    author_mail: String,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, PartialOrd)]
+pub struct OwnershipExtra {
+    author_name: String,
+    lines: usize,
+    share: f64,
+}
+
+pub type OwnershipVirtualTable = VirtualTable<OwnershipKey, OwnershipExtra>;
+
+pub struct OwnershipWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, OwnershipKey, OwnershipExtra> for OwnershipWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE ownership ( -- The share of each author of the changed lines of an entity
+            id INT NOT NULL PRIMARY KEY,
+            entity_id INT NOT NULL,
+            author_name TEXT NOT NULL,
+            author_mail TEXT NOT NULL,
+            lines INT NOT NULL, -- Lines added and deleted by the author
+            share REAL NOT NULL,
+
+            FOREIGN KEY(entity_id) REFERENCES entities(id),
+            UNIQUE(entity_id, author_mail)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO ownership (id, entity_id, author_name, author_mail, lines, share) \
+                   VALUES (?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &OwnershipKey, e: &OwnershipExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![
+            id,
+            k.entity_id,
+            e.author_name,
+            k.author_mail,
+            e.lines,
+            e.share
+        ])?)
+    }
+}
+
+// ========================================================
+// Bus Factor ---------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BusFactorKey {
+    kind: String,
+    name: String,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, PartialOrd)]
+pub struct BusFactorExtra {
+    entity_id: Option<Id>,
+    dominant_mail: String,
+    dominant_share: f64,
+    num_authors: usize,
+    bus_factor: usize,
+}
+
+pub type BusFactorVirtualTable = VirtualTable<BusFactorKey, BusFactorExtra>;
+
+pub struct BusFactorWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, BusFactorKey, BusFactorExtra> for BusFactorWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE bus_factors ( -- The bus factor of each file and package
+            id INT NOT NULL PRIMARY KEY,
+            kind TEXT NOT NULL, -- file or package
+            name TEXT NOT NULL,
+            entity_id INT, -- NULL for packages
+            dominant_mail TEXT NOT NULL,
+            dominant_share REAL NOT NULL,
+            num_authors INT NOT NULL,
+            bus_factor INT NOT NULL,
+
+            FOREIGN KEY(entity_id) REFERENCES entities(id),
+            UNIQUE(kind, name),
+            CHECK((kind = 'file' AND entity_id IS NOT NULL) OR
+                  (kind = 'package' AND entity_id IS NULL))
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO bus_factors (id
+                                          , kind
+                                          , name
+                                          , entity_id
+                                          , dominant_mail
+                                          , dominant_share
+                                          , num_authors
+                                          , bus_factor)
+                   VALUES (?, ?, ?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &BusFactorKey, e: &BusFactorExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![
+            id,
+            k.kind,
+            k.name,
+            e.entity_id,
+            e.dominant_mail,
+            e.dominant_share,
+            e.num_authors,
+            e.bus_factor
+        ])?)
+    }
+}
+
+// ========================================================
+// Coupling -----------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CouplingKey {
+    window_start: i64,
+    entity1_id: Id,
+    entity2_id: Id,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, PartialOrd)]
+pub struct CouplingExtra {
+    window_end: i64,
+    support: f64,
+    confidence: f64,
+}
+
+pub type CouplingVirtualTable = VirtualTable<CouplingKey, CouplingExtra>;
+
+pub struct CouplingWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, CouplingKey, CouplingExtra> for CouplingWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE coupling ( -- Pairs of entities which frequently co-change
+            id INT NOT NULL PRIMARY KEY,
+            window_start INT NOT NULL, -- Seconds since the Unix epoch (inclusive)
+            window_end INT NOT NULL, -- Seconds since the Unix epoch (exclusive)
+            entity1_id INT NOT NULL,
+            entity2_id INT NOT NULL,
+            support REAL NOT NULL,
+            confidence REAL NOT NULL,
+
+            FOREIGN KEY(entity1_id) REFERENCES entities(id),
+            FOREIGN KEY(entity2_id) REFERENCES entities(id),
+            UNIQUE(window_start, entity1_id, entity2_id)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO coupling (id
+                                       , window_start
+                                       , window_end
+                                       , entity1_id
+                                       , entity2_id
+                                       , support
+                                       , confidence)
+                   VALUES (?, ?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &CouplingKey, e: &CouplingExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![
+            id,
+            k.window_start,
+            e.window_end,
+            k.entity1_id,
+            k.entity2_id,
+            e.support,
+            e.confidence
+        ])?)
+    }
+}
+
+// ========================================================
+// Hotspot ------------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct HotspotKey {
+    window_start: i64,
+    entity_id: Id,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, PartialOrd)]
+pub struct HotspotExtra {
+    window_end: i64,
+    changes: f64,
+    churn: usize,
+    size: usize,
+    complexity: usize,
+    score: f64,
+}
+
+pub type HotspotVirtualTable = VirtualTable<HotspotKey, HotspotExtra>;
+
+pub struct HotspotWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, HotspotKey, HotspotExtra> for HotspotWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE hotspots ( -- Entities which change the most relative to their size
+            id INT NOT NULL PRIMARY KEY,
+            window_start INT NOT NULL, -- Seconds since the Unix epoch (inclusive)
+            window_end INT NOT NULL, -- Seconds since the Unix epoch (exclusive)
+            entity_id INT NOT NULL,
+            changes REAL NOT NULL,
+            churn INT NOT NULL, -- Lines added plus lines deleted
+            size INT NOT NULL,
+            complexity INT NOT NULL,
+            score REAL NOT NULL, -- changes times size (or complexity)
+
+            FOREIGN KEY(entity_id) REFERENCES entities(id),
+            UNIQUE(window_start, entity_id)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO hotspots (id
+                                       , window_start
+                                       , window_end
+                                       , entity_id
+                                       , changes
+                                       , churn
+                                       , size
+                                       , complexity
+                                       , score)
+                   VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &HotspotKey, e: &HotspotExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![
+            id,
+            k.window_start,
+            e.window_end,
+            k.entity_id,
+            e.changes,
+            e.churn,
+            e.size,
+            e.complexity,
+            e.score
+        ])?)
+    }
+}
+
+// ========================================================
+// Comparison ---------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ComparisonKey {
+    entity_id: Id,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, PartialOrd)]
+pub struct ComparisonExtra {
+    before_changes: f64,
+    after_changes: f64,
+    before_churn: usize,
+    after_churn: usize,
+    before_degree: usize,
+    after_degree: usize,
+}
+
+pub type ComparisonVirtualTable = VirtualTable<ComparisonKey, ComparisonExtra>;
+
+pub struct ComparisonWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, ComparisonKey, ComparisonExtra> for ComparisonWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE comparison ( -- Changes, churn, and coupling of entities in two periods
+            id INT NOT NULL PRIMARY KEY,
+            entity_id INT NOT NULL UNIQUE,
+            before_changes REAL NOT NULL,
+            after_changes REAL NOT NULL,
+            before_churn INT NOT NULL,
+            after_churn INT NOT NULL,
+            before_degree INT NOT NULL, -- The number of strongly coupled pairs
+            after_degree INT NOT NULL, -- The number of strongly coupled pairs
+
+            FOREIGN KEY(entity_id) REFERENCES entities(id)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO comparison (id
+                                         , entity_id
+                                         , before_changes
+                                         , after_changes
+                                         , before_churn
+                                         , after_churn
+                                         , before_degree
+                                         , after_degree)
+                   VALUES (?, ?, ?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &ComparisonKey, e: &ComparisonExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![
+            id,
+            k.entity_id,
+            e.before_changes,
+            e.after_changes,
+            e.before_churn,
+            e.after_churn,
+            e.before_degree,
+            e.after_degree
+        ])?)
+    }
+}
+
+// ========================================================
+// BulkCommit ---------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BulkCommitKey {
+    commit_id: Id,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BulkCommitExtra {
+    kind: &'static str,
+    num_files: usize,
+    num_entities: usize,
+}
+
+pub type BulkCommitVirtualTable = VirtualTable<BulkCommitKey, BulkCommitExtra>;
+
+pub struct BulkCommitWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, BulkCommitKey, BulkCommitExtra> for BulkCommitWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE bulk_commits ( -- Commits which are likely bulk or refactoring commits
+            id INT NOT NULL PRIMARY KEY,
+            commit_id INT NOT NULL UNIQUE,
+            kind TEXT NOT NULL,
+            num_files INT NOT NULL,
+            num_entities INT NOT NULL,
+
+            FOREIGN KEY(commit_id) REFERENCES commits(id),
+            CHECK(kind IN ('large', 'uniform', 'move', 'reformat'))
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO bulk_commits (id, commit_id, kind, num_files, num_entities)
+                   VALUES (?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &BulkCommitKey, e: &BulkCommitExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![id, k.commit_id, e.kind, e.num_files, e.num_entities])?)
+    }
+}
+
+// ========================================================
+// EntityLifespan -----------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntityLifespanKey {
+    entity_id: Id,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntityLifespanExtra {
+    birth_commit_id: Option<Id>,
+    death_commit_id: Option<Id>,
+    first_seen: i64,
+    last_seen: i64,
+}
+
+pub type EntityLifespanVirtualTable = VirtualTable<EntityLifespanKey, EntityLifespanExtra>;
+
+pub struct EntityLifespanWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, EntityLifespanKey, EntityLifespanExtra> for EntityLifespanWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE entity_lifespans ( -- When each entity was born, seen, and deleted
+            id INT NOT NULL PRIMARY KEY,
+            entity_id INT NOT NULL UNIQUE,
+            birth_commit_id INT, -- The first commit which added the entity
+            death_commit_id INT, -- The last commit which deleted it (unless re-added)
+            first_seen INT NOT NULL, -- Seconds since the Unix epoch
+            last_seen INT NOT NULL, -- Seconds since the Unix epoch
+
+            FOREIGN KEY(entity_id) REFERENCES entities(id),
+            FOREIGN KEY(birth_commit_id) REFERENCES commits(id),
+            FOREIGN KEY(death_commit_id) REFERENCES commits(id)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO entity_lifespans (id
+                                               , entity_id
+                                               , birth_commit_id
+                                               , death_commit_id
+                                               , first_seen
+                                               , last_seen)
+                   VALUES (?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &EntityLifespanKey, e: &EntityLifespanExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![
+            id,
+            k.entity_id,
+            e.birth_commit_id,
+            e.death_commit_id,
+            e.first_seen,
+            e.last_seen
+        ])?)
+    }
+}
+
+// ========================================================
+// EntityMetrics ------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntityMetricsKey {
+    entity_id: Id,
+}
+
+#[derive(Builder, Clone, Debug, Default, PartialEq, PartialOrd)]
+pub struct EntityMetricsExtra {
+    loc: usize,
+    nesting: usize,
+    branches: usize,
+    params: usize,
+    adds: usize,
+    dels: usize,
+    commits: usize,
+    authors: usize,
+    scattering: f64,
+    history_complexity: f64,
+}
+
+pub type EntityMetricsVirtualTable = VirtualTable<EntityMetricsKey, EntityMetricsExtra>;
+
+pub struct EntityMetricsWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, EntityMetricsKey, EntityMetricsExtra> for EntityMetricsWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE entity_metrics ( -- Size, complexity, and churn of each entity
+            id INT NOT NULL PRIMARY KEY,
+            entity_id INT NOT NULL UNIQUE,
+            loc INT NOT NULL, -- Lines at the last commit the entity was present
+            nesting INT NOT NULL,
+            branches INT NOT NULL,
+            params INT NOT NULL,
+            adds INT NOT NULL,
+            dels INT NOT NULL,
+            commits INT NOT NULL, -- The number of commits which changed the entity
+            authors INT NOT NULL,
+            scattering REAL NOT NULL, -- The mean number of files changed by those commits
+            history_complexity REAL NOT NULL, -- The entropy of those commits weighted by the share of the file
+
+            FOREIGN KEY(entity_id) REFERENCES entities(id)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO entity_metrics (id
+                                             , entity_id
+                                             , loc
+                                             , nesting
+                                             , branches
+                                             , params
+                                             , adds
+                                             , dels
+                                             , commits
+                                             , authors
+                                             , scattering
+                                             , history_complexity)
+                   VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &EntityMetricsKey, e: &EntityMetricsExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![
+            id,
+            k.entity_id,
+            e.loc,
+            e.nesting,
+            e.branches,
+            e.params,
+            e.adds,
+            e.dels,
+            e.commits,
+            e.authors,
+            e.scattering,
+            e.history_complexity
+        ])?)
+    }
+}
+
+// ========================================================
+// CommitMetrics ------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CommitMetricsKey {
+    commit_id: Id,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, PartialOrd)]
+pub struct CommitMetricsExtra {
+    files: usize,
+    entities: usize,
+    lines: usize,
+    entropy: f64,
+}
+
+pub type CommitMetricsVirtualTable = VirtualTable<CommitMetricsKey, CommitMetricsExtra>;
+
+pub struct CommitMetricsWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, CommitMetricsKey, CommitMetricsExtra> for CommitMetricsWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE commit_metrics ( -- Size and change entropy of each commit
+            id INT NOT NULL PRIMARY KEY,
+            commit_id INT NOT NULL UNIQUE,
+            files INT NOT NULL, -- The number of changed files
+            entities INT NOT NULL, -- The number of changed entities (including files)
+            lines INT NOT NULL, -- Lines added and deleted in files
+            entropy REAL NOT NULL, -- How evenly the lines are spread across files (0 to 1)
+
+            FOREIGN KEY(commit_id) REFERENCES commits(id)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO commit_metrics (id, commit_id, files, entities, lines, entropy)
+                   VALUES (?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &CommitMetricsKey, e: &CommitMetricsExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![id, k.commit_id, e.files, e.entities, e.lines, e.entropy])?)
+    }
+}
+
+// ========================================================
+// Test Link ----------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TestLinkKey {
+    test_id: Id,
+    entity_id: Id,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, PartialOrd)]
+pub struct TestLinkExtra {
+    by_name: bool,
+    support: f64,
+    confidence: f64,
+}
+
+pub type TestLinkVirtualTable = VirtualTable<TestLinkKey, TestLinkExtra>;
+
+pub struct TestLinkWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, TestLinkKey, TestLinkExtra> for TestLinkWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE test_links ( -- Tests linked to the production entities they test
+            id INT NOT NULL PRIMARY KEY,
+            test_id INT NOT NULL,
+            entity_id INT NOT NULL,
+            by_name INT NOT NULL, -- Whether the test is named after the entity
+            support REAL NOT NULL,
+            confidence REAL NOT NULL,
+
+            FOREIGN KEY(test_id) REFERENCES entities(id),
+            FOREIGN KEY(entity_id) REFERENCES entities(id),
+            UNIQUE(test_id, entity_id)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO test_links (id, test_id, entity_id, by_name, support, confidence)
+                   VALUES (?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &TestLinkKey, e: &TestLinkExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![
+            id,
+            k.test_id,
+            k.entity_id,
+            e.by_name,
+            e.support,
+            e.confidence
+        ])?)
+    }
+}
+
+// ========================================================
+// Clone --------------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CloneKey {
+    entity1_id: Id,
+    entity2_id: Id,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, PartialOrd)]
+pub struct CloneExtra {
+    lines: usize,
+    support: f64,
+    divergent: f64,
+}
+
+pub type CloneVirtualTable = VirtualTable<CloneKey, CloneExtra>;
+
+pub struct CloneWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, CloneKey, CloneExtra> for CloneWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE clones ( -- Entities in different files with the same source
+            id INT NOT NULL PRIMARY KEY,
+            entity1_id INT NOT NULL,
+            entity2_id INT NOT NULL,
+            lines INT NOT NULL, -- The number of lines of either entity
+            support REAL NOT NULL, -- How often both entities changed together
+            divergent REAL NOT NULL, -- How often only one of the entities changed
+
+            FOREIGN KEY(entity1_id) REFERENCES entities(id),
+            FOREIGN KEY(entity2_id) REFERENCES entities(id),
+            UNIQUE(entity1_id, entity2_id)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO clones (id, entity1_id, entity2_id, lines, support, divergent)
+                   VALUES (?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &CloneKey, e: &CloneExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![
+            id,
+            k.entity1_id,
+            k.entity2_id,
+            e.lines,
+            e.support,
+            e.divergent
+        ])?)
+    }
+}
+
+// ========================================================
+// Divergent Change ---------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DivergentChangeKey {
+    entity_id: Id,
+    partner_id: Id,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, PartialOrd)]
+pub struct DivergentChangeExtra {
+    cluster: usize,
+    support: f64,
+}
+
+pub type DivergentChangeVirtualTable = VirtualTable<DivergentChangeKey, DivergentChangeExtra>;
+
+pub struct DivergentChangeWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, DivergentChangeKey, DivergentChangeExtra> for DivergentChangeWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE divergent_changes ( -- Partners of entities which change for unrelated reasons
+            id INT NOT NULL PRIMARY KEY,
+            entity_id INT NOT NULL,
+            partner_id INT NOT NULL,
+            cluster INT NOT NULL, -- The group of the partner (0 is the largest)
+            support REAL NOT NULL,
+
+            FOREIGN KEY(entity_id) REFERENCES entities(id),
+            FOREIGN KEY(partner_id) REFERENCES entities(id),
+            UNIQUE(entity_id, partner_id)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO divergent_changes (id, entity_id, partner_id, cluster, support)
+                   VALUES (?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(
+        &mut self,
+        id: Id,
+        k: &DivergentChangeKey,
+        e: &DivergentChangeExtra,
+    ) -> Result<usize> {
+        Ok(self.stmt.execute(params![id, k.entity_id, k.partner_id, e.cluster, e.support])?)
+    }
+}
+
+// ========================================================
+// Cluster ------------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClusterKey {
+    entity_id: Id,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClusterExtra {
+    cluster: usize,
+    package: Option<String>,
+    misplaced: Option<bool>,
+}
+
+pub type ClusterVirtualTable = VirtualTable<ClusterKey, ClusterExtra>;
+
+pub struct ClusterWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, ClusterKey, ClusterExtra> for ClusterWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE clusters ( -- Groups of entities which co-change (proposed modules)
+            id INT NOT NULL PRIMARY KEY,
+            entity_id INT NOT NULL,
+            cluster INT NOT NULL, -- Numbered from 0 by decreasing size
+            package TEXT, -- The directory of the file of the entity (if any)
+            misplaced INT, -- Whether the package differs from the main package of the cluster
+
+            FOREIGN KEY(entity_id) REFERENCES entities(id),
+            UNIQUE(entity_id)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO clusters (id, entity_id, cluster, package, misplaced)
+                   VALUES (?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &ClusterKey, e: &ClusterExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![id, k.entity_id, e.cluster, e.package, e.misplaced])?)
+    }
+}
+
+// ========================================================
+// Interface ----------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct InterfaceKey {
+    entity_id: Id,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, PartialOrd)]
+pub struct InterfaceExtra {
+    fan_in: usize,
+    changes: f64,
+    churn: usize,
+    class: String,
+}
+
+pub type InterfaceVirtualTable = VirtualTable<InterfaceKey, InterfaceExtra>;
+
+pub struct InterfaceWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, InterfaceKey, InterfaceExtra> for InterfaceWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE interfaces ( -- Entities with many dependents and how often they change
+            id INT NOT NULL PRIMARY KEY,
+            entity_id INT NOT NULL,
+            fan_in INT NOT NULL, -- The number of entities which depend on this one
+            changes REAL NOT NULL,
+            churn INT NOT NULL,
+            class TEXT NOT NULL, -- volatile or stable
+
+            FOREIGN KEY(entity_id) REFERENCES entities(id),
+            UNIQUE(entity_id)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO interfaces (id, entity_id, fan_in, changes, churn, class)
+                   VALUES (?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &InterfaceKey, e: &InterfaceExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![id, k.entity_id, e.fan_in, e.changes, e.churn, e.class])?)
+    }
+}
+
+// ========================================================
+// Knowledge Loss -----------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct KnowledgeLossKey {
+    entity_id: Id,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, PartialOrd)]
+pub struct KnowledgeLossExtra {
+    lost: f64,
+    surviving: f64,
+}
+
+pub type KnowledgeLossVirtualTable = VirtualTable<KnowledgeLossKey, KnowledgeLossExtra>;
+
+pub struct KnowledgeLossWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, KnowledgeLossKey, KnowledgeLossExtra> for KnowledgeLossWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE knowledge_loss ( -- Entities left with too little ownership if authors leave
+            id INT NOT NULL PRIMARY KEY,
+            entity_id INT NOT NULL,
+            lost REAL NOT NULL, -- The share of the departing authors
+            surviving REAL NOT NULL, -- The share of the remaining authors
+
+            FOREIGN KEY(entity_id) REFERENCES entities(id),
+            UNIQUE(entity_id)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql =
+            "INSERT INTO knowledge_loss (id, entity_id, lost, surviving) VALUES (?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &KnowledgeLossKey, e: &KnowledgeLossExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![id, k.entity_id, e.lost, e.surviving])?)
+    }
+}
+
+// ========================================================
+// Run ----------------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RunKey {
+    started_at: i64,
+}
+
+#[derive(Builder, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RunExtra {
+    command: String,
+    version: String,
+    args: String,
+    #[builder(default)]
+    repo_path: Option<String>,
+    #[builder(default)]
+    repo_url: Option<String>,
+    #[builder(default)]
+    head_sha1: Option<String>,
+    finished_at: i64,
+    phases: String,
+    /// What was parsed of each language (as JSON)
+    languages: String,
+    /// How the commits were sampled (if they were)
+    #[builder(default)]
+    sampling: Option<String>,
+    /// The first commit which was not extracted because the run stopped early
+    #[builder(default)]
+    stopped_at: Option<String>,
+    /// Why the run stopped early (e.g. `time_budget` or `interrupted`)
+    #[builder(default)]
+    stop_reason: Option<String>,
+}
+
+pub type RunVirtualTable = VirtualTable<RunKey, RunExtra>;
+
+pub struct RunWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, RunKey, RunExtra> for RunWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE runs ( -- Every run of the tool which wrote to the database
+            id INT NOT NULL PRIMARY KEY,
+            command TEXT NOT NULL,
+            version TEXT NOT NULL,
+            args TEXT NOT NULL, -- The command line as a JSON array
+            repo_path TEXT,
+            repo_url TEXT,
+            head_sha1 CHAR(40),
+            started_at INT NOT NULL, -- Seconds since the Unix epoch
+            finished_at INT NOT NULL, -- Seconds since the Unix epoch
+            phases TEXT NOT NULL, -- The duration of each phase as JSON
+            languages TEXT NOT NULL, -- What was parsed of each language as JSON
+            sampling TEXT,
+            stopped_at CHAR(40), -- The first commit which was not extracted (if any)
+            stop_reason TEXT -- commit_budget, time_budget, or interrupted
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO runs (id
+                                   , command
+                                   , version
+                                   , args
+                                   , repo_path
+                                   , repo_url
+                                   , head_sha1
+                                   , started_at
+                                   , finished_at
+                                   , phases
+                                   , languages
+                                   , sampling
+                                   , stopped_at
+                                   , stop_reason)
+                   VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &RunKey, e: &RunExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![
+            id,
+            e.command,
+            e.version,
+            e.args,
+            e.repo_path,
+            e.repo_url,
+            e.head_sha1,
+            k.started_at,
+            e.finished_at,
+            e.phases,
+            e.languages,
+            e.sampling,
+            e.stopped_at,
+            e.stop_reason
+        ])?)
+    }
+}
+
+// ========================================================
+// Run Metrics --------------------------------------------
+// ========================================================
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RunMetricKey {
+    scope: &'static str,
+    phase: &'static str,
+    subject: Option<String>,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RunMetricExtra {
+    ms: usize,
+    count: Option<usize>,
+}
+
+pub type RunMetricVirtualTable = VirtualTable<RunMetricKey, RunMetricExtra>;
+
+pub struct RunMetricWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, RunMetricKey, RunMetricExtra> for RunMetricWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE run_metrics ( -- How long each phase, commit, and file took
+            id INT NOT NULL PRIMARY KEY,
+            scope TEXT NOT NULL, -- phase, commit, or file
+            phase TEXT NOT NULL,
+            subject TEXT,
+            ms INT NOT NULL,
+            count INT,
+
+            UNIQUE(scope, phase, subject),
+            CHECK(scope = 'phase' OR scope = 'commit' OR scope = 'file')
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql =
+            "INSERT INTO run_metrics (id, scope, phase, subject, ms, count) VALUES (?, ?, ?, \
+                   ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &RunMetricKey, e: &RunMetricExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![id, k.scope, k.phase, k.subject, e.ms, e.count])?)
+    }
+}
+
+// ========================================================
+// Meta Schema --------------------------------------------
+// ========================================================
+
+/// A table (if the column is missing) or a column of a table.
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MetaSchemaKey {
+    table_name: String,
+    column_name: Option<String>,
+}
+
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MetaSchemaExtra {
+    /// The declared type (of a column)
+    kind: Option<String>,
+    description: Option<String>,
+}
+
+pub type MetaSchemaVirtualTable = VirtualTable<MetaSchemaKey, MetaSchemaExtra>;
+
+pub struct MetaSchemaWriter<'a> {
+    stmt: CachedStatement<'a>,
+}
+
+impl<'a> SqlWriter<'a, MetaSchemaKey, MetaSchemaExtra> for MetaSchemaWriter<'a> {
+    fn create_table_script() -> &'static str {
+        "CREATE TABLE meta_schema ( -- Every table the tool may write and its columns
+            id INT NOT NULL PRIMARY KEY,
+            table_name TEXT NOT NULL,
+            column_name TEXT, -- NULL for the row describing the table itself
+            type TEXT, -- The declared type of the column
+            description TEXT,
+
+            UNIQUE(table_name, column_name)
+        ) WITHOUT ROWID;"
+    }
+
+    fn prepare(conn: &'a Connection) -> Result<Self> {
+        let sql = "INSERT INTO meta_schema (id, table_name, column_name, type, description) \
+                   VALUES (?, ?, ?, ?, ?);";
+        Ok(Self { stmt: conn.prepare_cached(sql)? })
+    }
+
+    fn execute(&mut self, id: Id, k: &MetaSchemaKey, e: &MetaSchemaExtra) -> Result<usize> {
+        Ok(self.stmt.execute(params![id, k.table_name, k.column_name, e.kind, e.description])?)
+    }
+}
+
+// ========================================================
+// Database -----------------------------------------------
+// ========================================================
+
+#[derive(Debug)]
+pub struct VirtualDb {
+    pub entity_vt: EntityVirtualTable,
+    pub commit_vt: CommitVirtualTable,
+    pub ref_vt: RefVirtualTable,
+    pub change_vt: ChangeVirtualTable,
+    // pub range_vt: RangeVirtualTable,
+    pub presence_vt: PresenceVirtualTable,
+    pub reachability_vt: ReachabilityVirtualTable,
+    pub commit_ref_vt: CommitRefVirtualTable,
+    pub commit_coverage_vt: CommitCoverageVirtualTable,
+    pub tag_vt: TagVirtualTable,
+    pub ref_history_vt: RefHistoryVirtualTable,
+    pub duplicate_vt: DuplicateVirtualTable,
+    pub revert_vt: RevertVirtualTable,
+    pub submodule_update_vt: SubmoduleUpdateVirtualTable,
+    pub symlink_update_vt: SymlinkUpdateVirtualTable,
+    pub diagnostic_vt: DiagnosticVirtualTable,
+    pub parse_error_vt: ParseErrorVirtualTable,
+    pub generated_entity_vt: GeneratedEntityVirtualTable,
+    pub entity_source_vt: EntitySourceVirtualTable,
+    pub change_lines_vt: ChangeLinesVirtualTable,
+    pub doc_change_vt: DocChangeVirtualTable,
+    pub hunk_vt: HunkVirtualTable,
+    pub hunk_entity_vt: HunkEntityVirtualTable,
+    /// Whether the directories of each file are inserted as entities above it
+    dir_entities: bool,
+    /// The path each file is inserted under if it differs from its own (e.g.
+    /// to merge paths which differ only by case)
+    path_aliases: HashMap<String, String>,
+    /// How many rows of each table are written per transaction (if limited)
+    txn_size: Option<usize>,
+}
+
+impl Default for VirtualDb {
+    /// The same change may be found more than once (e.g. by different groups of
+    /// hunks), so the lines of repeated changes are summed rather than dropped.
+    /// The flags of a commit are the union of those it was inserted with.
+    fn default() -> Self {
+        Self {
+            entity_vt: Default::default(),
+            commit_vt: VirtualTable::with_conflict(Conflict::Merge(CommitExtra::merge)),
+            ref_vt: Default::default(),
+            change_vt: VirtualTable::with_conflict(Conflict::Merge(ChangeExtra::merge)),
+            presence_vt: VirtualTable::with_conflict(Conflict::Merge(PresenceExtra::merge)),
+            reachability_vt: Default::default(),
+            commit_ref_vt: Default::default(),
+            commit_coverage_vt: Default::default(),
+            tag_vt: Default::default(),
+            ref_history_vt: Default::default(),
+            duplicate_vt: Default::default(),
+            revert_vt: Default::default(),
+            submodule_update_vt: Default::default(),
+            symlink_update_vt: Default::default(),
+            diagnostic_vt: Default::default(),
+            parse_error_vt: Default::default(),
+            generated_entity_vt: Default::default(),
+            entity_source_vt: Default::default(),
+            change_lines_vt: Default::default(),
+            doc_change_vt: VirtualTable::with_conflict(Conflict::Merge(DocChangeExtra::merge)),
+            hunk_vt: Default::default(),
+            hunk_entity_vt: VirtualTable::with_conflict(Conflict::Merge(HunkEntityExtra::merge)),
+            dir_entities: false,
+            path_aliases: HashMap::new(),
+            txn_size: None,
+        }
+    }
+}
+
+impl VirtualDb {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_dir_entities(&mut self, dir_entities: bool) {
+        self.dir_entities = dir_entities;
+    }
+
+    pub fn set_path_aliases(&mut self, path_aliases: HashMap<String, String>) {
+        self.path_aliases = path_aliases;
+    }
+
+    pub fn set_txn_size(&mut self, txn_size: Option<usize>) {
+        self.txn_size = txn_size;
+    }
+
+    /// Writes every table, parents (e.g. entities and commits) before the rows
+    /// which refer to them. Under a transaction size, each table is written in
+    /// transactions of its own (see `VirtualTable::write_chunked`).
+    pub fn write(self, conn: &Connection) -> Result<()> {
+        let txn_size = self.txn_size;
+        self.entity_vt.write_chunked::<EntityWriter>(conn, txn_size)?;
+        self.commit_vt.write_chunked::<CommitWriter>(conn, txn_size)?;
+        self.ref_vt.write_chunked::<RefWriter>(conn, txn_size)?;
+        self.change_vt.write_chunked::<ChangeWriter>(conn, txn_size)?;
+        // self.range_vt.write::<RangeWriter>(conn)?;
+        self.presence_vt.write_chunked::<PresenceWriter>(conn, txn_size)?;
+        self.reachability_vt.write_chunked::<ReachabilityWriter>(conn, txn_size)?;
+        self.commit_ref_vt.write_chunked::<CommitRefWriter>(conn, txn_size)?;
+        self.commit_coverage_vt.write_chunked::<CommitCoverageWriter>(conn, txn_size)?;
+        self.tag_vt.write_chunked::<TagWriter>(conn, txn_size)?;
+        self.ref_history_vt.write_chunked::<RefHistoryWriter>(conn, txn_size)?;
+        self.duplicate_vt.write_chunked::<DuplicateWriter>(conn, txn_size)?;
+        self.revert_vt.write_chunked::<RevertWriter>(conn, txn_size)?;
+        self.submodule_update_vt.write_chunked::<SubmoduleUpdateWriter>(conn, txn_size)?;
+        self.symlink_update_vt.write_chunked::<SymlinkUpdateWriter>(conn, txn_size)?;
+        self.diagnostic_vt.write_chunked::<DiagnosticWriter>(conn, txn_size)?;
+        self.parse_error_vt.write_chunked::<ParseErrorWriter>(conn, txn_size)?;
+        self.generated_entity_vt.write_chunked::<GeneratedEntityWriter>(conn, txn_size)?;
+        self.entity_source_vt.write_chunked::<EntitySourceWriter>(conn, txn_size)?;
+        self.change_lines_vt.write_chunked::<ChangeLinesWriter>(conn, txn_size)?;
+        self.doc_change_vt.write_chunked::<DocChangeWriter>(conn, txn_size)?;
+        self.hunk_vt.write_chunked::<HunkWriter>(conn, txn_size)?;
+        self.hunk_entity_vt.write_chunked::<HunkEntityWriter>(conn, txn_size)?;
+        Ok(())
+    }
+
+    /// Writes the rows inserted so far of the tables which are filled one
+    /// commit at a time (e.g. changes and presences) to free up their memory.
+    /// Entities and commits are kept, as later rows still refer to them. Only
+    /// flush between commits, so that no flushed row is inserted again.
+    pub fn flush(&mut self, conn: &Connection) -> Result<()> {
+        let txn_size = self.txn_size;
+        self.change_vt.flush::<ChangeWriter>(conn, txn_size)?;
+        self.presence_vt.flush::<PresenceWriter>(conn, txn_size)?;
+        self.symlink_update_vt.flush::<SymlinkUpdateWriter>(conn, txn_size)?;
+        self.entity_source_vt.flush::<EntitySourceWriter>(conn, txn_size)?;
+        self.change_lines_vt.flush::<ChangeLinesWriter>(conn, txn_size)?;
+        self.doc_change_vt.flush::<DocChangeWriter>(conn, txn_size)?;
+        self.hunk_vt.flush::<HunkWriter>(conn, txn_size)?;
+        self.hunk_entity_vt.flush::<HunkEntityWriter>(conn, txn_size)?;
+        Ok(())
+    }
+}
+
+/// Drops the given table so an analysis can be re-run on the same database.
+pub fn drop_table(tx: &Transaction, name: &str) -> Result<()> {
+    tx.execute(&format!("DROP TABLE IF EXISTS {};", name), params![])?;
+    Ok(())
+}
+
+/// Describes every table the tool may write (whether or not it has been yet)
+/// and its columns in the meta_schema table. The descriptions are the comments
+/// of the `CREATE TABLE` scripts, which SQLite also keeps in `sqlite_master`.
+pub fn write_meta_schema(tx: &Transaction) -> Result<()> {
+    let scripts = [
+        EntityWriter::create_table_script(),
+        CommitWriter::create_table_script(),
+        RefWriter::create_table_script(),
+        ChangeWriter::create_table_script(),
+        PresenceWriter::create_table_script(),
+        DepWriter::create_table_script(),
+        PullRequestWriter::create_table_script(),
+        PullRequestCommitWriter::create_table_script(),
+        PullRequestLabelWriter::create_table_script(),
+        PullRequestReviewerWriter::create_table_script(),
+        IssueWriter::create_table_script(),
+        CommitIssueWriter::create_table_script(),
+        ReachabilityWriter::create_table_script(),
+        TagWriter::create_table_script(),
+        RefHistoryWriter::create_table_script(),
+        CommitRefWriter::create_table_script(),
+        CommitCoverageWriter::create_table_script(),
+        DuplicateWriter::create_table_script(),
+        RevertWriter::create_table_script(),
+        SubmoduleUpdateWriter::create_table_script(),
+        SymlinkUpdateWriter::create_table_script(),
+        DiagnosticWriter::create_table_script(),
+        ChangeLinesWriter::create_table_script(),
+        DocChangeWriter::create_table_script(),
+        HunkWriter::create_table_script(),
+        HunkEntityWriter::create_table_script(),
+        ParseErrorWriter::create_table_script(),
+        GeneratedEntityWriter::create_table_script(),
+        EntitySourceWriter::create_table_script(),
+        HiddenDepWriter::create_table_script(),
+        DepCochangeWriter::create_table_script(),
+        OwnershipWriter::create_table_script(),
+        BusFactorWriter::create_table_script(),
+        CouplingWriter::create_table_script(),
+        HotspotWriter::create_table_script(),
+        ComparisonWriter::create_table_script(),
+        BulkCommitWriter::create_table_script(),
+        EntityLifespanWriter::create_table_script(),
+        EntityMetricsWriter::create_table_script(),
+        CommitMetricsWriter::create_table_script(),
+        TestLinkWriter::create_table_script(),
+        CloneWriter::create_table_script(),
+        DivergentChangeWriter::create_table_script(),
+        ClusterWriter::create_table_script(),
+        InterfaceWriter::create_table_script(),
+        KnowledgeLossWriter::create_table_script(),
+        RunWriter::create_table_script(),
+        RunMetricWriter::create_table_script(),
+        MetaSchemaWriter::create_table_script(),
+    ];
+    let mut vt = MetaSchemaVirtualTable::new();
+
+    for script in scripts {
+        insert_table_schema(&mut vt, script)?;
+    }
+
+    drop_table(tx, "meta_schema")?;
+    vt.write::<MetaSchemaWriter>(tx)
+}
+
+/// Inserts the table of a `CREATE TABLE` script and each of its columns along
+/// with the comment (if any) at the end of their lines. Lines which start with
+/// an uppercase keyword (e.g. `UNIQUE`) are constraints rather than columns.
+fn insert_table_schema(vt: &mut MetaSchemaVirtualTable, script: &str) -> Result<()> {
+    let mut lines = script.lines().map(|line| match line.split_once("--") {
+        Some((code, comment)) => (code.trim(), Some(comment.trim().to_string())),
+        None => (line.trim(), None),
+    });
+
+    let (header, description) = lines.next().unwrap_or_default();
+    let table = match header.strip_prefix("CREATE TABLE ") {
+        Some(rest) => rest.trim_end_matches('(').trim().to_string(),
+        None => bail!("expected a CREATE TABLE script but got: {}", header),
+    };
+    vt.insert(MetaSchemaKey::new(table.clone(), None), MetaSchemaExtra::new(None, description));
+
+    for (code, description) in lines {
+        let mut words = code.split_whitespace();
+        let column = words.next().filter(|w| w.starts_with(|c: char| c.is_ascii_lowercase()));
+
+        if let Some(column) = column {
+            let kind = words.next().map(|kind| kind.trim_end_matches(',').to_string());
+            let key = MetaSchemaKey::new(table.clone(), Some(column.to_string()));
+            vt.insert(key, MetaSchemaExtra::new(kind, description));
+        }
+    }
+
+    Ok(())
+}
+
+/// Derives the lifespan of each entity from its changes and presences.
+pub fn write_lifespans(tx: &Transaction) -> Result<()> {
+    let mut vt = EntityLifespanVirtualTable::new();
+
+    for l in find_lifespans(tx)? {
+        let key = EntityLifespanKey::new(l.entity_id);
+        let extra = EntityLifespanExtra::new(
+            l.birth_commit_id,
+            l.death_commit_id,
+            l.first_seen,
+            l.last_seen,
+        );
+        vt.insert(key, extra);
+    }
+
+    drop_table(tx, "entity_lifespans")?;
+    vt.write::<EntityLifespanWriter>(tx)?;
+    Ok(())
+}
+
+/// Derives the size, complexity, and change metrics of each entity.
+pub fn write_entity_metrics(tx: &Transaction) -> Result<()> {
+    let mut vt = EntityMetricsVirtualTable::new();
+
+    for m in find_entity_metrics(tx)? {
+        let key = EntityMetricsKey::new(m.entity_id);
+        let extra = EntityMetricsExtraBuilder::default()
+            .loc(m.loc)
+            .nesting(m.nesting)
+            .branches(m.branches)
+            .params(m.params)
+            .adds(m.adds)
+            .dels(m.dels)
+            .commits(m.commits)
+            .authors(m.authors)
+            .scattering(m.scattering)
+            .history_complexity(m.history_complexity)
+            .build()?;
+        vt.insert(key, extra);
+    }
+
+    drop_table(tx, "entity_metrics")?;
+    vt.write::<EntityMetricsWriter>(tx)?;
+    Ok(())
+}
+
+/// Derives the size and change entropy of each commit.
+pub fn write_commit_metrics(tx: &Transaction) -> Result<()> {
+    let mut vt = CommitMetricsVirtualTable::new();
+
+    for m in find_commit_metrics(tx)? {
+        let extra = CommitMetricsExtra::new(m.files, m.entities, m.lines, m.entropy);
+        vt.insert(CommitMetricsKey::new(m.commit_id), extra);
+    }
+
+    drop_table(tx, "commit_metrics")?;
+    vt.write::<CommitMetricsWriter>(tx)?;
+    Ok(())
+}
+
+/// Derives a stable identifier for an entity from the name, kind, and
+/// discriminator of it and each of its ancestors. Unlike ids, these keys are the
+/// same in every database, so databases can be merged or compared by them.
+pub fn to_entity_key(path: &[(String, Arc<String>, String)]) -> Result<String> {
+    let mut text = String::new();
+
+    for (name, kind, disc) in path {
+        text.push_str(&format!("{}\0{}\0{}\n", name, kind, disc));
+    }
+
+    Ok(git2::Oid::hash_object(git2::ObjectType::Blob, text.as_bytes())?.to_string())
+}
+
+/// The directories above the given file (outermost first). Each directory is
+/// named by its full path, just like files are.
+fn to_dirs(filename: &str) -> Vec<(String, Arc<String>, String)> {
+    let kind = Arc::new("dir".to_string());
+    filename
+        .match_indices('/')
+        .map(|(i, _)| (filename[..i].to_string(), kind.clone(), String::new()))
+        .collect()
+}
+
+/// Finds the entity with the given parent, inserting it if it is new. The key
+/// is derived from `path`, which ends with the entity itself.
+fn get_or_insert_entity(
+    db: &mut VirtualDb,
+    parent_id: Option<Id>,
+    path: &[(String, Arc<String>, String)],
+) -> Result<Id> {
+    let (name, kind, disc) = path.last().unwrap();
+    let key = EntityKey::new(parent_id, name.clone(), kind.clone(), disc.clone());
+
+    Ok(match db.entity_vt.get_id(&key) {
+        Some(id) => id,
+        None => db.entity_vt.insert(key, EntityExtra::new(to_entity_key(path)?)),
+    })
+}
+
+/// Inserts the entity with the given path (outermost first) and its ancestors.
+/// Directories are left out of the keys of the entities below them, so each
+/// entity has the same key with or without directory entities.
+fn insert_entity_path(db: &mut VirtualDb, path: &[(String, Arc<String>, String)]) -> Result<Id> {
+    let mut prev_id = None;
+
+    if db.dir_entities {
+        for dir in to_dirs(&path[0].0) {
+            prev_id = Some(get_or_insert_entity(db, prev_id, &[dir])?);
+        }
+    }
+
+    for i in 0..path.len() {
+        prev_id = Some(get_or_insert_entity(db, prev_id, &path[..=i])?);
+    }
+
+    Ok(prev_id.unwrap())
+}
+
+pub fn insert_entity<E: Borrow<Entity>>(db: &mut VirtualDb, entity: E) -> Result<Id> {
+    let mut path = entity.borrow().to_vec();
+
+    if let Some(alias) = db.path_aliases.get(&path[0].0) {
+        path[0].0 = alias.clone();
+    }
+
+    insert_entity_path(db, &path)
+}
+
+pub fn insert_commit(db: &mut VirtualDb, commit: &Commit) -> Result<Id> {
+    insert_commit_info(db, commit, CommitInfo::empty())
+}
+
+/// Inserts a commit along with which phases of the extraction ran for it (e.g.
+/// `CommitInfo::CHANGES` once all of its changes are known).
+pub fn insert_commit_info(db: &mut VirtualDb, commit: &Commit, info: CommitInfo) -> Result<Id> {
+    let key = CommitKey::new(commit.sha1.to_string());
+    let extra = CommitExtra::new(
+        commit.is_merge,
+        commit.author_name.clone(),
+        commit.author_mail.clone(),
+        commit.author_date.unix_timestamp(),
+        commit.commit_date.unix_timestamp(),
+        commit.message.to_string(),
+        info,
+    );
+    Ok(db.commit_vt.insert(key, extra))
+}
+
+/// Like `insert_commit_info`, but also records the settings which each of the
+/// phases ran with.
+pub fn insert_commit_coverage(
+    db: &mut VirtualDb,
+    commit: &Commit,
+    info: CommitInfo,
+    coverage: &Coverage,
+) -> Result<Id> {
+    let commit_id = insert_commit_info(db, commit, info)?;
+
+    for (flag, phase) in [(CommitInfo::CHANGES, 'C'), (CommitInfo::PRESENCE, 'P')] {
+        if info.contains(flag) {
+            let key = CommitCoverageKey::new(
+                commit_id,
+                phase,
+                coverage.files.clone(),
+                coverage.granularity.clone(),
+                coverage.languages.join(","),
+                coverage.excluded.join(","),
+            );
+            db.commit_coverage_vt.insert(key, NullExtra);
+        }
+    }
+
+    Ok(commit_id)
+}
+
+pub fn insert_change(db: &mut VirtualDb, change: &Change) -> Result<Id> {
+    let commit_id = insert_commit(db, &change.commit)?;
+    let entity_id = insert_entity(db, change.entity.clone())?;
+
+    let change_key = ChangeKey::new(commit_id, entity_id, change.parent);
+    let change_extra = ChangeExtra::new(change.kind, change.adds, change.dels);
+
+    Ok(db.change_vt.insert(change_key, change_extra))
+}
+
+pub fn insert_presence(db: &mut VirtualDb, presence: &Presence) -> Result<Id> {
+    let commit_id = insert_commit(db, &presence.commit)?;
+    let entity_id = insert_entity(db, presence.loc_entity.entity.clone())?;
+
+    let interval = presence.loc_entity.loc;
+    let complexity = presence.loc_entity.complexity;
+
+    let presence_key = PresenceKey::new(commit_id, entity_id);
+    let presence_extra = PresenceExtra::new(
+        interval.0,
+        interval.1,
+        complexity.nesting,
+        complexity.branches,
+        complexity.params,
+    );
+
+    Ok(db.presence_vt.insert(presence_key, presence_extra))
+}
+
+pub fn insert_ref(db: &mut VirtualDb, r#ref: &Ref) -> Result<Id> {
+    let commit_id = insert_commit(db, &r#ref.commit)?;
+
+    let ref_key = RefKey::new(r#ref.name.clone());
+    let ref_extra = RefExtra::new(commit_id);
+
+    Ok(db.ref_vt.insert(ref_key, ref_extra))
+}
+
+pub fn insert_tag(db: &mut VirtualDb, tag: &Tag) -> Result<Id> {
+    let commit_id = insert_commit(db, &tag.commit)?;
+
+    let key = TagKey::new(tag.name.clone());
+    let extra = TagExtra::new(
+        commit_id,
+        tag.tagger_name.clone(),
+        tag.tagger_mail.clone(),
+        tag.tag_date.map(|d| d.unix_timestamp()),
+        tag.message.clone(),
+    );
+
+    Ok(db.tag_vt.insert(key, extra))
+}
+
+pub fn insert_ref_update(db: &mut VirtualDb, update: &RefUpdate) -> Result<Id> {
+    let key = RefHistoryKey::new(update.name.clone(), update.seq);
+    let extra = RefHistoryExtra::new(
+        update.old.map(|oid| oid.to_string()),
+        update.new.to_string(),
+        update.time.unix_timestamp(),
+        update.message.clone(),
+    );
+
+    Ok(db.ref_history_vt.insert(key, extra))
+}
+
+pub fn insert_ref_member(db: &mut VirtualDb, member: &RefMember) -> Result<Id> {
+    let commit_id = insert_commit(db, &member.commit)?;
+    let ref_id = insert_ref(db, &member.r#ref)?;
+
+    Ok(db.commit_ref_vt.insert(CommitRefKey::new(commit_id, ref_id), NullExtra))
+}
+
+pub fn insert_duplicate(db: &mut VirtualDb, duplicate: &Duplicate) -> Result<Id> {
+    let commit_id = insert_commit(db, &duplicate.commit)?;
+    let original_id = insert_commit(db, &duplicate.original)?;
+
+    let key = DuplicateKey::new(commit_id);
+    let extra = DuplicateExtra::new(original_id, duplicate.patch_id.to_string());
+
+    Ok(db.duplicate_vt.insert(key, extra))
+}
+
+pub fn insert_revert(db: &mut VirtualDb, revert: &Revert) -> Result<Id> {
+    let commit_id = insert_commit(db, &revert.commit)?;
+    let reverted_id = insert_commit(db, &revert.reverted)?;
+
+    let key = RevertKey::new(commit_id, reverted_id);
+    let extra = RevertExtra::new(revert.kind.to_str());
+
+    Ok(db.revert_vt.insert(key, extra))
+}
+
+pub fn insert_submodule_update(db: &mut VirtualDb, update: &SubmoduleUpdate) -> Result<Id> {
+    let commit_id = insert_commit(db, &update.commit)?;
+
+    let key = SubmoduleUpdateKey::new(commit_id, update.parent, update.path.clone());
+    let extra = SubmoduleUpdateExtra::new(
+        update.old.map(|oid| oid.to_string()),
+        update.new.map(|oid| oid.to_string()),
+    );
+
+    Ok(db.submodule_update_vt.insert(key, extra))
+}
+
+pub fn insert_symlink_update(db: &mut VirtualDb, update: &SymlinkUpdate) -> Result<Id> {
+    let commit_id = insert_commit(db, &update.commit)?;
+
+    let key = SymlinkUpdateKey::new(commit_id, update.parent, update.path.clone());
+    let extra = SymlinkUpdateExtra::new(update.old_target.clone(), update.new_target.clone());
+
+    Ok(db.symlink_update_vt.insert(key, extra))
+}
+
+pub fn insert_diagnostic(db: &mut VirtualDb, diagnostic: &Diagnostic) -> Result<Id> {
+    let commit_id = diagnostic.commit.as_ref().map(|c| insert_commit(db, c)).transpose()?;
+
+    let key =
+        DiagnosticKey::new(commit_id, diagnostic.kind.to_string(), diagnostic.subject.clone());
+    let extra = DiagnosticExtra::new(diagnostic.message.clone());
+
+    Ok(db.diagnostic_vt.insert(key, extra))
+}
+
+/// Compresses lines given as intervals (each from its first line to past its
+/// last) into a list of inclusive ranges such as `3-5,9`.
+fn to_line_list(intervals: &[Interval]) -> String {
+    let mut intervals = intervals.to_vec();
+    intervals.sort();
+    let mut merged: Vec<Interval> = Vec::new();
+
+    for Interval(start, end) in intervals {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push(Interval(start, end)),
+        }
+    }
+
+    merged
+        .iter()
+        .map(|i| match i.1 - i.0 {
+            1 => i.0.to_string(),
+            _ => format!("{}-{}", i.0, i.1 - 1),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+pub fn insert_changed_lines(db: &mut VirtualDb, lines: &ChangedLines) -> Result<Id> {
+    let commit_id = insert_commit(db, &lines.commit)?;
+    let entity_id = insert_entity(db, lines.entity.clone())?;
+
+    let key = ChangeLinesKey::new(commit_id, entity_id, lines.parent);
+    let extra = ChangeLinesExtra::new(to_line_list(&lines.added), to_line_list(&lines.deleted));
+
+    Ok(db.change_lines_vt.insert(key, extra))
+}
+
+pub fn insert_doc_change(db: &mut VirtualDb, change: &DocChange) -> Result<Id> {
+    let commit_id = insert_commit(db, &change.commit)?;
+    let entity_id = insert_entity(db, change.entity.clone())?;
+
+    let key = DocChangeKey::new(commit_id, entity_id, change.parent);
+    let extra = DocChangeExtra::new(change.doc_adds, change.doc_dels, change.touched);
+
+    Ok(db.doc_change_vt.insert(key, extra))
+}
+
+pub fn insert_mapped_hunk(db: &mut VirtualDb, mapped: &MappedHunk) -> Result<Id> {
+    let commit_id = insert_commit(db, &mapped.commit)?;
+    let to_end = |end: usize| Some(end).filter(|&e| e != usize::MAX);
+    let (old, new) = (mapped.hunk.old_interval, mapped.hunk.new_interval);
+
+    let key = HunkKey::new(commit_id, mapped.parent, mapped.filename.clone(), mapped.ordinal);
+    let extra = HunkExtra::new(old.0, to_end(old.1), new.0, to_end(new.1));
+    let hunk_id = db.hunk_vt.insert(key, extra);
+
+    for (entity, adds, dels) in &mapped.entities {
+        let entity_id = insert_entity(db, entity.clone())?;
+        let key = HunkEntityKey::new(hunk_id, entity_id);
+        db.hunk_entity_vt.insert(key, HunkEntityExtra::new(*adds, *dels));
+    }
+
+    Ok(hunk_id)
+}
+
+pub fn insert_parse_error(db: &mut VirtualDb, error: &ParseError) -> Result<Id> {
+    let key = ParseErrorKey::new(error.filename.clone(), error.blob.to_string());
+    let extra = ParseErrorExtra::new(error.errors, error.error_bytes, error.bytes, error.skipped);
+
+    Ok(db.parse_error_vt.insert(key, extra))
+}
+
+pub fn insert_generated_entity(db: &mut VirtualDb, generated: &GeneratedEntity) -> Result<Id> {
+    let key = GeneratedEntityKey::new(insert_entity(db, generated.entity.clone())?);
+    let extra = GeneratedEntityExtra::new(generated.reason.to_string());
+
+    Ok(db.generated_entity_vt.insert(key, extra))
+}
+
+pub fn insert_entity_source(db: &mut VirtualDb, source: &EntitySource) -> Result<Id> {
+    let commit_id = insert_commit(db, &source.commit)?;
+    let entity_id = insert_entity(db, source.entity.clone())?;
+
+    let compressed = match &source.text {
+        Some(text) => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(text.as_bytes())?;
+            Some(encoder.finish()?)
+        }
+        None => None,
+    };
+
+    let key = EntitySourceKey::new(commit_id, entity_id);
+    let extra = EntitySourceExtra::new(source.hash.to_string(), compressed);
+    Ok(db.entity_source_vt.insert(key, extra))
+}
+
+// ========================================================
+// Merging ------------------------------------------------
+// ========================================================
+
+pub fn has_table(conn: &Connection, name: &str) -> Result<bool> {
+    let sql = "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?";
+    Ok(conn.query_row(sql, [name], |row| row.get::<_, usize>(0))? > 0)
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    let sql = "SELECT COUNT(*) FROM pragma_table_info(?) WHERE name = ?";
+    Ok(conn.query_row(sql, [table, column], |row| row.get::<_, usize>(0))? > 0)
+}
+
+/// The column if the table has it, or else the given default (as SQL).
+fn column_or<'a>(
+    conn: &Connection,
+    table: &str,
+    column: &'a str,
+    default: &'a str,
+) -> Result<&'a str> {
+    Ok(if has_column(conn, table, column)? { column } else { default })
+}
+
+fn remap(ids: &HashMap<Id, Id>, id: Id, table: &str) -> Result<Id> {
+    match ids.get(&id) {
+        Some(&new_id) => Ok(new_id),
+        None => bail!("{} refers to a missing row (id {})", table, id),
+    }
+}
+
+/// Whether the database has directory entities above its files.
+pub fn has_dir_entities(conn: &Connection) -> Result<bool> {
+    let sql = "SELECT COUNT(*) FROM entities WHERE kind = 'dir'";
+    Ok(conn.query_row(sql, [], |row| row.get::<_, usize>(0))? > 0)
+}
+
+/// Adds every row of an existing database to the virtual database (and its
+/// dependencies to `deps`). Commits are identified by their SHA-1 and entities
+/// by their path, so rows found in more than one database are only kept once
+/// (the first time they are seen), except that the flags of a commit are those
+/// of every database. Ids are reassigned. Tables derived by the analyses are not
+/// merged. Directory entities are only kept if the virtual
+/// database inserts them.
+pub fn merge_db(db: &mut VirtualDb, deps: &mut DepVirtualTable, conn: &Connection) -> Result<()> {
+    // Entities (parents always have a smaller id than their children)
+    let mut entity_ids = HashMap::new();
+    let mut entity_paths: HashMap<Id, Vec<(String, Arc<String>, String)>> = HashMap::new();
+    let mut kinds: HashMap<String, Arc<String>> = HashMap::new();
+    let mut stmt =
+        conn.prepare("SELECT id, parent_id, name, kind, disc FROM entities ORDER BY id")?;
+    let mut rows = stmt.query([])?;
+
+    while let Some(row) = rows.next()? {
+        let (id, parent_id, name, kind, disc): (Id, Option<Id>, String, String, String) =
+            (row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?);
+        let kind = kinds.entry(kind.clone()).or_insert_with(|| Arc::new(kind)).clone();
+
+        // Directories are (re-)inserted along with the files below them
+        if kind.as_str() == "dir" {
+            entity_paths.insert(id, Vec::new());
+            continue;
+        }
+
+        let mut path = parent_id.map(|p| entity_paths[&p].clone()).unwrap_or_default();
+        path.push((name, kind, disc));
+
+        entity_ids.insert(id, insert_entity_path(db, &path)?);
+        entity_paths.insert(id, path);
+    }
+
+    // Commits
+    // Older databases have neither the authors nor the messages of commits
+    let mut commit_ids = HashMap::new();
+    let sql = format!(
+        "SELECT id
+              , sha1
+              , is_merge
+              , {}
+              , {}
+              , author_date
+              , commit_date
+              , {}
+              , has_change_info
+              , has_presence_info
+              , has_reachability_info
+        FROM commits",
+        column_or(conn, "commits", "author_name", "''")?,
+        column_or(conn, "commits", "author_mail", "''")?,
+        column_or(conn, "commits", "message", "''")?,
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = stmt.query([])?;
+
+    while let Some(row) = rows.next()? {
+        let mut commit_info = CommitInfo::empty();
+        commit_info.set(CommitInfo::CHANGES, row.get(8)?);
+        commit_info.set(CommitInfo::PRESENCE, row.get(9)?);
+        commit_info.set(CommitInfo::REACHABILITY, row.get(10)?);
+
+        let key = CommitKey::new(row.get(1)?);
+        let extra = CommitExtra::new(
+            row.get(2)?,
+            row.get(3)?,
+            row.get(4)?,
+            row.get(5)?,
+            row.get(6)?,
+            row.get(7)?,
+            commit_info,
+        );
+        commit_ids.insert(row.get::<_, Id>(0)?, db.commit_vt.insert(key, extra));
+    }
+
+    // Refs
+    let mut ref_ids = HashMap::new();
+    let mut stmt = conn.prepare("SELECT id, commit_id, name FROM refs")?;
+    let mut rows = stmt.query([])?;
+
+    while let Some(row) = rows.next()? {
+        let extra = RefExtra::new(remap(&commit_ids, row.get(1)?, "refs")?);
+        ref_ids.insert(row.get::<_, Id>(0)?, db.ref_vt.insert(RefKey::new(row.get(2)?), extra));
+    }
+
+    // Changes
+    // Older databases only have changes against the first parent
+    let parent = if has_column(conn, "changes", "parent")? { "parent" } else { "0" };
+    // ...and only the basic kind of each change
+    let kind = if has_column(conn, "changes", "semantic_kind")? { "semantic_kind" } else { "kind" };
+    let sql = format!("SELECT commit_id, entity_id, {}, adds, dels, {} FROM changes", kind, parent);
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = stmt.query([])?;
+
+    while let Some(row) = rows.next()? {
+        let kind = row.get::<_, String>(2)?;
+        let kind = match kind.chars().next().and_then(ChangeKind::from_char) {
+            Some(kind) => kind,
+            None => bail!("changes contains an unknown kind of change ('{}')", kind),
+        };
+
+        let key = ChangeKey::new(
+            remap(&commit_ids, row.get(0)?, "changes")?,
+            remap(&entity_ids, row.get(1)?, "changes")?,
+            row.get(5)?,
+        );
+        db.change_vt.insert_first(key, ChangeExtra::new(kind, row.get(3)?, row.get(4)?));
+    }
+
+    // Presence
+    // Older databases have no complexity metrics
+    let sql = format!(
+        "SELECT commit_id, entity_id, start_row, end_row, {}, {}, {} FROM presence",
+        column_or(conn, "presence", "nesting", "0")?,
+        column_or(conn, "presence", "branches", "0")?,
+        column_or(conn, "presence", "params", "0")?,
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = stmt.query([])?;
+
+    while let Some(row) = rows.next()? {
+        let key = PresenceKey::new(
+            remap(&commit_ids, row.get(0)?, "presence")?,
+            remap(&entity_ids, row.get(1)?, "presence")?,
+        );
+        let extra =
+            PresenceExtra::new(row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?);
+        db.presence_vt.insert_first(key, extra);
+    }
+
+    // Reachability
+    let mut stmt = conn.prepare("SELECT source_id, target_id FROM reachability")?;
+    let mut rows = stmt.query([])?;
+
+    while let Some(row) = rows.next()? {
+        let key = ReachabilityKey::new(
+            remap(&commit_ids, row.get(0)?, "reachability")?,
+            remap(&commit_ids, row.get(1)?, "reachability")?,
+        );
+        db.reachability_vt.insert(key, NullExtra);
+    }
+
+    // The remaining tables are optional (older databases may not have them)
+    if has_table(conn, "commit_refs")? {
+        let mut stmt = conn.prepare("SELECT commit_id, ref_id FROM commit_refs")?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let key = CommitRefKey::new(
+                remap(&commit_ids, row.get(0)?, "commit_refs")?,
+                remap(&ref_ids, row.get(1)?, "commit_refs")?,
+            );
+            db.commit_ref_vt.insert(key, NullExtra);
+        }
+    }
+
+    if has_table(conn, "commit_coverage")? {
+        // Older databases do not record the vendored directories left out
+        let sql = format!(
+            "SELECT commit_id, phase, files, granularity, languages, {} FROM commit_coverage",
+            column_or(conn, "commit_coverage", "excluded", "''")?
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let phase = match row.get::<_, String>(1)?.as_str() {
+                "C" => 'C',
+                "P" => 'P',
+                phase => bail!("commit_coverage contains an unknown phase ('{}')", phase),
+            };
+            let key = CommitCoverageKey::new(
+                remap(&commit_ids, row.get(0)?, "commit_coverage")?,
+                phase,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+            );
+            db.commit_coverage_vt.insert(key, NullExtra);
+        }
+    }
+
+    if has_table(conn, "tags")? {
+        let mut stmt = conn.prepare(
+            "SELECT commit_id, name, tagger_name, tagger_mail, tag_date, message FROM tags",
+        )?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let extra = TagExtra::new(
+                remap(&commit_ids, row.get(0)?, "tags")?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+            );
+            db.tag_vt.insert(TagKey::new(row.get(1)?), extra);
+        }
+    }
+
+    if has_table(conn, "ref_history")? {
+        let mut stmt =
+            conn.prepare("SELECT name, seq, old_sha1, new_sha1, time, message FROM ref_history")?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let key = RefHistoryKey::new(row.get(0)?, row.get(1)?);
+            let extra = RefHistoryExtra::new(row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?);
+            db.ref_history_vt.insert(key, extra);
+        }
+    }
+
+    if has_table(conn, "duplicate_commits")? {
+        let mut stmt =
+            conn.prepare("SELECT commit_id, original_id, patch_id FROM duplicate_commits")?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let key = DuplicateKey::new(remap(&commit_ids, row.get(0)?, "duplicate_commits")?);
+            let extra = DuplicateExtra::new(
+                remap(&commit_ids, row.get(1)?, "duplicate_commits")?,
+                row.get(2)?,
+            );
+            db.duplicate_vt.insert(key, extra);
+        }
+    }
+
+    if has_table(conn, "reverts")? {
+        let mut stmt = conn.prepare("SELECT commit_id, reverted_id, kind FROM reverts")?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let kind = match row.get::<_, String>(2)?.as_str() {
+                "message" => RevertKind::Message,
+                "patch" => RevertKind::Patch,
+                kind => bail!("reverts contains an unknown kind of revert ('{}')", kind),
+            };
+
+            let key = RevertKey::new(
+                remap(&commit_ids, row.get(0)?, "reverts")?,
+                remap(&commit_ids, row.get(1)?, "reverts")?,
+            );
+            db.revert_vt.insert(key, RevertExtra::new(kind.to_str()));
+        }
+    }
+
+    if has_table(conn, "submodule_updates")? {
+        let sql = "SELECT commit_id, parent, path, old_sha1, new_sha1 FROM submodule_updates";
+        let mut stmt = conn.prepare(sql)?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let key = SubmoduleUpdateKey::new(
+                remap(&commit_ids, row.get(0)?, "submodule_updates")?,
+                row.get(1)?,
+                row.get(2)?,
+            );
+            let extra = SubmoduleUpdateExtra::new(row.get(3)?, row.get(4)?);
+            db.submodule_update_vt.insert(key, extra);
+        }
+    }
+
+    if has_table(conn, "symlink_updates")? {
+        let sql = "SELECT commit_id, parent, path, old_target, new_target FROM symlink_updates";
+        let mut stmt = conn.prepare(sql)?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let key = SymlinkUpdateKey::new(
+                remap(&commit_ids, row.get(0)?, "symlink_updates")?,
+                row.get(1)?,
+                row.get(2)?,
+            );
+            let extra = SymlinkUpdateExtra::new(row.get(3)?, row.get(4)?);
+            db.symlink_update_vt.insert(key, extra);
+        }
+    }
+
+    if has_table(conn, "diagnostics")? {
+        let sql = "SELECT commit_id, kind, subject, message FROM diagnostics";
+        let mut stmt = conn.prepare(sql)?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let commit_id = row
+                .get::<_, Option<Id>>(0)?
+                .map(|id| remap(&commit_ids, id, "diagnostics"))
+                .transpose()?;
+            let key = DiagnosticKey::new(commit_id, row.get(1)?, row.get(2)?);
+            db.diagnostic_vt.insert(key, DiagnosticExtra::new(row.get(3)?));
+        }
+    }
+
+    if has_table(conn, "change_lines")? {
+        let sql = "SELECT commit_id, entity_id, parent, added, deleted FROM change_lines";
+        let mut stmt = conn.prepare(sql)?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let key = ChangeLinesKey::new(
+                remap(&commit_ids, row.get(0)?, "change_lines")?,
+                remap(&entity_ids, row.get(1)?, "change_lines")?,
+                row.get(2)?,
+            );
+            db.change_lines_vt.insert(key, ChangeLinesExtra::new(row.get(3)?, row.get(4)?));
+        }
+    }
+
+    if has_table(conn, "doc_changes")? {
+        let sql =
+            "SELECT commit_id, entity_id, parent, doc_adds, doc_dels, touched FROM doc_changes";
+        let mut stmt = conn.prepare(sql)?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let touched = row.get::<_, String>(5)?;
+            let touched = match touched.chars().next().and_then(Touched::from_char) {
+                Some(touched) => touched,
+                None => bail!("doc_changes contains an unknown part ('{}')", touched),
+            };
+
+            let key = DocChangeKey::new(
+                remap(&commit_ids, row.get(0)?, "doc_changes")?,
+                remap(&entity_ids, row.get(1)?, "doc_changes")?,
+                row.get(2)?,
+            );
+            db.doc_change_vt
+                .insert_first(key, DocChangeExtra::new(row.get(3)?, row.get(4)?, touched));
+        }
+    }
+
+    let mut hunk_ids = HashMap::new();
+
+    if has_table(conn, "hunks")? {
+        let sql = "SELECT id, commit_id, parent, filename, ordinal, old_start, old_end, new_start, new_end
+                   FROM hunks";
+        let mut stmt = conn.prepare(sql)?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let key = HunkKey::new(
+                remap(&commit_ids, row.get(1)?, "hunks")?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+            );
+            let extra = HunkExtra::new(row.get(5)?, row.get(6)?, row.get(7)?, row.get(8)?);
+            hunk_ids.insert(row.get::<_, Id>(0)?, db.hunk_vt.insert(key, extra));
+        }
+    }
+
+    if has_table(conn, "hunk_entities")? {
+        let mut stmt = conn.prepare("SELECT hunk_id, entity_id, adds, dels FROM hunk_entities")?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let key = HunkEntityKey::new(
+                remap(&hunk_ids, row.get(0)?, "hunk_entities")?,
+                remap(&entity_ids, row.get(1)?, "hunk_entities")?,
+            );
+            db.hunk_entity_vt.insert_first(key, HunkEntityExtra::new(row.get(2)?, row.get(3)?));
+        }
+    }
+
+    if has_table(conn, "parse_errors")? {
+        let sql = "SELECT filename, sha1, errors, error_bytes, bytes, skipped FROM parse_errors";
+        let mut stmt = conn.prepare(sql)?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let key = ParseErrorKey::new(row.get(0)?, row.get(1)?);
+            let extra = ParseErrorExtra::new(row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?);
+            db.parse_error_vt.insert(key, extra);
+        }
+    }
+
+    if has_table(conn, "generated_entities")? {
+        let mut stmt = conn.prepare("SELECT entity_id, reason FROM generated_entities")?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let key =
+                GeneratedEntityKey::new(remap(&entity_ids, row.get(0)?, "generated_entities")?);
+            db.generated_entity_vt.insert(key, GeneratedEntityExtra::new(row.get(1)?));
+        }
+    }
+
+    if has_table(conn, "entity_sources")? {
+        let sql = "SELECT commit_id, entity_id, sha1, source FROM entity_sources";
+        let mut stmt = conn.prepare(sql)?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let key = EntitySourceKey::new(
+                remap(&commit_ids, row.get(0)?, "entity_sources")?,
+                remap(&entity_ids, row.get(1)?, "entity_sources")?,
+            );
+            db.entity_source_vt.insert(key, EntitySourceExtra::new(row.get(2)?, row.get(3)?));
+        }
+    }
+
+    if has_table(conn, "deps")? {
+        let mut stmt = conn.prepare("SELECT commit_id, src_id, tgt_id, kind, lineno FROM deps")?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let key = DepKey::new(
+                remap(&commit_ids, row.get(0)?, "deps")?,
+                remap(&entity_ids, row.get(1)?, "deps")?,
+                remap(&entity_ids, row.get(2)?, "deps")?,
+                row.get(3)?,
+            );
+            deps.insert(key, DepExtra::new(row.get(4)?));
+        }
+    }
+
+    Ok(())
 }
@@ -0,0 +1,224 @@
+//! Typed reads of a database written by the tool, for Rust programs which use
+//! the SQLite file directly instead of running the command line.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::bail;
+use anyhow::Result;
+use rusqlite::Connection;
+use rusqlite::OpenFlags;
+use rusqlite::Row;
+
+use crate::analysis::load_entity_paths;
+use crate::analysis::resolve_entity;
+use crate::db::has_table;
+use crate::db::Id;
+
+/// A row of the `commits` table. Dates are seconds since the Unix epoch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Commit {
+    pub id: Id,
+    pub sha1: String,
+    pub is_merge: bool,
+    pub author_name: String,
+    pub author_mail: String,
+    pub author_date: i64,
+    pub commit_date: i64,
+    pub message: String,
+}
+
+impl Commit {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            sha1: row.get(1)?,
+            is_merge: row.get(2)?,
+            author_name: row.get(3)?,
+            author_mail: row.get(4)?,
+            author_date: row.get(5)?,
+            commit_date: row.get(6)?,
+            message: row.get(7)?,
+        })
+    }
+}
+
+/// A row of the `entities` table along with its path (e.g.
+/// `src/Foo.java::Foo::bar`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entity {
+    pub id: Id,
+    pub parent_id: Option<Id>,
+    pub name: String,
+    pub kind: String,
+    pub path: String,
+}
+
+/// A change to an entity along with the commit which made it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    pub commit: Commit,
+    /// One of `A` (added), `D` (deleted), or `M` (modified)
+    pub kind: String,
+    pub adds: usize,
+    pub dels: usize,
+    /// The index of the parent of the commit which the change is relative to
+    pub parent: usize,
+}
+
+/// A row of the `coupling` table, as written by `analyze coupling`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CoupledPair {
+    pub window_start: i64,
+    pub window_end: i64,
+    pub entity1_id: Id,
+    pub entity2_id: Id,
+    pub support: f64,
+    pub confidence: f64,
+}
+
+const COMMIT_COLUMNS: &str = "CO.id, CO.sha1, CO.is_merge, CO.author_name, CO.author_mail, \
+                              CO.author_date, CO.commit_date, CO.message";
+
+/// A read-only view of a database.
+pub struct Reader {
+    conn: Connection,
+}
+
+impl Reader {
+    /// Opens an existing database without allowing writes.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        Ok(Self::new(Connection::open_with_flags(path, flags)?))
+    }
+
+    pub fn new(conn: Connection) -> Self {
+        Self { conn }
+    }
+
+    /// The underlying connection, for queries not covered here.
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Every commit, the most recently committed first.
+    pub fn commits(&self) -> Result<Vec<Commit>> {
+        let sql = format!(
+            "SELECT {} FROM commits CO ORDER BY CO.commit_date DESC, CO.id DESC",
+            COMMIT_COLUMNS
+        );
+        let mut stmt = self.conn.prepare(&sql)?;
+        let commits = stmt.query_map([], Commit::from_row)?.collect::<Result<_, _>>()?;
+        Ok(commits)
+    }
+
+    /// The commit with the given hash (if it was extracted).
+    pub fn commit(&self, sha1: &str) -> Result<Option<Commit>> {
+        let sql = format!("SELECT {} FROM commits CO WHERE CO.sha1 = ?", COMMIT_COLUMNS);
+        let mut stmt = self.conn.prepare(&sql)?;
+        let mut commits = stmt.query_map([sha1], Commit::from_row)?;
+        Ok(commits.next().transpose()?)
+    }
+
+    /// Every entity, ordered by id (so parents come before their children).
+    pub fn entities(&self) -> Result<Vec<Entity>> {
+        let mut paths = load_entity_paths(&self.conn)?;
+        let mut stmt =
+            self.conn.prepare("SELECT id, parent_id, name, kind FROM entities ORDER BY id")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, Id>(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?;
+        let mut entities = Vec::new();
+
+        for row in rows {
+            let (id, parent_id, name, kind) = row?;
+            let path = paths.remove(&id).unwrap_or_default();
+            entities.push(Entity { id, parent_id, name, kind, path });
+        }
+
+        Ok(entities)
+    }
+
+    /// The paths of every entity by id.
+    pub fn entity_paths(&self) -> Result<HashMap<Id, String>> {
+        load_entity_paths(&self.conn)
+    }
+
+    /// Finds an entity by its path, which may omit leading directories so long
+    /// as the remainder is unambiguous (as with `log`).
+    pub fn find_entity(&self, path: &str) -> Result<Id> {
+        resolve_entity(&load_entity_paths(&self.conn)?, path)
+    }
+
+    /// Calls `f` with each change to the given entity, the most recent first.
+    /// Iteration stops at the first error.
+    pub fn for_each_change<F>(&self, entity_id: Id, mut f: F) -> Result<()>
+    where
+        F: FnMut(Change) -> Result<()>,
+    {
+        let sql = format!(
+            "SELECT {}, CH.kind, CH.adds, CH.dels, CH.parent
+            FROM changes CH
+            JOIN commits CO ON CO.id = CH.commit_id
+            WHERE CH.entity_id = ?
+            ORDER BY CO.commit_date DESC, CO.id DESC, CH.parent",
+            COMMIT_COLUMNS
+        );
+        let mut stmt = self.conn.prepare(&sql)?;
+        let mut rows = stmt.query([entity_id])?;
+
+        while let Some(row) = rows.next()? {
+            f(Change {
+                commit: Commit::from_row(row)?,
+                kind: row.get(8)?,
+                adds: row.get(9)?,
+                dels: row.get(10)?,
+                parent: row.get(11)?,
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// The changes to the given entity, the most recent first.
+    pub fn changes(&self, entity_id: Id) -> Result<Vec<Change>> {
+        let mut changes = Vec::new();
+        self.for_each_change(entity_id, |change| {
+            changes.push(change);
+            Ok(())
+        })?;
+        Ok(changes)
+    }
+
+    /// Calls `f` with each pair written by `analyze coupling` without loading
+    /// them all into memory, ordered by window and then by support and
+    /// confidence. Iteration stops at the first error.
+    pub fn for_each_coupled_pair<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(CoupledPair) -> Result<()>,
+    {
+        if !has_table(&self.conn, "coupling")? {
+            bail!("the database has no coupled pairs (run `analyze coupling` first)");
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT window_start, window_end, entity1_id, entity2_id, support, confidence
+            FROM coupling
+            ORDER BY window_start, support DESC, confidence DESC, entity1_id, entity2_id",
+        )?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            f(CoupledPair {
+                window_start: row.get(0)?,
+                window_end: row.get(1)?,
+                entity1_id: row.get(2)?,
+                entity2_id: row.get(3)?,
+                support: row.get(4)?,
+                confidence: row.get(5)?,
+            })?;
+        }
+
+        Ok(())
+    }
+}
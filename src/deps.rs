@@ -2,11 +2,8 @@ use std::collections::HashMap;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::fs::read_to_string;
-use std::hash::Hash;
 use std::path::Path;
 
-use anyhow::bail;
-use anyhow::Context;
 use anyhow::Result;
 use rusqlite::Connection;
 
@@ -85,7 +82,7 @@ pub struct Endpoint {
 
 impl Endpoint {
     pub fn name(&self) -> &str {
-        match self.full_name.split('.').last() {
+        match self.full_name.split('.').next_back() {
             Some(name) => name,
             None => &self.full_name,
         }
@@ -140,8 +137,9 @@ pub fn load_dep_file<P: AsRef<Path>>(path: P) -> Result<Vec<Dep>> {
 pub fn get_commit_id(conn: &Connection, sha1: &String) -> Result<usize> {
     let mut stmt = conn.prepare("SELECT id FROM commits WHERE sha1 = :sha1")?;
 
-    let res =
-        stmt.query_map(&[(":sha1", &sha1)], |row| Ok(row.get(0)?))?.try_collect::<Vec<usize>>()?;
+    let res = stmt
+        .query_map(&[(":sha1", &sha1)], |row| row.get(0))?
+        .collect::<Result<Vec<usize>, _>>()?;
 
     assert!(res.len() == 1);
     Ok(res[0])
@@ -206,7 +204,7 @@ pub fn match_entity_id(locs: &HashMap<String, Vec<Loc>>, ep: &Endpoint) -> Optio
         return None;
     }
 
-    let locs = locs.unwrap().into_iter();
+    let locs = locs.unwrap().iter();
 
     let locs: Vec<_> = match (ep.line, ep.kind) {
         (_, EndpointKind::File) => locs.filter(|l| l.level == 0).collect(),
@@ -217,24 +215,24 @@ pub fn match_entity_id(locs: &HashMap<String, Vec<Loc>>, ep: &Endpoint) -> Optio
         _ => locs.filter(|l| ep.line >= l.start_row && ep.line <= l.end_row).collect(),
     };
 
-    if locs.len() == 0 {
+    if locs.is_empty() {
         log::warn!("Could not find a {} at {}:{}", &ep.kind, &ep.file, &ep.line);
         return None;
     } else if locs.len() == 1 {
-        return Some(locs.get(0).unwrap().entity_id);
+        return Some(locs.first().unwrap().entity_id);
     }
 
     let ep_name = ep.name();
     let by_name_locs = locs.iter().filter(|l| l.name == ep_name).collect::<Vec<_>>();
 
-    if by_name_locs.len() == 0 {
+    if by_name_locs.is_empty() {
         // There are a couple reasons why an entity can't be found by name:
         // - It is a parameter name
         // - It is a function inside an anonymous class inside a function
         // Maybe we could check for these cases?
         log::debug!("Could not find a {} named '{}' at {}:{}", &ep.kind, ep_name, &ep.file, &ep.line);
     } else if by_name_locs.len() == 1 {
-        return Some(by_name_locs.get(0).unwrap().entity_id);
+        return Some(by_name_locs.first().unwrap().entity_id);
     }
 
     // If can't find by name, default to the max level
@@ -242,7 +240,7 @@ pub fn match_entity_id(locs: &HashMap<String, Vec<Loc>>, ep: &Endpoint) -> Optio
     let locs = locs.into_iter().filter(|l| l.level == max_level).collect::<Vec<_>>();
 
     if locs.len() == 1 {
-        return Some(locs.get(0).unwrap().entity_id);
+        return Some(locs.first().unwrap().entity_id);
     }
 
     log::warn!("Found too many entities named '{}' at {}:{}", ep_name, &ep.file, &ep.line);
@@ -0,0 +1,1036 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use arrow_array::ArrayRef;
+use arrow_array::BooleanArray;
+use arrow_array::Float64Array;
+use arrow_array::Int64Array;
+use arrow_array::RecordBatch;
+use arrow_array::StringArray;
+use arrow_schema::DataType;
+use arrow_schema::Field;
+use arrow_schema::Schema;
+use derive_new::new;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use rusqlite::types::Value;
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::ir;
+
+/// Quotes a CSV field if (and only if) it needs to be quoted.
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Writes the given header and rows to a CSV file (RFC 4180).
+pub fn write_csv<P, I>(path: P, header: &[&str], rows: I) -> Result<()>
+where
+    P: AsRef<Path>,
+    I: IntoIterator<Item = Vec<String>>,
+{
+    let file = File::create(path.as_ref())
+        .with_context(|| format!("failed to create {}", path.as_ref().to_string_lossy()))?;
+    let mut writer = BufWriter::new(file);
+
+    writeln!(writer, "{}", header.iter().map(|h| escape_csv(h)).collect::<Vec<_>>().join(","))?;
+
+    for row in rows {
+        writeln!(writer, "{}", row.iter().map(|f| escape_csv(f)).collect::<Vec<_>>().join(","))?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads the rows of a CSV file (RFC 4180), including its header (if any).
+pub fn read_csv<P: AsRef<Path>>(path: P) -> Result<Vec<Vec<String>>> {
+    let text = std::fs::read_to_string(path.as_ref())
+        .with_context(|| format!("failed to read {}", path.as_ref().to_string_lossy()))?;
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (false, '"') => quoted = true,
+            (false, ',') => row.push(std::mem::take(&mut field)),
+            (false, '\r') => {}
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (_, c) => field.push(c),
+        }
+    }
+
+    if quoted {
+        bail!("unterminated quote in {}", path.as_ref().to_string_lossy());
+    }
+
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum TableFormat {
+    Csv,
+    Parquet,
+}
+
+impl TableFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            TableFormat::Csv => "csv",
+            TableFormat::Parquet => "parquet",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColumnType {
+    Int,
+    Real,
+    Text,
+    Bool,
+}
+
+impl ColumnType {
+    /// Maps the declared type of a SQLite column to a column type following the
+    /// type affinity rules of SQLite (with BOOLEAN kept apart).
+    fn from_decl(decl: &str) -> Self {
+        let decl = decl.to_uppercase();
+
+        if decl.contains("BOOL") {
+            ColumnType::Bool
+        } else if decl.contains("INT") {
+            ColumnType::Int
+        } else if decl.contains("REAL") || decl.contains("FLOA") || decl.contains("DOUB") {
+            ColumnType::Real
+        } else {
+            ColumnType::Text
+        }
+    }
+
+    fn to_data_type(self) -> DataType {
+        match self {
+            ColumnType::Int => DataType::Int64,
+            ColumnType::Real => DataType::Float64,
+            ColumnType::Text => DataType::Utf8,
+            ColumnType::Bool => DataType::Boolean,
+        }
+    }
+}
+
+#[derive(new, Clone, Debug)]
+pub struct Column {
+    pub name: String,
+    pub ty: ColumnType,
+    pub nullable: bool,
+}
+
+/// The names of every table in the database.
+pub fn load_table_names(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt =
+        conn.prepare("SELECT name FROM sqlite_schema WHERE type = 'table' ORDER BY name")?;
+    let names = stmt.query_map([], |row| row.get(0))?.collect::<Result<_, _>>()?;
+    Ok(names)
+}
+
+/// The columns of a table as declared in its schema.
+pub fn load_columns(conn: &Connection, table: &str) -> Result<Vec<Column>> {
+    let mut stmt = conn.prepare("SELECT name, type, \"notnull\" FROM pragma_table_info(?)")?;
+    let rows = stmt.query_map([table], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, bool>(2)?))
+    })?;
+
+    let mut columns = Vec::new();
+
+    for row in rows {
+        let (name, decl, not_null) = row?;
+        columns.push(Column::new(name, ColumnType::from_decl(&decl), !not_null));
+    }
+
+    if columns.is_empty() {
+        bail!("no table named '{}'", table);
+    }
+
+    Ok(columns)
+}
+
+/// Whether a range of lines ends at its last line or just past it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum RangeEnd {
+    Exclusive,
+    Inclusive,
+}
+
+/// How line ranges are written for a consumer which expects other line numbers
+/// than those stored. Presence rows are stored 1-based and inclusive, while the
+/// ends of hunks are stored exclusive.
+#[derive(new, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineConvention {
+    /// The number of the first line of a file (0 or 1)
+    pub base: usize,
+    /// How each range ends (or as stored if missing)
+    pub end: Option<RangeEnd>,
+}
+
+impl LineConvention {
+    /// Writes line ranges as they are stored.
+    pub const STORED: LineConvention = LineConvention { base: 1, end: None };
+
+    /// Converts a range which is stored as from `start` to `end` (1-based),
+    /// where `end` is past the last line if `exclusive`. A missing end stays
+    /// missing. An empty range before the first line (stored from 0 to 0, e.g.
+    /// the old side of the hunk of an added file) has no line to renumber, so
+    /// it is written from 0 to 0 whatever the convention.
+    pub fn convert(&self, start: i64, end: Option<i64>, exclusive: bool) -> (i64, Option<i64>) {
+        if exclusive && start == 0 && end == Some(0) {
+            return (0, Some(0));
+        }
+
+        let offset = self.base as i64 - 1;
+        let end = end.map(|end| match (exclusive, self.end) {
+            (true, Some(RangeEnd::Inclusive)) => end - 1,
+            (false, Some(RangeEnd::Exclusive)) => end + 1,
+            _ => end,
+        });
+        (start + offset, end.map(|end| end + offset))
+    }
+}
+
+/// The columns of the given table which hold line ranges, as the columns of
+/// their start and end along with whether the end is exclusive.
+fn to_line_ranges(table: &str) -> &'static [(&'static str, &'static str, bool)] {
+    match table {
+        "presence" => &[("start_row", "end_row", false)],
+        "hunks" => &[("old_start", "old_end", true), ("new_start", "new_end", true)],
+        _ => &[],
+    }
+}
+
+/// Writes a whole table of the database to a file, with its line ranges (see
+/// `to_line_ranges`) converted to the given convention.
+pub fn export_table<P: AsRef<Path>>(
+    conn: &Connection,
+    table: &str,
+    path: P,
+    format: TableFormat,
+    lines: LineConvention,
+) -> Result<()> {
+    let columns = load_columns(conn, table)?;
+    let index = |name: &str| columns.iter().position(|c| c.name == name);
+    let ranges = if lines == LineConvention::STORED { &[] } else { to_line_ranges(table) };
+    let ranges = ranges
+        .iter()
+        .filter_map(|&(start, end, exclusive)| Some((index(start)?, index(end)?, exclusive)))
+        .collect::<Vec<_>>();
+
+    let mut stmt = conn.prepare(&format!("SELECT * FROM \"{}\"", table.replace('"', "\"\"")))?;
+    let rows = stmt.query_map([], |row| {
+        let mut values = (0..columns.len()).map(|i| row.get(i)).collect::<Result<Vec<_>, _>>()?;
+
+        for &(start, end, exclusive) in &ranges {
+            convert_range(&mut values, start, end, exclusive, lines);
+        }
+
+        Ok(values)
+    })?;
+    write_table(path, format, &columns, rows.map(|r| Ok(r?)))
+}
+
+fn convert_range(
+    values: &mut [Value],
+    start: usize,
+    end: usize,
+    exclusive: bool,
+    lines: LineConvention,
+) {
+    let to_int = |value: &Value| match value {
+        Value::Integer(i) => Some(*i),
+        _ => None,
+    };
+
+    if let Some(start_line) = to_int(&values[start]) {
+        let (start_line, end_line) = lines.convert(start_line, to_int(&values[end]), exclusive);
+        values[start] = Value::Integer(start_line);
+        values[end] = end_line.map_or(Value::Null, Value::Integer);
+    }
+}
+
+/// Writes the given rows to a file. Each row must hold one value per column.
+pub fn write_table<P, I>(path: P, format: TableFormat, columns: &[Column], rows: I) -> Result<()>
+where
+    P: AsRef<Path>,
+    I: IntoIterator<Item = Result<Vec<Value>>>,
+{
+    match format {
+        TableFormat::Csv => {
+            let header = columns.iter().map(|c| c.name.as_str()).collect::<Vec<_>>();
+            let rows = rows.into_iter().map(|r| Ok(r?.iter().map(to_csv_field).collect()));
+            write_csv(path, &header, rows.collect::<Result<Vec<_>>>()?)
+        }
+        TableFormat::Parquet => write_parquet(path, columns, rows),
+    }
+}
+
+fn to_csv_field(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Integer(i) => i.to_string(),
+        Value::Real(r) => r.to_string(),
+        Value::Text(t) => t.clone(),
+        Value::Blob(b) => b.iter().map(|byte| format!("{:02x}", byte)).collect(),
+    }
+}
+
+/// Writes the rows of a query to a CSV file under the given header.
+fn write_query_csv<P: AsRef<Path>>(
+    conn: &Connection,
+    sql: &str,
+    path: P,
+    header: &[&str],
+) -> Result<()> {
+    let mut stmt = conn.prepare(sql)?;
+    let n = stmt.column_count();
+    let rows = stmt.query_map([], |row| {
+        (0..n).map(|i| row.get::<_, Value>(i)).collect::<Result<Vec<_>, _>>()
+    })?;
+    let rows =
+        rows.map(|r| Ok(r?.iter().map(to_csv_field).collect())).collect::<Result<Vec<_>>>()?;
+    write_csv(path, header, rows)
+}
+
+/// Writes the database as a property graph in the CSV layout of
+/// `neo4j-admin database import`. The nodes are commits, authors, and entities.
+/// The relationships are AUTHORED (author to commit), CHANGED (commit to entity),
+/// CONTAINS (entity to child entity), and DEPENDS_ON (entity to entity, only if
+/// add-deps was run). Returns the names of the files and whether each holds nodes.
+///
+/// Commit messages may span several lines, so the import needs
+/// `--multiline-fields=true`.
+pub fn write_neo4j_import<P: AsRef<Path>>(
+    conn: &Connection,
+    dir: P,
+) -> Result<Vec<(String, bool)>> {
+    let dir = dir.as_ref();
+    let mut files = Vec::new();
+
+    write_query_csv(
+        conn,
+        "SELECT id
+              , sha1
+              , CASE WHEN is_merge THEN 'true' ELSE 'false' END
+              , author_date
+              , commit_date
+              , message
+              , 'Commit'
+        FROM commits",
+        dir.join("commits.csv"),
+        &[
+            "id:ID(Commit)",
+            "sha1",
+            "is_merge:boolean",
+            "author_date:long",
+            "commit_date:long",
+            "message",
+            ":LABEL",
+        ],
+    )?;
+    files.push(("commits.csv".to_string(), true));
+
+    write_query_csv(
+        conn,
+        "SELECT author_mail, MAX(author_name), 'Author' FROM commits GROUP BY author_mail",
+        dir.join("authors.csv"),
+        &["mail:ID(Author)", "name", ":LABEL"],
+    )?;
+    files.push(("authors.csv".to_string(), true));
+
+    write_query_csv(
+        conn,
+        "SELECT id, name, kind, disc, 'Entity' FROM entities",
+        dir.join("entities.csv"),
+        &["id:ID(Entity)", "name", "kind", "disc", ":LABEL"],
+    )?;
+    files.push(("entities.csv".to_string(), true));
+
+    write_query_csv(
+        conn,
+        "SELECT author_mail, id, 'AUTHORED' FROM commits",
+        dir.join("authored.csv"),
+        &[":START_ID(Author)", ":END_ID(Commit)", ":TYPE"],
+    )?;
+    files.push(("authored.csv".to_string(), false));
+
+    write_query_csv(
+        conn,
+        "SELECT commit_id, entity_id, kind, adds, dels, 'CHANGED' FROM changes",
+        dir.join("changed.csv"),
+        &[":START_ID(Commit)", ":END_ID(Entity)", "kind", "adds:int", "dels:int", ":TYPE"],
+    )?;
+    files.push(("changed.csv".to_string(), false));
+
+    write_query_csv(
+        conn,
+        "SELECT parent_id, id, 'CONTAINS' FROM entities WHERE parent_id IS NOT NULL",
+        dir.join("contains.csv"),
+        &[":START_ID(Entity)", ":END_ID(Entity)", ":TYPE"],
+    )?;
+    files.push(("contains.csv".to_string(), false));
+
+    if load_table_names(conn)?.iter().any(|t| t == "deps") {
+        write_query_csv(
+            conn,
+            "SELECT src_id, tgt_id, kind, commit_id, 'DEPENDS_ON' FROM deps",
+            dir.join("depends_on.csv"),
+            &[":START_ID(Entity)", ":END_ID(Entity)", "kind", "commit_id:long", ":TYPE"],
+        )?;
+        files.push(("depends_on.csv".to_string(), false));
+    }
+
+    Ok(files)
+}
+
+/// The number of rows buffered before they are written as a row group.
+const PARQUET_BATCH_SIZE: usize = 64 * 1024;
+
+/// Writes the given rows to an Apache Parquet file (compressed with Snappy).
+/// Values which do not match the type of their column are written as nulls.
+pub fn write_parquet<P, I>(path: P, columns: &[Column], rows: I) -> Result<()>
+where
+    P: AsRef<Path>,
+    I: IntoIterator<Item = Result<Vec<Value>>>,
+{
+    let file = File::create(path.as_ref())
+        .with_context(|| format!("failed to create {}", path.as_ref().to_string_lossy()))?;
+    let fields = columns
+        .iter()
+        .map(|c| Field::new(&c.name, c.ty.to_data_type(), c.nullable))
+        .collect::<Vec<_>>();
+    let schema = Arc::new(Schema::new(fields));
+    let props = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+    let mut writer = ArrowWriter::try_new(file, schema.clone(), Some(props))?;
+    let mut batch = Vec::with_capacity(PARQUET_BATCH_SIZE);
+
+    for row in rows {
+        batch.push(row?);
+
+        if batch.len() == PARQUET_BATCH_SIZE {
+            writer.write(&to_record_batch(&schema, columns, &batch)?)?;
+            batch.clear();
+        }
+    }
+
+    if !batch.is_empty() {
+        writer.write(&to_record_batch(&schema, columns, &batch)?)?;
+    }
+
+    writer.close()?;
+    Ok(())
+}
+
+fn to_record_batch(
+    schema: &Arc<Schema>,
+    columns: &[Column],
+    rows: &[Vec<Value>],
+) -> Result<RecordBatch> {
+    let arrays = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| -> ArrayRef {
+            let values = rows.iter().map(|row| &row[i]);
+
+            match column.ty {
+                ColumnType::Int => Arc::new(
+                    values
+                        .map(|v| match v {
+                            Value::Integer(i) => Some(*i),
+                            _ => None,
+                        })
+                        .collect::<Int64Array>(),
+                ),
+                ColumnType::Real => Arc::new(
+                    values
+                        .map(|v| match v {
+                            Value::Real(r) => Some(*r),
+                            Value::Integer(i) => Some(*i as f64),
+                            _ => None,
+                        })
+                        .collect::<Float64Array>(),
+                ),
+                ColumnType::Text => Arc::new(
+                    values
+                        .map(|v| match v {
+                            Value::Text(t) => Some(t.clone()),
+                            Value::Null => None,
+                            other => Some(to_csv_field(other)),
+                        })
+                        .collect::<StringArray>(),
+                ),
+                ColumnType::Bool => Arc::new(
+                    values
+                        .map(|v| match v {
+                            Value::Integer(i) => Some(*i != 0),
+                            _ => None,
+                        })
+                        .collect::<BooleanArray>(),
+                ),
+            }
+        })
+        .collect();
+
+    Ok(RecordBatch::try_new(schema.clone(), arrays)?)
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub id: String,
+    pub label: String,
+    pub kind: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Edge {
+    pub source: String,
+    pub target: String,
+    pub kind: String,
+    pub weight: f64,
+}
+
+/// A weighted graph which can be written in several interchange formats.
+#[derive(Debug, Default)]
+pub struct Graph {
+    pub directed: bool,
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum GraphFormat {
+    Graphml,
+    Gexf,
+    Dot,
+    EdgeList,
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl Graph {
+    pub fn add_node<I: Into<String>, L: Into<String>, K: Into<String>>(
+        &mut self,
+        id: I,
+        label: L,
+        kind: K,
+    ) {
+        self.nodes.push(Node { id: id.into(), label: label.into(), kind: kind.into() });
+    }
+
+    pub fn add_edge<S: Into<String>, T: Into<String>, K: Into<String>>(
+        &mut self,
+        source: S,
+        target: T,
+        kind: K,
+        weight: f64,
+    ) {
+        self.edges.push(Edge {
+            source: source.into(),
+            target: target.into(),
+            kind: kind.into(),
+            weight,
+        });
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P, format: GraphFormat) -> Result<()> {
+        let file = File::create(path.as_ref())
+            .with_context(|| format!("failed to create {}", path.as_ref().to_string_lossy()))?;
+        let mut writer = BufWriter::new(file);
+
+        match format {
+            GraphFormat::Graphml => self.write_graphml(&mut writer)?,
+            GraphFormat::Gexf => self.write_gexf(&mut writer)?,
+            GraphFormat::Dot => self.write_dot(&mut writer)?,
+            GraphFormat::EdgeList => self.write_edge_list(&mut writer)?,
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn write_graphml<W: Write>(&self, w: &mut W) -> Result<()> {
+        writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(w, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#)?;
+        writeln!(w, r#"  <key id="label" for="node" attr.name="label" attr.type="string"/>"#)?;
+        writeln!(w, r#"  <key id="nkind" for="node" attr.name="kind" attr.type="string"/>"#)?;
+        writeln!(w, r#"  <key id="ekind" for="edge" attr.name="kind" attr.type="string"/>"#)?;
+        writeln!(w, r#"  <key id="weight" for="edge" attr.name="weight" attr.type="double"/>"#)?;
+
+        let edge_default = if self.directed { "directed" } else { "undirected" };
+        writeln!(w, r#"  <graph id="G" edgedefault="{}">"#, edge_default)?;
+
+        for node in &self.nodes {
+            writeln!(w, r#"    <node id="{}">"#, escape_xml(&node.id))?;
+            writeln!(w, r#"      <data key="label">{}</data>"#, escape_xml(&node.label))?;
+            writeln!(w, r#"      <data key="nkind">{}</data>"#, escape_xml(&node.kind))?;
+            writeln!(w, r#"    </node>"#)?;
+        }
+
+        for edge in &self.edges {
+            let (source, target) = (escape_xml(&edge.source), escape_xml(&edge.target));
+            writeln!(w, r#"    <edge source="{}" target="{}">"#, source, target)?;
+            writeln!(w, r#"      <data key="ekind">{}</data>"#, escape_xml(&edge.kind))?;
+            writeln!(w, r#"      <data key="weight">{}</data>"#, edge.weight)?;
+            writeln!(w, r#"    </edge>"#)?;
+        }
+
+        writeln!(w, "  </graph>")?;
+        writeln!(w, "</graphml>")?;
+        Ok(())
+    }
+
+    fn write_gexf<W: Write>(&self, w: &mut W) -> Result<()> {
+        writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(w, r#"<gexf xmlns="http://gexf.net/1.3" version="1.3">"#)?;
+
+        let edge_type = if self.directed { "directed" } else { "undirected" };
+        writeln!(w, r#"  <graph defaultedgetype="{}">"#, edge_type)?;
+        writeln!(w, r#"    <attributes class="node">"#)?;
+        writeln!(w, r#"      <attribute id="kind" title="kind" type="string"/>"#)?;
+        writeln!(w, r#"    </attributes>"#)?;
+        writeln!(w, r#"    <attributes class="edge">"#)?;
+        writeln!(w, r#"      <attribute id="kind" title="kind" type="string"/>"#)?;
+        writeln!(w, r#"    </attributes>"#)?;
+        writeln!(w, r#"    <nodes>"#)?;
+
+        for node in &self.nodes {
+            let (id, label) = (escape_xml(&node.id), escape_xml(&node.label));
+            writeln!(w, r#"      <node id="{}" label="{}">"#, id, label)?;
+            writeln!(w, r#"        <attvalues>"#)?;
+            writeln!(w, r#"          <attvalue for="kind" value="{}"/>"#, escape_xml(&node.kind))?;
+            writeln!(w, r#"        </attvalues>"#)?;
+            writeln!(w, r#"      </node>"#)?;
+        }
+
+        writeln!(w, r#"    </nodes>"#)?;
+        writeln!(w, r#"    <edges>"#)?;
+
+        for (i, edge) in self.edges.iter().enumerate() {
+            let (source, target) = (escape_xml(&edge.source), escape_xml(&edge.target));
+            writeln!(
+                w,
+                r#"      <edge id="{}" source="{}" target="{}" weight="{}">"#,
+                i, source, target, edge.weight
+            )?;
+            writeln!(w, r#"        <attvalues>"#)?;
+            writeln!(w, r#"          <attvalue for="kind" value="{}"/>"#, escape_xml(&edge.kind))?;
+            writeln!(w, r#"        </attvalues>"#)?;
+            writeln!(w, r#"      </edge>"#)?;
+        }
+
+        writeln!(w, r#"    </edges>"#)?;
+        writeln!(w, "  </graph>")?;
+        writeln!(w, "</gexf>")?;
+        Ok(())
+    }
+
+    /// Writes the graph in the DOT language of Graphviz.
+    fn write_dot<W: Write>(&self, w: &mut W) -> Result<()> {
+        let (keyword, op) = if self.directed { ("digraph", "->") } else { ("graph", "--") };
+        writeln!(w, "{} G {{", keyword)?;
+
+        for node in &self.nodes {
+            let (id, label, kind) =
+                (escape_dot(&node.id), escape_dot(&node.label), escape_dot(&node.kind));
+            writeln!(w, r#"  "{}" [label="{}", kind="{}"];"#, id, label, kind)?;
+        }
+
+        for edge in &self.edges {
+            let (source, target) = (escape_dot(&edge.source), escape_dot(&edge.target));
+            let kind = escape_dot(&edge.kind);
+            writeln!(
+                w,
+                r#"  "{}" {} "{}" [kind="{}", weight={}];"#,
+                source, op, target, kind, edge.weight
+            )?;
+        }
+
+        writeln!(w, "}}")?;
+        Ok(())
+    }
+
+    /// Writes one `source target weight` line per edge (tab-separated). Node
+    /// labels are used in place of ids.
+    fn write_edge_list<W: Write>(&self, w: &mut W) -> Result<()> {
+        let labels = self.nodes.iter().map(|n| (&n.id, &n.label)).collect::<HashMap<_, _>>();
+
+        for edge in &self.edges {
+            let source = labels.get(&edge.source).copied().unwrap_or(&edge.source);
+            let target = labels.get(&edge.target).copied().unwrap_or(&edge.target);
+            writeln!(w, "{}\t{}\t{}", source, target, edge.weight)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum SeriesFormat {
+    Csv,
+    /// An array of objects
+    Json,
+}
+
+/// Writes a time series where each point is a list of named values (in the
+/// same order for every point), e.g. for plotting in a dashboard.
+pub fn write_series<W: Write>(
+    w: &mut W,
+    format: SeriesFormat,
+    points: &[Vec<(&str, serde_json::Value)>],
+) -> Result<()> {
+    match format {
+        SeriesFormat::Csv => {
+            if let Some(point) = points.first() {
+                let header = point.iter().map(|(name, _)| escape_csv(name)).collect::<Vec<_>>();
+                writeln!(w, "{}", header.join(","))?;
+            }
+
+            for point in points {
+                let fields = point.iter().map(|(_, value)| match value {
+                    serde_json::Value::Null => String::new(),
+                    serde_json::Value::String(s) => escape_csv(s),
+                    value => escape_csv(&value.to_string()),
+                });
+                writeln!(w, "{}", fields.collect::<Vec<_>>().join(","))?;
+            }
+        }
+        SeriesFormat::Json => {
+            let points = points
+                .iter()
+                .map(|point| {
+                    let fields =
+                        point.iter().map(|(name, value)| (name.to_string(), value.clone()));
+                    serde_json::Value::Object(fields.collect())
+                })
+                .collect::<Vec<_>>();
+            serde_json::to_writer_pretty(&mut *w, &points)?;
+            writeln!(w)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    Text,
+    Sarif,
+}
+
+/// A problem found in a file (e.g. by the check subcommand).
+#[derive(new, Debug, Clone)]
+pub struct Finding {
+    pub path: String,
+    pub message: String,
+    /// The first and last line of the problem (if known)
+    pub lines: Option<(usize, usize)>,
+}
+
+/// The kind of problem a set of findings reports.
+#[derive(Debug, Clone, Copy)]
+pub struct Rule {
+    pub id: &'static str,
+    pub description: &'static str,
+}
+
+pub const MISSING_COCHANGE: Rule = Rule {
+    id: "missing-cochange",
+    description: "An entity which usually changes together with a changed entity was not changed",
+};
+
+pub const HOTSPOT: Rule = Rule {
+    id: "hotspot",
+    description: "An entity which changes often relative to its size or complexity",
+};
+
+pub const HIDDEN_DEPENDENCY: Rule = Rule {
+    id: "hidden-dependency",
+    description: "Two entities which usually change together but neither depends on the other",
+};
+
+pub const VOLATILE_INTERFACE: Rule = Rule {
+    id: "volatile-interface",
+    description: "An entity which many other entities depend on but which changes often",
+};
+
+#[derive(Serialize)]
+struct SarifLog<'a> {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    version: &'static str,
+    runs: Vec<SarifRun<'a>>,
+}
+
+#[derive(Serialize)]
+struct SarifRun<'a> {
+    tool: SarifTool,
+    results: Vec<SarifResult<'a>>,
+}
+
+#[derive(Serialize)]
+struct SarifTool {
+    driver: SarifDriver,
+}
+
+#[derive(Serialize)]
+struct SarifDriver {
+    name: &'static str,
+    version: &'static str,
+    rules: Vec<SarifRule>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifRule {
+    id: &'static str,
+    short_description: SarifMessage<'static>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifResult<'a> {
+    rule_id: &'a str,
+    level: &'static str,
+    message: SarifMessage<'a>,
+    locations: Vec<SarifLocation<'a>>,
+}
+
+#[derive(Serialize)]
+struct SarifMessage<'a> {
+    text: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifLocation<'a> {
+    physical_location: SarifPhysicalLocation<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifPhysicalLocation<'a> {
+    artifact_location: SarifArtifactLocation<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    region: Option<SarifRegion>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifRegion {
+    start_line: usize,
+    end_line: usize,
+}
+
+#[derive(Serialize)]
+struct SarifArtifactLocation<'a> {
+    uri: &'a str,
+}
+
+/// Writes the findings as a SARIF 2.1.0 log (e.g. for GitHub code scanning).
+/// Every finding is reported as a warning of the given rule.
+pub fn write_sarif<W: Write>(w: &mut W, rule: Rule, findings: &[Finding]) -> Result<()> {
+    let results = findings
+        .iter()
+        .map(|f| SarifResult {
+            rule_id: rule.id,
+            level: "warning",
+            message: SarifMessage { text: &f.message },
+            locations: vec![SarifLocation {
+                physical_location: SarifPhysicalLocation {
+                    artifact_location: SarifArtifactLocation { uri: &f.path },
+                    region: f
+                        .lines
+                        .map(|(start_line, end_line)| SarifRegion { start_line, end_line }),
+                },
+            }],
+        })
+        .collect();
+
+    let log = SarifLog {
+        schema: "https://json.schemastore.org/sarif-2.1.0.json",
+        version: "2.1.0",
+        runs: vec![SarifRun {
+            tool: SarifTool {
+                driver: SarifDriver {
+                    name: env!("CARGO_PKG_NAME"),
+                    version: env!("CARGO_PKG_VERSION"),
+                    rules: vec![SarifRule {
+                        id: rule.id,
+                        short_description: SarifMessage { text: rule.description },
+                    }],
+                },
+            },
+            results,
+        }],
+    };
+
+    serde_json::to_writer_pretty(&mut *w, &log)?;
+    writeln!(w)?;
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum DumpFormat {
+    Sqlite,
+    Jsonl,
+}
+
+#[derive(Serialize)]
+struct JsonEntity<'a> {
+    name: String,
+    kind: &'a str,
+    disc: String,
+}
+
+fn to_json_entity(entity: &ir::Entity) -> Vec<JsonEntity<'_>> {
+    let mut path = Vec::new();
+    let mut current = Some(entity);
+
+    while let Some(e) = current {
+        path.push(JsonEntity { name: e.name.clone(), kind: &e.kind, disc: e.disc.clone() });
+        current = e.parent.as_deref();
+    }
+
+    path.reverse();
+    path
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum JsonRecord<'a> {
+    Commit {
+        sha1: String,
+        is_merge: bool,
+        author_name: &'a str,
+        author_mail: &'a str,
+        author_date: i64,
+        commit_date: i64,
+        message: &'a str,
+    },
+    Change {
+        commit: String,
+        entity: Vec<JsonEntity<'a>>,
+        kind: char,
+        semantic_kind: char,
+        adds: usize,
+        dels: usize,
+        parent: usize,
+    },
+    Presence {
+        commit: String,
+        entity: Vec<JsonEntity<'a>>,
+        start_row: usize,
+        end_row: usize,
+        nesting: usize,
+        branches: usize,
+        params: usize,
+    },
+}
+
+/// Writes the commits, changes, and presences as JSON Lines (one record per
+/// line). Every record has a `type` field. Commits are written first (from
+/// oldest to newest) and are referenced by their hash. Entities are given as
+/// the path from their file down to themselves. The rows of presences follow
+/// the given convention.
+pub fn write_jsonl<W: Write>(
+    w: &mut W,
+    changes: &[ir::Change],
+    presences: &[ir::Presence],
+    lines: LineConvention,
+) -> Result<()> {
+    let mut commits = changes.iter().map(|c| &c.commit).collect::<Vec<_>>();
+    commits.extend(presences.iter().map(|p| &p.commit));
+    commits.sort_by_key(|c| (c.commit_date, c.sha1));
+    commits.dedup_by_key(|c| c.sha1);
+
+    for commit in commits {
+        let record = JsonRecord::Commit {
+            sha1: commit.sha1.to_string(),
+            is_merge: commit.is_merge,
+            author_name: &commit.author_name,
+            author_mail: &commit.author_mail,
+            author_date: commit.author_date.unix_timestamp(),
+            commit_date: commit.commit_date.unix_timestamp(),
+            message: &commit.message,
+        };
+        serde_json::to_writer(&mut *w, &record)?;
+        writeln!(w)?;
+    }
+
+    for change in changes {
+        let record = JsonRecord::Change {
+            commit: change.commit.sha1.to_string(),
+            entity: to_json_entity(&change.entity),
+            kind: change.kind.to_basic().to_char(),
+            semantic_kind: change.kind.to_char(),
+            adds: change.adds,
+            dels: change.dels,
+            parent: change.parent,
+        };
+        serde_json::to_writer(&mut *w, &record)?;
+        writeln!(w)?;
+    }
+
+    for presence in presences {
+        let (loc, complexity) = (presence.loc_entity.loc, presence.loc_entity.complexity);
+        let (start_row, end_row) = lines.convert(loc.0 as i64, Some(loc.1 as i64), false);
+        let record = JsonRecord::Presence {
+            commit: presence.commit.sha1.to_string(),
+            entity: to_json_entity(&presence.loc_entity.entity),
+            start_row: start_row as usize,
+            end_row: end_row.unwrap_or_default() as usize,
+            nesting: complexity.nesting,
+            branches: complexity.branches,
+            params: complexity.params,
+        };
+        serde_json::to_writer(&mut *w, &record)?;
+        writeln!(w)?;
+    }
+
+    w.flush()?;
+    Ok(())
+}
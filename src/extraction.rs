@@ -1,16 +1,37 @@
+use std::collections::hash_map::Entry;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
 use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
 
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
+use derive_new::new;
 use git2::Oid;
 use time::OffsetDateTime;
+use tree_sitter::Tree;
 
 use crate::gtl;
 use crate::ir;
+use crate::metrics::LanguageStats;
+use crate::parsing::get_code_rows;
+use crate::parsing::get_doc_comments;
+use crate::parsing::inject;
+use crate::parsing::normalize_path;
+use crate::parsing::to_file_entity;
+use crate::parsing::to_input_edits;
+use crate::parsing::to_line_starts;
+use crate::parsing::EntityParser;
 use crate::parsing::FileParser;
+use crate::vcs::Blobs;
+use crate::vcs::Vcs;
 
 // Be explicit about whether an identifier is from the git2 namespace or ir
 // namespace.
@@ -23,14 +44,53 @@ pub enum RefGlobKind {
     Remotes,
 }
 
+/// A predicate which a commit must satisfy to be yielded by a [`CommitWalk`].
+#[derive(Clone)]
+pub struct CommitFilter(Arc<dyn Fn(&git2::Commit) -> bool>);
+
+impl std::fmt::Debug for CommitFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CommitFilter")
+    }
+}
+
+/// How many commits in a row must be older than --since before a walk stops
+/// (like the slop of git-rev-list), since a commit with a skewed clock may come
+/// before ancestors which are still in range.
+const SINCE_SLOP: usize = 5;
+
 #[derive(Debug, Clone)]
 pub struct CommitWalk {
     sort_mode: git2::Sort,
     max_count: Option<usize>,
     since: Option<OffsetDateTime>,
     until: Option<OffsetDateTime>,
+    /// Whether the whole history is walked to find every commit in range
+    strict_dates: bool,
     globs: Vec<String>,
     start_oids: HashSet<Oid>,
+    hide_globs: Vec<String>,
+    hide_oids: HashSet<Oid>,
+    filters: Vec<CommitFilter>,
+    /// Only every nth commit which passes the filters is taken
+    every_nth: Option<usize>,
+}
+
+impl Default for CommitWalk {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn to_glob(kind: RefGlobKind, glob: Option<String>) -> String {
+    let glob = glob.unwrap_or("*".to_string());
+
+    match kind {
+        RefGlobKind::All => glob,
+        RefGlobKind::Branches => format!("heads/{}", glob),
+        RefGlobKind::Tags => format!("tags/{}", glob),
+        RefGlobKind::Remotes => format!("remotes/{}", glob),
+    }
 }
 
 impl CommitWalk {
@@ -40,8 +100,13 @@ impl CommitWalk {
             max_count: None,
             since: None,
             until: None,
+            strict_dates: false,
             globs: Vec::new(),
             start_oids: HashSet::new(),
+            hide_globs: Vec::new(),
+            hide_oids: HashSet::new(),
+            filters: Vec::new(),
+            every_nth: None,
         }
     }
 
@@ -61,26 +126,52 @@ impl CommitWalk {
         self.until = Some(until);
     }
 
-    pub fn push_glob(&mut self, kind: RefGlobKind, glob: Option<String>) {
-        let glob = glob.unwrap_or("*".to_string());
+    /// Walks the whole history instead of stopping once enough commits in a
+    /// row are older than --since. This finds every commit in range no matter
+    /// how skewed the clocks are, but may be much slower.
+    pub fn set_strict_dates(&mut self, strict_dates: bool) {
+        self.strict_dates = strict_dates;
+    }
 
-        self.globs.push(match kind {
-            RefGlobKind::All => glob,
-            RefGlobKind::Branches => format!("heads/{}", glob),
-            RefGlobKind::Tags => format!("tags/{}", glob),
-            RefGlobKind::Remotes => format!("remotes/{}", glob),
-        });
+    pub fn push_glob(&mut self, kind: RefGlobKind, glob: Option<String>) {
+        self.globs.push(to_glob(kind, glob));
     }
 
     pub fn push_start_oid(&mut self, oid: Oid) {
         self.start_oids.insert(oid);
     }
 
+    /// Excludes the commits reachable from the refs matching the glob.
+    #[allow(dead_code)]
+    pub fn hide_glob(&mut self, kind: RefGlobKind, glob: Option<String>) {
+        self.hide_globs.push(to_glob(kind, glob));
+    }
+
+    /// Excludes the given commit and all of its ancestors.
+    pub fn hide_oid(&mut self, oid: Oid) {
+        self.hide_oids.insert(oid);
+    }
+
+    /// Skips the commits for which `filter` returns false. Skipped commits do
+    /// not count towards the max count.
+    pub fn add_filter<F: Fn(&git2::Commit) -> bool + 'static>(&mut self, filter: F) {
+        self.filters.push(CommitFilter(Arc::new(filter)));
+    }
+
+    /// Takes only every nth commit which passes the filters (starting with the
+    /// first). Each walk counts on its own (e.g. each start of
+    /// `walk_per_start`).
+    pub fn set_every_nth(&mut self, every_nth: usize) {
+        self.every_nth = Some(every_nth);
+    }
+
     pub fn revwalk<'r>(&self, repo: &'r git2::Repository) -> Result<git2::Revwalk<'r>> {
         let mut revwalk = repo.revwalk()?;
         revwalk.set_sorting(self.sort_mode)?;
         self.globs.iter().try_for_each(|g| revwalk.push_glob(g))?;
         self.start_oids.iter().try_for_each(|&oid| revwalk.push(oid))?;
+        self.hide_globs.iter().try_for_each(|g| revwalk.hide_glob(g))?;
+        self.hide_oids.iter().try_for_each(|&oid| revwalk.hide(oid))?;
         Ok(revwalk)
     }
 
@@ -88,6 +179,61 @@ impl CommitWalk {
         let revwalk = self.revwalk(repo)?;
         Ok(CommitWalkIterator::new(self, repo, revwalk))
     }
+
+    /// Like `walk`, but takes at most `max_count` commits from each starting
+    /// commit (including each ref matched by a glob) rather than in total.
+    /// Commits reachable from several starts are only taken once. The commits
+    /// are sorted by time (newest first) and then limited by the overall max
+    /// count, if any. Also returns the commits which were skipped (see
+    /// `CommitWalkIterator::into_skipped`).
+    pub fn walk_per_start<'r>(
+        self,
+        repo: &'r git2::Repository,
+        max_count: usize,
+    ) -> Result<(Vec<git2::Commit<'r>>, HashSet<Oid>)> {
+        let mut starts = self.start_oids.iter().copied().collect::<Vec<_>>();
+
+        for glob in &self.globs {
+            // Like a revwalk, skip the refs which do not point to a commit
+            for reference in repo.references_glob(&format!("refs/{}", glob))? {
+                starts.extend(reference?.peel_to_commit().ok().map(|c| c.id()));
+            }
+        }
+
+        starts.sort();
+        starts.dedup();
+        let mut commits: Vec<git2::Commit> = Vec::new();
+        let mut seen = HashSet::new();
+        let mut skipped = HashSet::new();
+
+        for start in starts {
+            let mut walk = self.clone();
+            walk.globs.clear();
+            walk.start_oids = HashSet::from([start]);
+            walk.max_count = Some(max_count);
+
+            let mut iter = walk.walk(repo)?;
+
+            for commit in iter.by_ref() {
+                let commit = commit?;
+
+                if seen.insert(commit.id()) {
+                    commits.push(commit);
+                }
+            }
+
+            skipped.extend(iter.into_skipped());
+        }
+
+        commits.sort_by_key(|c| std::cmp::Reverse(c.time().seconds()));
+
+        if let Some(n) = self.max_count {
+            commits.truncate(n);
+        }
+
+        skipped.retain(|oid| !seen.contains(oid));
+        Ok((commits, skipped))
+    }
 }
 
 pub struct CommitWalkIterator<'r> {
@@ -95,11 +241,24 @@ pub struct CommitWalkIterator<'r> {
     repo: &'r git2::Repository,
     revwalk: git2::Revwalk<'r>,
     count: usize,
+    /// How many commits in a row were older than --since
+    old_count: usize,
+    /// How many commits passed the filters (see `CommitWalk::set_every_nth`)
+    filtered_count: usize,
+    /// The commits walked over but skipped by --until or a filter
+    skipped: HashSet<Oid>,
 }
 
 impl<'r> CommitWalkIterator<'r> {
     fn new(walk: CommitWalk, repo: &'r git2::Repository, revwalk: git2::Revwalk<'r>) -> Self {
-        Self { walk, repo, revwalk, count: 0 }
+        let skipped = HashSet::new();
+        Self { walk, repo, revwalk, count: 0, old_count: 0, filtered_count: 0, skipped }
+    }
+
+    /// The commits which were walked over so far but not yielded because of
+    /// --until or a filter (see `rewrite_parents`).
+    pub fn into_skipped(self) -> HashSet<Oid> {
+        self.skipped
     }
 }
 
@@ -112,6 +271,13 @@ impl<'r> Iterator for CommitWalkIterator<'r> {
             let commit_res = oid_res.and_then(|oid| self.repo.find_commit(oid));
 
             if let Err(err) = commit_res {
+                // The parents of the boundary commits of a shallow clone are
+                // missing, so the walk ends there
+                if err.code() == git2::ErrorCode::NotFound && self.repo.is_shallow() {
+                    log::debug!("Skipping a missing commit of a shallow clone: {}", err.message());
+                    continue;
+                }
+
                 return Some(Err(anyhow::Error::new(err)));
             }
 
@@ -128,11 +294,33 @@ impl<'r> Iterator for CommitWalkIterator<'r> {
             let is_valid_by_until = self.walk.until.map(|t| commit_time <= t).unwrap_or(true);
             let is_valid_by_n = self.walk.max_count.map(|n| self.count < n).unwrap_or(true);
 
-            if !is_valid_by_since || !is_valid_by_n {
+            if !is_valid_by_n {
                 break;
             }
 
-            if !is_valid_by_until {
+            // Older commits are skipped rather than ending the walk, since
+            // commits in range may still follow one with a skewed clock
+            if !is_valid_by_since {
+                self.old_count += 1;
+
+                if !self.walk.strict_dates && self.old_count >= SINCE_SLOP {
+                    break;
+                }
+
+                continue;
+            }
+
+            self.old_count = 0;
+
+            if !is_valid_by_until || !self.walk.filters.iter().all(|f| (f.0)(&commit)) {
+                self.skipped.insert(commit.id());
+                continue;
+            }
+
+            self.filtered_count += 1;
+
+            if self.walk.every_nth.is_some_and(|n| !(self.filtered_count - 1).is_multiple_of(n)) {
+                self.skipped.insert(commit.id());
                 continue;
             }
 
@@ -140,222 +328,2283 @@ impl<'r> Iterator for CommitWalkIterator<'r> {
             return Some(Ok(commit));
         }
 
-        return None;
+        None
     }
 }
 
-pub struct ExtractionCtx<'r> {
-    repo: &'r git2::Repository,
-    parser: FileParser,
-    cache: HashMap<(String, Oid), Vec<ir::LocEntity>>,
-}
+/// Opens the repository at or above the given directory, or else the one named
+/// by `$GIT_DIR`, or else the one at or above the current directory. The
+/// directory may be a working tree, a linked worktree, or a bare repository
+/// (such as a mirror).
+///
+/// Like git, the search upwards stops at the directories listed in
+/// `$GIT_CEILING_DIRECTORIES`. Unless a directory is given, the working tree is
+/// `$GIT_WORK_TREE` if set.
+///
+/// libgit2 refuses to open a repository when `$GIT_COMMON_DIR` is set, so it is
+/// resolved here instead: if it names a directory other than the one the
+/// repository already shares, its objects are added as an alternate.
+pub fn open_repo(path: Option<&Path>) -> Result<git2::Repository> {
+    let ceilings = std::env::var_os("GIT_CEILING_DIRECTORIES")
+        .map(|dirs| std::env::split_paths(&dirs).filter(|d| d.is_absolute()).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let discover = |path: &Path| {
+        git2::Repository::open_ext(path, git2::RepositoryOpenFlags::empty(), &ceilings)
+    };
+
+    let repo = match (path, std::env::var_os("GIT_DIR")) {
+        (Some(path), _) => discover(path),
+        (None, Some(git_dir)) => git2::Repository::open(git_dir),
+        (None, None) => discover(Path::new(".")),
+    }
+    .context("failed to find git repository at or above the provided directory")?;
 
-impl<'r> ExtractionCtx<'r> {
-    pub fn new(repo: &'r git2::Repository, parsing_ctx: FileParser) -> Self {
-        Self { repo, parser: parsing_ctx, cache: HashMap::new() }
+    // libgit2 does not read $GIT_WORK_TREE itself
+    if let (None, Some(work_tree)) = (path, std::env::var_os("GIT_WORK_TREE")) {
+        repo.set_workdir(Path::new(&work_tree), false)
+            .context("failed to use $GIT_WORK_TREE as the working tree")?;
     }
 
-    fn get_entities(&mut self, filename: &String, blob: Oid) -> &Vec<ir::LocEntity> {
-        self.cache.entry((filename.clone(), blob)).or_insert_with(|| {
-            if blob.is_zero() {
-                return Vec::new();
-            }
+    if let Some(common_dir) = std::env::var_os("GIT_COMMON_DIR") {
+        let common_dir = repo.path().join(common_dir);
+        let canonical = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
 
-            let blob = self.repo.find_blob(blob).unwrap();
-            self.parser.parse(blob.content(), filename).unwrap()
-        })
+        if canonical(&common_dir) != canonical(&get_common_dir(&repo)) {
+            let objects = common_dir.join("objects");
+            log::warn!("Using objects of $GIT_COMMON_DIR ({}) as an alternate", objects.display());
+            repo.odb()?
+                .add_disk_alternate(&objects.to_string_lossy())
+                .context("failed to add objects of $GIT_COMMON_DIR")?;
+        }
+    }
+
+    if repo.is_bare() {
+        log::info!("Opened bare repository at {}", repo.path().display());
+    } else if repo.is_worktree() {
+        log::info!("Opened linked worktree of {}", get_common_dir(&repo).display());
     }
+
+    Ok(repo)
 }
 
-impl TryFrom<git2::DiffHunk<'_>> for ir::Hunk {
-    type Error = anyhow::Error;
+/// The directory which linked worktrees share with the main worktree. This is
+/// the git directory itself for anything other than a linked worktree.
+fn get_common_dir(repo: &git2::Repository) -> PathBuf {
+    let commondir = repo.path().join("commondir");
 
-    fn try_from(diff_hunk: git2::DiffHunk<'_>) -> Result<Self, Self::Error> {
-        let old_start: usize = diff_hunk.old_start().try_into()?;
-        let new_start: usize = diff_hunk.new_start().try_into()?;
-        let old_lines: usize = diff_hunk.old_lines().try_into()?;
-        let new_lines: usize = diff_hunk.new_lines().try_into()?;
+    match std::fs::read_to_string(&commondir) {
+        Ok(dir) => repo.path().join(dir.trim()),
+        Err(_) => repo.path().to_path_buf(),
+    }
+}
 
-        Ok(ir::Hunk::new(
-            ir::Interval(old_start, old_start + old_lines),
-            ir::Interval(new_start, new_start + new_lines),
-        ))
+/// The objects directory of the repository, which linked worktrees share with
+/// the main worktree.
+fn get_objects_dir(repo: &git2::Repository) -> PathBuf {
+    get_common_dir(repo).join("objects")
+}
+
+/// Whether the repository has a commit-graph file. Walks over very large
+/// histories are much faster with one since commits need not be parsed to learn
+/// their parents and generation numbers.
+pub fn has_commit_graph(repo: &git2::Repository) -> bool {
+    let info = get_objects_dir(repo).join("info");
+    info.join("commit-graph").exists() || info.join("commit-graphs/commit-graph-chain").exists()
+}
+
+/// Writes a commit-graph file for every reachable commit. libgit2 cannot write
+/// these, so this runs the git executable.
+pub fn write_commit_graph(repo: &git2::Repository) -> Result<()> {
+    let status = Command::new("git")
+        .arg("--git-dir")
+        .arg(repo.path())
+        .args(["commit-graph", "write", "--reachable"])
+        .status()
+        .context("failed to run git (is it installed?)")?;
+
+    if !status.success() {
+        bail!("git commit-graph write failed ({})", status);
     }
+
+    Ok(())
 }
 
-fn get_diff_delta_path(diff_delta: &git2::DiffDelta) -> Result<String> {
-    let old_path = diff_delta.old_file().path();
-    let new_path = diff_delta.new_file().path();
+/// The hash of the empty tree, which git knows of even if no object exists.
+const EMPTY_TREE: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
 
-    Ok(match (old_path, new_path) {
-        (None, None) => bail!("at least one side of diff must be non-empty"),
-        (None, Some(path)) => path,
-        (Some(path), None) => path,
-        (Some(old_path), Some(new_path)) => {
-            if old_path != new_path {
-                bail!("renames and moves are not supported");
-            } else {
-                old_path
+/// The lines added and deleted in each text file of a commit (against its first
+/// parent) as counted by `git diff --numstat`. This runs the git executable so
+/// that the counts do not depend on libgit2.
+pub fn get_numstat(
+    repo: &git2::Repository,
+    commit: &git2::Commit,
+) -> Result<HashMap<String, (usize, usize)>> {
+    let parent = match commit.parent_ids().next() {
+        Some(parent) => parent.to_string(),
+        None => EMPTY_TREE.to_string(),
+    };
+
+    let output = Command::new("git")
+        .arg("--git-dir")
+        .arg(repo.path())
+        .args(["diff", "--numstat", "-z", "--no-renames", "--no-ext-diff", "--no-textconv"])
+        .arg(parent)
+        .arg(commit.id().to_string())
+        .output()
+        .context("failed to run git (is it installed?)")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("git diff --numstat failed ({}): {}", output.status, stderr.trim());
+    }
+
+    let mut numstat = HashMap::new();
+
+    for record in String::from_utf8_lossy(&output.stdout).split_terminator('\0') {
+        let mut fields = record.splitn(3, '\t');
+
+        match (fields.next(), fields.next(), fields.next()) {
+            // Binary files are counted as "-"
+            (Some(adds), Some(dels), Some(path)) => {
+                if let (Ok(adds), Ok(dels)) = (adds.parse(), dels.parse()) {
+                    numstat.insert(path.to_string(), (adds, dels));
+                }
             }
+            _ => bail!("unexpected output from git diff --numstat: {}", record),
         }
     }
-    .to_string_lossy()
-    .to_string())
+
+    Ok(numstat)
 }
 
-pub fn get_changes(ctx: &mut ExtractionCtx, df: &ir::DiffedFile) -> Result<Vec<ir::Change>> {
-    // Tree-sitter uses zero-based indices for rows and colums
-    // What about git-diff? I think its one-based.
-    // TODO: Confirm that git-diff is one-based.
-    // TODO: Check the inclusivity/exclusivity of the endpoints
-    let mut changes: HashMap<Arc<ir::Entity>, ir::ChangeBuilder> = HashMap::new();
+/// Which of the walked commits to take presence snapshots of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sampling {
+    /// Every tagged commit
+    Tags,
+    /// Every nth commit (starting with the oldest)
+    Commits(usize),
+    /// The first commit in each period (starting with the oldest)
+    Period(std::time::Duration),
+}
 
-    let filename = &df.filename;
-    let old_file = df.old_file;
-    let new_file = df.new_file;
+/// Selects a subset of the given commits according to `sampling`. The commits
+/// are returned from oldest to newest.
+pub fn sample_commits<'r>(
+    repo: &'r git2::Repository,
+    commits: &[git2::Commit<'r>],
+    sampling: Sampling,
+) -> Result<Vec<git2::Commit<'r>>> {
+    let mut commits = commits.to_vec();
+    commits.sort_by_key(|c| (c.time().seconds(), c.id()));
+
+    match sampling {
+        Sampling::Tags => {
+            let mut tagged = HashSet::new();
+
+            for r#ref in repo.references_glob("refs/tags/*")? {
+                if let Ok(commit) = r#ref?.peel_to_commit() {
+                    tagged.insert(commit.id());
+                }
+            }
 
-    for old_entity in ctx.get_entities(filename, old_file) {
-        let dels = df.hunks.iter().map(|h| h.old_interval.intersect(&old_entity.loc)).sum();
+            Ok(commits.into_iter().filter(|c| tagged.contains(&c.id())).collect())
+        }
+        Sampling::Commits(n) => Ok(commits.into_iter().step_by(n.max(1)).collect()),
+        Sampling::Period(period) => {
+            let period = period.as_secs() as i64;
+            let mut next = i64::MIN;
+            let mut sampled = Vec::new();
+
+            for commit in commits {
+                if commit.time().seconds() >= next {
+                    next = commit.time().seconds().saturating_add(period);
+                    sampled.push(commit);
+                }
+            }
 
-        if dels > 0 {
-            changes.entry(old_entity.entity.clone()).or_default().dels(dels);
+            Ok(sampled)
         }
     }
+}
 
-    for new_entity in ctx.get_entities(filename, new_file) {
-        let adds = df.hunks.iter().map(|h| h.new_interval.intersect(&new_entity.loc)).sum();
+/// One of `count` disjoint parts of the commits. Commits are assigned by their
+/// hash, so every process agrees on the part a commit belongs to no matter which
+/// other commits it has walked.
+#[derive(new, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    /// Zero-based index of this part
+    index: usize,
+    count: usize,
+}
 
-        if adds > 0 {
-            changes.entry(new_entity.entity.clone()).or_default().adds(adds);
-        }
+impl Shard {
+    pub fn contains(&self, oid: Oid) -> bool {
+        (hash_prefix(oid) % self.count as u64) as usize == self.index
     }
+}
 
-    let old_entities = ctx
-        .get_entities(filename, old_file)
-        .iter()
-        .map(|t| t.entity.clone())
-        .collect::<HashSet<_>>();
-    let new_entities = ctx
-        .get_entities(filename, new_file)
-        .iter()
-        .map(|t| t.entity.clone())
-        .collect::<HashSet<_>>();
+/// Whether the commit is among the given fraction (between 0 and 1) of all
+/// commits. Like shards, commits are picked by their hash, so every run picks
+/// the same commits.
+pub fn is_sampled(oid: Oid, rate: f64) -> bool {
+    (hash_prefix(oid) as f64) < rate * u64::MAX as f64
+}
+
+fn hash_prefix(oid: Oid) -> u64 {
+    let mut prefix = [0; 8];
+    prefix.copy_from_slice(&oid.as_bytes()[..8]);
+    u64::from_be_bytes(prefix)
+}
+
+/// Finds which of the given commits are reachable from each of the given refs.
+pub fn get_ref_members(
+    repo: &git2::Repository,
+    refs: &[ir::Ref],
+    commits: &[git2::Commit],
+) -> Result<Vec<ir::RefMember>> {
+    let mut processed = HashMap::new();
 
-    for deleted in old_entities.difference(&new_entities) {
-        changes.entry(deleted.clone()).or_default().kind(ir::ChangeKind::Deleted);
+    for commit in commits {
+        processed.insert(commit.id(), gtl::to_commit(commit)?);
     }
 
-    for created in new_entities.difference(&old_entities) {
-        changes.entry(created.clone()).or_default().kind(ir::ChangeKind::Added);
+    let mut members = Vec::new();
+
+    for r#ref in refs {
+        let mut revwalk = repo.revwalk()?;
+        revwalk.push(r#ref.commit.sha1)?;
+
+        for oid in revwalk {
+            if let Some(commit) = processed.get(&oid?) {
+                members.push(ir::RefMember::new(commit.clone(), r#ref.clone()));
+            }
+        }
     }
 
-    Ok(changes
-        .into_iter()
-        .map(|(e, mut change)| change.entity(e).commit(df.commit.clone()).build())
-        .try_collect()?)
+    Ok(members)
 }
 
-pub fn get_presences(
-    ctx: &mut ExtractionCtx,
-    commit: &ir::Commit,
-    suffix: &'static str,
-) -> Result<Vec<ir::Presence>> {
-    let mut blobs = Vec::new();
+/// Finds the tags which point to any of the given commits.
+pub fn get_tags(repo: &git2::Repository, commits: &[git2::Commit]) -> Result<Vec<ir::Tag>> {
+    let processed = commits.iter().map(|c| c.id()).collect::<HashSet<_>>();
+    let mut tags = Vec::new();
 
-    let tree = ctx.repo.find_commit(commit.sha1)?.tree()?;
+    for r#ref in repo.references_glob("refs/tags/*")? {
+        let r#ref = r#ref?;
 
-    tree.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
-        if !matches!(entry.kind().unwrap(), git2::ObjectType::Blob) {
-            return git2::TreeWalkResult::Ok;
+        match r#ref.peel_to_commit() {
+            Ok(commit) if processed.contains(&commit.id()) => tags.push(gtl::to_tag(&r#ref)?),
+            _ => continue,
         }
+    }
 
-        let filename = format!("{}{}", dir, entry.name().unwrap());
+    Ok(tags)
+}
 
-        if !filename.ends_with(suffix) {
-            return git2::TreeWalkResult::Ok;
+/// Reads the reflog of each of the given refs (given by full name). Entries are
+/// numbered from oldest to newest.
+pub fn get_ref_history(repo: &git2::Repository, names: &[String]) -> Result<Vec<ir::RefUpdate>> {
+    let mut updates = Vec::new();
+
+    for name in names {
+        let reflog = repo.reflog(name)?;
+
+        // The reflog lists the newest entry first
+        for (i, entry) in reflog.iter().enumerate() {
+            let old = Some(entry.id_old()).filter(|oid| !oid.is_zero());
+            let time = gtl::to_datetime(&entry.committer().when())?;
+            let message = entry.message_bytes().map(|m| String::from_utf8_lossy(m).to_string());
+            let seq = reflog.len() - i - 1;
+            updates.push(ir::RefUpdate::new(name.clone(), seq, old, entry.id_new(), time, message));
         }
+    }
+
+    Ok(updates)
+}
 
-        blobs.push((filename, entry.id()));
-        git2::TreeWalkResult::Ok
-    })?;
+/// A blob along with how it is parsed (see `ExtractionCtx::cache_key`).
+type CacheKey = (Oid, String);
+
+/// A parsed blob along with how many bytes it roughly takes up and when it was
+/// last used.
+struct CacheEntry {
+    /// The path which the entities are rooted at, which is the path the blob
+    /// was last found at
+    filename: String,
+    entities: Vec<ir::LocEntity>,
+    /// The entities marked as generated code
+    generated: Vec<ir::GeneratedEntity>,
+    /// The syntax tree of a blob parsed by tree-sitter, which lets the blobs
+    /// edited from it be parsed incrementally
+    tree: Option<Tree>,
+    size: usize,
+    last_used: u64,
+}
 
-    let mut presences = Vec::new();
+impl CacheEntry {
+    /// Moves the entities to the file at another path, so that a blob found at
+    /// several paths (e.g. a copied file) is only parsed once.
+    fn reroot(&mut self, filename: &str) {
+        let mut rerooted = HashMap::new();
 
-    for (filename, blob) in &blobs {
-        for loc_entity in ctx.get_entities(&filename, blob.clone()) {
-            presences.push(ir::Presence::new(loc_entity.clone(), commit.clone()));
+        for loc_entity in &mut self.entities {
+            loc_entity.entity = reroot(&loc_entity.entity, filename, &mut rerooted);
+        }
+
+        for generated in &mut self.generated {
+            generated.entity = reroot(&generated.entity, filename, &mut rerooted);
+        }
+
+        self.filename = filename.to_string();
+    }
+}
+
+/// The same entity within the file at another path. The rebuilt ancestors are
+/// shared through the map, which is keyed by the old ones.
+fn reroot(
+    entity: &Arc<ir::Entity>,
+    filename: &str,
+    rerooted: &mut HashMap<*const ir::Entity, Arc<ir::Entity>>,
+) -> Arc<ir::Entity> {
+    if let Some(new_entity) = rerooted.get(&Arc::as_ptr(entity)) {
+        return new_entity.clone();
+    }
+
+    let new_entity = match &entity.parent {
+        None => {
+            ir::Entity::new_root(normalize_path(filename), entity.kind.clone(), entity.disc.clone())
+        }
+        Some(parent) => ir::Entity::new(
+            reroot(parent, filename, rerooted),
+            entity.name.clone(),
+            entity.kind.clone(),
+            entity.disc.clone(),
+        ),
+    };
+    let new_entity = Arc::new(new_entity);
+    rerooted.insert(Arc::as_ptr(entity), new_entity.clone());
+    new_entity
+}
+
+/// How finely files are broken down into entities.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Granularity {
+    /// Parse the files of the supported language into entities and skip every
+    /// other file
+    Entity,
+    /// Record every file as a single entity without parsing it
+    File,
+    /// Parse the files of the supported language into entities and record
+    /// every other file as a single entity
+    Auto,
+}
+
+impl Granularity {
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            Granularity::Entity => "entity",
+            Granularity::File => "file",
+            Granularity::Auto => "auto",
         }
     }
+}
+
+/// A blob (or a snippet injected into it, by its index) parsed in a language.
+type ParsedKey = (String, Oid, Option<usize>);
 
-    Ok(presences)
+/// How many entities were found in a parsed blob, whether parsing it failed,
+/// and how long it took.
+type Parsed = (usize, bool, Duration);
+
+/// What a context found besides entities (see `ExtractionCtx::take_findings`).
+#[derive(Debug, Default)]
+pub struct Findings {
+    parse_errors: Vec<ir::ParseError>,
+    generated: HashSet<ir::GeneratedEntity>,
+    diagnostics: Vec<ir::Diagnostic>,
+    languages: HashMap<ParsedKey, Parsed>,
 }
 
-pub fn diff_all_files(
-    repo: &git2::Repository,
-    commits: &Vec<git2::Commit>,
+pub struct ExtractionCtx<'r> {
+    vcs: &'r dyn Vcs,
+    parser: FileParser,
+    /// The suffix of the files which the parser supports
     suffix: &'static str,
-) -> Result<Vec<ir::DiffedFile>> {
-    let mut diffed_files: HashMap<(String, Oid), ir::DiffedFile> = HashMap::new();
+    granularity: Granularity,
+    /// Parses the files which the parser does not support (with `auto`)
+    fallback: Option<Box<dyn EntityParser>>,
+    /// The extension to parse the code of each injected language like
+    injections: HashMap<String, String>,
+    /// Files with more than this fraction of their bytes in ERROR nodes are
+    /// recorded as a single entity
+    max_error_ratio: Option<f64>,
+    /// The blobs with syntax errors parsed so far
+    parse_errors: Vec<ir::ParseError>,
+    /// The entities marked as generated code found so far
+    generated: HashSet<ir::GeneratedEntity>,
+    /// The entries of each tree read so far (see `Vcs::read_tree`), so that a
+    /// directory seen before (e.g. unchanged at another ref) is not read again
+    trees: HashMap<Oid, Arc<Vec<(String, Oid)>>>,
+    /// What was parsed so far, where a blob which was parsed again (e.g. by
+    /// another thread or after it was evicted from the cache) is only kept once
+    languages: HashMap<ParsedKey, Parsed>,
+    /// Whether git is asked to fetch blobs which are missing from a partial
+    /// clone
+    fetch_missing: bool,
+    /// The blobs which could not be found so far
+    diagnostics: Vec<ir::Diagnostic>,
+    /// Whether changed lines which are blank or only hold comments are left
+    /// out of the adds and dels of parsed files
+    exclude_comments: bool,
+    /// The files below directories with these names (or paths) are left out
+    vendored_dirs: Vec<String>,
+    cache: HashMap<CacheKey, CacheEntry>,
+    /// The keys of the cache ordered from least to most recently used
+    recency: BTreeMap<u64, CacheKey>,
+    tick: u64,
+    /// Roughly how many bytes the cache takes up
+    cache_size: usize,
+    /// The least recently used blobs are evicted whenever the cache would grow
+    /// beyond this many bytes or entries
+    max_cache_size: Option<usize>,
+    max_cache_entries: Option<usize>,
+}
 
-    let mut opts = git2::DiffOptions::new();
-    opts.ignore_filemode(true);
-    opts.ignore_whitespace(false);
-    opts.ignore_whitespace_change(false);
-    opts.ignore_whitespace_eol(false);
-    opts.ignore_blank_lines(false);
-    opts.indent_heuristic(false);
-    opts.context_lines(0);
+impl<'r> ExtractionCtx<'r> {
+    pub fn new(vcs: &'r dyn Vcs, parsing_ctx: FileParser, suffix: &'static str) -> Self {
+        Self {
+            vcs,
+            parser: parsing_ctx,
+            suffix,
+            granularity: Granularity::Entity,
+            fallback: None,
+            injections: HashMap::new(),
+            max_error_ratio: None,
+            parse_errors: Vec::new(),
+            generated: HashSet::new(),
+            trees: HashMap::new(),
+            languages: HashMap::new(),
+            fetch_missing: false,
+            diagnostics: Vec::new(),
+            exclude_comments: false,
+            vendored_dirs: Vec::new(),
+            cache: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            cache_size: 0,
+            max_cache_size: None,
+            max_cache_entries: None,
+        }
+    }
 
-    for commit in commits {
-        let parents = commit.parents().collect::<Vec<_>>();
-        let new_tree = commit.tree()?;
+    pub fn set_max_cache_size(&mut self, max_cache_size: usize) {
+        self.max_cache_size = Some(max_cache_size);
+    }
 
-        let diff = match parents.len() {
-            0 => repo.diff_tree_to_tree(None, Some(&new_tree), Some(&mut opts)),
-            1 => {
-                let parent = parents.get(0).unwrap();
-                let old_tree = parent.tree()?;
-                repo.diff_tree_to_tree(Some(&old_tree), Some(&new_tree), Some(&mut opts))
+    pub fn set_max_cache_entries(&mut self, max_cache_entries: usize) {
+        self.max_cache_entries = Some(max_cache_entries.max(1));
+    }
+
+    pub fn set_granularity(&mut self, granularity: Granularity) {
+        self.granularity = granularity;
+    }
+
+    /// Sets the parser of the files which the parser does not support. These
+    /// are otherwise recorded as a single entity with `auto`.
+    pub fn set_fallback_parser(&mut self, fallback: Box<dyn EntityParser>) {
+        self.fallback = Some(fallback);
+    }
+
+    /// Parses the code of the given language which is injected into parsed
+    /// files (e.g. SQL in Java strings) like files with the given extension.
+    pub fn add_injection(&mut self, language: String, extension: String) {
+        self.injections.insert(language, extension);
+    }
+
+    pub fn set_max_error_ratio(&mut self, max_error_ratio: f64) {
+        self.max_error_ratio = Some(max_error_ratio);
+    }
+
+    /// Has the VCS fetch blobs which are missing from a partial clone. Otherwise,
+    /// such blobs are treated as if they had no entities.
+    pub fn set_fetch_missing(&mut self, fetch_missing: bool) {
+        self.fetch_missing = fetch_missing;
+    }
+
+    /// Takes the blobs which could not be found so far (each once).
+    pub fn take_diagnostics(&mut self) -> Vec<ir::Diagnostic> {
+        let mut diagnostics = std::mem::take(&mut self.diagnostics);
+        diagnostics.sort();
+        diagnostics.dedup();
+        diagnostics
+    }
+
+    /// Leaves changed lines which are blank or only hold comments out of the
+    /// adds and dels of the files which are parsed by tree-sitter.
+    pub fn set_exclude_comments(&mut self, exclude_comments: bool) {
+        self.exclude_comments = exclude_comments;
+    }
+
+    /// Leaves out the files below directories with the given names (e.g.
+    /// `node_modules`) or paths (e.g. `src/external`), wherever they are.
+    pub fn set_vendored_dirs(&mut self, vendored_dirs: Vec<String>) {
+        self.vendored_dirs = vendored_dirs;
+    }
+
+    /// Whether a file is below a vendored directory (see `set_vendored_dirs`).
+    pub fn is_vendored(&self, filename: &str) -> bool {
+        let path = format!("/{}", filename);
+        self.vendored_dirs.iter().any(|dir| path.contains(&format!("/{}/", dir.trim_matches('/'))))
+    }
+
+    /// Adds the files below a tree (which are not vendored) to `files`, with
+    /// their paths prefixed by `dir`. Trees which were read before are not read
+    /// again.
+    fn list_tree(&mut self, tree: Oid, dir: &str, files: &mut HashMap<String, Oid>) -> Result<()> {
+        let entries = match self.trees.get(&tree) {
+            Some(entries) => entries.clone(),
+            None => {
+                let entries = Arc::new(self.vcs.read_tree(tree)?);
+                self.trees.insert(tree, entries.clone());
+                entries
             }
-            _ => continue,
-        }?;
-
-        diff.foreach(
-            &mut |_, _| true,
-            None,
-            Some(&mut |delta, hunk| {
-                let is_supported_status = match delta.status() {
-                    git2::Delta::Added => true,
-                    git2::Delta::Deleted => true,
-                    git2::Delta::Modified => true,
-                    _ => false,
-                };
-
-                if !is_supported_status {
-                    log::warn!("Skipping unsupported diff status: {:?}", &delta.status());
-                    return true;
-                }
+        };
 
-                let filename = get_diff_delta_path(&delta)
-                    .expect("failed to get the path of the changed file");
+        for (name, oid) in entries.iter() {
+            let path = format!("{}{}", dir, name);
 
-                if !filename.to_lowercase().ends_with(suffix) {
-                    return true;
-                }
+            if !path.ends_with('/') {
+                files.insert(path, *oid);
+            } else if !self.is_vendored(&path) {
+                self.list_tree(*oid, &path, files)?;
+            }
+        }
 
-                let diffed_file =
-                    diffed_files.entry((filename.clone(), commit.id())).or_insert_with(|| {
-                        gtl::to_diffed_file(filename.clone(), commit, &delta)
-                            .expect("failed to create a diffed file")
-                    });
+        Ok(())
+    }
 
-                diffed_file.hunks.push(hunk.try_into().expect("failed to convert hunk"));
-                true
-            }),
-            None,
-        )
-        .context("failed to iterate over diff")?;
+    /// Takes the blobs with syntax errors which were parsed so far, sorted by
+    /// path. Each blob is kept once, under the first (in sorted order) of the
+    /// paths it was parsed at, so it does not matter which thread parsed it.
+    pub fn take_parse_errors(&mut self) -> Vec<ir::ParseError> {
+        let mut parse_errors = std::mem::take(&mut self.parse_errors);
+        parse_errors.sort_by(|a, b| (a.blob, &a.filename).cmp(&(b.blob, &b.filename)));
+        parse_errors.dedup_by_key(|e| e.blob);
+        parse_errors.sort();
+        parse_errors
+    }
+
+    /// Takes the entities marked as generated code which were found so far.
+    pub fn take_generated(&mut self) -> Vec<ir::GeneratedEntity> {
+        let mut generated = std::mem::take(&mut self.generated).into_iter().collect::<Vec<_>>();
+        generated.sort();
+        generated
+    }
+
+    /// The VCS which blobs are read from.
+    pub fn vcs(&self) -> &'r dyn Vcs {
+        self.vcs
+    }
+
+    /// Takes whatever was found so far besides entities (e.g. parse errors), so
+    /// that the findings of the contexts of several threads can be combined.
+    pub fn take_findings(&mut self) -> Findings {
+        Findings {
+            parse_errors: std::mem::take(&mut self.parse_errors),
+            generated: std::mem::take(&mut self.generated),
+            diagnostics: std::mem::take(&mut self.diagnostics),
+            languages: std::mem::take(&mut self.languages),
+        }
+    }
+
+    /// Adds the findings of another context (see `take_findings`).
+    pub fn add_findings(&mut self, findings: Findings) {
+        self.parse_errors.extend(findings.parse_errors);
+        self.generated.extend(findings.generated);
+        self.diagnostics.extend(findings.diagnostics);
+
+        for (key, parsed) in findings.languages {
+            self.languages.entry(key).or_insert(parsed);
+        }
+    }
+
+    /// Takes what was parsed of each language so far, sorted by language. Each
+    /// blob counts once however many times it was parsed.
+    pub fn take_language_stats(&mut self) -> Vec<LanguageStats> {
+        let mut languages: BTreeMap<String, LanguageStats> = BTreeMap::new();
+
+        for ((language, _, _), (entities, failed, duration)) in std::mem::take(&mut self.languages)
+        {
+            let stats = languages
+                .entry(language.clone())
+                .or_insert_with(|| LanguageStats { language, ..LanguageStats::default() });
+            stats.files += 1;
+            stats.entities += entities;
+            stats.parse_errors += failed as usize;
+            stats.duration += duration;
+        }
+
+        languages.into_values().collect()
+    }
+
+    fn record_language(
+        &mut self,
+        language: &str,
+        blob: Oid,
+        injection: Option<usize>,
+        parsed: Parsed,
+    ) {
+        self.languages.entry((language.to_string(), blob, injection)).or_insert(parsed);
+    }
+
+    /// The suffix of the files to extract (or nothing if every file is).
+    pub fn suffix(&self) -> Option<&'static str> {
+        match self.granularity {
+            Granularity::Entity => Some(self.suffix),
+            Granularity::File | Granularity::Auto => None,
+        }
+    }
+
+    /// The settings which decide what is extracted from each commit.
+    pub fn coverage(&self) -> ir::Coverage {
+        let files = format!("*{}", self.suffix().unwrap_or(""));
+        let mut languages = Vec::new();
+
+        if self.granularity != Granularity::File {
+            languages.push(self.suffix.to_string());
+            languages.extend(self.injections.iter().map(|(l, e)| format!("{}={}", l, e)));
+        }
+
+        if self.granularity == Granularity::Auto && self.fallback.is_some() {
+            languages.push("ctags".to_string());
+        }
+
+        languages.sort();
+        let mut excluded = self.vendored_dirs.clone();
+        excluded.sort();
+        excluded.dedup();
+        ir::Coverage::new(files, self.granularity.to_str().to_string(), languages, excluded)
+    }
+
+    fn is_parsed(&self, filename: &str) -> bool {
+        self.granularity != Granularity::File && has_suffix(filename, Some(self.suffix))
+    }
+
+    /// Blobs are cached by their hash alone, so that a blob found at several
+    /// paths or refs is only parsed once. How a blob is parsed still depends on
+    /// its name, though: by tree-sitter, by the fallback parser (which may
+    /// guess the language from the name), or not at all.
+    fn cache_key(&self, filename: &str, blob: Oid) -> CacheKey {
+        let parser = if self.is_parsed(filename) {
+            self.suffix.to_string()
+        } else if self.granularity == Granularity::Auto && self.fallback.is_some() {
+            filename.rsplit('/').next().unwrap_or(filename).to_string()
+        } else {
+            String::new()
+        };
+
+        (blob, parser)
+    }
+
+    /// Parses a file and keeps track of its syntax errors. Files which are
+    /// mostly errors are recorded as a single entity instead.
+    fn parse(
+        &mut self,
+        filename: &String,
+        blob: Oid,
+        source: &[u8],
+        old_tree: Option<&Tree>,
+    ) -> (Vec<ir::LocEntity>, Tree) {
+        let start = Instant::now();
+        let (tree, mut entities) = self.parser.parse_tree(source, filename, old_tree).unwrap();
+        let errors = self.parser.syntax_errors();
+        let (bytes, error_bytes) = (source.len(), errors.bytes);
+        let skipped = errors.count > 0
+            && self.max_error_ratio.is_some_and(|max| error_bytes as f64 > max * bytes as f64);
+        let language = self.suffix.trim_start_matches('.');
+        let count = if skipped { 1 } else { entities.len() };
+        self.record_language(language, blob, None, (count, errors.count > 0, start.elapsed()));
+
+        if !self.injections.is_empty() {
+            self.add_injections(&tree, blob, source, filename, &mut entities);
+        }
+
+        if errors.count == 0 {
+            return (entities, tree);
+        }
+
+        let parse_error =
+            ir::ParseError::new(filename.clone(), blob, errors.count, error_bytes, bytes, skipped);
+        self.parse_errors.push(parse_error);
+
+        if skipped {
+            (vec![to_file_entity(source, filename)], tree)
+        } else {
+            (entities, tree)
+        }
+    }
+
+    /// Adds the entities of the code injected into a parsed file. Injected code
+    /// which cannot be parsed is skipped.
+    fn add_injections(
+        &mut self,
+        tree: &Tree,
+        blob: Oid,
+        source: &[u8],
+        filename: &String,
+        entities: &mut Vec<ir::LocEntity>,
+    ) {
+        let injections = self.parser.get_injections(tree, source);
+
+        for (i, injection) in injections.iter().enumerate() {
+            let extension = match self.injections.get(&injection.language) {
+                Some(extension) => extension,
+                None => continue,
+            };
+
+            // The name only tells the parser (e.g. ctags) which language to expect
+            let guest_name = format!("{}#{}{}", filename, i, extension);
+            let content = injection.content(source);
+            let start = Instant::now();
+
+            let guest = if has_suffix(&guest_name, Some(self.suffix)) {
+                self.parser.parse(&content, &guest_name)
+            } else if let Some(fallback) = &mut self.fallback {
+                fallback.parse(&content, &guest_name)
+            } else {
+                continue;
+            };
+
+            let count = guest.as_ref().map_or(0, |guest| guest.len());
+            let parsed = (count, guest.is_err(), start.elapsed());
+            self.record_language(&injection.language, blob, Some(i), parsed);
+
+            match guest {
+                Ok(guest) => inject(entities, injection, guest),
+                Err(err) => log::warn!(
+                    "Failed to parse {} code in {}: {:#}",
+                    injection.language,
+                    filename,
+                    err
+                ),
+            }
+        }
+    }
+
+    /// The cached tree of the old blob, edited to match the new blob so that
+    /// only the changed parts of it need to be parsed again.
+    fn get_edited_tree(
+        &self,
+        filename: &str,
+        old_blob: Oid,
+        hunks: &[ir::Hunk],
+        source: &[u8],
+    ) -> Option<Tree> {
+        let mut tree = self.cache.get(&self.cache_key(filename, old_blob))?.tree.clone()?;
+        let old_source = self.vcs.read_blob(filename, old_blob).ok()?;
+
+        for edit in to_input_edits(&old_source, source, hunks) {
+            tree.edit(&edit);
+        }
+
+        Some(tree)
+    }
+
+    /// Finds a blob, fetching it first if it is missing (and fetching is on).
+    /// Blobs which are still missing are recorded as diagnostics.
+    fn find_blob(&mut self, filename: &str, blob: Oid) -> Option<Vec<u8>> {
+        let mut found = self.vcs.read_blob(filename, blob);
+
+        if found.as_ref().map_or_else(is_missing_object, |_| false) && self.fetch_missing {
+            match self.vcs.fetch_blob(blob) {
+                Ok(()) => found = self.vcs.read_blob(filename, blob),
+                Err(err) => log::warn!("Failed to fetch blob {}: {:#}", blob, err),
+            }
+        }
+
+        match found {
+            Ok(found) => Some(found),
+            Err(err) => {
+                log::warn!("Skipping {} ({}): {:#}", filename, blob, err);
+                let subject = format!("{}:{}", filename, blob);
+                let message = format!("{:#}", err);
+                self.diagnostics.push(ir::Diagnostic::new(None, "missing_blob", subject, message));
+                None
+            }
+        }
+    }
+
+    fn is_cache_full(&self, incoming: usize) -> bool {
+        let by_size = self.max_cache_size.is_some_and(|max| self.cache_size + incoming > max);
+        let by_entries = self.max_cache_entries.is_some_and(|max| self.cache.len() >= max);
+        !self.cache.is_empty() && (by_size || by_entries)
+    }
+
+    fn get_entities(&mut self, filename: &String, blob: Oid) -> &Vec<ir::LocEntity> {
+        self.get_edited_entities(filename, blob, None, None)
+    }
+
+    /// Which rows of a blob hold code (see `get_code_rows`) if comments are
+    /// excluded and the blob was parsed by tree-sitter.
+    fn get_code_rows(&mut self, filename: &String, blob: Oid) -> Option<Vec<bool>> {
+        if !self.exclude_comments {
+            return None;
+        }
+
+        self.get_parsed_code_rows(filename, blob)
+    }
+
+    /// Which rows of a blob hold code (see `get_code_rows`) if the blob was
+    /// parsed by tree-sitter, whether or not comments are excluded.
+    fn get_parsed_code_rows(&mut self, filename: &String, blob: Oid) -> Option<Vec<bool>> {
+        self.get_entities(filename, blob);
+        self.cache[&self.cache_key(filename, blob)].tree.as_ref().map(get_code_rows)
+    }
+
+    /// The doc comment of each entity of a blob which has one. A doc comment
+    /// belongs to the outermost entity starting on the line after it.
+    fn get_docs(&mut self, filename: &String, blob: Oid) -> HashMap<Arc<ir::Entity>, ir::Interval> {
+        self.get_entities(filename, blob);
+        let entry = &self.cache[&self.cache_key(filename, blob)];
+        let mut docs = HashMap::new();
+
+        let (tree, source) = match (&entry.tree, self.vcs.read_blob(filename, blob)) {
+            (Some(tree), Ok(source)) => (tree, source),
+            _ => return docs,
+        };
+
+        for (doc, line) in get_doc_comments(tree, &source) {
+            let starting = entry.entities.iter().filter(|e| e.loc.0 == line);
+            let end = starting.clone().map(|e| e.loc.1).max();
+
+            for loc_entity in starting.filter(|e| Some(e.loc.1) == end) {
+                docs.insert(loc_entity.entity.clone(), doc);
+            }
+        }
+
+        docs
+    }
+
+    /// Like `get_entities`, but if the blob needs to be parsed, the tree of
+    /// the blob it was edited from (with the given hunks) is reused if cached,
+    /// and the blob is only read if its content is not given.
+    fn get_edited_entities(
+        &mut self,
+        filename: &String,
+        blob: Oid,
+        base: Option<(Oid, &[ir::Hunk])>,
+        content: Option<&[u8]>,
+    ) -> &Vec<ir::LocEntity> {
+        let key = self.cache_key(filename, blob);
+        self.tick += 1;
+
+        if let Some(entry) = self.cache.get_mut(&key) {
+            self.recency.remove(&entry.last_used);
+            self.recency.insert(self.tick, key.clone());
+            entry.last_used = self.tick;
+
+            if entry.filename != *filename {
+                entry.reroot(filename);
+                self.generated.extend(entry.generated.iter().cloned());
+            }
+        } else {
+            let source = match content {
+                Some(content) => Some(content.to_vec()),
+                None if blob.is_zero() => None,
+                None => self.find_blob(filename, blob),
+            };
+
+            let (entities, tree) = match &source {
+                // Missing blobs have no entities
+                None => (Vec::new(), None),
+                Some(source) if self.is_parsed(filename) => {
+                    let old_tree = base.and_then(|(old_blob, hunks)| {
+                        self.get_edited_tree(filename, old_blob, hunks, source)
+                    });
+                    let (entities, tree) = self.parse(filename, blob, source, old_tree.as_ref());
+                    (entities, Some(tree))
+                }
+                Some(source) => {
+                    let start = Instant::now();
+                    let mut failed = false;
+                    let (language, entities) = match (&mut self.fallback, self.granularity) {
+                        (Some(fallback), Granularity::Auto) => {
+                            let entities = fallback.parse(source, filename).unwrap_or_else(|err| {
+                                log::warn!("Failed to parse {}: {:#}", filename, err);
+                                failed = true;
+                                vec![to_file_entity(source, filename)]
+                            });
+                            ("ctags", entities)
+                        }
+                        _ => ("unparsed", vec![to_file_entity(source, filename)]),
+                    };
+                    let parsed = (entities.len(), failed, start.elapsed());
+                    self.record_language(language, blob, None, parsed);
+                    (entities, None)
+                }
+            };
+
+            let generated = source.as_ref().map_or_else(Vec::new, |s| find_generated(s, &entities));
+            self.generated.extend(generated.iter().cloned());
+
+            // A tree is assumed to take up about as many bytes as its source
+            let tree_size = tree.as_ref().map_or(0, |t| t.root_node().end_byte());
+
+            let size = filename.len()
+                + tree_size
+                + entities
+                    .iter()
+                    .map(|e| {
+                        std::mem::size_of::<ir::LocEntity>()
+                            + std::mem::size_of::<ir::Entity>()
+                            + e.entity.name.len()
+                            + e.entity.disc.len()
+                    })
+                    .sum::<usize>();
+
+            while self.is_cache_full(size) {
+                let (_, evicted) = self.recency.pop_first().unwrap();
+                self.cache_size -= self.cache.remove(&evicted).unwrap().size;
+            }
+
+            self.cache_size += size;
+            self.recency.insert(self.tick, key.clone());
+            let filename = filename.clone();
+            let entry =
+                CacheEntry { filename, entities, generated, tree, size, last_used: self.tick };
+            self.cache.insert(key.clone(), entry);
+        }
+
+        &self.cache[&key].entities
+    }
+}
+
+impl TryFrom<git2::DiffHunk<'_>> for ir::Hunk {
+    type Error = anyhow::Error;
+
+    fn try_from(diff_hunk: git2::DiffHunk<'_>) -> Result<Self, Self::Error> {
+        let old_start: usize = diff_hunk.old_start().try_into()?;
+        let new_start: usize = diff_hunk.new_start().try_into()?;
+        let old_lines: usize = diff_hunk.old_lines().try_into()?;
+        let new_lines: usize = diff_hunk.new_lines().try_into()?;
+
+        Ok(ir::Hunk::new(
+            ir::Interval(old_start, old_start + old_lines),
+            ir::Interval(new_start, new_start + new_lines),
+        ))
+    }
+}
+
+fn get_diff_delta_path(diff_delta: &git2::DiffDelta) -> Result<String> {
+    let old_path = diff_delta.old_file().path();
+    let new_path = diff_delta.new_file().path();
+
+    Ok(match (old_path, new_path) {
+        (None, None) => bail!("at least one side of diff must be non-empty"),
+        (None, Some(path)) => path,
+        (Some(path), None) => path,
+        (Some(old_path), Some(new_path)) => {
+            if old_path != new_path {
+                bail!("renames and moves are not supported");
+            } else {
+                old_path
+            }
+        }
+    }
+    .to_string_lossy()
+    .to_string())
+}
+
+pub fn get_changes(ctx: &mut ExtractionCtx, df: &ir::DiffedFile) -> Result<Vec<ir::Change>> {
+    // Tree-sitter uses zero-based indices for rows and colums
+    // What about git-diff? I think its one-based.
+    // TODO: Confirm that git-diff is one-based.
+    // TODO: Check the inclusivity/exclusivity of the endpoints
+    let mut changes: HashMap<Arc<ir::Entity>, ir::ChangeBuilder> = HashMap::new();
+
+    if df.is_symlink() {
+        return Ok(vec![get_symlink_change(df)?]);
+    }
+
+    let filename = &df.filename;
+    let old_file = df.old_file;
+    let new_file = df.new_file;
+
+    let old_code_rows = ctx.get_code_rows(filename, old_file);
+
+    for old_entity in ctx.get_entities(filename, old_file) {
+        let dels = df
+            .hunks
+            .iter()
+            .map(|h| count_lines(&h.old_interval, &old_entity.loc, old_code_rows.as_deref()))
+            .sum();
+
+        if dels > 0 {
+            changes.entry(old_entity.entity.clone()).or_default().dels(dels);
+        }
+    }
+
+    let base = Some((old_file, &df.hunks[..])).filter(|_| !old_file.is_zero());
+    ctx.get_edited_entities(filename, new_file, base, None);
+    let new_code_rows = ctx.get_code_rows(filename, new_file);
+
+    for new_entity in ctx.get_entities(filename, new_file) {
+        let adds = df
+            .hunks
+            .iter()
+            .map(|h| count_lines(&h.new_interval, &new_entity.loc, new_code_rows.as_deref()))
+            .sum();
+
+        if adds > 0 {
+            changes.entry(new_entity.entity.clone()).or_default().adds(adds);
+        }
+    }
+
+    let old_entities = ctx
+        .get_entities(filename, old_file)
+        .iter()
+        .map(|t| t.entity.clone())
+        .collect::<HashSet<_>>();
+    let new_entities = ctx
+        .get_entities(filename, new_file)
+        .iter()
+        .map(|t| t.entity.clone())
+        .collect::<HashSet<_>>();
+
+    let deleted = old_entities.difference(&new_entities).cloned().collect::<Vec<_>>();
+    let created = new_entities.difference(&old_entities).cloned().collect::<Vec<_>>();
+    let kinds = get_semantic_kinds(ctx, df, &deleted, &created);
+
+    for deleted in deleted {
+        changes.entry(deleted).or_default().kind(ir::ChangeKind::Deleted);
+    }
+
+    for created in created {
+        changes.entry(created).or_default().kind(ir::ChangeKind::Added);
+    }
+
+    for (entity, kind) in kinds {
+        changes.entry(entity).or_default().kind(kind);
+    }
+
+    if !old_file.is_zero() && !new_file.is_zero() && df.modes.0 != df.modes.1 {
+        if let Some(file) = new_entities.iter().find(|e| e.parent.is_none()) {
+            changes.entry(file.clone()).or_default().kind(ir::ChangeKind::ModeChanged);
+        }
+    }
+
+    Ok(changes
+        .into_iter()
+        .map(|(e, mut change)| change.entity(e).commit(df.commit.clone()).parent(df.parent).build())
+        .collect::<Result<_, _>>()?)
+}
+
+/// A symlink is recorded as a file entity whose only line is its target. That
+/// it is a symlink (and where it points) is kept in `symlink_updates`.
+fn get_symlink_change(df: &ir::DiffedFile) -> Result<ir::Change> {
+    let kind = Arc::new("file".to_string());
+    let entity = ir::Entity::new_root(normalize_path(&df.filename), kind, String::new());
+
+    let kind = match (df.old_file.is_zero(), df.new_file.is_zero()) {
+        (true, _) => ir::ChangeKind::Added,
+        (_, true) => ir::ChangeKind::Deleted,
+        _ => ir::ChangeKind::Retargeted,
+    };
+
+    Ok(ir::ChangeBuilder::default()
+        .entity(Arc::new(entity))
+        .commit(df.commit.clone())
+        .parent(df.parent)
+        .kind(kind)
+        .adds(usize::from(!df.new_file.is_zero()))
+        .dels(usize::from(!df.old_file.is_zero()))
+        .build()?)
+}
+
+/// The old and new target of a diffed symlink (or nothing if it is not one).
+pub fn get_symlink_update(
+    ctx: &ExtractionCtx,
+    df: &ir::DiffedFile,
+) -> Result<Option<ir::SymlinkUpdate>> {
+    if !df.is_symlink() {
+        return Ok(None);
+    }
+
+    let get_target = |blob: Oid| -> Result<Option<String>> {
+        if blob.is_zero() {
+            return Ok(None);
+        }
+
+        let blob = ctx.vcs.read_blob(&df.filename, blob)?;
+        Ok(Some(String::from_utf8_lossy(&blob).to_string()))
+    };
+
+    let (old_target, new_target) = (get_target(df.old_file)?, get_target(df.new_file)?);
+    let (commit, path) = (df.commit.clone(), df.filename.clone());
+    Ok(Some(ir::SymlinkUpdate::new(commit, df.parent, path, old_target, new_target)))
+}
+
+/// The number of lines of a hunk within the lines of an entity. If the rows
+/// which hold code are given, only these lines are counted.
+fn count_lines(hunk: &ir::Interval, loc: &ir::Interval, code_rows: Option<&[bool]>) -> usize {
+    match (hunk.intersection(loc), code_rows) {
+        (None, _) => 0,
+        (Some(lines), None) => lines.1 - lines.0,
+        (Some(lines), Some(code_rows)) => (lines.0..lines.1)
+            .filter(|line| line.checked_sub(1).and_then(|row| code_rows.get(row)) != Some(&false))
+            .count(),
+    }
+}
+
+/// Refines the kinds of the changes to a diffed file. An added entity which
+/// replaces exactly one deleted entity is renamed, moved, or has a changed
+/// signature, and a modified entity whose changed lines are all blank or
+/// comments is only a change to its documentation. Binary files are skipped.
+///
+/// Comments are told apart from code by the tree of each blob, so changes to
+/// the documentation of blobs which tree-sitter did not parse are not found.
+/// Neither are they with --exclude-comments, as such changes have no lines.
+fn get_semantic_kinds(
+    ctx: &mut ExtractionCtx,
+    df: &ir::DiffedFile,
+    deleted: &[Arc<ir::Entity>],
+    created: &[Arc<ir::Entity>],
+) -> HashMap<Arc<ir::Entity>, ir::ChangeKind> {
+    let mut kinds = HashMap::new();
+
+    if df.old_file.is_zero()
+        || df.new_file.is_zero()
+        || df.hunks.iter().any(|h| h.new_interval.1 == usize::MAX)
+    {
+        return kinds;
+    }
+
+    let read = |blob| ctx.vcs.read_blob(&df.filename, blob);
+    let (old_blob, new_blob) = match (read(df.old_file), read(df.new_file)) {
+        (Ok(old_blob), Ok(new_blob)) => (old_blob, new_blob),
+        _ => return kinds,
+    };
+    let old_source = String::from_utf8_lossy(&old_blob);
+    let new_source = String::from_utf8_lossy(&new_blob);
+    let old_lines = old_source.lines().collect::<Vec<_>>();
+    let new_lines = new_source.lines().collect::<Vec<_>>();
+
+    let old_locs = to_locs(ctx.get_entities(&df.filename, df.old_file));
+    let new_locs = to_locs(ctx.get_entities(&df.filename, df.new_file));
+
+    for entity in created {
+        let is_moved = |old: &&Arc<ir::Entity>| {
+            old.name == entity.name && old.disc == entity.disc && old.parent != entity.parent
+        };
+        let is_resigned = |old: &&Arc<ir::Entity>| {
+            old.name == entity.name && old.disc != entity.disc && old.parent == entity.parent
+        };
+        let is_renamed = |old: &&Arc<ir::Entity>| {
+            old.name != entity.name && old.disc == entity.disc && old.parent == entity.parent && {
+                let old_text = get_text(&old_lines, old_locs[*old]);
+                let new_text = get_text(&new_lines, new_locs[entity]);
+                old_text.replace(&old.name, &entity.name) == new_text
+            }
+        };
+
+        // The file itself has no parent, and is never replaced
+        let candidates = deleted
+            .iter()
+            .filter(|old| entity.parent.is_some() && old.kind == entity.kind)
+            .collect::<Vec<_>>();
+        let is_unique = |pred: &dyn Fn(&&Arc<ir::Entity>) -> bool| {
+            candidates.iter().filter(|old| pred(old)).count() == 1
+        };
+
+        let kind = if is_unique(&is_moved) {
+            ir::ChangeKind::Moved
+        } else if is_unique(&is_resigned) {
+            ir::ChangeKind::SignatureChanged
+        } else if is_unique(&is_renamed) {
+            ir::ChangeKind::Renamed
+        } else {
+            continue;
+        };
+
+        kinds.insert(entity.clone(), kind);
+    }
+
+    if ctx.exclude_comments {
+        return kinds;
+    }
+
+    let old_code_rows = ctx.get_parsed_code_rows(&df.filename, df.old_file);
+    let new_code_rows = ctx.get_parsed_code_rows(&df.filename, df.new_file);
+    let (old_code_rows, new_code_rows) = match (old_code_rows, new_code_rows) {
+        (Some(old_code_rows), Some(new_code_rows)) => (old_code_rows, new_code_rows),
+        _ => return kinds,
+    };
+
+    for (entity, &old_loc) in &old_locs {
+        let new_loc = match new_locs.get(entity) {
+            Some(&new_loc) => new_loc,
+            None => continue,
+        };
+        let old_changed = df.hunks.iter().filter_map(|h| h.old_interval.intersection(&old_loc));
+        let new_changed = df.hunks.iter().filter_map(|h| h.new_interval.intersection(&new_loc));
+        let mut changed = old_changed
+            .map(|i| (&old_code_rows, i))
+            .chain(new_changed.map(|i| (&new_code_rows, i)))
+            .flat_map(|(code_rows, i)| {
+                (i.0..i.1).map(|line| line.checked_sub(1).and_then(|row| code_rows.get(row)))
+            })
+            .peekable();
+
+        if changed.peek().is_some() && changed.all(|is_code| is_code == Some(&false)) {
+            kinds.insert(entity.clone(), ir::ChangeKind::DocOnly);
+        }
+    }
+
+    kinds
+}
+
+fn to_locs(loc_entities: &[ir::LocEntity]) -> HashMap<Arc<ir::Entity>, ir::Interval> {
+    loc_entities.iter().map(|e| (e.entity.clone(), e.loc)).collect()
+}
+
+/// The text of the lines an entity spans (including its last line).
+fn get_text(lines: &[&str], loc: ir::Interval) -> String {
+    let start = loc.0.saturating_sub(1).min(lines.len());
+    let end = loc.1.min(lines.len()).max(start);
+    lines[start..end].join("\n")
+}
+
+/// The lines of each entity which a diffed file added and deleted. They add up
+/// to the adds and dels of the changes found by `get_changes` (unless comments
+/// are excluded from those).
+pub fn get_changed_lines(ctx: &mut ExtractionCtx, df: &ir::DiffedFile) -> Vec<ir::ChangedLines> {
+    if df.is_symlink() {
+        return Vec::new();
+    }
+
+    let mut lines: HashMap<Arc<ir::Entity>, (Vec<ir::Interval>, Vec<ir::Interval>)> =
+        HashMap::new();
+
+    for old_entity in ctx.get_entities(&df.filename, df.old_file) {
+        for hunk in &df.hunks {
+            if let Some(deleted) = hunk.old_interval.intersection(&old_entity.loc) {
+                lines.entry(old_entity.entity.clone()).or_default().1.push(deleted);
+            }
+        }
+    }
+
+    for new_entity in ctx.get_entities(&df.filename, df.new_file) {
+        for hunk in &df.hunks {
+            if let Some(added) = hunk.new_interval.intersection(&new_entity.loc) {
+                lines.entry(new_entity.entity.clone()).or_default().0.push(added);
+            }
+        }
+    }
+
+    lines
+        .into_iter()
+        .map(|(entity, (added, deleted))| {
+            ir::ChangedLines::new(df.commit.clone(), df.parent, entity, added, deleted)
+        })
+        .collect()
+}
+
+/// Maps each hunk of a diffed file onto the entities whose lines it deleted or
+/// added. The entities of both blobs are usually still cached by `get_changes`.
+pub fn get_mapped_hunks(ctx: &mut ExtractionCtx, df: &ir::DiffedFile) -> Vec<ir::MappedHunk> {
+    if df.is_symlink() {
+        return Vec::new();
+    }
+
+    let mut lines: Vec<HashMap<Arc<ir::Entity>, (usize, usize)>> =
+        vec![HashMap::new(); df.hunks.len()];
+
+    for old_entity in ctx.get_entities(&df.filename, df.old_file) {
+        for (i, hunk) in df.hunks.iter().enumerate() {
+            let dels = hunk.old_interval.intersect(&old_entity.loc);
+
+            if dels > 0 {
+                lines[i].entry(old_entity.entity.clone()).or_default().1 += dels;
+            }
+        }
+    }
+
+    for new_entity in ctx.get_entities(&df.filename, df.new_file) {
+        for (i, hunk) in df.hunks.iter().enumerate() {
+            let adds = hunk.new_interval.intersect(&new_entity.loc);
+
+            if adds > 0 {
+                lines[i].entry(new_entity.entity.clone()).or_default().0 += adds;
+            }
+        }
+    }
+
+    df.hunks
+        .iter()
+        .zip(lines)
+        .enumerate()
+        .map(|(i, (hunk, lines))| {
+            let mut entities =
+                lines.into_iter().map(|(e, (adds, dels))| (e, adds, dels)).collect::<Vec<_>>();
+            entities.sort();
+            ir::MappedHunk::new(
+                df.commit.clone(),
+                df.parent,
+                df.filename.clone(),
+                i,
+                *hunk,
+                entities,
+            )
+        })
+        .collect()
+}
+
+/// How a diffed file changed the doc comment of each entity which has one
+/// (before or after). Entities whose doc comment and code are both unchanged
+/// are left out.
+pub fn get_doc_changes(ctx: &mut ExtractionCtx, df: &ir::DiffedFile) -> Vec<ir::DocChange> {
+    if df.is_symlink() {
+        return Vec::new();
+    }
+
+    let old_docs = ctx.get_docs(&df.filename, df.old_file);
+    let new_docs = ctx.get_docs(&df.filename, df.new_file);
+
+    // The doc adds, doc dels, and changed lines of code of each entity
+    let mut lines: HashMap<Arc<ir::Entity>, (usize, usize, usize)> =
+        old_docs.keys().chain(new_docs.keys()).map(|e| (e.clone(), (0, 0, 0))).collect();
+
+    for old_entity in ctx.get_entities(&df.filename, df.old_file) {
+        if let Some(lines) = lines.get_mut(&old_entity.entity) {
+            for hunk in &df.hunks {
+                lines.1 +=
+                    old_docs.get(&old_entity.entity).map_or(0, |d| hunk.old_interval.intersect(d));
+                lines.2 += hunk.old_interval.intersect(&old_entity.loc);
+            }
+        }
+    }
+
+    for new_entity in ctx.get_entities(&df.filename, df.new_file) {
+        if let Some(lines) = lines.get_mut(&new_entity.entity) {
+            for hunk in &df.hunks {
+                lines.0 +=
+                    new_docs.get(&new_entity.entity).map_or(0, |d| hunk.new_interval.intersect(d));
+                lines.2 += hunk.new_interval.intersect(&new_entity.loc);
+            }
+        }
+    }
+
+    lines
+        .into_iter()
+        .filter_map(|(entity, (doc_adds, doc_dels, code))| {
+            let touched = match (doc_adds + doc_dels > 0, code > 0) {
+                (true, true) => ir::Touched::Both,
+                (true, false) => ir::Touched::Doc,
+                (false, true) => ir::Touched::Code,
+                (false, false) => return None,
+            };
+            let (commit, parent) = (df.commit.clone(), df.parent);
+            Some(ir::DocChange::new(commit, parent, entity, doc_adds, doc_dels, touched))
+        })
+        .collect()
+}
+
+/// What is stored of the source text of each present entity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum SourceMode {
+    /// The (compressed) text along with its hash
+    Text,
+    /// Only the hash of the text
+    Hash,
+}
+
+/// The entities present at a commit. With a source mode, the source text of
+/// each is also returned, which runs from the start of its first line through
+/// the end of its last. Only the hash of the text is kept unless the mode is
+/// `Text`. Each file is read at most once, and files which cannot be read are
+/// recorded as diagnostics (see `ExtractionCtx::take_diagnostics`).
+pub fn get_presences(
+    ctx: &mut ExtractionCtx,
+    commit: &ir::Commit,
+    sources: Option<SourceMode>,
+) -> Result<(Vec<ir::Presence>, Vec<ir::EntitySource>)> {
+    let blobs = list_present_files(ctx, commit)?;
+    let (mut presences, mut entity_sources) = (Vec::new(), Vec::new());
+
+    for (filename, blob) in &blobs {
+        let mode = match sources {
+            Some(mode) => mode,
+            None => {
+                for loc_entity in ctx.get_entities(filename, *blob) {
+                    presences.push(ir::Presence::new(loc_entity.clone(), commit.clone()));
+                }
+
+                continue;
+            }
+        };
+
+        // Files which cannot be read have no entities either
+        let content = match ctx.find_blob(filename, *blob) {
+            Some(content) => content,
+            None => continue,
+        };
+        let line_starts = to_line_starts(&content);
+        let to_byte = |line: usize| line_starts.get(line).copied().unwrap_or(content.len());
+
+        for loc_entity in ctx.get_edited_entities(filename, *blob, None, Some(&content)) {
+            let (first, last) = (loc_entity.loc.0, loc_entity.loc.1);
+            let text =
+                &content[to_byte(first.saturating_sub(1))..to_byte(last).max(to_byte(first))];
+            let hash = Oid::hash_object(git2::ObjectType::Blob, text)?;
+            let text = Some(String::from_utf8_lossy(text).to_string())
+                .filter(|_| mode == SourceMode::Text);
+            let entity = loc_entity.entity.clone();
+            entity_sources.push(ir::EntitySource::new(entity, commit.clone(), hash, text));
+            presences.push(ir::Presence::new(loc_entity.clone(), commit.clone()));
+        }
+    }
+
+    Ok((presences, entity_sources))
+}
+
+/// The files of a commit whose entities are present, sorted by path. Only the
+/// trees which were not seen before are read (if the VCS has trees), so the
+/// snapshots of similar commits (e.g. the heads of branches) are listed
+/// quickly whatever order they come in. Otherwise, every file is listed.
+fn list_present_files(ctx: &mut ExtractionCtx, commit: &ir::Commit) -> Result<Vec<(String, Oid)>> {
+    let suffix = ctx.suffix();
+    let files = match ctx.vcs.commit_tree(commit.sha1)? {
+        Some(tree) => {
+            let mut files = HashMap::new();
+            ctx.list_tree(tree, "", &mut files)?;
+            files.into_iter().collect::<Vec<_>>()
+        }
+        None => ctx.vcs.list_files(commit.sha1)?,
+    };
+
+    let mut blobs = files
+        .into_iter()
+        .filter(|(filename, _)| has_suffix(filename, suffix) && !ctx.is_vendored(filename))
+        .collect::<Vec<_>>();
+    blobs.sort();
+    Ok(blobs)
+}
+
+/// The names of the directories which usually hold third-party code.
+pub const VENDORED_DIRS: &[&str] =
+    &["node_modules", "third-party", "third_party", "thirdparty", "vendor"];
+
+/// Whether the file ends with the suffix (ignoring case). Every file matches
+/// a missing suffix.
+pub fn has_suffix(filename: &str, suffix: Option<&str>) -> bool {
+    suffix.is_none_or(|suffix| filename.to_lowercase().ends_with(suffix))
+}
+
+fn get_diff_options() -> git2::DiffOptions {
+    let mut opts = git2::DiffOptions::new();
+    opts.ignore_filemode(true);
+    opts.include_typechange(true);
+    opts.ignore_whitespace(false);
+    opts.ignore_whitespace_change(false);
+    opts.ignore_whitespace_eol(false);
+    opts.ignore_blank_lines(false);
+    opts.indent_heuristic(false);
+    opts.context_lines(0);
+    opts
+}
+
+/// How to diff merge commits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum MergeMode {
+    /// Ignore merge commits
+    Skip,
+    /// Diff merge commits (including octopus merges) against each parent
+    AllParents,
+}
+
+/// What to do with paths which differ only by case. On case-insensitive file
+/// systems (e.g. on macOS and Windows) they are the same file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum CaseCollisions {
+    /// Keep each path as a separate file
+    Ignore,
+    /// Keep each path as a separate file but record a diagnostic
+    Flag,
+    /// Record every path as the most recently changed one (and a diagnostic)
+    Merge,
+}
+
+/// Groups the paths of the diffed files which differ only by case (after
+/// Unicode normalization). The paths of each group are ordered from the most
+/// to the least recently changed.
+pub fn find_case_collisions(diffed_files: &[ir::DiffedFile]) -> Vec<Vec<String>> {
+    let mut diffed_files = diffed_files.iter().collect::<Vec<_>>();
+    diffed_files.sort_by(|a, b| {
+        b.commit.commit_date.cmp(&a.commit.commit_date).then_with(|| a.filename.cmp(&b.filename))
+    });
+
+    let mut groups: HashMap<String, Vec<String>> = HashMap::new();
+
+    for diffed_file in diffed_files {
+        let path = normalize_path(&diffed_file.filename);
+        let group = groups.entry(path.to_lowercase()).or_default();
+
+        if !group.contains(&path) {
+            group.push(path);
+        }
+    }
+
+    let mut collisions = groups.into_values().filter(|g| g.len() > 1).collect::<Vec<_>>();
+    collisions.sort();
+    collisions
+}
+
+/// Rewrites the parents of each commit to its nearest ancestors among the
+/// walked commits, like the parent rewriting of git-log. A parent which was
+/// skipped by the walk (see `CommitWalkIterator::into_skipped`) is replaced by
+/// its own (rewritten) parents. Any other parent (e.g. one which is hidden or
+/// older than --since) is kept, so the walk never reaches beyond its boundary.
+pub fn rewrite_parents(
+    repo: &git2::Repository,
+    commits: &[git2::Commit],
+    skipped: &HashSet<Oid>,
+) -> Result<HashMap<Oid, Vec<Oid>>> {
+    let mut resolved = HashMap::new();
+    let mut rewritten = HashMap::new();
+
+    for commit in commits {
+        let parent_ids = commit.parent_ids().collect::<Vec<_>>();
+        let parents = resolve_parents(repo, &parent_ids, skipped, &mut resolved)?;
+        rewritten.insert(commit.id(), parents);
+    }
+
+    Ok(rewritten)
+}
+
+/// Replaces each skipped commit among the parents by its resolved parents,
+/// keeping the first occurrence of each. The parents of skipped commits are
+/// resolved with an explicit stack, since long runs of them are common.
+fn resolve_parents(
+    repo: &git2::Repository,
+    parent_ids: &[Oid],
+    skipped: &HashSet<Oid>,
+    resolved: &mut HashMap<Oid, Vec<Oid>>,
+) -> Result<Vec<Oid>> {
+    let mut stack =
+        parent_ids.iter().filter(|id| skipped.contains(id)).copied().collect::<Vec<_>>();
+
+    while let Some(&id) = stack.last() {
+        if resolved.contains_key(&id) {
+            stack.pop();
+            continue;
+        }
+
+        let grandparent_ids = repo.find_commit(id)?.parent_ids().collect::<Vec<_>>();
+        let pending = grandparent_ids
+            .iter()
+            .filter(|p| skipped.contains(p) && !resolved.contains_key(p))
+            .copied()
+            .collect::<Vec<_>>();
+
+        if pending.is_empty() {
+            let ids = resolve_parents(repo, &grandparent_ids, skipped, resolved)?;
+            resolved.insert(id, ids);
+            stack.pop();
+        } else {
+            stack.extend(pending);
+        }
+    }
+
+    let mut parents = Vec::new();
+
+    for id in parent_ids {
+        let ids = match resolved.get(id) {
+            Some(ids) => ids.clone(),
+            None => vec![*id],
+        };
+
+        for id in ids {
+            if !parents.contains(&id) {
+                parents.push(id);
+            }
+        }
+    }
+
+    Ok(parents)
+}
+
+/// Diffed files keyed by filename, commit and parent index.
+type DiffedFiles = HashMap<(String, Oid, usize), ir::DiffedFile>;
+
+/// The changed files of the given commits (along with any submodules whose
+/// pointer was bumped). Each commit is diffed against its parents, or else
+/// against its rewritten parents if given (see `rewrite_parents`).
+///
+/// Commits which cannot be diffed because objects are missing (e.g. in a
+/// shallow or partial clone) are skipped and reported as diagnostics. With
+/// `fetch_missing`, git is first asked to fetch the missing objects of a
+/// partial clone from its promisor remote.
+pub fn diff_all_files(
+    repo: &git2::Repository,
+    commits: &Vec<git2::Commit>,
+    rewritten: Option<&HashMap<Oid, Vec<Oid>>>,
+    suffix: Option<&'static str>,
+    merges: MergeMode,
+    fetch_missing: bool,
+) -> Result<(Vec<ir::DiffedFile>, Vec<ir::SubmoduleUpdate>, Vec<ir::Diagnostic>)> {
+    let mut diffed_files: DiffedFiles = HashMap::new();
+    let mut submodule_updates = Vec::new();
+    let mut diagnostics = Vec::new();
+    let mut opts = get_diff_options();
+    // Changes to the executable bit are recorded as changes of their own
+    opts.ignore_filemode(false);
+
+    for commit in commits {
+        let parent_ids = match rewritten.and_then(|r| r.get(&commit.id())) {
+            Some(ids) => ids.clone(),
+            None => commit.parent_ids().collect(),
+        };
+        let parents =
+            parent_ids.iter().filter_map(|&id| repo.find_commit(id).ok()).collect::<Vec<_>>();
+
+        // The parents beyond the boundary of a shallow clone are missing. Such
+        // a commit is not diffed against nothing, which would add every file.
+        if parents.len() < parent_ids.len() {
+            let message = format!(
+                "{} of its {} parents are missing (is the repository shallow?)",
+                parent_ids.len() - parents.len(),
+                parent_ids.len()
+            );
+            log::warn!("Skipping commit {}: {}", commit.id(), message);
+            let commit = Some(gtl::to_commit(commit)?);
+            diagnostics.push(ir::Diagnostic::new(
+                commit,
+                "missing_parents",
+                String::new(),
+                message,
+            ));
+            continue;
+        }
+
+        let mut diffed = diff_commit(repo, commit, &parents, suffix, merges, &mut opts);
+
+        if fetch_missing && diffed.as_ref().map_or_else(is_missing_object, |_| false) {
+            fetch_missing_objects(repo, commit)?;
+            diffed = diff_commit(repo, commit, &parents, suffix, merges, &mut opts);
+        }
+
+        match diffed {
+            Ok((files, updates)) => {
+                diffed_files.extend(files);
+                submodule_updates.extend(updates);
+            }
+            Err(err) if is_missing_object(&err) => {
+                let message = format!("{:#}", err);
+                log::warn!("Skipping commit {}: {}", commit.id(), message);
+                let commit = Some(gtl::to_commit(commit)?);
+                diagnostics.push(ir::Diagnostic::new(
+                    commit,
+                    "missing_objects",
+                    String::new(),
+                    message,
+                ));
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    let diffed_files = diffed_files.into_values().collect::<Vec<_>>();
+    Ok((diffed_files, submodule_updates, diagnostics))
+}
+
+/// The changed files and submodule updates of a single commit (against each of
+/// the given parents).
+fn diff_commit(
+    repo: &git2::Repository,
+    commit: &git2::Commit,
+    parents: &[git2::Commit],
+    suffix: Option<&'static str>,
+    merges: MergeMode,
+    opts: &mut git2::DiffOptions,
+) -> Result<(DiffedFiles, Vec<ir::SubmoduleUpdate>)> {
+    let mut diffed_files = HashMap::new();
+    let mut submodule_updates = Vec::new();
+    let new_tree = commit.tree()?;
+
+    let diffs = match (parents.len(), merges) {
+        (0, _) => vec![repo.diff_tree_to_tree(None, Some(&new_tree), Some(opts))?],
+        (1, _) | (_, MergeMode::AllParents) => parents
+            .iter()
+            .map(|parent| {
+                let old_tree = parent.tree()?;
+                repo.diff_tree_to_tree(Some(&old_tree), Some(&new_tree), Some(opts))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        (_, MergeMode::Skip) => Vec::new(),
+    };
+
+    for (parent, diff) in diffs.iter().enumerate() {
+        diff_file_hunks(&mut diffed_files, commit, parent, diff, suffix)?;
+
+        // Binary files are only known to be binary once their hunks were
+        // looked for
+        for delta in diff.deltas() {
+            if is_submodule(&delta) {
+                let path = get_diff_delta_path(&delta)?;
+                let old = Some(delta.old_file().id()).filter(|oid| !oid.is_zero());
+                let new = Some(delta.new_file().id()).filter(|oid| !oid.is_zero());
+                let commit = gtl::to_commit(commit)?;
+                submodule_updates.push(ir::SubmoduleUpdate::new(commit, parent, path, old, new));
+            } else if delta.status() == git2::Delta::Typechange {
+                add_typechange(&mut diffed_files, commit, parent, &delta, suffix)?;
+            } else if !is_supported_status(delta.status()) {
+                log::warn!("Skipping unsupported diff status: {:?}", &delta.status());
+            } else if delta.flags().is_binary() {
+                add_binary(&mut diffed_files, commit, parent, &delta, suffix)?;
+            } else if delta.status() == git2::Delta::Modified
+                && delta.old_file().mode() != delta.new_file().mode()
+            {
+                add_mode_change(&mut diffed_files, commit, parent, &delta, suffix)?;
+            }
+        }
+    }
+
+    Ok((diffed_files, submodule_updates))
+}
+
+/// Whether an error was caused by an object missing from the repository.
+fn is_missing_object(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<git2::Error>().is_some_and(|e| e.code() == git2::ErrorCode::NotFound)
+}
+
+/// Has git fetch the objects needed to diff a commit which are missing from a
+/// partial clone. libgit2 cannot fetch from a promisor remote, but git does so
+/// whenever it needs a missing object.
+fn fetch_missing_objects(repo: &git2::Repository, commit: &git2::Commit) -> Result<()> {
+    log::info!("Fetching the missing objects of commit {}", commit.id());
+
+    let status = Command::new("git")
+        .arg("--git-dir")
+        .arg(repo.path())
+        .args(["diff-tree", "-r", "-p", "-m", "--root", "--no-renames", "--no-ext-diff"])
+        .arg(commit.id().to_string())
+        .stdout(Stdio::null())
+        .status()
+        .context("failed to run git (is it installed?)")?;
+
+    if !status.success() {
+        log::warn!("Failed to fetch the missing objects of commit {} ({})", commit.id(), status);
+    }
+
+    // Let libgit2 see the packs which were just fetched
+    repo.odb()?.refresh()?;
+    Ok(())
+}
+
+/// Has git fetch a single blob which is missing from a partial clone.
+pub fn fetch_missing_blob(repo: &git2::Repository, blob: Oid) -> Result<()> {
+    let status = Command::new("git")
+        .arg("--git-dir")
+        .arg(repo.path())
+        .args(["cat-file", "blob"])
+        .arg(blob.to_string())
+        .stdout(Stdio::null())
+        .status()
+        .context("failed to run git (is it installed?)")?;
+
+    if !status.success() {
+        log::warn!("Failed to fetch blob {} ({})", blob, status);
+    }
+
+    repo.odb()?.refresh()?;
+    Ok(())
+}
+
+fn is_supported_status(status: git2::Delta) -> bool {
+    matches!(status, git2::Delta::Added | git2::Delta::Deleted | git2::Delta::Modified)
+}
+
+/// Whether either side of the delta is a submodule (i.e. a gitlink).
+fn is_submodule(delta: &git2::DiffDelta) -> bool {
+    delta.old_file().mode() == git2::FileMode::Commit
+        || delta.new_file().mode() == git2::FileMode::Commit
+}
+
+/// Adds a file which became a symlink (or vice versa). The symlink side is
+/// treated as missing, so every entity of the file is either deleted or added.
+fn add_typechange(
+    diffed_files: &mut DiffedFiles,
+    commit: &git2::Commit,
+    parent: usize,
+    delta: &git2::DiffDelta,
+    suffix: Option<&'static str>,
+) -> Result<()> {
+    let is_link = |file: &git2::DiffFile| file.mode() == git2::FileMode::Link;
+    let old_file = if is_link(&delta.old_file()) { Oid::zero() } else { delta.old_file().id() };
+    let new_file = if is_link(&delta.new_file()) { Oid::zero() } else { delta.new_file().id() };
+    add_whole_file(diffed_files, commit, parent, delta, old_file, new_file, suffix)
+}
+
+/// Adds a binary file. Binary files have no hunks, so each side is changed as
+/// a whole.
+fn add_binary(
+    diffed_files: &mut DiffedFiles,
+    commit: &git2::Commit,
+    parent: usize,
+    delta: &git2::DiffDelta,
+    suffix: Option<&'static str>,
+) -> Result<()> {
+    let (old_file, new_file) = (delta.old_file().id(), delta.new_file().id());
+    add_whole_file(diffed_files, commit, parent, delta, old_file, new_file, suffix)
+}
+
+/// Adds a file whose executable bit changed. If none of its lines changed, it
+/// has no hunks.
+fn add_mode_change(
+    diffed_files: &mut DiffedFiles,
+    commit: &git2::Commit,
+    parent: usize,
+    delta: &git2::DiffDelta,
+    suffix: Option<&'static str>,
+) -> Result<()> {
+    let filename = get_diff_delta_path(delta)?;
+
+    if !has_suffix(&filename, suffix) {
+        return Ok(());
+    }
+
+    let key = (filename.clone(), commit.id(), parent);
+
+    if let Entry::Vacant(entry) = diffed_files.entry(key) {
+        entry.insert(gtl::to_diffed_file(filename, commit, parent, delta)?);
+    }
+
+    Ok(())
+}
+
+fn add_whole_file(
+    diffed_files: &mut DiffedFiles,
+    commit: &git2::Commit,
+    parent: usize,
+    delta: &git2::DiffDelta,
+    old_file: Oid,
+    new_file: Oid,
+    suffix: Option<&'static str>,
+) -> Result<()> {
+    let filename = get_diff_delta_path(delta)?;
+
+    if !has_suffix(&filename, suffix) {
+        return Ok(());
+    }
+
+    // Every line of each present side is changed
+    let everything = ir::Interval(0, usize::MAX);
+    let nothing = ir::Interval(0, 0);
+    let hunk = ir::Hunk::new(
+        if old_file.is_zero() { nothing } else { everything },
+        if new_file.is_zero() { nothing } else { everything },
+    );
+
+    let commit = gtl::to_commit(commit)?;
+    let modes = gtl::to_modes(delta);
+    let diffed_file = ir::DiffedFile::new(
+        filename.clone(),
+        commit,
+        parent,
+        old_file,
+        new_file,
+        vec![hunk],
+        modes,
+    );
+    diffed_files.insert((filename, diffed_file.commit.sha1, parent), diffed_file);
+    Ok(())
+}
+
+fn diff_file_hunks(
+    diffed_files: &mut DiffedFiles,
+    commit: &git2::Commit,
+    parent: usize,
+    diff: &git2::Diff,
+    suffix: Option<&'static str>,
+) -> Result<()> {
+    diff.foreach(
+        &mut |_, _| true,
+        None,
+        Some(&mut |delta, hunk| {
+            // Unsupported statuses, typechanges, and submodules are already handled
+            if !is_supported_status(delta.status()) || is_submodule(&delta) {
+                return true;
+            }
+
+            let filename =
+                get_diff_delta_path(&delta).expect("failed to get the path of the changed file");
+
+            if !has_suffix(&filename, suffix) {
+                return true;
+            }
+
+            let key = (filename.clone(), commit.id(), parent);
+            let diffed_file = diffed_files.entry(key).or_insert_with(|| {
+                gtl::to_diffed_file(filename.clone(), commit, parent, &delta)
+                    .expect("failed to create a diffed file")
+            });
+
+            diffed_file.hunks.push(hunk.try_into().expect("failed to convert hunk"));
+            true
+        }),
+        None,
+    )
+    .context("failed to iterate over diff")?;
+
+    Ok(())
+}
+
+/// The source of the new side of an uncommitted change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Uncommitted {
+    /// The staged changes (i.e. `git diff --cached`)
+    Index,
+    /// The staged and unstaged changes, including untracked files (i.e. `git
+    /// diff HEAD`)
+    Workdir,
+}
+
+/// Diffs HEAD against the index or the working tree as if the difference were
+/// a commit. The pseudo-commit has a zero hash and is authored by the current
+/// user (if configured) at the current time.
+///
+/// Files from the working tree are hashed (as `git hash-object` would, but
+/// without filters) and returned by their hashes rather than written to the
+/// object database. Read them through a `WorkdirVcs`.
+pub fn diff_uncommitted(
+    repo: &git2::Repository,
+    source: Uncommitted,
+    suffix: Option<&'static str>,
+) -> Result<(Vec<ir::DiffedFile>, Blobs)> {
+    if repo.is_bare() {
+        bail!("{} is a bare repository, so it has no index or working tree", repo.path().display());
+    }
+
+    let mut diffed_files: HashMap<String, ir::DiffedFile> = HashMap::new();
+    let mut opts = get_diff_options();
+    let head = repo.head().and_then(|h| h.peel_to_tree()).ok();
+
+    let diff = match source {
+        Uncommitted::Index => repo.diff_tree_to_index(head.as_ref(), None, Some(&mut opts))?,
+        Uncommitted::Workdir => {
+            opts.include_untracked(true);
+            opts.recurse_untracked_dirs(true);
+            opts.show_untracked_content(true);
+            repo.diff_tree_to_workdir_with_index(head.as_ref(), Some(&mut opts))?
+        }
+    };
+
+    let now = OffsetDateTime::now_utc();
+    let (name, mail) = match repo.signature() {
+        Ok(sig) => (
+            String::from_utf8_lossy(sig.name_bytes()).to_string(),
+            String::from_utf8_lossy(sig.email_bytes()).to_lowercase(),
+        ),
+        Err(_) => (String::new(), String::new()),
+    };
+    let message = Arc::new(String::new());
+    let commit = ir::Commit::new(Oid::zero(), false, name, mail, now, now, message);
+
+    diff.foreach(
+        &mut |_, _| true,
+        None,
+        Some(&mut |delta, hunk| {
+            let is_supported_status = matches!(
+                delta.status(),
+                git2::Delta::Added
+                    | git2::Delta::Deleted
+                    | git2::Delta::Modified
+                    | git2::Delta::Untracked
+            );
+
+            if !is_supported_status {
+                log::warn!("Skipping unsupported diff status: {:?}", &delta.status());
+                return true;
+            }
+
+            let filename =
+                get_diff_delta_path(&delta).expect("failed to get the path of the changed file");
+
+            if !has_suffix(&filename, suffix) {
+                return true;
+            }
+
+            let diffed_file = diffed_files.entry(filename.clone()).or_insert_with(|| {
+                let old_file = delta.old_file().id();
+                let new_file = delta.new_file().id();
+                let modes = gtl::to_modes(&delta);
+                ir::DiffedFile::new(filename, commit.clone(), 0, old_file, new_file, vec![], modes)
+            });
+
+            diffed_file.hunks.push(hunk.try_into().expect("failed to convert hunk"));
+            true
+        }),
+        None,
+    )
+    .context("failed to iterate over diff")?;
+
+    let mut blobs = Blobs::new();
+
+    if source == Uncommitted::Workdir {
+        let workdir = repo.workdir().context("repository has no working tree")?;
+
+        for diffed_file in diffed_files.values_mut() {
+            let path = workdir.join(&diffed_file.filename);
+
+            if path.exists() {
+                let content = std::fs::read(&path)
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+                diffed_file.new_file = Oid::hash_object(git2::ObjectType::Blob, &content)?;
+                blobs.insert(diffed_file.new_file, content);
+            }
+        }
+    }
+
+    Ok((diffed_files.into_values().collect::<Vec<_>>(), blobs))
+}
+
+/// Computes the patch-id (see git-patch-id) of the diff of each commit against
+/// its parent along with the patch-id of the reverse diff. Merge commits and
+/// commits which change nothing are skipped.
+pub fn get_patch_ids(
+    repo: &git2::Repository,
+    commits: &[git2::Commit],
+) -> Result<HashMap<Oid, (Oid, Oid)>> {
+    let mut patch_ids = HashMap::new();
+
+    for commit in commits {
+        if commit.parent_count() > 1 {
+            continue;
+        }
+
+        let new_tree = commit.tree()?;
+        let old_tree = match commit.parent_count() {
+            0 => None,
+            _ => Some(commit.parent(0)?.tree()?),
+        };
+
+        let diff = repo.diff_tree_to_tree(old_tree.as_ref(), Some(&new_tree), None)?;
+
+        if diff.deltas().len() == 0 {
+            continue;
+        }
+
+        let reverse = repo.diff_tree_to_tree(Some(&new_tree), old_tree.as_ref(), None)?;
+        patch_ids.insert(commit.id(), (diff.patchid(None)?, reverse.patchid(None)?));
+    }
+
+    Ok(patch_ids)
+}
+
+/// Finds the commits whose patch is identical to that of an older commit.
+pub fn find_duplicates(
+    commits: &[git2::Commit],
+    patch_ids: &HashMap<Oid, (Oid, Oid)>,
+) -> Result<Vec<ir::Duplicate>> {
+    let mut commits =
+        commits.iter().filter(|c| patch_ids.contains_key(&c.id())).collect::<Vec<_>>();
+    commits.sort_by_key(|c| (c.time().seconds(), c.id()));
+
+    let mut originals: HashMap<Oid, &git2::Commit> = HashMap::new();
+    let mut duplicates = Vec::new();
+
+    for commit in commits {
+        let (patch_id, _) = patch_ids[&commit.id()];
+
+        match originals.get(&patch_id) {
+            Some(original) => duplicates.push(ir::Duplicate::new(
+                gtl::to_commit(commit)?,
+                gtl::to_commit(original)?,
+                patch_id,
+            )),
+            None => {
+                originals.insert(patch_id, commit);
+            }
+        }
+    }
+
+    Ok(duplicates)
+}
+
+/// Finds the commits which revert another commit, either by the line git-revert
+/// adds to the message or by applying the inverse of the other's patch.
+pub fn find_reverts(
+    commits: &[git2::Commit],
+    patch_ids: &HashMap<Oid, (Oid, Oid)>,
+) -> Result<Vec<ir::Revert>> {
+    let by_sha1 = commits.iter().map(|c| (c.id().to_string(), c)).collect::<HashMap<_, _>>();
+    let mut by_patch_id: HashMap<Oid, Vec<&git2::Commit>> = HashMap::new();
+
+    for commit in commits {
+        if let Some(&(patch_id, _)) = patch_ids.get(&commit.id()) {
+            by_patch_id.entry(patch_id).or_default().push(commit);
+        }
+    }
+
+    let mut reverts = Vec::new();
+
+    for commit in commits {
+        let message = String::from_utf8_lossy(commit.message_bytes());
+        let reverted = message.split("This reverts commit ").nth(1).and_then(|rest| {
+            let sha1 = rest.chars().take_while(|c| c.is_ascii_hexdigit()).collect::<String>();
+            by_sha1.get(&sha1.to_lowercase())
+        });
+
+        if let Some(reverted) = reverted {
+            let (commit, reverted) = (gtl::to_commit(commit)?, gtl::to_commit(reverted)?);
+            reverts.push(ir::Revert::new(commit, reverted, ir::RevertKind::Message));
+            continue;
+        }
+
+        let reverse_patch_id = match patch_ids.get(&commit.id()) {
+            Some(&(_, reverse_patch_id)) => reverse_patch_id,
+            None => continue,
+        };
+
+        // The reverted commit must be older than the revert
+        let reverted = by_patch_id
+            .get(&reverse_patch_id)
+            .into_iter()
+            .flatten()
+            .filter(|c| c.time().seconds() <= commit.time().seconds() && c.id() != commit.id())
+            .max_by_key(|c| c.time().seconds());
+
+        if let Some(reverted) = reverted {
+            let (commit, reverted) = (gtl::to_commit(commit)?, gtl::to_commit(reverted)?);
+            reverts.push(ir::Revert::new(commit, reverted, ir::RevertKind::Patch));
+        }
+    }
+
+    Ok(reverts)
+}
+
+/// Whether a line of source code has an annotation whose name ends with `Test`
+/// (e.g. `@Test`, `@org.junit.Test`, or `@ParameterizedTest`).
+fn has_test_annotation(line: &str) -> bool {
+    line.split('@').skip(1).any(|rest| {
+        let is_name = |c: char| c.is_alphanumeric() || c == '_' || c == '.';
+        rest.split(|c: char| !is_name(c)).next().unwrap_or_default().ends_with("Test")
+    })
+}
+
+/// The markers which tools write into the headers of the files they generate.
+/// Each is matched case-insensitively, except for `@generated`.
+const GENERATED_MARKERS: &[&str] = &["do not edit", "auto-generated", "autogenerated"];
+
+/// How many lines at the start of a file are searched for the markers.
+const GENERATED_HEADER_LINES: usize = 10;
+
+/// Whether a line of source code has a `@Generated` annotation (e.g. of
+/// `javax.annotation.processing.Generated` or `lombok.Generated`).
+fn has_generated_annotation(line: &str) -> bool {
+    line.split('@').skip(1).any(|rest| {
+        let is_name = |c: char| c.is_alphanumeric() || c == '_' || c == '.';
+        let name = rest.split(|c: char| !is_name(c)).next().unwrap_or_default();
+        name == "Generated" || name.ends_with(".Generated")
+    })
+}
+
+/// Finds the entities of a blob which are marked as generated code. If the
+/// header of the file is marked, only the file is returned. Otherwise, the
+/// entities annotated with `@Generated` (which is part of an entity from its
+/// first line up to the line with its name) are.
+fn find_generated(source: &[u8], entities: &[ir::LocEntity]) -> Vec<ir::GeneratedEntity> {
+    let source = String::from_utf8_lossy(source);
+    let lines = source.lines().collect::<Vec<_>>();
+
+    let is_marked = |line: &&str| {
+        let lower = line.to_lowercase();
+        line.contains("@generated") || GENERATED_MARKERS.iter().any(|m| lower.contains(m))
+    };
+
+    if lines.iter().take(GENERATED_HEADER_LINES).any(is_marked) {
+        let file = entities.iter().find(|e| e.entity.parent.is_none());
+        return file
+            .map(|f| ir::GeneratedEntity::new(f.entity.clone(), "header"))
+            .into_iter()
+            .collect();
+    }
+
+    if !source.contains("Generated") {
+        return Vec::new();
+    }
+
+    let is_annotated = |e: &&ir::LocEntity| {
+        for line in
+            lines.iter().skip(e.loc.0.saturating_sub(1)).take(e.loc.1.saturating_sub(e.loc.0))
+        {
+            if has_generated_annotation(line) {
+                return true;
+            }
+
+            if line.contains(e.entity.name.as_str()) {
+                return false;
+            }
+        }
+
+        false
+    };
+
+    entities
+        .iter()
+        .filter(|e| e.entity.parent.is_some())
+        .filter(is_annotated)
+        .map(|e| ir::GeneratedEntity::new(e.entity.clone(), "annotation"))
+        .collect()
+}
+
+/// Finds the files which have a method annotated as a test at the given
+/// commit. Each method is given by its file, its name, and its first row (its
+/// annotations are part of the method).
+pub fn find_annotated_test_files(
+    repo: &git2::Repository,
+    commit: Oid,
+    methods: &[(String, String, usize)],
+) -> Result<HashSet<String>> {
+    let tree = repo.find_commit(commit)?.tree()?;
+    let mut by_file: HashMap<&str, Vec<(&str, usize)>> = HashMap::new();
+
+    for (filename, name, start_row) in methods {
+        by_file.entry(filename).or_default().push((name, *start_row));
+    }
+
+    let mut test_files = HashSet::new();
+
+    for (filename, methods) in by_file {
+        let entry = match tree.get_path(std::path::Path::new(filename)) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        let blob = repo.find_blob(entry.id())?;
+        let source = String::from_utf8_lossy(blob.content());
+        let lines = source.lines().collect::<Vec<_>>();
+
+        // Check every line from the start of the method up to (and including)
+        // the line with its name
+        let is_test = |&(name, start_row): &(&str, usize)| {
+            for line in lines.iter().skip(start_row.saturating_sub(1)) {
+                if has_test_annotation(line) {
+                    return true;
+                }
+
+                if line.contains(&format!("{}(", name)) {
+                    return false;
+                }
+            }
+
+            false
+        };
+
+        if methods.iter().any(is_test) {
+            test_files.insert(filename.to_string());
+        }
     }
 
-    Ok(diffed_files.into_values().collect::<Vec<_>>())
+    Ok(test_files)
 }
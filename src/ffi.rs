@@ -0,0 +1,302 @@
+//! A small C API for embedding the tool (e.g. in IDE plugins) without running
+//! the command line. Every function returns a null pointer or a negative
+//! number on failure, after which `cochange_last_error` describes what went
+//! wrong. Panics are caught and reported the same way rather than unwinding
+//! into the caller. Strings are UTF-8 and owned by the caller unless noted otherwise.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::ffi::CStr;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::os::raw::c_int;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::ptr;
+use std::sync::Once;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use rusqlite::Connection;
+
+use crate::analysis::find_coupled_pairs;
+use crate::analysis::load_change_sets;
+use crate::analysis::load_entity_paths;
+use crate::analysis::load_parents;
+use crate::analysis::Coupling;
+use crate::db::insert_change;
+use crate::db::insert_commit_coverage;
+use crate::db::insert_diagnostic;
+use crate::db::insert_presence;
+use crate::db::write_commit_metrics;
+use crate::db::write_entity_metrics;
+use crate::db::write_lifespans;
+use crate::db::write_meta_schema;
+use crate::db::VirtualDb;
+use crate::extraction::get_changes;
+use crate::extraction::get_presences;
+use crate::extraction::open_repo;
+use crate::extraction::ExtractionCtx;
+use crate::extraction::MergeMode;
+use crate::ir::CommitInfo;
+use crate::metrics::init_logger;
+use crate::metrics::LogFormat;
+use crate::parsing::get_java_parser;
+use crate::vcs::Vcs;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+static INIT: Once = Once::new();
+
+/// An open database of co-change data.
+pub struct CochangeDb {
+    conn: Connection,
+}
+
+/// A pair of entities which frequently change together.
+#[repr(C)]
+pub struct CochangePair {
+    pub entity1: *mut c_char,
+    pub entity2: *mut c_char,
+    pub support: f64,
+    pub confidence: f64,
+}
+
+/// A list of pairs, which must be freed with `cochange_pairs_free`.
+#[repr(C)]
+pub struct CochangePairs {
+    pub pairs: *mut CochangePair,
+    pub len: usize,
+}
+
+/// Initializes logging to stderr, where a verbosity of 0 only logs errors and
+/// each level above adds warnings, info, debug, and trace messages. Only the
+/// first call has an effect.
+#[no_mangle]
+pub extern "C" fn cochange_init(verbosity: c_int) {
+    handle(|| {
+        INIT.call_once(|| {
+            let level = match verbosity {
+                i32::MIN..=0 => log::LevelFilter::Error,
+                1 => log::LevelFilter::Warn,
+                2 => log::LevelFilter::Info,
+                3 => log::LevelFilter::Debug,
+                _ => log::LevelFilter::Trace,
+            };
+            init_logger(level, LogFormat::Text);
+        });
+        Ok(())
+    });
+}
+
+/// The message of the last error on this thread (or null if there was none).
+/// It is owned by the library and valid until the next call on this thread.
+#[no_mangle]
+pub extern "C" fn cochange_last_error() -> *const c_char {
+    let result = panic::catch_unwind(|| {
+        LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+    });
+    result.unwrap_or(ptr::null())
+}
+
+/// Extracts the history of HEAD of the git repository at `repo_path` into a new
+/// database at `db_path`, like `dump` does with its default options. Returns 0
+/// on success. Fails if the database already exists.
+///
+/// # Safety
+///
+/// Both paths must be valid, null-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn cochange_extract(
+    repo_path: *const c_char,
+    db_path: *const c_char,
+) -> c_int {
+    handle(|| extract(to_path(repo_path)?, to_path(db_path)?)).map_or(-1, |_| 0)
+}
+
+/// Opens an existing database.
+///
+/// # Safety
+///
+/// The path must be a valid, null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn cochange_open(db_path: *const c_char) -> *mut CochangeDb {
+    let result = handle(|| {
+        let conn = Connection::open(to_path(db_path)?)?;
+        Ok(Box::new(CochangeDb { conn }))
+    });
+    result.map_or(ptr::null_mut(), Box::into_raw)
+}
+
+/// Closes a database opened with `cochange_open`.
+///
+/// # Safety
+///
+/// The database must have been returned by `cochange_open` and not yet closed.
+#[no_mangle]
+pub unsafe extern "C" fn cochange_close(db: *mut CochangeDb) {
+    handle(|| {
+        if !db.is_null() {
+            drop(Box::from_raw(db));
+        }
+
+        Ok(())
+    });
+}
+
+/// Finds the pairs of entities which meet the given support and confidence
+/// thresholds (see `analyze coupling`), sorted by support and then by
+/// confidence.
+///
+/// # Safety
+///
+/// The database must have been returned by `cochange_open` and not yet closed.
+#[no_mangle]
+pub unsafe extern "C" fn cochange_query_pairs(
+    db: *const CochangeDb,
+    min_support: f64,
+    min_confidence: f64,
+) -> *mut CochangePairs {
+    let result = handle(|| {
+        let db = db.as_ref().context("database is null")?;
+        let pairs = query_pairs(&db.conn, min_support, min_confidence)?;
+        let len = pairs.len();
+        let pairs = Box::into_raw(pairs.into_boxed_slice()) as *mut CochangePair;
+        Ok(Box::new(CochangePairs { pairs, len }))
+    });
+    result.map_or(ptr::null_mut(), Box::into_raw)
+}
+
+/// Frees a list of pairs returned by `cochange_query_pairs`.
+///
+/// # Safety
+///
+/// The list must have been returned by `cochange_query_pairs` and not yet
+/// freed.
+#[no_mangle]
+pub unsafe extern "C" fn cochange_pairs_free(pairs: *mut CochangePairs) {
+    handle(|| {
+        if pairs.is_null() {
+            return Ok(());
+        }
+
+        let pairs = Box::from_raw(pairs);
+        let slice = ptr::slice_from_raw_parts_mut(pairs.pairs, pairs.len);
+
+        for pair in Box::from_raw(slice).iter() {
+            drop(CString::from_raw(pair.entity1));
+            drop(CString::from_raw(pair.entity2));
+        }
+
+        Ok(())
+    });
+}
+
+/// Runs the body of an exported function, turning a panic into an error.
+/// Records the error (if any) so that it can be read with
+/// `cochange_last_error`.
+fn handle<T>(body: impl FnOnce() -> Result<T>) -> Option<T> {
+    let result = panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|payload| {
+        let message = match payload.downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => payload.downcast_ref::<String>().cloned().unwrap_or_default(),
+        };
+        Err(anyhow!("panicked: {}", message))
+    });
+
+    match result {
+        Ok(value) => Some(value),
+        Err(err) => {
+            let message = format!("{:#}", err).replace('\0', "");
+            LAST_ERROR.with(|e| *e.borrow_mut() = CString::new(message).ok());
+            None
+        }
+    }
+}
+
+unsafe fn to_path<'a>(path: *const c_char) -> Result<&'a Path> {
+    let path = path.as_ref().context("path is null")?;
+    Ok(Path::new(CStr::from_ptr(path).to_str().context("path is not valid UTF-8")?))
+}
+
+fn extract(repo_path: &Path, db_path: &Path) -> Result<()> {
+    if db_path.exists() {
+        bail!("the database ('{}') already exists", db_path.display());
+    }
+
+    let repo = open_repo(Some(repo_path))?;
+    let mut ctx = ExtractionCtx::new(&repo, get_java_parser()?, ".java");
+    let commits = Vcs::walk(&repo, &["HEAD".to_string()], &[])?;
+    let (diffed_files, diagnostics) = Vcs::diff(&repo, &commits, ctx.suffix(), MergeMode::Skip)?;
+    let mut db = VirtualDb::new();
+
+    for diffed_file in &diffed_files {
+        for change in get_changes(&mut ctx, diffed_file)? {
+            insert_change(&mut db, &change)?;
+        }
+    }
+
+    // Merges are skipped, as are commits which could not be diffed
+    let skipped = diagnostics.iter().filter_map(|d| d.commit.as_ref().map(|c| c.sha1));
+    let skipped = skipped.collect::<HashSet<_>>();
+    let coverage = ctx.coverage();
+
+    for commit in commits.iter().filter(|c| !c.is_merge && !skipped.contains(&c.sha1)) {
+        insert_commit_coverage(&mut db, commit, CommitInfo::CHANGES, &coverage)?;
+    }
+
+    if let Some(head) = commits.first() {
+        for presence in get_presences(&mut ctx, head, None)?.0 {
+            insert_presence(&mut db, &presence)?;
+        }
+
+        insert_commit_coverage(&mut db, head, CommitInfo::PRESENCE, &coverage)?;
+    }
+
+    for diagnostic in diagnostics.iter().chain(&ctx.take_diagnostics()) {
+        insert_diagnostic(&mut db, diagnostic)?;
+    }
+
+    let mut conn = Connection::open(db_path)?;
+    let tx = conn.transaction()?;
+    db.write(&tx)?;
+    write_meta_schema(&tx)?;
+    write_lifespans(&tx)?;
+    write_entity_metrics(&tx)?;
+    write_commit_metrics(&tx)?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn query_pairs(
+    conn: &Connection,
+    min_support: f64,
+    min_confidence: f64,
+) -> Result<Vec<CochangePair>> {
+    let coupling = Coupling::new(&load_change_sets(conn)?);
+    let paths = load_entity_paths(conn)?;
+    let pairs = find_coupled_pairs(&coupling, &load_parents(conn)?, min_support, min_confidence);
+
+    // Only hand out the names once every one is valid, so that none leak
+    let names = pairs
+        .iter()
+        .map(|pair| {
+            let entity1 = CString::new(paths[&pair.entity1_id].clone())?;
+            let entity2 = CString::new(paths[&pair.entity2_id].clone())?;
+            Ok((entity1, entity2))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let pairs = pairs.iter().zip(names).map(|(pair, (entity1, entity2))| CochangePair {
+        entity1: entity1.into_raw(),
+        entity2: entity2.into_raw(),
+        support: pair.support,
+        confidence: pair.confidence,
+    });
+    Ok(pairs.collect())
+}
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use anyhow::Context;
 use anyhow::Result;
 use time::OffsetDateTime;
@@ -12,11 +14,16 @@ pub fn to_datetime(time: &git2::Time) -> Result<OffsetDateTime> {
 }
 
 pub fn to_commit(commit: &git2::Commit) -> Result<ir::Commit> {
+    let author = commit.author();
+
     Ok(ir::Commit::new(
         commit.id(),
         commit.parent_count() > 1,
-        to_datetime(&commit.author().when())?,
+        String::from_utf8_lossy(author.name_bytes()).to_string(),
+        String::from_utf8_lossy(author.email_bytes()).to_lowercase(),
+        to_datetime(&author.when())?,
         to_datetime(&commit.committer().when())?,
+        Arc::new(String::from_utf8_lossy(commit.message_bytes()).to_string()),
     ))
 }
 
@@ -26,16 +33,53 @@ pub fn to_ref(r#ref: &git2::Reference) -> Result<ir::Ref> {
     Ok(ir::Ref::new(commit, name))
 }
 
+pub fn to_tag(r#ref: &git2::Reference) -> Result<ir::Tag> {
+    let commit = to_commit(&r#ref.peel_to_commit()?)?;
+    let name = r#ref.shorthand().context("missing tag name")?.to_string();
+
+    // Lightweight tags point directly to a commit
+    let tag = match r#ref.peel_to_tag() {
+        Ok(tag) => tag,
+        Err(_) => return Ok(ir::Tag::new(name, commit, None, None, None, None)),
+    };
+
+    let tagger = tag.tagger();
+
+    Ok(ir::Tag::new(
+        name,
+        commit,
+        tagger.as_ref().map(|t| String::from_utf8_lossy(t.name_bytes()).to_string()),
+        tagger.as_ref().map(|t| String::from_utf8_lossy(t.email_bytes()).to_lowercase()),
+        tagger.as_ref().map(|t| to_datetime(&t.when())).transpose()?,
+        tag.message_bytes().map(|m| String::from_utf8_lossy(m).to_string()),
+    ))
+}
+
 pub fn to_diffed_file(
     name: String,
     commit: &git2::Commit,
+    parent: usize,
     delta: &git2::DiffDelta,
 ) -> Result<ir::DiffedFile> {
     Ok(ir::DiffedFile::new(
         name,
-        to_commit(&commit).unwrap(),
+        to_commit(commit).unwrap(),
+        parent,
         delta.old_file().id(),
         delta.new_file().id(),
         Vec::new(),
+        to_modes(delta),
     ))
 }
+
+pub fn to_file_mode(file: &git2::DiffFile) -> ir::FileMode {
+    match file.mode() {
+        git2::FileMode::BlobExecutable => ir::FileMode::Executable,
+        git2::FileMode::Link => ir::FileMode::Symlink,
+        _ => ir::FileMode::Regular,
+    }
+}
+
+pub fn to_modes(delta: &git2::DiffDelta) -> (ir::FileMode, ir::FileMode) {
+    (to_file_mode(&delta.old_file()), to_file_mode(&delta.new_file()))
+}
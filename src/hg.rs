@@ -0,0 +1,372 @@
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Arc;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use git2::Oid;
+use serde::Deserialize;
+use time::OffsetDateTime;
+use time::UtcOffset;
+
+use crate::extraction::has_suffix;
+use crate::extraction::MergeMode;
+use crate::ir;
+use crate::vcs::Vcs;
+
+/// The revision walked when none is given (the head of the default branch).
+const DEFAULT_REV: &str = "default";
+
+/// A changeset as written by `hg log -T json`.
+#[derive(Debug, Deserialize)]
+struct Changeset {
+    node: String,
+    user: String,
+    /// Seconds since the epoch and the offset of the timezone in seconds west
+    /// of UTC
+    date: (f64, i64),
+    desc: String,
+    parents: Vec<String>,
+}
+
+/// A file of a manifest as written by `hg manifest --debug -T json`.
+#[derive(Debug, Deserialize)]
+struct ManifestEntry {
+    hash: String,
+    path: String,
+    /// Either "@" for symlinks, "*" for executables, or nothing
+    #[serde(rename = "type")]
+    r#type: String,
+}
+
+/// The revision and mode of each file of a manifest.
+type Manifest = HashMap<String, (Oid, ir::FileMode)>;
+
+/// The hunks of each changed file of a diff, along with the files which are
+/// binary (and so have no hunks).
+#[derive(Debug, Default)]
+struct ParsedDiff {
+    hunks: HashMap<String, Vec<ir::Hunk>>,
+    binary: BTreeSet<String>,
+}
+
+/// A Mercurial repository, read by running the hg executable. Mercurial has no
+/// separate commit date, so the author date is used for both.
+pub struct Mercurial {
+    root: PathBuf,
+}
+
+impl Mercurial {
+    /// Opens the repository at or above the given directory.
+    pub fn open(path: &Path) -> Result<Self> {
+        let output = run(Command::new("hg").current_dir(path).arg("root"))
+            .context("failed to find Mercurial repository at or above the provided directory")?;
+        let root = PathBuf::from(String::from_utf8_lossy(&output).trim());
+        log::info!("Opened Mercurial repository at {}", root.display());
+        Ok(Self { root })
+    }
+
+    /// Runs hg at the root of the repository and returns what it wrote.
+    fn hg<I: IntoIterator<Item = S>, S: AsRef<OsStr>>(&self, args: I) -> Result<Vec<u8>> {
+        run(Command::new("hg").current_dir(&self.root).args(args))
+    }
+
+    fn log(&self, revset: &str) -> Result<Vec<Changeset>> {
+        let output = self.hg(["log", "-r", revset, "-T", "json"])?;
+        serde_json::from_slice(&output).context("failed to parse output of hg log")
+    }
+
+    fn manifest(&self, node: Oid) -> Result<Manifest> {
+        let output = self.hg(["manifest", "--debug", "-T", "json", "-r", &node.to_string()])?;
+        let entries: Vec<ManifestEntry> =
+            serde_json::from_slice(&output).context("failed to parse output of hg manifest")?;
+        entries
+            .into_iter()
+            .map(|entry| {
+                let mode = match entry.r#type.as_str() {
+                    "@" => ir::FileMode::Symlink,
+                    "*" => ir::FileMode::Executable,
+                    _ => ir::FileMode::Regular,
+                };
+                Ok((entry.path, (Oid::from_str(&entry.hash)?, mode)))
+            })
+            .collect()
+    }
+
+    /// The changed files of a commit against one of its parents (or against
+    /// nothing if it has none).
+    fn diff_parent(
+        &self,
+        commit: &ir::Commit,
+        parent: usize,
+        old: Option<Oid>,
+        new_manifest: &Manifest,
+        suffix: Option<&'static str>,
+    ) -> Result<Vec<ir::DiffedFile>> {
+        let old_manifest = old.map(|old| self.manifest(old)).transpose()?.unwrap_or_default();
+        let diff = self.diff_hunks(old, commit.sha1)?;
+        let paths = old_manifest.keys().chain(new_manifest.keys()).collect::<BTreeSet<_>>();
+        let mut diffed_files = Vec::new();
+
+        for path in paths.into_iter().filter(|p| has_suffix(p, suffix)) {
+            let missing = (Oid::zero(), ir::FileMode::Regular);
+            let (old_file, old_mode) = *old_manifest.get(path).unwrap_or(&missing);
+            let (new_file, new_mode) = *new_manifest.get(path).unwrap_or(&missing);
+
+            if (old_file, old_mode) == (new_file, new_mode) {
+                continue;
+            }
+
+            let is_link = |file: Oid, mode| !file.is_zero() && mode == ir::FileMode::Symlink;
+            let is_typechange = !old_file.is_zero()
+                && !new_file.is_zero()
+                && is_link(old_file, old_mode) != is_link(new_file, new_mode);
+            let hunks = diff.hunks.get(path).cloned().unwrap_or_default();
+
+            // Like git, a file which became a symlink (or vice versa) is changed
+            // as a whole, with the symlink side treated as missing
+            let (old_file, new_file, hunks) = if is_typechange {
+                let old_file = if is_link(old_file, old_mode) { Oid::zero() } else { old_file };
+                let new_file = if is_link(new_file, new_mode) { Oid::zero() } else { new_file };
+                (old_file, new_file, vec![to_whole_hunk(old_file, new_file)])
+            } else if diff.binary.contains(path) {
+                (old_file, new_file, vec![to_whole_hunk(old_file, new_file)])
+            } else if hunks.is_empty() && old_mode == new_mode {
+                // The revision of a file may change while its content does not
+                continue;
+            } else {
+                (old_file, new_file, hunks)
+            };
+
+            diffed_files.push(ir::DiffedFile::new(
+                path.clone(),
+                commit.clone(),
+                parent,
+                old_file,
+                new_file,
+                hunks,
+                (old_mode, new_mode),
+            ));
+        }
+
+        Ok(diffed_files)
+    }
+
+    /// The hunks of the files changed between two commits (the first of which
+    /// may be missing).
+    fn diff_hunks(&self, old: Option<Oid>, new: Oid) -> Result<ParsedDiff> {
+        let old = old.map_or("null".to_string(), |oid| oid.to_string());
+        let output = self.hg(["diff", "-U", "0", "-r", &old, "-r", &new.to_string()])?;
+        parse_diff(&output)
+    }
+}
+
+impl Vcs for Mercurial {
+    fn root(&self) -> PathBuf {
+        self.root.clone()
+    }
+
+    fn remote_url(&self) -> Option<String> {
+        let output = self.hg(["paths", "default"]).ok()?;
+        Some(String::from_utf8_lossy(&output).trim().to_string()).filter(|url| !url.is_empty())
+    }
+
+    fn head(&self) -> Option<Oid> {
+        let output = self.hg(["log", "-r", ".", "-T", "{node}"]).ok()?;
+        Oid::from_str(String::from_utf8_lossy(&output).trim()).ok().filter(|oid| !oid.is_zero())
+    }
+
+    fn resolve(&self, rev: &str) -> Result<ir::Commit> {
+        match self.log(&format!("last({})", rev))?.first() {
+            Some(changeset) => to_commit(changeset),
+            None => bail!("revision '{}' matches no commit", rev),
+        }
+    }
+
+    /// The revisions may be any revsets (e.g. `default` or `branch(stable)`).
+    fn walk(&self, revs: &[String], hide: &[String]) -> Result<Vec<ir::Commit>> {
+        let join = |revs: &[String]| revs.iter().map(|r| format!("({})", r)).collect::<Vec<_>>();
+        let revs = if revs.is_empty() { vec![DEFAULT_REV.to_string()] } else { join(revs) };
+        let mut revset = format!("::({})", revs.join(" + "));
+
+        if !hide.is_empty() {
+            revset = format!("{} - ::({})", revset, join(hide).join(" + "));
+        }
+
+        let revset = format!("sort({}, -date)", revset);
+        self.log(&revset)?.iter().map(to_commit).collect()
+    }
+
+    fn diff(
+        &self,
+        commits: &[ir::Commit],
+        suffix: Option<&'static str>,
+        merges: MergeMode,
+    ) -> Result<(Vec<ir::DiffedFile>, Vec<ir::Diagnostic>)> {
+        let mut diffed_files = Vec::new();
+
+        for commit in commits {
+            let changeset = self.log(&commit.sha1.to_string())?;
+            let parents = get_parents(changeset.first().context("missing changeset")?)?;
+
+            let parents = match (parents.len(), merges) {
+                (0, _) => vec![None],
+                (1, _) | (_, MergeMode::AllParents) => parents.into_iter().map(Some).collect(),
+                (_, MergeMode::Skip) => Vec::new(),
+            };
+
+            let new_manifest = self.manifest(commit.sha1)?;
+
+            for (parent, old) in parents.into_iter().enumerate() {
+                diffed_files.extend(self.diff_parent(
+                    commit,
+                    parent,
+                    old,
+                    &new_manifest,
+                    suffix,
+                )?);
+            }
+        }
+
+        Ok((diffed_files, Vec::new()))
+    }
+
+    fn list_files(&self, commit: Oid) -> Result<Vec<(String, Oid)>> {
+        Ok(self
+            .manifest(commit)?
+            .into_iter()
+            .filter(|(_, (_, mode))| *mode != ir::FileMode::Symlink)
+            .map(|(path, (blob, _))| (path, blob))
+            .collect())
+    }
+
+    /// Mercurial keeps the revisions of each file apart, so these are read one
+    /// at a time with `hg debugdata`.
+    fn read_blob(&self, path: &str, blob: Oid) -> Result<Vec<u8>> {
+        let data = self.hg(["debugdata", path, &blob.to_string()])?;
+        Ok(strip_copy_metadata(data))
+    }
+
+    fn reopen(&self) -> Result<Box<dyn Vcs + Send>> {
+        Ok(Box::new(Self { root: self.root.clone() }))
+    }
+}
+
+/// Runs a command and returns what it wrote to stdout. HGPLAIN keeps the
+/// configuration of the user (e.g. aliases and colors) out of the output.
+fn run(command: &mut Command) -> Result<Vec<u8>> {
+    let output = command
+        .env("HGPLAIN", "1")
+        .output()
+        .context("failed to run hg (is Mercurial installed?)")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("hg failed ({}): {}", output.status, stderr.trim());
+    }
+
+    Ok(output.stdout)
+}
+
+/// The parents of a changeset (without the null revision of root changesets).
+fn get_parents(changeset: &Changeset) -> Result<Vec<Oid>> {
+    let mut parents = Vec::new();
+
+    for node in &changeset.parents {
+        let oid = Oid::from_str(node)?;
+
+        if !oid.is_zero() {
+            parents.push(oid);
+        }
+    }
+
+    Ok(parents)
+}
+
+fn to_commit(changeset: &Changeset) -> Result<ir::Commit> {
+    let (seconds, west) = changeset.date;
+    let datetime = OffsetDateTime::from_unix_timestamp(seconds as i64)?;
+    let date = datetime.replace_offset(UtcOffset::from_whole_seconds(-i32::try_from(west)?)?);
+
+    let (name, mail) = match changeset.user.split_once('<') {
+        Some((name, mail)) => (name.trim(), mail.trim_end_matches('>').trim()),
+        None => (changeset.user.trim(), ""),
+    };
+
+    Ok(ir::Commit::new(
+        Oid::from_str(&changeset.node)?,
+        get_parents(changeset)?.len() > 1,
+        name.to_string(),
+        mail.to_lowercase(),
+        date,
+        date,
+        Arc::new(changeset.desc.clone()),
+    ))
+}
+
+/// Every line of each present side is changed.
+fn to_whole_hunk(old_file: Oid, new_file: Oid) -> ir::Hunk {
+    let everything = ir::Interval(0, usize::MAX);
+    let nothing = ir::Interval(0, 0);
+    ir::Hunk::new(
+        if old_file.is_zero() { nothing } else { everything },
+        if new_file.is_zero() { nothing } else { everything },
+    )
+}
+
+/// Revisions of copied files start with metadata (e.g. the source of the copy)
+/// between two `\x01\n` markers.
+fn strip_copy_metadata(data: Vec<u8>) -> Vec<u8> {
+    if !data.starts_with(b"\x01\n") {
+        return data;
+    }
+
+    match data[2..].windows(2).position(|w| w == b"\x01\n") {
+        Some(end) => data[end + 4..].to_vec(),
+        None => data,
+    }
+}
+
+/// Parses the hunks of a diff written by `hg diff -U 0` (without `--git`).
+fn parse_diff(output: &[u8]) -> Result<ParsedDiff> {
+    let mut parsed = ParsedDiff::default();
+    let mut path = None;
+
+    for line in output.split(|&b| b == b'\n') {
+        let line = String::from_utf8_lossy(line);
+
+        // Headers look like "diff -r 0123456789ab -r ba9876543210 path/to/file"
+        if line.starts_with("diff -r ") {
+            path = line.splitn(6, ' ').nth(5).map(str::to_string);
+        } else if let Some(rest) = line.strip_prefix("Binary file ") {
+            if let Some(binary) = rest.strip_suffix(" has changed") {
+                parsed.binary.insert(binary.to_string());
+            }
+        } else if line.starts_with("@@ -") {
+            let path = path.clone().context("found a hunk before any file")?;
+            parsed.hunks.entry(path).or_default().push(parse_hunk_header(&line)?);
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// Parses a hunk header such as "@@ -3,2 +3 @@" (where a missing count is 1).
+fn parse_hunk_header(line: &str) -> Result<ir::Hunk> {
+    let mut ranges = line.split(' ').skip(1).take(2);
+    let mut parse_range = |sign: char| -> Result<ir::Interval> {
+        let range = ranges.next().and_then(|r| r.strip_prefix(sign));
+        let range = range.with_context(|| format!("invalid hunk header: {}", line))?;
+        let (start, lines) = range.split_once(',').unwrap_or((range, "1"));
+        let (start, lines) = (start.parse::<usize>()?, lines.parse::<usize>()?);
+        Ok(ir::Interval(start, start + lines))
+    };
+    let old_interval = parse_range('-')?;
+    let new_interval = parse_range('+')?;
+    Ok(ir::Hunk::new(old_interval, new_interval))
+}
@@ -1,5 +1,7 @@
 use bitflags::bitflags;
 use derive_new::new;
+use std::fmt::Display;
+use std::fmt::Formatter;
 use std::sync::Arc;
 use time::OffsetDateTime;
 
@@ -44,14 +46,33 @@ impl Interval {
     pub fn intersect(&self, other: &Interval) -> usize {
         let p0 = self.0.max(other.0);
         let p1 = self.1.min(other.1);
-        p1.checked_sub(p0).unwrap_or_default()
+        p1.saturating_sub(p0)
+    }
+
+    /// The lines both intervals share (if any), counted as by `intersect`.
+    pub fn intersection(&self, other: &Interval) -> Option<Interval> {
+        let p0 = self.0.max(other.0);
+        let p1 = self.1.min(other.1);
+        Some(Interval(p0, p1)).filter(|_| p0 < p1)
     }
 }
 
+/// Lightweight complexity indicators of an entity, taken from its syntax tree.
+#[derive(new, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Complexity {
+    /// The deepest nesting of control flow statements
+    pub nesting: usize,
+    /// The number of branches and loops (i.e. decision points)
+    pub branches: usize,
+    /// The number of parameters (not counting those of nested entities)
+    pub params: usize,
+}
+
 #[derive(new, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct LocEntity {
     pub entity: Arc<Entity>,
     pub loc: Interval,
+    pub complexity: Complexity,
 }
 
 #[derive(new, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -64,8 +85,19 @@ pub struct Hunk {
 pub struct Commit {
     pub sha1: Oid,
     pub is_merge: bool,
+    pub author_name: String,
+    pub author_mail: String,
     pub author_date: OffsetDateTime,
     pub commit_date: OffsetDateTime,
+    pub message: Arc<String>,
+}
+
+impl Commit {
+    /// Roughly how many bytes a copy of this commit takes up. The message is
+    /// shared between copies, so it is not counted.
+    pub fn approx_size(&self) -> usize {
+        std::mem::size_of::<Self>() + self.author_name.len() + self.author_mail.len()
+    }
 }
 
 #[derive(new, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -74,12 +106,34 @@ pub struct Presence {
     pub commit: Commit,
 }
 
+impl Presence {
+    /// Roughly how many bytes this presence takes up, not counting its entity
+    /// (which is shared).
+    pub fn approx_size(&self) -> usize {
+        std::mem::size_of::<Self>() + self.commit.approx_size()
+    }
+}
+
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum ChangeKind {
     Added,
     #[default]
     Modified,
     Deleted,
+    /// Added in place of a deleted entity which only differed by its name
+    Renamed,
+    /// Added in place of a deleted entity with the same name but a different
+    /// parent
+    Moved,
+    /// Added in place of a deleted entity with the same name and parent but a
+    /// different discriminator (e.g. the parameters of a method)
+    SignatureChanged,
+    /// Modified, but only on lines which are blank or comments
+    DocOnly,
+    /// The executable bit of the file changed (along with any of its lines)
+    ModeChanged,
+    /// The symlink now points somewhere else
+    Retargeted,
 }
 
 impl From<&ChangeKind> for char {
@@ -88,17 +142,55 @@ impl From<&ChangeKind> for char {
             ChangeKind::Added => 'A',
             ChangeKind::Modified => 'M',
             ChangeKind::Deleted => 'D',
+            ChangeKind::Renamed => 'R',
+            ChangeKind::Moved => 'V',
+            ChangeKind::SignatureChanged => 'S',
+            ChangeKind::DocOnly => 'C',
+            ChangeKind::ModeChanged => 'X',
+            ChangeKind::Retargeted => 'L',
         }
     }
 }
 
+impl Display for ChangeKind {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.to_char())
+    }
+}
+
 impl ChangeKind {
     pub fn to_char(&self) -> char {
         self.into()
     }
 
-    pub fn to_string(&self) -> String {
-        self.to_char().to_string()
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            'A' => Some(ChangeKind::Added),
+            'M' => Some(ChangeKind::Modified),
+            'D' => Some(ChangeKind::Deleted),
+            'R' => Some(ChangeKind::Renamed),
+            'V' => Some(ChangeKind::Moved),
+            'S' => Some(ChangeKind::SignatureChanged),
+            'C' => Some(ChangeKind::DocOnly),
+            'X' => Some(ChangeKind::ModeChanged),
+            'L' => Some(ChangeKind::Retargeted),
+            _ => None,
+        }
+    }
+
+    /// The kind this would have been without the semantic kinds. The entity of
+    /// a rename, move, or signature change is still a new entity, so each of
+    /// these is an addition (and the old entity a deletion).
+    pub fn to_basic(&self) -> Self {
+        match self {
+            ChangeKind::Renamed | ChangeKind::Moved | ChangeKind::SignatureChanged => {
+                ChangeKind::Added
+            }
+            ChangeKind::DocOnly | ChangeKind::ModeChanged | ChangeKind::Retargeted => {
+                ChangeKind::Modified
+            }
+            kind => *kind,
+        }
     }
 }
 
@@ -112,6 +204,18 @@ pub struct Change {
     pub adds: usize,
     #[builder(default)]
     pub dels: usize,
+    /// Which parent of the commit this change is relative to (always 0 unless
+    /// the commit is a merge)
+    #[builder(default)]
+    pub parent: usize,
+}
+
+impl Change {
+    /// Roughly how many bytes this change takes up, not counting its entity
+    /// (which is shared).
+    pub fn approx_size(&self) -> usize {
+        std::mem::size_of::<Self>() + self.commit.approx_size()
+    }
 }
 
 #[derive(new, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -120,13 +224,285 @@ pub struct Ref {
     pub name: String,
 }
 
+/// A tag along with its metadata. Only annotated tags have a tagger, date, and
+/// message.
+#[derive(new, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Tag {
+    pub name: String,
+    pub commit: Commit,
+    pub tagger_name: Option<String>,
+    pub tagger_mail: Option<String>,
+    pub tag_date: Option<OffsetDateTime>,
+    pub message: Option<String>,
+}
+
+/// An entry of the reflog of a ref. The old commit is missing when the entry
+/// created the ref.
+#[derive(new, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RefUpdate {
+    pub name: String,
+    pub seq: usize,
+    pub old: Option<Oid>,
+    pub new: Oid,
+    pub time: OffsetDateTime,
+    pub message: Option<String>,
+}
+
+/// A commit which is reachable from a ref.
+#[derive(new, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RefMember {
+    pub commit: Commit,
+    pub r#ref: Ref,
+}
+
+/// A commit which applies the same patch as an earlier commit (e.g. a
+/// cherry-pick).
+#[derive(new, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Duplicate {
+    pub commit: Commit,
+    pub original: Commit,
+    pub patch_id: Oid,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum RevertKind {
+    /// Found by the "This reverts commit <SHA>" line of the message
+    Message,
+    /// Found by the patch being the inverse of the patch of another commit
+    Patch,
+}
+
+impl RevertKind {
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            RevertKind::Message => "message",
+            RevertKind::Patch => "patch",
+        }
+    }
+}
+
+#[derive(new, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Revert {
+    pub commit: Commit,
+    pub reverted: Commit,
+    pub kind: RevertKind,
+}
+
+/// A submodule whose pointer changed in a commit. The old commit is missing when
+/// the submodule was added and the new commit is missing when it was removed.
+#[derive(new, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SubmoduleUpdate {
+    pub commit: Commit,
+    pub parent: usize,
+    pub path: String,
+    pub old: Option<Oid>,
+    pub new: Option<Oid>,
+}
+
+/// A limitation of the extracted data, such as a commit which could not be
+/// diffed because objects are missing from a shallow or partial clone.
+#[derive(new, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Diagnostic {
+    /// The affected commit (if any)
+    pub commit: Option<Commit>,
+    /// What kind of limitation this is (e.g. `missing_parents`)
+    pub kind: &'static str,
+    /// What is affected within the commit (e.g. a file), if anything
+    pub subject: String,
+    pub message: String,
+}
+
+/// A symlink which was added, retargeted, or removed in a commit. The old
+/// target is missing when the symlink was added and the new target is missing
+/// when it was removed.
+#[derive(new, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SymlinkUpdate {
+    pub commit: Commit,
+    pub parent: usize,
+    pub path: String,
+    pub old_target: Option<String>,
+    pub new_target: Option<String>,
+}
+
+/// A pull request (or GitLab merge request) along with its commits.
+#[derive(Builder, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PullRequest {
+    pub number: u64,
+    pub title: String,
+    pub author: String,
+    /// Either "open", "closed", or "merged"
+    pub state: String,
+    pub created_at: OffsetDateTime,
+    #[builder(default)]
+    pub merged_at: Option<OffsetDateTime>,
+    pub url: String,
+    /// The commits of the pull request itself
+    #[builder(default)]
+    pub commits: Vec<Oid>,
+    /// The commits which merged the pull request (e.g. a merge or squash
+    /// commit)
+    #[builder(default)]
+    pub merge_commits: Vec<Oid>,
+    #[builder(default)]
+    pub labels: Vec<String>,
+    /// Everyone who was asked to review or reviewed (other than the author)
+    #[builder(default)]
+    pub reviewers: Vec<String>,
+}
+
+/// An issue of an issue tracker (e.g. a GitHub issue or a Jira ticket).
+#[derive(new, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Issue {
+    /// How commit messages refer to the issue (e.g. `#123` or `PROJ-123`)
+    pub key: String,
+    /// The type of the issue (e.g. "Bug" or "Story") if the tracker has one
+    pub kind: Option<String>,
+    pub title: String,
+    pub status: String,
+    /// How the issue was resolved (e.g. "Fixed" or "Won't Do") if it was
+    pub resolution: Option<String>,
+    pub labels: Vec<String>,
+    /// Whether the issue reports a defect (as opposed to e.g. a feature)
+    pub is_defect: bool,
+}
+
+/// The lines of an entity which a commit added (to the new blob) and deleted
+/// (from the old blob). Each interval runs from its first line to past its last.
+#[derive(new, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChangedLines {
+    pub commit: Commit,
+    pub parent: usize,
+    pub entity: Arc<Entity>,
+    pub added: Vec<Interval>,
+    pub deleted: Vec<Interval>,
+}
+
+/// Which parts of an entity a change touched.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Touched {
+    #[default]
+    Code,
+    /// Only its doc comment
+    Doc,
+    Both,
+}
+
+impl Touched {
+    pub fn to_char(&self) -> char {
+        match self {
+            Touched::Code => 'C',
+            Touched::Doc => 'D',
+            Touched::Both => 'B',
+        }
+    }
+
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            'C' => Some(Touched::Code),
+            'D' => Some(Touched::Doc),
+            'B' => Some(Touched::Both),
+            _ => None,
+        }
+    }
+}
+
+/// How many lines of the doc comment of an entity (e.g. its Javadoc) a commit
+/// added and deleted, and whether the code of the entity changed as well.
+#[derive(new, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DocChange {
+    pub commit: Commit,
+    pub parent: usize,
+    pub entity: Arc<Entity>,
+    pub doc_adds: usize,
+    pub doc_dels: usize,
+    pub touched: Touched,
+}
+
+/// A hunk of a diffed file along with how many of its lines fall within each
+/// entity (as deleted from the old side or added to the new side).
+#[derive(new, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MappedHunk {
+    pub commit: Commit,
+    pub parent: usize,
+    pub filename: String,
+    /// The position of the hunk within the diff of the file
+    pub ordinal: usize,
+    pub hunk: Hunk,
+    /// Each entity along with the number of lines added and deleted
+    pub entities: Vec<(Arc<Entity>, usize, usize)>,
+}
+
+/// An entity which is marked as generated code, either by a header of its file
+/// (e.g. "DO NOT EDIT") or by a `@Generated` annotation.
+#[derive(new, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GeneratedEntity {
+    pub entity: Arc<Entity>,
+    /// Either `header` or `annotation`
+    pub reason: &'static str,
+}
+
+/// The source text of an entity present at a commit (or only its hash).
+#[derive(new, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntitySource {
+    pub entity: Arc<Entity>,
+    pub commit: Commit,
+    /// The hash of the text as a git blob
+    pub hash: Oid,
+    pub text: Option<String>,
+}
+
+impl EntitySource {
+    /// Roughly how many bytes this source takes up, not counting its entity
+    /// (which is shared).
+    pub fn approx_size(&self) -> usize {
+        let text = self.text.as_ref().map_or(0, |t| t.len());
+        std::mem::size_of::<Self>() + self.commit.approx_size() + text
+    }
+}
+
+/// A blob which tree-sitter could not fully parse. The entities outside of its
+/// broken regions are still extracted unless the whole file was skipped.
+#[derive(new, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ParseError {
+    pub filename: String,
+    pub blob: Oid,
+    /// The number of ERROR and MISSING nodes
+    pub errors: usize,
+    /// The number of bytes within ERROR nodes
+    pub error_bytes: usize,
+    pub bytes: usize,
+    /// Whether the file was recorded as a single entity instead
+    pub skipped: bool,
+}
+
+/// What a file in a tree is, as far as its mode goes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum FileMode {
+    #[default]
+    Regular,
+    Executable,
+    Symlink,
+}
+
 #[derive(new, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct DiffedFile {
     pub filename: String,
     pub commit: Commit,
+    /// The index of the parent the commit was diffed against
+    pub parent: usize,
     pub old_file: Oid,
     pub new_file: Oid,
     pub hunks: Vec<Hunk>,
+    /// The modes of the old and new file (regular if missing)
+    pub modes: (FileMode, FileMode),
+}
+
+impl DiffedFile {
+    /// Whether each side of the file which is present is a symlink.
+    pub fn is_symlink(&self) -> bool {
+        let is_symlink = |file: &Oid, mode| file.is_zero() || mode == FileMode::Symlink;
+        is_symlink(&self.old_file, self.modes.0) && is_symlink(&self.new_file, self.modes.1)
+    }
 }
 
 bitflags! {
@@ -142,3 +518,19 @@ impl Default for CommitInfo {
         Self::empty()
     }
 }
+
+/// The settings which decide what the changes and presences of a commit
+/// include, so that a commit processed with other settings can be told apart.
+#[derive(new, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Coverage {
+    /// The glob of the files which were processed (e.g. `*.java` or `*`)
+    pub files: String,
+    /// How finely files were broken down into entities (e.g. `entity`)
+    pub granularity: String,
+    /// The languages which were parsed into entities, sorted (e.g. `.java`,
+    /// `ctags`, or an injection such as `sql=.sql`)
+    pub languages: Vec<String>,
+    /// The vendored directories whose files were left out, sorted (e.g.
+    /// `node_modules`, or nothing with --include-vendored)
+    pub excluded: Vec<String>,
+}
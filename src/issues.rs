@@ -0,0 +1,205 @@
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::ir;
+use crate::prs::encode;
+use crate::prs::get_json;
+use crate::prs::get_str;
+use crate::prs::get_strs;
+
+/// Words which mark an issue type or label as a defect.
+const DEFECT_WORDS: [&str; 3] = ["bug", "defect", "regression"];
+
+/// The kind given to GitHub pull requests, which say nothing about whether a
+/// commit fixes a defect.
+pub const PULL_REQUEST_KIND: &str = "Pull Request";
+
+/// Where the issues of a repository are tracked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Tracker {
+    /// GitHub (or GitHub Enterprise) issues, referred to as `#123`
+    Github,
+    /// Jira issues, referred to as `PROJ-123`
+    Jira,
+}
+
+impl Tracker {
+    /// The environment variable which holds the access token.
+    pub fn token_var(self) -> &'static str {
+        match self {
+            Tracker::Github => "GITHUB_TOKEN",
+            Tracker::Jira => "JIRA_TOKEN",
+        }
+    }
+}
+
+/// Finds the keys of the issues a commit message refers to, in the order they
+/// first appear. On GitHub these are `#123`. On Jira these are `PROJ-123` where
+/// `PROJ` is one of the given projects (as otherwise e.g. `UTF-8` would match).
+pub fn extract_issue_keys(message: &str, tracker: Tracker, projects: &[String]) -> Vec<String> {
+    let mut keys = Vec::new();
+    let bytes = message.as_bytes();
+
+    for (i, _) in message.match_indices(|c: char| c == '#' || c.is_ascii_uppercase()) {
+        // Keys must start a word
+        if i > 0 && (bytes[i - 1].is_ascii_alphanumeric() || bytes[i - 1] == b'_') {
+            continue;
+        }
+
+        let key = match tracker {
+            Tracker::Github => match bytes[i] {
+                b'#' => to_issue_key(message, i, i + 1),
+                _ => None,
+            },
+            Tracker::Jira => projects.iter().find_map(|project| {
+                let rest = message[i..].strip_prefix(project.as_str())?.strip_prefix('-')?;
+                to_issue_key(message, i, message.len() - rest.len())
+            }),
+        };
+
+        if let Some(key) = key.filter(|key| !keys.contains(key)) {
+            keys.push(key);
+        }
+    }
+
+    keys
+}
+
+/// The key from `start` through the number at `number_start` if there is one
+/// and it ends a word.
+fn to_issue_key(message: &str, start: usize, number_start: usize) -> Option<String> {
+    let number = &message[number_start..];
+    let len = number.find(|c: char| !c.is_ascii_digit()).unwrap_or(number.len());
+    let next = number[len..].chars().next();
+
+    if len == 0 || next.is_some_and(|c| c.is_alphanumeric() || c == '_') {
+        return None;
+    }
+
+    Some(message[start..number_start + len].to_string())
+}
+
+/// Reads issues from the REST API of an issue tracker. Requests are made by
+/// running curl.
+pub struct IssueClient {
+    tracker: Tracker,
+    api_url: String,
+    headers: Vec<String>,
+    /// The URL path of the repository (only used on GitHub)
+    project: String,
+}
+
+impl IssueClient {
+    /// The project is `owner/name` on GitHub and ignored on Jira, which has no
+    /// default API URL. On Jira Cloud, the token is an API token and the user
+    /// is the email of its owner. On other Jira instances, the token is a
+    /// personal access token and there is no user.
+    pub fn new(
+        tracker: Tracker,
+        api_url: Option<String>,
+        project: &str,
+        token: Option<String>,
+        user: Option<String>,
+    ) -> Result<Self> {
+        let api_url = match (tracker, api_url) {
+            (_, Some(api_url)) => api_url.trim_end_matches('/').to_string(),
+            (Tracker::Github, None) => "https://api.github.com".to_string(),
+            (Tracker::Jira, None) => bail!("Jira requires the URL of its instance"),
+        };
+
+        let mut headers = vec!["Accept: application/json".to_string()];
+
+        match (token, user) {
+            (Some(token), Some(user)) => {
+                let credentials = to_base64(format!("{}:{}", user, token).as_bytes());
+                headers.push(format!("Authorization: Basic {}", credentials));
+            }
+            (Some(token), None) => headers.push(format!("Authorization: Bearer {}", token)),
+            (None, _) => {}
+        }
+
+        let project = format!("repos/{}", project.trim_matches('/'));
+        Ok(Self { tracker, api_url, headers, project })
+    }
+
+    /// Gets the issue with the given key, which is `None` if there is none.
+    pub fn get(&self, key: &str) -> Result<Option<ir::Issue>> {
+        match self.tracker {
+            Tracker::Github => {
+                let number = key.trim_start_matches('#');
+                let url = format!("{}/{}/issues/{}", self.api_url, self.project, number);
+                get_json(&url, &self.headers)?.map(|item| to_github_issue(key, &item)).transpose()
+            }
+            Tracker::Jira => {
+                let url = format!(
+                    "{}/rest/api/2/issue/{}?fields=issuetype,summary,status,resolution,labels",
+                    self.api_url,
+                    encode(key)
+                );
+                get_json(&url, &self.headers)?.map(|item| to_jira_issue(key, &item)).transpose()
+            }
+        }
+    }
+}
+
+fn to_github_issue(key: &str, item: &Value) -> Result<ir::Issue> {
+    let labels = get_strs(&item["labels"], "name");
+
+    // Pull requests are also issues, but their type comes from the issues
+    // they refer to
+    let kind = match item["type"]["name"].as_str() {
+        _ if item.get("pull_request").is_some() => Some(PULL_REQUEST_KIND.to_string()),
+        kind => kind.map(str::to_string),
+    };
+
+    let is_defect = kind.iter().chain(&labels).any(|name| is_defect_name(name));
+    let resolution = item["state_reason"].as_str().map(str::to_string);
+    let title = get_str(item, "title")?.to_string();
+    let status = get_str(item, "state")?.to_string();
+    Ok(ir::Issue::new(key.to_string(), kind, title, status, resolution, labels, is_defect))
+}
+
+fn to_jira_issue(key: &str, item: &Value) -> Result<ir::Issue> {
+    let fields = &item["fields"];
+    let kind = get_str(&fields["issuetype"], "name").context("missing issue type")?;
+    let labels = fields["labels"].as_array().into_iter().flatten();
+    let labels = labels.filter_map(|label| label.as_str().map(str::to_string)).collect();
+
+    Ok(ir::Issue::new(
+        key.to_string(),
+        Some(kind.to_string()),
+        get_str(fields, "summary")?.to_string(),
+        get_str(&fields["status"], "name")?.to_string(),
+        fields["resolution"]["name"].as_str().map(str::to_string),
+        labels,
+        is_defect_name(kind),
+    ))
+}
+
+/// Whether the name of an issue type or label marks a defect (e.g. "Bug" or
+/// "type: regression").
+fn is_defect_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    DEFECT_WORDS.iter().any(|word| name.contains(word))
+}
+
+fn to_base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | ((b as u32) << (16 - 8 * i)));
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
@@ -0,0 +1,22 @@
+#[macro_use]
+extern crate derive_builder;
+extern crate derive_new;
+
+pub mod analysis;
+pub mod browse;
+pub mod ctags;
+pub mod db;
+pub mod deps;
+pub mod export;
+pub mod extraction;
+pub mod ffi;
+pub mod gtl;
+pub mod hg;
+pub mod ir;
+pub mod issues;
+pub mod metrics;
+pub mod parsing;
+pub mod prs;
+pub mod report;
+pub mod serve;
+pub mod vcs;
@@ -1,19 +1,18 @@
-#![feature(iterator_try_collect)]
-
-#[macro_use]
-extern crate derive_builder;
-extern crate derive_new;
-
-mod db;
-mod extraction;
-mod gtl;
-mod ir;
-mod parsing;
-mod deps;
-
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs::create_dir_all;
 use std::fs::remove_file;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
 use std::time::Instant;
 
 use ::time::format_description::well_known::Iso8601;
@@ -26,31 +25,270 @@ use clap::App;
 use clap::CommandFactory;
 use clap_verbosity_flag::InfoLevel;
 use clap_verbosity_flag::Verbosity;
+use git2::Oid;
 use git2::Reference;
 use git2::Repository;
 use git2::Sort;
-use parsing::FileParser;
+use rusqlite::types::Value;
 use rusqlite::Connection;
-use tree_sitter::Language;
-
-use crate::db::DepVirtualTable;
-use crate::db::DepWriter;
-use crate::db::insert_change;
-use crate::db::insert_presence;
-use crate::db::insert_ref;
-use crate::db::VirtualDb;
-use crate::deps::get_commit_id;
-use crate::deps::insert_deps;
-use crate::deps::match_entity_id;
-use crate::deps::load_dep_file;
-use crate::deps::load_locs;
-use crate::extraction::diff_all_files;
-use crate::extraction::get_changes;
-use crate::extraction::get_presences;
-use crate::extraction::CommitWalk;
-use crate::extraction::ExtractionCtx;
-use crate::extraction::RefGlobKind;
-use crate::ir::*;
+use serde_json::json;
+
+use cochange_tool::analysis::align_clusters;
+use cochange_tool::analysis::ancestors_or_self;
+use cochange_tool::analysis::apply_half_life;
+use cochange_tool::analysis::classify_commit;
+use cochange_tool::analysis::find_bus_factors;
+use cochange_tool::analysis::find_churn_trend;
+use cochange_tool::analysis::find_clones;
+use cochange_tool::analysis::find_clusters;
+use cochange_tool::analysis::find_commit_metrics;
+use cochange_tool::analysis::find_coupled_pairs;
+use cochange_tool::analysis::find_dep_cochanges;
+use cochange_tool::analysis::find_dev_pairs;
+use cochange_tool::analysis::find_divergent_changes;
+use cochange_tool::analysis::find_features;
+use cochange_tool::analysis::find_hidden_deps;
+use cochange_tool::analysis::find_hotspots;
+use cochange_tool::analysis::find_impact;
+use cochange_tool::analysis::find_interfaces;
+use cochange_tool::analysis::find_knowledge_loss;
+use cochange_tool::analysis::find_suggestions;
+use cochange_tool::analysis::find_test_links;
+use cochange_tool::analysis::group_pull_requests;
+use cochange_tool::analysis::group_sessions;
+use cochange_tool::analysis::in_window;
+use cochange_tool::analysis::load_change_sets;
+use cochange_tool::analysis::load_commit_authors;
+use cochange_tool::analysis::load_commit_messages;
+use cochange_tool::analysis::load_commit_shapes;
+use cochange_tool::analysis::load_commit_shas;
+use cochange_tool::analysis::load_contributions;
+use cochange_tool::analysis::load_deps;
+use cochange_tool::analysis::load_duplicate_ids;
+use cochange_tool::analysis::load_entity_keys;
+use cochange_tool::analysis::load_entity_log;
+use cochange_tool::analysis::load_entity_paths;
+use cochange_tool::analysis::load_extracted_commits;
+use cochange_tool::analysis::load_files;
+use cochange_tool::analysis::load_generated_ids;
+use cochange_tool::analysis::load_kinds;
+use cochange_tool::analysis::load_latest_presence_commit;
+use cochange_tool::analysis::load_locations;
+use cochange_tool::analysis::load_matching_files;
+use cochange_tool::analysis::load_measures;
+use cochange_tool::analysis::load_ownership;
+use cochange_tool::analysis::load_parents;
+use cochange_tool::analysis::load_present_methods;
+use cochange_tool::analysis::load_pull_request_ids;
+use cochange_tool::analysis::load_releases;
+use cochange_tool::analysis::load_source_hashes;
+use cochange_tool::analysis::load_test_links;
+use cochange_tool::analysis::load_touches;
+use cochange_tool::analysis::read_aliases;
+use cochange_tool::analysis::resolve_entity;
+use cochange_tool::analysis::roll_up;
+use cochange_tool::analysis::to_aliases;
+use cochange_tool::analysis::to_file_ids;
+use cochange_tool::analysis::to_package;
+use cochange_tool::analysis::to_period_metrics;
+use cochange_tool::analysis::to_release_windows;
+use cochange_tool::analysis::to_rollup;
+use cochange_tool::analysis::to_shares;
+use cochange_tool::analysis::to_trend_point;
+use cochange_tool::analysis::to_windows;
+use cochange_tool::analysis::Aggregation;
+use cochange_tool::analysis::BulkKind;
+use cochange_tool::analysis::BulkThresholds;
+use cochange_tool::analysis::ChangeSet;
+use cochange_tool::analysis::Coupling;
+use cochange_tool::analysis::DepGraph;
+use cochange_tool::analysis::HotspotMeasure;
+use cochange_tool::analysis::InterfaceClass;
+use cochange_tool::analysis::PeriodMetrics;
+use cochange_tool::analysis::Suggestion;
+use cochange_tool::analysis::Volatility;
+use cochange_tool::analysis::Window;
+use cochange_tool::browse::browse;
+use cochange_tool::ctags::CtagsParser;
+use cochange_tool::db::drop_table;
+use cochange_tool::db::BulkCommitExtra;
+use cochange_tool::db::BulkCommitKey;
+use cochange_tool::db::BulkCommitVirtualTable;
+use cochange_tool::db::BulkCommitWriter;
+use cochange_tool::db::BusFactorExtra;
+use cochange_tool::db::BusFactorKey;
+use cochange_tool::db::BusFactorVirtualTable;
+use cochange_tool::db::BusFactorWriter;
+use cochange_tool::db::CloneExtra;
+use cochange_tool::db::CloneKey;
+use cochange_tool::db::CloneVirtualTable;
+use cochange_tool::db::CloneWriter;
+use cochange_tool::db::ClusterExtra;
+use cochange_tool::db::ClusterKey;
+use cochange_tool::db::ClusterVirtualTable;
+use cochange_tool::db::ClusterWriter;
+use cochange_tool::db::ComparisonExtra;
+use cochange_tool::db::ComparisonKey;
+use cochange_tool::db::ComparisonVirtualTable;
+use cochange_tool::db::ComparisonWriter;
+use cochange_tool::db::CouplingExtra;
+use cochange_tool::db::CouplingKey;
+use cochange_tool::db::CouplingVirtualTable;
+use cochange_tool::db::CouplingWriter;
+use cochange_tool::db::DepCochangeExtra;
+use cochange_tool::db::DepCochangeKey;
+use cochange_tool::db::DepCochangeVirtualTable;
+use cochange_tool::db::DepCochangeWriter;
+use cochange_tool::db::DepVirtualTable;
+use cochange_tool::db::DepWriter;
+use cochange_tool::db::DivergentChangeExtra;
+use cochange_tool::db::DivergentChangeKey;
+use cochange_tool::db::DivergentChangeVirtualTable;
+use cochange_tool::db::DivergentChangeWriter;
+use cochange_tool::db::HiddenDepExtra;
+use cochange_tool::db::HiddenDepKey;
+use cochange_tool::db::HiddenDepVirtualTable;
+use cochange_tool::db::HiddenDepWriter;
+use cochange_tool::db::HotspotExtra;
+use cochange_tool::db::HotspotKey;
+use cochange_tool::db::HotspotVirtualTable;
+use cochange_tool::db::HotspotWriter;
+use cochange_tool::db::Id;
+use cochange_tool::db::InterfaceExtra;
+use cochange_tool::db::InterfaceKey;
+use cochange_tool::db::InterfaceVirtualTable;
+use cochange_tool::db::InterfaceWriter;
+use cochange_tool::db::IssueDb;
+use cochange_tool::db::KnowledgeLossExtra;
+use cochange_tool::db::KnowledgeLossKey;
+use cochange_tool::db::KnowledgeLossVirtualTable;
+use cochange_tool::db::KnowledgeLossWriter;
+use cochange_tool::db::OwnershipExtra;
+use cochange_tool::db::OwnershipKey;
+use cochange_tool::db::OwnershipVirtualTable;
+use cochange_tool::db::OwnershipWriter;
+use cochange_tool::db::PullRequestDb;
+use cochange_tool::db::RunExtraBuilder;
+use cochange_tool::db::RunKey;
+use cochange_tool::db::RunMetricExtra;
+use cochange_tool::db::RunMetricKey;
+use cochange_tool::db::RunMetricVirtualTable;
+use cochange_tool::db::RunMetricWriter;
+use cochange_tool::db::RunVirtualTable;
+use cochange_tool::db::RunWriter;
+use cochange_tool::db::TestLinkExtra;
+use cochange_tool::db::TestLinkKey;
+use cochange_tool::db::TestLinkVirtualTable;
+use cochange_tool::db::TestLinkWriter;
+use cochange_tool::db::has_dir_entities;
+use cochange_tool::db::has_table;
+use cochange_tool::db::insert_change;
+use cochange_tool::db::insert_changed_lines;
+use cochange_tool::db::insert_commit_coverage;
+use cochange_tool::db::insert_diagnostic;
+use cochange_tool::db::insert_doc_change;
+use cochange_tool::db::insert_duplicate;
+use cochange_tool::db::insert_entity_source;
+use cochange_tool::db::insert_issue;
+use cochange_tool::db::insert_mapped_hunk;
+use cochange_tool::db::insert_generated_entity;
+use cochange_tool::db::insert_parse_error;
+use cochange_tool::db::insert_presence;
+use cochange_tool::db::insert_pull_request;
+use cochange_tool::db::insert_ref;
+use cochange_tool::db::insert_ref_member;
+use cochange_tool::db::insert_ref_update;
+use cochange_tool::db::insert_revert;
+use cochange_tool::db::insert_submodule_update;
+use cochange_tool::db::insert_symlink_update;
+use cochange_tool::db::insert_tag;
+use cochange_tool::db::merge_db;
+use cochange_tool::db::write_commit_metrics;
+use cochange_tool::db::write_entity_metrics;
+use cochange_tool::db::write_lifespans;
+use cochange_tool::db::write_meta_schema;
+use cochange_tool::db::VirtualDb;
+use cochange_tool::deps::get_commit_id;
+use cochange_tool::deps::insert_deps;
+use cochange_tool::deps::load_dep_file;
+use cochange_tool::deps::load_locs;
+use cochange_tool::export::export_table;
+use cochange_tool::export::load_table_names;
+use cochange_tool::export::write_csv;
+use cochange_tool::export::write_jsonl;
+use cochange_tool::export::write_neo4j_import;
+use cochange_tool::export::write_sarif;
+use cochange_tool::export::write_series;
+use cochange_tool::export::write_table;
+use cochange_tool::export::Column;
+use cochange_tool::export::ColumnType;
+use cochange_tool::export::DumpFormat;
+use cochange_tool::export::Finding;
+use cochange_tool::export::Graph;
+use cochange_tool::export::GraphFormat;
+use cochange_tool::export::LineConvention;
+use cochange_tool::export::RangeEnd;
+use cochange_tool::export::ReportFormat;
+use cochange_tool::export::SeriesFormat;
+use cochange_tool::export::TableFormat;
+use cochange_tool::export::HIDDEN_DEPENDENCY;
+use cochange_tool::export::HOTSPOT;
+use cochange_tool::export::MISSING_COCHANGE;
+use cochange_tool::export::VOLATILE_INTERFACE;
+use cochange_tool::extraction::diff_all_files;
+use cochange_tool::extraction::diff_uncommitted;
+use cochange_tool::extraction::find_annotated_test_files;
+use cochange_tool::extraction::find_case_collisions;
+use cochange_tool::extraction::find_duplicates;
+use cochange_tool::extraction::find_reverts;
+use cochange_tool::extraction::get_changed_lines;
+use cochange_tool::extraction::get_changes;
+use cochange_tool::extraction::get_doc_changes;
+use cochange_tool::extraction::get_mapped_hunks;
+use cochange_tool::extraction::get_numstat;
+use cochange_tool::extraction::get_patch_ids;
+use cochange_tool::extraction::get_presences;
+use cochange_tool::extraction::get_ref_history;
+use cochange_tool::extraction::get_ref_members;
+use cochange_tool::extraction::get_symlink_update;
+use cochange_tool::extraction::get_tags;
+use cochange_tool::extraction::has_commit_graph;
+use cochange_tool::extraction::is_sampled;
+use cochange_tool::extraction::open_repo;
+use cochange_tool::extraction::rewrite_parents;
+use cochange_tool::extraction::sample_commits;
+use cochange_tool::extraction::write_commit_graph;
+use cochange_tool::extraction::CaseCollisions;
+use cochange_tool::extraction::CommitWalk;
+use cochange_tool::extraction::ExtractionCtx;
+use cochange_tool::extraction::Granularity;
+use cochange_tool::extraction::MergeMode;
+use cochange_tool::extraction::RefGlobKind;
+use cochange_tool::extraction::Sampling;
+use cochange_tool::extraction::Shard;
+use cochange_tool::extraction::SourceMode;
+use cochange_tool::extraction::Uncommitted;
+use cochange_tool::extraction::VENDORED_DIRS;
+use cochange_tool::gtl;
+use cochange_tool::hg::Mercurial;
+use cochange_tool::ir::*;
+use cochange_tool::issues::extract_issue_keys;
+use cochange_tool::issues::IssueClient;
+use cochange_tool::issues::Tracker;
+use cochange_tool::metrics::init_logger;
+use cochange_tool::metrics::LogFormat;
+use cochange_tool::metrics::Metrics;
+use cochange_tool::parsing::get_java_parser;
+use cochange_tool::prs::Platform;
+use cochange_tool::prs::PrClient;
+use cochange_tool::report::write_html_report;
+use cochange_tool::report::Report;
+use cochange_tool::serve;
+use cochange_tool::serve::serve;
+use cochange_tool::vcs::strip_credentials;
+use cochange_tool::vcs::Vcs;
+use cochange_tool::vcs::VcsKind;
+use cochange_tool::vcs::WorkdirVcs;
 
 #[derive(Debug, clap::Parser)]
 #[clap(version, author)]
@@ -59,14 +297,33 @@ struct Cli {
     #[clap(flatten)]
     verbose: Verbosity<InfoLevel>,
 
+    /// Format of the log written to stderr. With `json`, each line is a JSON
+    /// object and timings are given as fields.
+    #[clap(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
     #[clap(subcommand)]
     command: CliSubCommand,
 }
 
 #[derive(Debug, clap::Subcommand)]
 enum CliSubCommand {
-    Dump(CliDumpCommand),
+    Dump(Box<CliDumpCommand>),
     AddDeps(AddDeps),
+    Analyze(CliAnalyzeCommand),
+    Log(CliLogCommand),
+    Suggest(CliSuggestCommand),
+    Impact(CliImpactCommand),
+    Check(CliCheckCommand),
+    Notes(CliNotesCommand),
+    Export(CliExportCommand),
+    Report(CliReportCommand),
+    Browse(CliBrowseCommand),
+    Serve(CliServeCommand),
+    Merge(CliMergeCommand),
+    Audit(CliAuditCommand),
+    ImportPrs(CliImportPrsCommand),
+    ImportIssues(CliImportIssuesCommand),
 }
 
 /// Dump the co-change data of a git repository.
@@ -86,21 +343,35 @@ enum CliSubCommand {
 /// - Arbitrary commit hashs are not accepted as input. Starting commits must be
 ///   provided as named references ([REFS]).
 ///
-/// - Parent rewriting is not supported. Each commit is diffed with its real
-///   parent to determine the (co-)changes of that commit.
+/// - Parent rewriting is opt-in (see --rewrite-parents). Otherwise, each commit
+///   is diffed with its real parent to determine the (co-)changes of that
+///   commit.
 ///
-/// - Set subtraction (i.e. `foo ^bar` or `foo..bar`) is not supported.
+/// - Set subtraction (i.e. `foo ^bar` or `foo..bar`) is only supported through
+///   --not (e.g. `foo --not bar`).
 #[derive(Debug, clap::Args)]
 struct CliDumpCommand {
     /// Starting commits given as named references (e.g. HEAD, branchname, etc.)
     #[clap()]
     refs: Vec<String>,
 
-    /// Use the given path to a git repository instead of the current directory.
+    /// Use the given path to a git repository instead of the current directory
+    /// (or $GIT_DIR if set). This may be a bare repository or a linked worktree.
     #[clap(help_heading = "I/O", long, short = 'C')]
     repo: Option<PathBuf>,
 
+    /// The version control system of the repository.
+    ///
+    /// With `hg`, the hg executable is run to read a Mercurial repository and
+    /// [REFS] are revsets (`default` if none are given). Only commits, changes,
+    /// presences, and refs are extracted, so the options which need git (e.g.
+    /// --all, --reflog, and --patch-ids) are not supported.
+    #[clap(help_heading = "I/O", long, value_enum, default_value_t = VcsKind::Git)]
+    vcs: VcsKind,
+
     /// Path to the database of co-change data.
+    ///
+    /// Use `-` to write JSON Lines to stdout instead (implies --format jsonl).
     #[clap(help_heading = "I/O", long)]
     db: PathBuf,
 
@@ -108,6 +379,229 @@ struct CliDumpCommand {
     #[clap(help_heading = "I/O", long)]
     force: bool,
 
+    /// Format of the output. With `jsonl`, one JSON record is written per line
+    /// for each commit, change, and presence (and nothing else is stored).
+    #[clap(help_heading = "I/O", long, value_enum, default_value_t = DumpFormat::Sqlite)]
+    format: DumpFormat,
+
+    #[clap(flatten)]
+    lines: LineArgs,
+
+    /// Also store how long each phase, commit, and file took in the
+    /// run_metrics table.
+    #[clap(help_heading = "I/O", long)]
+    run_metrics: bool,
+
+    /// Also store the hunks of each diffed file in the hunks table, along with
+    /// how many of their lines fall within each entity in the hunk_entities
+    /// table.
+    ///
+    /// This lets changes be attributed to entities differently without
+    /// extracting them again. Ignored with --format jsonl.
+    #[clap(help_heading = "I/O", long)]
+    store_hunks: bool,
+
+    /// Also store which lines of each entity were added and deleted by each
+    /// change in the change_lines table (as lists such as `3-5,9`).
+    ///
+    /// Added lines are numbered as in the new blob and deleted lines as in the
+    /// old blob. Ignored with --format jsonl.
+    #[clap(help_heading = "I/O", long)]
+    store_lines: bool,
+
+    /// Also store how each change touched the doc comment (e.g. Javadoc) of an
+    /// entity in the doc_changes table.
+    ///
+    /// Each row counts the added and deleted lines of the doc comment and tells
+    /// whether the change touched only the doc comment (D), only the code (C),
+    /// or both (B). Only entities with a doc comment are included. Ignored with
+    /// --format jsonl.
+    #[clap(help_heading = "I/O", long)]
+    store_docs: bool,
+
+    /// Also store the source of each entity at the presence snapshots in the
+    /// entity_sources table.
+    ///
+    /// With `text`, the source is stored compressed (with zlib) along with its
+    /// hash. With `hash`, only the hash is stored, which is enough to tell
+    /// whether an entity changed between snapshots. The hash is that of the
+    /// source as a git blob. Ignored with --format jsonl.
+    #[clap(help_heading = "I/O", long, value_name = "MODE", value_enum)]
+    store_sources: Option<SourceMode>,
+
+    /// Roughly limit the memory used while extracting (e.g. 512MiB or 8GB).
+    ///
+    /// Half of it bounds the parse cache (split between the --jobs threads)
+    /// unless --cache-size is given. Whenever the extracted rows (e.g. changes
+    /// and presences) outgrow the other half, they are written to the database
+    /// between commits and dropped from memory. Entities and commits are kept
+    /// in memory until the end. With --format jsonl, only the cache is limited.
+    #[clap(help_heading = "I/O", long, value_name = "SIZE")]
+    max_memory: Option<String>,
+
+    /// Commit after writing every <N> thousand rows of each table rather than
+    /// writing the whole database in a single transaction.
+    ///
+    /// The schema is written first and tables are still written in order (e.g.
+    /// entities before changes), so a failure leaves a smaller but consistent
+    /// database behind. The derived tables (e.g. lifespans) and the record of
+    /// the run are only written at the end, so such a database lacks them.
+    #[clap(help_heading = "I/O", long, value_name = "N")]
+    txn_size: Option<usize>,
+
+    /// Parse the changed files on this many threads.
+    ///
+    /// Commits are still diffed on a single thread. Each thread reads blobs
+    /// from its own handle of the repository and keeps its own parse cache,
+    /// between which --cache-size (or --max-memory) is split.
+    #[clap(help_heading = "I/O", long, short = 'j', value_name = "N", default_value_t = 1)]
+    jobs: usize,
+
+    /// Only extract presence, without diffing any commit.
+    ///
+    /// The commits are still walked to find the snapshots (e.g. for
+    /// --presence-every), but none of them is marked as having its changes
+    /// extracted. Combine with a run using --skip-presence by the merge
+    /// subcommand, so that the cheap changes can be extracted more often than
+    /// the snapshots.
+    #[clap(help_heading = "DIFFING", long, conflicts_with_all = &["skip-presence", "resume"])]
+    skip_changes: bool,
+
+    /// How to diff merge commits.
+    ///
+    /// With `all-parents`, merges (including octopus merges) are diffed against
+    /// each of their parents and each change records the index of the parent.
+    #[clap(help_heading = "DIFFING", long, value_enum, default_value_t = MergeMode::Skip)]
+    merges: MergeMode,
+
+    /// Diff each commit against its nearest ancestors among the processed
+    /// commits instead of its real parents, like the parent rewriting of
+    /// git-log.
+    ///
+    /// Commits left out by --grep or --until are then folded into the next
+    /// processed commit, so its changes include theirs. Parents which are
+    /// hidden (e.g. by --not) or older than --since are still used as is.
+    #[clap(help_heading = "DIFFING", long)]
+    rewrite_parents: bool,
+
+    /// How finely files are broken down into entities.
+    ///
+    /// With `file`, no file is parsed and every file (in any language) is
+    /// recorded as a single entity. With `auto`, Java files are parsed as usual
+    /// and every other file falls back to a single entity.
+    #[clap(help_heading = "DIFFING", long, value_enum, default_value_t = Granularity::Entity)]
+    granularity: Granularity,
+
+    /// Parse the files which have no tree-sitter grammar by running
+    /// universal-ctags (e.g. `--ctags ctags`). Requires --granularity auto.
+    ///
+    /// The executable must be built with JSON support. Its tags are nested by
+    /// their line ranges and have no complexity.
+    #[clap(help_heading = "DIFFING", long, value_name = "PROGRAM")]
+    ctags: Option<PathBuf>,
+
+    /// Like --ctags, but read the tags from a file written by running `ctags
+    /// -R --output-format=json --fields=+neS` at the root of the repository.
+    ///
+    /// Its line numbers only match the commit it was generated from.
+    #[clap(help_heading = "DIFFING", long, value_name = "FILE", conflicts_with = "ctags")]
+    tags_file: Option<PathBuf>,
+
+    /// Parse the code of a language embedded in Java files (e.g. SQL strings)
+    /// like files with the given extension (e.g. `--inject sql=.sql`).
+    ///
+    /// Embedded code is found by queries/java/injections.scm, which names its
+    /// language. Its entities become children of the innermost entity around
+    /// them. Extensions other than .java are parsed with --ctags. Java is the
+    /// only host, as no tree-sitter grammar is bundled for JSP or HTML files.
+    #[clap(help_heading = "DIFFING", long, value_name = "LANGUAGE=EXT")]
+    inject: Vec<String>,
+
+    /// Record files with more than this fraction of their bytes in syntax
+    /// errors (e.g. 0.5) as a single entity instead of parsing them.
+    ///
+    /// Otherwise, only the entities within broken regions are left out. Each
+    /// parsed blob with syntax errors is stored in the parse_errors table.
+    #[clap(help_heading = "DIFFING", long, value_name = "RATIO")]
+    max_error_ratio: Option<f64>,
+
+    /// Leave changed lines which are blank or only hold comments out of the
+    /// adds and dels of each entity.
+    ///
+    /// Comments are found in the syntax tree, so this only applies to the files
+    /// parsed by tree-sitter. Entities whose only changed lines are comments
+    /// are no longer recorded as modified.
+    #[clap(help_heading = "DIFFING", long)]
+    exclude_comments: bool,
+
+    /// Also leave out the files below directories with this name (or path).
+    /// May be repeated.
+    ///
+    /// Vendored third-party code (e.g. below `node_modules`, `third_party`,
+    /// `thirdparty`, `third-party`, or `vendor`) is left out by default so that
+    /// updating it does not dominate the churn of the repository.
+    #[clap(help_heading = "DIFFING", long, value_name = "DIR")]
+    vendored_dir: Vec<String>,
+
+    /// Extract the files below vendored directories as well.
+    #[clap(help_heading = "DIFFING", long)]
+    include_vendored: bool,
+
+    /// Have git fetch the objects which are missing from a partial clone (e.g.
+    /// one cloned with `--filter=blob:none`) from its promisor remote.
+    ///
+    /// Otherwise, commits which cannot be diffed and blobs which cannot be
+    /// parsed because of missing objects are skipped. Either way, whatever is
+    /// skipped (including the missing parents of a shallow clone) is stored in
+    /// the diagnostics table.
+    #[clap(help_heading = "DIFFING", long)]
+    fetch_missing: bool,
+
+    /// Insert an entity (of kind `dir`) for each directory above the changed
+    /// and present files.
+    ///
+    /// Files become children of their directory (and directories of their
+    /// parent directory), so packages can be queried without splitting paths.
+    #[clap(help_heading = "DIFFING", long)]
+    dir_entities: bool,
+
+    /// What to do with changed files whose paths differ only by case.
+    ///
+    /// On case-insensitive file systems (e.g. on macOS and Windows) they are
+    /// the same file, whose history would otherwise be split between them.
+    /// With `merge`, each is recorded under the path which was changed most
+    /// recently. Unless they are ignored, they are stored in the diagnostics
+    /// table.
+    #[clap(
+        help_heading = "DIFFING",
+        long,
+        value_enum,
+        value_name = "MODE",
+        default_value_t = CaseCollisions::Flag
+    )]
+    case_collisions: CaseCollisions,
+
+    /// Write a commit-graph file (using the git executable) before walking if
+    /// the repository does not have one yet.
+    ///
+    /// Walks over very large histories are much faster with a commit-graph.
+    /// One is used whenever it exists.
+    #[clap(help_heading = "I/O", long)]
+    write_commit_graph: bool,
+
+    /// Keep at most this many parsed files in the cache, evicting the least
+    /// recently used first.
+    #[clap(help_heading = "I/O", long, value_name = "NUMBER")]
+    cache_entries: Option<usize>,
+
+    /// Keep at most this much of parsed files in the cache (e.g. 512MiB or
+    /// 8GB), evicting the least recently used first.
+    ///
+    /// The size of a parsed file is estimated, so the limit is approximate.
+    /// Only the cache is limited, not the extracted changes and presences.
+    #[clap(help_heading = "I/O", long, value_name = "SIZE")]
+    cache_size: Option<String>,
+
     /// Limit the number of commits to process (i.e. extract (co-)change
     /// information from).
     ///
@@ -122,6 +616,15 @@ struct CliDumpCommand {
     )]
     max_count: Option<usize>,
 
+    /// Limit the number of commits to process from each starting commit (e.g.
+    /// the latest 100 commits of each branch with --branches).
+    ///
+    /// Commits reachable from several starting commits are processed once.
+    /// With --max-count, the commits taken from every starting commit are then
+    /// limited to the most recent ones overall.
+    #[clap(help_heading = "COMMIT LIMITING", display_order = 6, long, value_name = "NUMBER")]
+    max_count_per_ref: Option<usize>,
+
     /// Only process commits created after a specific date.
     ///
     /// Expected to be ISO 8601. Time portion is optional. Timezone defaults to
@@ -140,6 +643,14 @@ struct CliDumpCommand {
     #[clap(help_heading = "COMMIT LIMITING", display_order = 4, long, value_name = "DATE")]
     until: Option<String>,
 
+    /// Walk the whole history to find every commit within --since and --until.
+    ///
+    /// Otherwise, the walk stops once several commits in a row are older than
+    /// --since (like git-log), which may miss commits in range behind commits
+    /// with skewed clocks.
+    #[clap(help_heading = "COMMIT LIMITING", display_order = 5, long)]
+    strict_dates: bool,
+
     /// Pretend as if all the refs in `refs/`, along with `HEAD`, are listed on
     /// the command line as [REFS].
     #[clap(help_heading = "COMMIT LIMITING", display_order = 7, long, action)]
@@ -181,6 +692,102 @@ struct CliDumpCommand {
         value_name = "GLOB_PATTERN"
     )]
     glob: Option<String>,
+
+    /// Exclude the commits reachable from the given ref (e.g. a branch or tag).
+    /// May be given multiple times.
+    #[clap(help_heading = "COMMIT LIMITING", display_order = 12, long, value_name = "REF")]
+    not: Vec<String>,
+
+    /// Only process commits whose message contains <TEXT> (unlike git-log, this
+    /// is not a regular expression).
+    #[clap(help_heading = "COMMIT LIMITING", display_order = 13, long, value_name = "TEXT")]
+    grep: Option<String>,
+
+    /// Only process the commits of the <I>th of <N> disjoint shards (e.g.
+    /// 1/4, 2/4, 3/4, and 4/4).
+    ///
+    /// Commits are assigned to shards by their hash, so running every shard
+    /// with otherwise identical options processes each commit exactly once.
+    /// The resulting databases may be combined with the merge subcommand.
+    /// Duplicate and reverted patches are only found within a shard.
+    #[clap(help_heading = "COMMIT LIMITING", display_order = 14, long, value_name = "I/N")]
+    shard: Option<String>,
+
+    /// Only process roughly the given fraction of commits (e.g. 0.1).
+    ///
+    /// Commits are picked by their hash, so every run with the same rate picks
+    /// the same commits. The sampling is recorded in the runs table.
+    #[clap(help_heading = "COMMIT LIMITING", display_order = 15, long, value_name = "RATE")]
+    sample_rate: Option<f64>,
+
+    /// Only process every <N>th of the commits which would otherwise be
+    /// processed, starting with the most recent.
+    ///
+    /// The sampling is recorded in the runs table.
+    #[clap(help_heading = "COMMIT LIMITING", display_order = 16, long, value_name = "N")]
+    every_nth: Option<usize>,
+
+    /// Stop after extracting the changes of this many commits.
+    ///
+    /// Whatever was completed is still written (without presence, which is
+    /// only taken once every commit is extracted). The first commit which was
+    /// not extracted is recorded in the stopped_at column of the runs table.
+    #[clap(help_heading = "COMMIT LIMITING", display_order = 17, long, value_name = "N")]
+    commit_budget: Option<usize>,
+
+    /// Stop extracting changes (between commits) once the run has taken this
+    /// long (e.g. 30min), like --commit-budget.
+    #[clap(help_heading = "COMMIT LIMITING", display_order = 18, long, value_name = "DURATION")]
+    time_budget: Option<String>,
+
+    /// Skip the commits whose changes the given database already has, e.g. to
+    /// continue a run which stopped because of a budget or Ctrl-C.
+    ///
+    /// (The first Ctrl-C stops a dump between commits and writes whatever was
+    /// completed, recording `interrupted` in the stop_reason column of the runs
+    /// table. A second one quits without writing anything.)
+    ///
+    /// The new database holds the remaining commits, so the two should be
+    /// combined with the merge subcommand.
+    #[clap(help_heading = "COMMIT LIMITING", display_order = 19, long, value_name = "DB")]
+    resume: Option<PathBuf>,
+
+    /// Only extract the changes of the commits, without any presence
+    /// snapshot (see --skip-changes).
+    #[clap(
+        help_heading = "PRESENCE",
+        long,
+        conflicts_with_all = &["presence-at", "presence-every", "store-sources"]
+    )]
+    skip_presence: bool,
+
+    /// Also store the entities present at the given commit (e.g. v1.0, HEAD~10,
+    /// or a hash). May be given multiple times.
+    ///
+    /// By default, presence is only stored for the starting commits.
+    #[clap(help_heading = "PRESENCE", long, value_name = "REV")]
+    presence_at: Vec<String>,
+
+    /// Also store the entities present at a sample of the processed commits.
+    ///
+    /// Either a duration (e.g. 1month) to sample the first commit of each
+    /// period, a number of commits (e.g. 100 or 100commits) to sample every nth
+    /// commit, or `tags` to sample every tagged commit.
+    #[clap(help_heading = "PRESENCE", long, value_name = "SPEC")]
+    presence_every: Option<String>,
+
+    /// Compute the patch-id of each processed commit to record commits which
+    /// duplicate another (e.g. cherry-picks) and commits which revert another.
+    #[clap(help_heading = "PATCHES", long)]
+    patch_ids: bool,
+
+    /// Also store the reflog of the given ref (e.g. a branch). May be given
+    /// multiple times.
+    ///
+    /// Only local reflogs are available, so this mostly records how refs moved
+    /// in the clone being dumped (e.g. fetches and force-pushes).
+    #[clap(help_heading = "REF HISTORY", long, value_name = "REF")]
+    reflog: Vec<String>,
     // /// Only commits modifying the given <PATHS> are selected.
     // #[clap(help_heading = "COMMIT LIMITING", display_order = 12, long)]
     // paths: Vec<String>,
@@ -202,238 +809,4886 @@ struct AddDeps {
     commit: String,
 }
 
-fn parse_time_input<S: AsRef<str>>(text: S) -> Option<OffsetDateTime> {
-    // First, try to parse it as a date and time
-    if let Ok(datetime) = OffsetDateTime::parse(text.as_ref(), &Iso8601::PARSING) {
-        return Some(datetime);
-    }
-
-    // If that doesn't work, try parsing it as just a date
-    if let Ok(date) = Date::parse(text.as_ref(), &Iso8601::PARSING) {
-        return Some(PrimitiveDateTime::new(date, Time::MIDNIGHT).assume_utc());
-    }
-
-    // Finally, try to prase it as a duration and subtract
-    if let Ok(duration) = humantime::parse_duration(text.as_ref()) {
-        return Some(OffsetDateTime::now_utc() - duration);
-    }
-
-    None
-}
+/// Print the change history of a single entity.
+///
+/// The entity is given by its path, e.g. `src/Foo.java::Foo::bar(int)`. The
+/// leading part of the path may be omitted so long as the remainder is
+/// unambiguous. Each change is printed as its commit, commit date, kind of
+/// change (A, D, or M), adds, and dels, most recent first.
+#[derive(Debug, clap::Args)]
+struct CliLogCommand {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
 
-fn validate_time_input<S: AsRef<str>>(
-    app: &mut App,
-    input: S,
-    argument: &'static str,
-) -> OffsetDateTime {
-    match parse_time_input(&input) {
-        Some(datetime) => datetime,
-        None => {
-            let msg = format!(
-                "The value ('{}') supplied to '{}' is not an ISO 8601 date or a duration.",
-                input.as_ref(),
-                &argument
-            );
-            app.error(clap::ErrorKind::ValueValidation, msg).exit();
-        }
-    }
-}
+    /// Path of the entity.
+    entity: String,
 
-fn validate_ref_input<'r, S: AsRef<str>>(
-    app: &mut App,
-    repo: &'r Repository,
-    input: S,
-) -> Reference<'r> {
-    match repo.resolve_reference_from_short_name(input.as_ref()) {
-        Ok(reference) => reference,
-        Err(_) => {
-            let msg =
-                format!("The given ref ('{}') was not found in this repository", input.as_ref());
-            app.error(clap::ErrorKind::ValueValidation, msg).exit();
-        }
-    }
+    /// Maximum number of changes to print.
+    #[clap(long, short = 'n')]
+    limit: Option<usize>,
 }
 
-fn get_lead_refs(
-    cmd: &mut App,
-    cli: &CliDumpCommand,
-    repo: &Repository,
-) -> anyhow::Result<Vec<Ref>> {
-    if cli.all {
-        return Ok(repo.references()?.map(|r| gtl::to_ref(&r.unwrap())).try_collect::<Vec<_>>()?);
-    }
+/// Options which select the changed entities for suggest and check.
+#[derive(Debug, clap::Args)]
+struct ChangedArgs {
+    /// Paths of the changed entities or files (see log).
+    entities: Vec<String>,
 
-    let mut lead_refs = Vec::new();
+    /// Also treat the entities changed by the staged changes in the repository
+    /// (see `git diff --cached`) as changed.
+    #[clap(long, conflicts_with = "worktree")]
+    staged: bool,
 
-    for ref_name in &cli.refs {
-        lead_refs.push(gtl::to_ref(&validate_ref_input(cmd, &repo, ref_name))?);
-    }
+    /// Also treat the entities changed in the working tree of the repository,
+    /// whether staged or not (see `git diff HEAD`), as changed.
+    #[clap(long)]
+    worktree: bool,
 
-    Ok(lead_refs)
+    /// Path to the git repository used by --staged and --worktree (defaults to
+    /// $GIT_DIR or the current directory). This may be a linked worktree.
+    #[clap(long)]
+    repo: Option<PathBuf>,
 }
 
-fn get_commit_walk(
-    cmd: &mut App,
-    cli: &CliDumpCommand,
-    repo: &Repository,
-) -> anyhow::Result<CommitWalk> {
-    let mut walk = CommitWalk::new();
-    let since = cli.since.as_ref().map(|s| validate_time_input(cmd, s, "--since"));
-    let until = cli.until.as_ref().map(|s| validate_time_input(cmd, s, "--until"));
-    since.map(|s| walk.set_since(s));
-    until.map(|u| walk.set_until(u));
-    cli.max_count.map(|n| walk.set_max_count(n));
+/// Suggest entities which may also need to change.
+///
+/// Given a set of changed entities (or files), prints the entities which
+/// historically changed together with them, e.g. "you changed X; consider
+/// also changing Y". Each suggestion is printed as its confidence, support,
+/// path, and the changed entity it is most strongly coupled to.
+///
+/// With --staged (or --worktree), the uncommitted changes in the given
+/// repository are extracted as if they were a commit and the entities they
+/// change are also treated as changed. This makes the command suitable for a
+/// pre-commit hook or a code review bot.
+#[derive(Debug, clap::Args)]
+struct CliSuggestCommand {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
 
-    walk.set_sort(Sort::TIME);
+    #[clap(flatten)]
+    changed: ChangedArgs,
 
-    if cli.all {
-        walk.push_glob(RefGlobKind::All, None);
-        return Ok(walk);
-    }
+    /// Only suggest entities of this kind (e.g. file, class, method).
+    #[clap(long)]
+    kind: Option<String>,
 
-    cli.branches.as_ref().map(|g| walk.push_glob(RefGlobKind::Branches, g.clone()));
-    cli.tags.as_ref().map(|g| walk.push_glob(RefGlobKind::Tags, g.clone()));
-    cli.remotes.as_ref().map(|g| walk.push_glob(RefGlobKind::Remotes, g.clone()));
+    /// Minimum (weighted) number of commits in which both entities changed.
+    #[clap(long, default_value_t = 2.0)]
+    min_support: f64,
 
-    for ref_name in &cli.refs {
-        let r#ref = validate_ref_input(cmd, &repo, ref_name);
-        walk.push_start_oid(r#ref.peel_to_commit()?.id());
-    }
+    /// Minimum fraction of the changes to a changed entity which also changed
+    /// the suggested entity.
+    #[clap(long, default_value_t = 0.5)]
+    min_confidence: f64,
 
-    Ok(walk)
-}
+    /// Maximum number of suggestions to print.
+    #[clap(long, short = 'n', default_value_t = 10)]
+    limit: usize,
 
-extern "C" {
-    fn tree_sitter_java() -> Language;
+    #[clap(flatten)]
+    coupling: CouplingArgs,
 }
 
-fn main() -> anyhow::Result<()> {
-    let cli = <Cli as clap::Parser>::parse();
-    env_logger::Builder::new().filter_level(cli.verbose.log_level_filter()).init();
+/// List the tests and dependents which may be affected by a change.
+///
+/// Given a set of changed entities (or files), prints the tests linked to them
+/// (see analyze test-links) and, with --commit, the entities which depend on
+/// them at that commit (see add-deps). Each is printed as its confidence,
+/// support, whether it is a test or a dependent, its path, and the changed
+/// entity it is most strongly linked to.
+///
+/// Results are ranked by confidence, so the tests and dependents which
+/// historically changed along with the changed entities come first. This makes
+/// the command suitable for selecting the tests to run in CI.
+#[derive(Debug, clap::Args)]
+struct CliImpactCommand {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
 
-    match cli.command {
-        CliSubCommand::Dump(args) => dump(&args),
-        CliSubCommand::AddDeps(args) => add_deps(&args),
-    }
-}
+    #[clap(flatten)]
+    changed: ChangedArgs,
 
-fn dump(cli: &CliDumpCommand) -> anyhow::Result<()> {
-    let mut cmd = Cli::command();
+    /// The hash (SHA-1) of the commit that the dependencies were extracted
+    /// from. Without it, dependents are not listed.
+    #[clap(long)]
+    commit: Option<String>,
 
-    // Check if database already exists
-    if !cli.force && Path::new(&cli.db).exists() {
-        let msg = format!(
-            "The database ('{}') already exists. Use --force to overwrite it. (Updating databases \
-             is not yet supported.)",
-            &cli.db.to_string_lossy()
-        );
-        cmd.error(clap::ErrorKind::Io, msg).exit();
-    }
+    /// Also list indirect (transitive) dependents.
+    #[clap(long)]
+    transitive: bool,
 
-    // Open repository
-    let repo_path = cli.repo.clone().unwrap_or(PathBuf::from("."));
-    let repo = Repository::discover(repo_path)
-        .context("failed to find git repository at or above the provided directory")?;
+    /// Only list entities of this kind (e.g. file, class, method).
+    #[clap(long)]
+    kind: Option<String>,
 
-    // This is a necessary config for Windows. Even though we never touch the actual
-    // filesystem, because libgit2 emulates the behavior of the real git, it will
-    // still crash on Windows when encountering especially long paths.
-    repo.config()?.set_bool("core.longpaths", true)?;
+    /// Minimum confidence of the listed tests and dependents.
+    #[clap(long, default_value_t = 0.0)]
+    min_confidence: f64,
 
-    // Setup tree sitter
-    let language = unsafe { tree_sitter_java() };
-    let java_query = include_str!("../queries/java/tags.scm");
-    let parsing_ctx = FileParser::new(language, java_query)?;
-    let mut cache = ExtractionCtx::new(&repo, parsing_ctx);
+    /// Maximum number of tests and dependents to print.
+    #[clap(long, short = 'n', default_value_t = 20)]
+    limit: usize,
 
-    // Initial collection of commits into HashMap
-    // We walk in reverse chronological order. This is to ensure the "-n" flag works
-    // as expected. For instance, "-n 50" should fetch the 50 most recent commits.
-    let walk = get_commit_walk(&mut cmd, &cli, &repo)?;
-    let start = Instant::now();
-    let commits = walk.walk(&repo)?.try_collect::<Vec<_>>()?;
-    log::info!("Found {} commits in {}ms.", commits.len(), start.elapsed().as_millis());
+    #[clap(flatten)]
+    coupling: CouplingArgs,
+}
 
-    // Collect changed files
-    let start = Instant::now();
-    let diffed_files = diff_all_files(&repo, &commits, ".java")?;
-    log::info!("Found {} changed files in {}ms", diffed_files.len(), start.elapsed().as_millis());
+/// Fail when entities which usually change together were not changed together.
+///
+/// Intended for pre-commit hooks and CI. Given a set of changed entities (or
+/// files), reports each unchanged entity which is strongly coupled to one of
+/// them (see suggest) and exits with a non-zero status if there are any.
+///
+/// The report is written as plain text or as SARIF, which many code review
+/// tools can display inline.
+#[derive(Debug, clap::Args)]
+struct CliCheckCommand {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
 
-    // Calculate changes
-    let start = Instant::now();
-    let changes = diffed_files
-        .iter()
-        .flat_map(|diffed_file| get_changes(&mut cache, diffed_file).unwrap())
-        .collect::<Vec<_>>();
-    log::info!("Generated changes in {}ms", start.elapsed().as_millis());
+    #[clap(flatten)]
+    changed: ChangedArgs,
 
-    // Calculate presence
-    let lead_refs = get_lead_refs(&mut cmd, &cli, &repo)?;
-    let start = Instant::now();
-    let presences = lead_refs
-        .iter()
-        .flat_map(|r| get_presences(&mut cache, &r.commit, ".java").unwrap())
-        .collect::<Vec<_>>();
-    log::info!("Generated presences in {}ms", start.elapsed().as_millis());
+    /// Only report entities of this kind (e.g. file, class, method).
+    #[clap(long, default_value = "file")]
+    kind: String,
 
-    // Create and insert into virtual database
-    let mut db = VirtualDb::new();
-    let start = Instant::now();
+    /// Minimum (weighted) number of commits in which both entities changed.
+    #[clap(long, default_value_t = 5.0)]
+    min_support: f64,
 
-    for change in &changes {
-        insert_change(&mut db, change)?;
-    }
+    /// Minimum fraction of the changes to a changed entity which also changed
+    /// the unchanged entity.
+    #[clap(long, default_value_t = 0.8)]
+    min_confidence: f64,
 
-    for presence in &presences {
-        insert_presence(&mut db, presence)?;
-    }
+    /// Format of the report.
+    #[clap(long, value_enum, default_value_t = ReportFormat::Text)]
+    format: ReportFormat,
 
-    for r#ref in &lead_refs {
-        insert_ref(&mut db, r#ref)?;
-    }
+    /// Path of the file to write the report to [default: stdout]
+    #[clap(long, short = 'o')]
+    output: Option<PathBuf>,
 
-    log::info!("Populated virtual database in {}ms", start.elapsed().as_millis());
+    #[clap(flatten)]
+    coupling: CouplingArgs,
+}
 
-    // Delete database if --force is given
-    if cli.force && Path::new(&cli.db).exists() {
-        log::warn!("Overwriting existing database ('{}')...", &cli.db.to_string_lossy());
-        remove_file(&cli.db)?;
+/// Write a summary of each commit to git notes.
+///
+/// For each commit in the database, writes a note listing the entities it
+/// changed and the coupling alerts it would have raised (see check), i.e. the
+/// unchanged entities which are strongly coupled to those it changed. The
+/// notes are written to their own ref so that they travel with the repository
+/// when it is pushed (e.g. `git push origin refs/notes/cochange`) and can be
+/// shown by `git log --notes=cochange`.
+///
+/// Notes which were written before are replaced. Commits which are missing
+/// from the repository are skipped.
+#[derive(Debug, clap::Args)]
+struct CliNotesCommand {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// Path to the git repository (defaults to $GIT_DIR or the current
+    /// directory).
+    #[clap(long)]
+    repo: Option<PathBuf>,
+
+    /// The ref to write the notes to.
+    #[clap(long, value_name = "REF", default_value = "refs/notes/cochange")]
+    notes_ref: String,
+
+    /// Only list changed entities and alerts of this kind (e.g. file, class,
+    /// method).
+    #[clap(long, default_value = "file")]
+    kind: String,
+
+    /// Minimum (weighted) number of commits in which both entities changed.
+    #[clap(long, default_value_t = 5.0)]
+    min_support: f64,
+
+    /// Minimum fraction of the changes to a changed entity which also changed
+    /// the unchanged entity.
+    #[clap(long, default_value_t = 0.8)]
+    min_confidence: f64,
+
+    /// Only write notes for the <NUMBER> most recent commits.
+    #[clap(short = 'n', long, value_name = "NUMBER")]
+    max_count: Option<usize>,
+
+    #[clap(flatten)]
+    coupling: CouplingArgs,
+}
+
+/// Render a self-contained HTML report of a co-change database.
+///
+/// The report lists the top hotspots, the most strongly coupled pairs, the
+/// monthly churn, and the share of each author along with the packages with the
+/// lowest bus factors. It needs no server or network access to be viewed.
+#[derive(Debug, clap::Args)]
+struct CliReportCommand {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// Path of the HTML file to write the report to.
+    #[clap(long, short = 'o')]
+    output: PathBuf,
+
+    /// Title shown at the top of the report [default: name of the database]
+    #[clap(long)]
+    title: Option<String>,
+
+    /// Maximum number of rows in each table of the report.
+    #[clap(long, short = 'n', default_value_t = 20)]
+    limit: usize,
+
+    /// Minimum (weighted) number of commits in which both entities changed.
+    #[clap(long, default_value_t = 5.0)]
+    min_support: f64,
+
+    /// Minimum fraction of the changes to either entity which also changed the
+    /// other.
+    #[clap(long, default_value_t = 0.5)]
+    min_confidence: f64,
+
+    #[clap(flatten)]
+    coupling: CouplingArgs,
+}
+
+/// Interactively explore a co-change database in the terminal.
+///
+/// Type to filter the entities by path. The change history and the strongest
+/// co-change partners of the selected entity are shown alongside.
+#[derive(Debug, clap::Args)]
+struct CliBrowseCommand {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// Maximum number of partners to show.
+    #[clap(long, short = 'n', default_value_t = 50)]
+    limit: usize,
+
+    #[clap(flatten)]
+    coupling: CouplingArgs,
+}
+
+/// Serve a co-change database through a read-only HTTP API.
+///
+/// Every response is JSON. The routes are:
+///
+/// - `GET /entities?q=<TEXT>&kind=<KIND>&limit=<N>`
+/// - `GET /entities/<ID>`
+/// - `GET /entities/<ID>/changes`
+/// - `GET /entities/<ID>/partners?limit=<N>`
+/// - `GET /coupling?min_support=<N>&min_confidence=<N>&limit=<N>`
+/// - `GET /suggestions?entity=<PATH>&entity=<PATH>&min_support=<N>&min_confidence=<N>`
+///
+/// Co-changes are counted once at startup according to the coupling options.
+#[derive(Debug, clap::Args)]
+struct CliServeCommand {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// Address to listen on.
+    #[clap(long, default_value = "127.0.0.1:8080")]
+    listen: String,
+
+    /// Let pages served from this origin (e.g. http://localhost:3000) read the
+    /// responses.
+    ///
+    /// Without it, no CORS headers are sent, so only same-origin pages and
+    /// non-browser clients can use the API.
+    #[clap(long, value_name = "ORIGIN")]
+    cors_origin: Option<String>,
+
+    #[clap(flatten)]
+    coupling: CouplingArgs,
+}
+
+/// Merge two or more co-change databases into one.
+///
+/// Useful when extraction is split up by time range or by repository. Commits
+/// are matched by their hash and entities by their path, so anything found in
+/// more than one database is only kept once. When the databases disagree (e.g.
+/// on the commit a ref points to), the first database given wins.
+///
+/// Only the extracted data (and any dependencies) are merged. Re-run the
+/// analyses on the merged database.
+#[derive(Debug, clap::Args)]
+struct CliMergeCommand {
+    /// Paths to the databases to merge.
+    #[clap(required = true, min_values = 2)]
+    inputs: Vec<PathBuf>,
+
+    /// Path of the merged database.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// Forcefully overwrite existing database.
+    #[clap(long)]
+    force: bool,
+}
+
+/// Audit the lines attributed to entities against `git diff --numstat`.
+///
+/// For a sample of the commits reachable from the given refs, the adds and dels
+/// of each changed file (i.e. of its file entity) are compared with the counts
+/// of git. Every line of a hunk must be attributed to its file exactly once, so
+/// any difference points to an off-by-one between the hunks and the rows of
+/// the parsed entities. Each difference is printed and the command exits with a
+/// non-zero status if there are any.
+///
+/// Merge commits are skipped. Binary files are not counted by git and are also
+/// skipped.
+#[derive(Debug, clap::Args)]
+struct CliAuditCommand {
+    /// Starting commits given as named references [default: HEAD]
+    #[clap()]
+    refs: Vec<String>,
+
+    /// Use the given path to a git repository instead of the current directory
+    /// (or $GIT_DIR if set). This may be a bare repository or a linked worktree.
+    #[clap(long, short = 'C')]
+    repo: Option<PathBuf>,
+
+    /// Which of the reachable commits to audit.
+    ///
+    /// Either a duration (e.g. 1month) to sample the first commit of each
+    /// period, a number of commits (e.g. 100 or 100commits) to sample every nth
+    /// commit, or `tags` to sample every tagged commit.
+    #[clap(long, value_name = "SPEC", default_value = "100commits")]
+    every: String,
+
+    /// How finely files are broken down into entities (see dump).
+    #[clap(long, value_enum, default_value_t = Granularity::Entity)]
+    granularity: Granularity,
+}
+
+/// Import the pull requests (or merge requests) of a repository.
+///
+/// Reads the pull requests of the repository from the API of the platform
+/// hosting it and stores their number, title, author, state, labels, review
+/// participants, and commits in an existing co-change database. Run it after
+/// dump (or merge) so that the commits of each pull request can be linked to
+/// the commits of the database. Any previously imported pull requests are
+/// replaced.
+///
+/// The commits of a pull request are those it was opened with along with the
+/// commit which merged it (e.g. a merge or squash commit). Once imported,
+/// coupling can be computed per pull request with --by-pull-request.
+///
+/// An access token is read from GITHUB_TOKEN or GITLAB_TOKEN (depending on
+/// the platform). Without one, only public repositories can be read and the
+/// API allows far fewer requests.
+#[derive(Debug, clap::Args)]
+struct CliImportPrsCommand {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// The platform hosting the repository.
+    #[clap(long, value_enum, default_value = "github")]
+    platform: Platform,
+
+    /// The repository on the platform, i.e. `owner/name` on GitHub or the full
+    /// path of the project (e.g. `group/subgroup/name`) on GitLab.
+    #[clap(long)]
+    project: String,
+
+    /// The base URL of the API (e.g. of a GitHub Enterprise or self-hosted
+    /// GitLab instance). Defaults to that of github.com or gitlab.com.
+    #[clap(long, value_name = "URL")]
+    api_url: Option<String>,
+
+    /// Also import pull requests which are open or were closed without being
+    /// merged.
+    #[clap(long)]
+    include_unmerged: bool,
+
+    /// Import at most <NUMBER> pull requests, the most recently created first.
+    #[clap(short = 'n', long, value_name = "NUMBER")]
+    max_count: Option<usize>,
+}
+
+/// Import the issues which commit messages refer to from an issue tracker.
+///
+/// Finds the issue keys in the message of each commit in an existing co-change
+/// database (`#123` on GitHub and `PROJ-123` on Jira) and reads the type,
+/// status, resolution, and labels of each issue from the API of the tracker.
+/// Any previously imported issues are replaced.
+///
+/// Issues whose type or labels mark them as defects (e.g. "Bug") tell bug-fix
+/// commits apart from other commits more reliably than keywords in their
+/// messages, so analyses which count bug fixes use them once imported.
+///
+/// An access token is read from GITHUB_TOKEN or JIRA_TOKEN (depending on the
+/// tracker). On Jira Cloud, JIRA_USER must also be set to the email of the
+/// owner of the token.
+#[derive(Debug, clap::Args)]
+struct CliImportIssuesCommand {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// The issue tracker.
+    #[clap(long, value_enum, default_value = "github")]
+    tracker: Tracker,
+
+    /// The repository on GitHub (i.e. `owner/name`) or the key of a project on
+    /// Jira (e.g. `PROJ`). May be given multiple times on Jira.
+    #[clap(long, required = true)]
+    project: Vec<String>,
+
+    /// The base URL of the API on GitHub (defaults to that of github.com) or
+    /// the URL of the instance on Jira (e.g. https://example.atlassian.net).
+    #[clap(long, value_name = "URL")]
+    api_url: Option<String>,
+}
+
+/// Export data from a co-change database for use by other tools.
+#[derive(Debug, clap::Args)]
+struct CliExportCommand {
+    #[clap(subcommand)]
+    command: ExportSubCommand,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum ExportSubCommand {
+    Features(ExportFeatures),
+    Graph(ExportGraph),
+    Tables(ExportTables),
+    Neo4j(ExportNeo4j),
+}
+
+/// Export a per-entity feature matrix for defect prediction.
+///
+/// Each row holds the size and complexity of an entity along with its number of
+/// changing commits, churn (adds and dels), number of authors, authorship
+/// entropy, coupling degree, age (in days), and number of bug-fix commits.
+///
+/// Bug-fix commits are recognized by the type of the issues they refer to if
+/// these were imported with import-issues and otherwise by keywords (e.g. "fix"
+/// or "bug") in their messages. The coupling degree is the number of entities an entity is
+/// strongly coupled to (see --min-support and --min-confidence).
+#[derive(Debug, clap::Args)]
+struct ExportFeatures {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// Path of the file to write the features to.
+    #[clap(long, short = 'o')]
+    output: PathBuf,
+
+    /// Format of the written file.
+    #[clap(long, value_enum, default_value_t = TableFormat::Csv)]
+    format: TableFormat,
+
+    /// Only export entities of this kind (e.g. file, class, method).
+    #[clap(long)]
+    kind: Option<String>,
+
+    /// Minimum (weighted) number of commits in which both entities changed.
+    #[clap(long, default_value_t = 5.0)]
+    min_support: f64,
+
+    /// Minimum fraction of the changes to either entity which also changed the
+    /// other.
+    #[clap(long, default_value_t = 0.5)]
+    min_confidence: f64,
+
+    #[clap(flatten)]
+    coupling: CouplingArgs,
+}
+
+/// Export the weighted co-change graph of entities.
+///
+/// Two entities are connected if they are strongly coupled (see --min-support
+/// and --min-confidence). Each edge is weighted by the support of the pair. With
+/// --deps-at, the dependencies extracted from the given commit are added as
+/// edges of their own kind (e.g. for Gephi, Cytoscape, or Graphviz).
+#[derive(Debug, clap::Args)]
+struct ExportGraph {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// Path of the file to write the graph to.
+    #[clap(long, short = 'o')]
+    output: PathBuf,
+
+    /// Format of the written graph.
+    #[clap(long, value_enum, default_value_t = GraphFormat::Graphml)]
+    format: GraphFormat,
+
+    /// Only consider entities of this kind (e.g. file, class, method).
+    #[clap(long)]
+    kind: Option<String>,
+
+    /// Minimum (weighted) number of commits in which both entities changed.
+    #[clap(long, default_value_t = 5.0)]
+    min_support: f64,
+
+    /// Minimum fraction of the changes to either entity which also changed the
+    /// other.
+    #[clap(long, default_value_t = 0.5)]
+    min_confidence: f64,
+
+    /// Also add the dependencies extracted from the commit with this hash
+    /// (SHA-1). See add-deps.
+    #[clap(long, value_name = "COMMIT")]
+    deps_at: Option<String>,
+
+    #[clap(flatten)]
+    coupling: CouplingArgs,
+}
+
+/// Export tables of a co-change database, including the results of analyses.
+///
+/// Each table is written to <OUTPUT>/<TABLE>.<FORMAT>. Column types follow the
+/// types declared in the database, so Parquet files keep integers, reals, and
+/// booleans apart.
+#[derive(Debug, clap::Args)]
+struct ExportTables {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// Path of the directory to write the tables to.
+    #[clap(long, short = 'o')]
+    output: PathBuf,
+
+    /// Format of the written files.
+    #[clap(long, value_enum, default_value_t = TableFormat::Parquet)]
+    format: TableFormat,
+
+    /// Names of the tables to export [default: every table]
+    tables: Vec<String>,
+
+    #[clap(flatten)]
+    lines: LineArgs,
+}
+
+/// Export a co-change database as a property graph for Neo4j.
+///
+/// Commits, authors, and entities become nodes connected by AUTHORED, CHANGED,
+/// CONTAINS, and (if add-deps was run) DEPENDS_ON relationships. The files are
+/// written in the CSV layout of `neo4j-admin database import` and the command
+/// to import them is printed.
+#[derive(Debug, clap::Args)]
+struct ExportNeo4j {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// Path of the directory to write the CSV files to.
+    #[clap(long, short = 'o')]
+    output: PathBuf,
+}
+
+/// Run an analysis over a co-change database.
+#[derive(Debug, clap::Args)]
+struct CliAnalyzeCommand {
+    #[clap(subcommand)]
+    command: AnalyzeSubCommand,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum AnalyzeSubCommand {
+    HiddenDeps(HiddenDeps),
+    DepCochange(DepCochange),
+    Ownership(Ownership),
+    DevNetwork(DevNetwork),
+    Coupling(CouplingCommand),
+    Hotspots(Hotspots),
+    Compare(Compare),
+    BulkCommits(BulkCommits),
+    TestLinks(TestLinks),
+    Clones(Clones),
+    DivergentChange(DivergentChange),
+    Clusters(Clusters),
+    Interfaces(Interfaces),
+    Trend(Trend),
+    KnowledgeLoss(KnowledgeLossCommand),
+}
+
+/// Options which control how line ranges (e.g. of presences and hunks) are
+/// exported, for consumers which number lines differently than the database.
+#[derive(Debug, clap::Args)]
+struct LineArgs {
+    /// Number the first line of a file 0 or 1.
+    #[clap(help_heading = "LINES", long, value_name = "BASE", default_value_t = 1)]
+    line_base: usize,
+
+    /// End each line range at its last line (inclusive) or just past it
+    /// (exclusive).
+    ///
+    /// By default, ranges end as they are stored: presence rows are inclusive
+    /// while hunks are exclusive. Only applies to --format jsonl when dumping.
+    #[clap(help_heading = "LINES", long, value_enum)]
+    end: Option<RangeEnd>,
+}
+
+/// Options which control how co-changes are counted by the coupling analyses.
+#[derive(Debug, clap::Args)]
+struct CouplingArgs {
+    /// Weight each commit by its age such that its weight halves every
+    /// <DURATION> (e.g. 1year 6months).
+    ///
+    /// Ages are relative to the most recent commit in the database. Without
+    /// this option, every commit has the same weight.
+    #[clap(help_heading = "COUPLING", long, value_name = "DURATION")]
+    half_life: Option<String>,
+
+    /// Also exclude commits which are classified as uniform, move, or reformat
+    /// commits (see bulk-commits).
+    ///
+    /// Commits which exceed --max-commit-files or --max-commit-entities are
+    /// always excluded.
+    #[clap(help_heading = "COUPLING", long)]
+    exclude_bulk: bool,
+
+    /// Merge consecutive commits by the same author into a single change set
+    /// when they are at most <DURATION> (e.g. 1h) apart.
+    ///
+    /// This counters differences in committing style, e.g. between developers
+    /// who commit a change all at once and those who commit it bit by bit.
+    #[clap(help_heading = "COUPLING", long, value_name = "DURATION")]
+    session_gap: Option<String>,
+
+    /// Merge the commits of each merged pull request into a single change
+    /// set, so that coupling is computed per pull request rather than per
+    /// commit. Commits which do not belong to a merged pull request are kept as
+    /// is.
+    ///
+    /// This matches how changes are actually shipped by teams which merge pull
+    /// requests without squashing them.
+    ///
+    /// Requires pull requests imported with import-prs.
+    #[clap(help_heading = "COUPLING", long, conflicts_with = "session-gap")]
+    by_pull_request: bool,
+
+    /// Ignore commits which apply the same patch as an earlier commit (e.g.
+    /// cherry-picks) so that each patch is only counted once.
+    ///
+    /// Requires a database dumped with --patch-ids.
+    #[clap(help_heading = "COUPLING", long)]
+    collapse_duplicates: bool,
+
+    /// Roll changes up to the given level before counting co-changes (e.g.
+    /// `file` for file-level coupling instead of method-level coupling).
+    ///
+    /// With `package`, changes are rolled up to the directory of their file,
+    /// which requires a database dumped with --dir-entities.
+    #[clap(
+        help_heading = "COUPLING",
+        long,
+        value_enum,
+        default_value_t = Aggregation::Entity
+    )]
+    granularity: Aggregation,
+
+    /// Also count the changes to generated code.
+    ///
+    /// By default, the entities which were marked as generated when the
+    /// database was dumped (by a "DO NOT EDIT" or "@generated" header or by a
+    /// `@Generated` annotation) are left out along with the entities below
+    /// them, as are the files matching --generated-pattern.
+    #[clap(help_heading = "COUPLING", long)]
+    include_generated: bool,
+
+    /// Treat the files whose path matches this glob pattern (e.g.
+    /// `*/generated/*`) as generated code as well. May be repeated.
+    #[clap(help_heading = "COUPLING", long, value_name = "GLOB")]
+    generated_pattern: Vec<String>,
+
+    /// Merge entities according to a CSV file of aliases before counting
+    /// co-changes, e.g. to correct refactorings which were not tracked.
+    ///
+    /// Each row holds the full path of an entity (as printed by log) and the
+    /// path it is merged into, e.g. `src/old/Foo.java,src/new/Foo.java`. The
+    /// entities below the first path (e.g. the methods of a class or the files
+    /// of a directory) are merged into their counterparts below the second.
+    /// Lines starting with `#` are skipped.
+    #[clap(help_heading = "COUPLING", long, value_name = "FILE")]
+    aliases: Option<PathBuf>,
+
+    #[clap(flatten)]
+    bulk: BulkArgs,
+}
+
+/// Options which control which commits are classified as bulk commits.
+#[derive(Debug, clap::Args)]
+struct BulkArgs {
+    /// Treat commits which change more than <N> files as bulk commits.
+    #[clap(help_heading = "BULK COMMITS", long, value_name = "N")]
+    max_commit_files: Option<usize>,
+
+    /// Treat commits which change more than <N> entities as bulk commits.
+    #[clap(help_heading = "BULK COMMITS", long, value_name = "N")]
+    max_commit_entities: Option<usize>,
+
+    /// Minimum number of changed files for a commit to be classified as a
+    /// uniform, move, or reformat commit.
+    #[clap(help_heading = "BULK COMMITS", long, value_name = "N", default_value_t = 10)]
+    bulk_min_files: usize,
+}
+
+impl BulkArgs {
+    fn to_thresholds(&self) -> BulkThresholds {
+        BulkThresholds {
+            max_files: self.max_commit_files,
+            max_entities: self.max_commit_entities,
+            min_files: self.bulk_min_files,
+        }
+    }
+}
+
+/// Options which split the history into (possibly overlapping) time windows.
+#[derive(Debug, clap::Args)]
+struct WindowArgs {
+    /// Compute the results separately for each window of <DURATION> (e.g.
+    /// 3months). Windows start at the oldest commit in the database.
+    #[clap(help_heading = "WINDOWING", long, value_name = "DURATION")]
+    window: Option<String>,
+
+    /// Compute the results separately for each interval between consecutive
+    /// releases, i.e. the tags matching <GLOB_PATTERN> (e.g. 'v*').
+    ///
+    /// Releases are ordered by the time of their commits.
+    #[clap(
+        help_heading = "WINDOWING",
+        long,
+        value_name = "GLOB_PATTERN",
+        conflicts_with = "window"
+    )]
+    releases: Option<String>,
+
+    /// Start a new window every <DURATION> [default: the window length]
+    #[clap(help_heading = "WINDOWING", long, value_name = "DURATION", requires = "window")]
+    step: Option<String>,
+}
+
+/// Find entities which frequently co-change.
+///
+/// Pairs of entities are ranked by support and then by confidence. With
+/// --window, the pairs are found separately for each window. Results are stored
+/// in the `coupling` table and the top pairs of each window are printed.
+#[derive(Debug, clap::Args)]
+#[clap(name = "coupling")]
+struct CouplingCommand {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// Minimum (weighted) number of commits in which both entities changed.
+    #[clap(long, default_value_t = 5.0)]
+    min_support: f64,
+
+    /// Minimum fraction of the changes to either entity which also changed the
+    /// other.
+    #[clap(long, default_value_t = 0.5)]
+    min_confidence: f64,
+
+    /// Number of top-ranked pairs to print (per window).
+    #[clap(long, short = 'n', default_value_t = 20)]
+    limit: usize,
+
+    #[clap(flatten)]
+    coupling: CouplingArgs,
+
+    #[clap(flatten)]
+    window: WindowArgs,
+}
+
+/// Find the entities which change the most relative to their size.
+///
+/// The hotspot score of an entity is its (weighted) number of changes times its
+/// size in lines or (with --by complexity) its approximate cyclomatic
+/// complexity. The churn (adds + dels) is reported alongside. With --window,
+/// the hotspots are found separately for each window. Results are stored in
+/// the `hotspots` table and the top hotspots of each window are printed.
+#[derive(Debug, clap::Args)]
+struct Hotspots {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// What to multiply the number of changes by.
+    #[clap(long, value_enum, default_value_t = HotspotMeasure::Size)]
+    by: HotspotMeasure,
+
+    /// Only consider entities of this kind (e.g. file, class, method).
+    #[clap(long)]
+    kind: Option<String>,
+
+    /// Number of top-ranked hotspots to print (per window).
+    #[clap(long, short = 'n', default_value_t = 20)]
+    limit: usize,
+
+    /// Format of the printed hotspots. As SARIF, only the hotspots of the most
+    /// recent window are reported.
+    #[clap(long, value_enum, default_value_t = ReportFormat::Text)]
+    format: ReportFormat,
+
+    /// Path of the file to print the hotspots to [default: stdout]
+    #[clap(long, short = 'o')]
+    output: Option<PathBuf>,
+
+    #[clap(flatten)]
+    coupling: CouplingArgs,
+
+    #[clap(flatten)]
+    window: WindowArgs,
+}
+
+/// Export a time series of coupling metrics, one point per window.
+///
+/// Each point holds the (weighted) number of commits in the window, the number
+/// of strongly coupled pairs (see --min-support and --min-confidence), their
+/// total support and mean confidence, the support of the top pair, the largest
+/// number of pairs which one entity belongs to, and the score of the top
+/// hotspot (see hotspots). Rising values suggest that the architecture is
+/// eroding. Either --window or --releases is required.
+#[derive(Debug, clap::Args)]
+struct Trend {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// Minimum (weighted) number of commits in which both entities changed.
+    #[clap(long, default_value_t = 5.0)]
+    min_support: f64,
+
+    /// Minimum fraction of the changes to either entity which also changed the
+    /// other.
+    #[clap(long, default_value_t = 0.5)]
+    min_confidence: f64,
+
+    /// Format of the time series.
+    #[clap(long, value_enum, default_value_t = SeriesFormat::Csv)]
+    format: SeriesFormat,
+
+    /// Path of the file to write the time series to [default: stdout]
+    #[clap(long, short = 'o')]
+    output: Option<PathBuf>,
+
+    #[clap(flatten)]
+    coupling: CouplingArgs,
+
+    #[clap(flatten)]
+    window: WindowArgs,
+}
+
+/// Compare the changes, churn, and coupling of entities between two periods.
+///
+/// Each period is given as <START>..<END> where either end may be omitted and
+/// each end is an ISO 8601 date or a duration (e.g. 2022-01-01..6months). The
+/// coupling degree of an entity is the number of strongly coupled pairs (see
+/// --min-support and --min-confidence) it belongs to within the period.
+///
+/// Results are stored in the `comparison` table. The number of strongly coupled
+/// pairs in each period is printed along with the entities whose coupling
+/// degree changed the most.
+#[derive(Debug, clap::Args)]
+struct Compare {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// The earlier period.
+    #[clap(long, value_name = "START..END")]
+    before: String,
+
+    /// The later period.
+    #[clap(long, value_name = "START..END")]
+    after: String,
+
+    /// Minimum (weighted) number of commits in which both entities changed.
+    #[clap(long, default_value_t = 5.0)]
+    min_support: f64,
+
+    /// Minimum fraction of the changes to either entity which also changed the
+    /// other.
+    #[clap(long, default_value_t = 0.5)]
+    min_confidence: f64,
+
+    /// Number of entities to print.
+    #[clap(long, short = 'n', default_value_t = 20)]
+    limit: usize,
+
+    #[clap(flatten)]
+    coupling: CouplingArgs,
+}
+
+/// Tag the commits which are likely bulk or refactoring commits.
+///
+/// Bulk commits (e.g. reformatting the code base or updating license headers)
+/// change many files at once and so dominate co-change counts. A commit is
+/// classified as:
+///
+///   large     if it exceeds --max-commit-files or --max-commit-entities,
+///   uniform   if most files have the same number of adds and dels,
+///   move      if most files are deleted and re-added elsewhere, or
+///   reformat  if most files are modified with about as many adds as dels.
+///
+/// Results are stored in the `bulk_commits` table and printed. Use
+/// --exclude-bulk to exclude these commits from the coupling analyses.
+#[derive(Debug, clap::Args)]
+struct BulkCommits {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    #[clap(flatten)]
+    bulk: BulkArgs,
+}
+
+/// Link tests to the production entities they (likely) test.
+///
+/// Test files are the files whose path matches a --test-pattern. With
+/// --commit, the files with a method annotated with `@Test` (or any other
+/// annotation ending in `Test`, e.g. `@ParameterizedTest`) at that commit are
+/// test files as well. Every entity of a test file is a test entity.
+///
+/// Each test file is linked to the production files it is named after (e.g.
+/// `FooTest.java` to `Foo.java`). Each test entity is also linked to the
+/// production entities it frequently co-changes with. The confidence of a link
+/// is the fraction of the changes to the production entity which also changed
+/// the test.
+///
+/// Results are ranked by confidence and then by support. They are stored in
+/// the `test_links` table and the top results are printed.
+#[derive(Debug, clap::Args)]
+struct TestLinks {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// Shell glob matched against the paths of files to find test files. May be
+    /// given more than once.
+    #[clap(
+        long,
+        value_name = "PATTERN",
+        default_values = &["*/test/*", "*Test.java", "*Tests.java", "*IT.java"]
+    )]
+    test_pattern: Vec<String>,
+
+    /// The hash (SHA-1) of a commit at which to look for methods annotated as
+    /// tests. Requires the presence of that commit (see dump --presence).
+    #[clap(long)]
+    commit: Option<String>,
+
+    /// Path to the git repository used by --commit (defaults to $GIT_DIR or the
+    /// current directory). This may be a bare repository or a linked worktree.
+    #[clap(long)]
+    repo: Option<PathBuf>,
+
+    /// Minimum (weighted) number of commits in which the test and the
+    /// production entity both changed.
+    #[clap(long, default_value_t = 2.0)]
+    min_support: f64,
+
+    /// Minimum fraction of the changes to the production entity which also
+    /// changed the test.
+    #[clap(long, default_value_t = 0.25)]
+    min_confidence: f64,
+
+    /// Number of top-ranked links to print.
+    #[clap(long, short = 'n', default_value_t = 20)]
+    limit: usize,
+
+    #[clap(flatten)]
+    coupling: CouplingArgs,
+}
+
+/// Find duplicated entities and how often they were changed without their twin.
+///
+/// Two entities in different files are clones if their sources at the given
+/// commit are the same, which requires a database dumped with --store-sources
+/// (either mode). Clones inside larger clones (e.g. the methods of two
+/// identical classes) are left out.
+///
+/// A clone which was often changed without its twin is a risk, since a fix to
+/// one copy may be missing from the other. Clones are ranked by the (weighted)
+/// number of commits which changed only one of them. Results are stored in the
+/// `clones` table and the top results are printed.
+#[derive(Debug, clap::Args)]
+struct Clones {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// The hash (SHA-1) of the commit whose entity sources are compared.
+    #[clap(long)]
+    commit: String,
+
+    /// Minimum number of lines of a clone.
+    #[clap(long, value_name = "N", default_value_t = 5)]
+    min_lines: usize,
+
+    /// Number of top-ranked clones to print.
+    #[clap(long, short = 'n', default_value_t = 20)]
+    limit: usize,
+
+    #[clap(flatten)]
+    coupling: CouplingArgs,
+}
+
+/// Find entities which change for unrelated reasons (divergent change).
+///
+/// The partners of an entity are the entities it is strongly coupled with (see
+/// --min-support and --min-confidence). Partners which are strongly coupled
+/// with each other (directly or through other partners) form a group, which
+/// stands for one reason the entity changes. An entity with several groups is
+/// likely doing too much and is a candidate for splitting, one part per group.
+/// This is the complement of shotgun surgery.
+///
+/// Entities are ranked by their number of groups and then by their number of
+/// partners. Results are stored in the `divergent_changes` table (one row per
+/// partner) and the top entities are printed, each followed by its groups.
+#[derive(Debug, clap::Args)]
+struct DivergentChange {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// Minimum (weighted) number of commits in which two entities changed for
+    /// them to be partners.
+    #[clap(long, default_value_t = 3.0)]
+    min_support: f64,
+
+    /// Minimum fraction of the changes to either entity which also changed the
+    /// other for them to be partners.
+    #[clap(long, default_value_t = 0.5)]
+    min_confidence: f64,
+
+    /// Minimum number of groups of partners for an entity to be reported.
+    #[clap(long, value_name = "N", default_value_t = 2)]
+    min_clusters: usize,
+
+    /// Only report entities of this kind (e.g. file, class, method). Their
+    /// partners may be of any kind.
+    #[clap(long)]
+    kind: Option<String>,
+
+    /// Number of top-ranked entities to print.
+    #[clap(long, short = 'n', default_value_t = 20)]
+    limit: usize,
+
+    #[clap(flatten)]
+    coupling: CouplingArgs,
+}
+
+/// Group entities into modules by how they co-change and compare the groups
+/// with the packages.
+///
+/// The co-change graph has an edge between each pair of strongly coupled
+/// entities (see --min-support and --min-confidence), which is weighted by its
+/// support. Clusters are found by label propagation: each entity repeatedly
+/// joins the cluster which most of its co-changes are with. Use --granularity
+/// file to cluster files rather than methods.
+///
+/// The package of an entity is the directory of its file. A cluster is aligned
+/// with the package structure if its members belong to one package, and an
+/// entity is misplaced if its package differs from the main package of its
+/// cluster. The purity of the clusters (how many entities are in the main
+/// package of their cluster) and of the packages (how many entities are in the
+/// main cluster of their package) is printed along with the largest clusters
+/// and their misplaced entities. Results are stored in the `clusters` table.
+#[derive(Debug, clap::Args)]
+struct Clusters {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// Minimum (weighted) number of commits in which two entities changed for
+    /// them to be connected.
+    #[clap(long, default_value_t = 2.0)]
+    min_support: f64,
+
+    /// Minimum fraction of the changes to either entity which also changed the
+    /// other for them to be connected.
+    #[clap(long, default_value_t = 0.25)]
+    min_confidence: f64,
+
+    /// Stop propagating labels after <N> rounds even if entities still move.
+    #[clap(long, value_name = "N", default_value_t = 100)]
+    max_iterations: usize,
+
+    /// Number of clusters to print (largest first).
+    #[clap(long, short = 'n', default_value_t = 20)]
+    limit: usize,
+
+    #[clap(flatten)]
+    coupling: CouplingArgs,
+}
+
+/// Find entities which frequently co-change but have no structural dependency.
+///
+/// Pairs of entities with high evolutionary coupling but no structural
+/// dependency between them (in either direction) are likely modularity
+/// violations. Only entities present at the given commit are considered, and
+/// dependencies must have already been added for that commit (see add-deps).
+///
+/// Results are ranked by support and then by confidence. They are stored in
+/// the `hidden_deps` table (in rank order) and the top results are printed.
+#[derive(Debug, clap::Args)]
+struct HiddenDeps {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// The hash (SHA-1) of the commit that the dependencies were extracted from
+    #[clap(long)]
+    commit: String,
+
+    /// Minimum (weighted) number of commits in which both entities changed.
+    #[clap(long, default_value_t = 5.0)]
+    min_support: f64,
+
+    /// Minimum fraction of the changes to either entity which also changed the
+    /// other.
+    #[clap(long, default_value_t = 0.5)]
+    min_confidence: f64,
+
+    /// Also treat indirect (transitive) dependencies as structural dependencies.
+    #[clap(long)]
+    transitive: bool,
+
+    /// Number of top-ranked pairs to print.
+    #[clap(long, short = 'n', default_value_t = 20)]
+    limit: usize,
+
+    /// Format of the printed pairs. As SARIF, each pair is reported in the
+    /// file of its first entity.
+    #[clap(long, value_enum, default_value_t = ReportFormat::Text)]
+    format: ReportFormat,
+
+    /// Path of the file to print the pairs to [default: stdout]
+    #[clap(long, short = 'o')]
+    output: Option<PathBuf>,
+
+    #[clap(flatten)]
+    coupling: CouplingArgs,
+}
+
+/// Report how often the endpoints of each structural dependency co-change.
+///
+/// For each dependency at the given commit, counts the commits in which its
+/// source and target changed together. The propagation of a dependency is the
+/// fraction of changes to its target which also changed its source.
+///
+/// Targets with a high structural fan-in are treated as interfaces. An
+/// interface is "fragile" when changes to it tend to propagate to its
+/// dependents and "stable" otherwise. Results are stored in the `dep_cochange`
+/// table and the fragile interfaces are printed.
+#[derive(Debug, clap::Args)]
+struct DepCochange {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// The hash (SHA-1) of the commit that the dependencies were extracted from
+    #[clap(long)]
+    commit: String,
+
+    /// Minimum number of dependents for a target to be classified as an
+    /// interface.
+    #[clap(long, default_value_t = 5)]
+    min_fan_in: usize,
+
+    /// Minimum mean propagation for an interface to be considered fragile.
+    #[clap(long, default_value_t = 0.25)]
+    min_propagation: f64,
+
+    #[clap(flatten)]
+    coupling: CouplingArgs,
+}
+
+/// Classify the interfaces of a commit as stable or volatile.
+///
+/// An interface is an entity which many entities depend on (at least
+/// --min-fan-in at the given commit, see add-deps). It is volatile if it
+/// changed at least --min-changes times and stable otherwise. A volatile
+/// interface is heavily depended on but churns, so each change to it risks
+/// breaking its dependents.
+///
+/// Volatile interfaces are listed first, each sorted by fan-in and then by
+/// changes. Results are stored in the `interfaces` table and the top results
+/// are printed. As SARIF, only the volatile interfaces are reported.
+#[derive(Debug, clap::Args)]
+struct Interfaces {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// The hash (SHA-1) of the commit that the dependencies were extracted from
+    #[clap(long)]
+    commit: String,
+
+    /// Minimum number of dependents for an entity to be an interface.
+    #[clap(long, default_value_t = 5)]
+    min_fan_in: usize,
+
+    /// Minimum (weighted) number of changes for an interface to be volatile.
+    #[clap(long, default_value_t = 5.0)]
+    min_changes: f64,
+
+    /// Only consider interfaces of this kind (e.g. file, class, method).
+    #[clap(long)]
+    kind: Option<String>,
+
+    /// Number of top-ranked interfaces to print.
+    #[clap(long, short = 'n', default_value_t = 20)]
+    limit: usize,
+
+    /// Format of the printed interfaces.
+    #[clap(long, value_enum, default_value_t = ReportFormat::Text)]
+    format: ReportFormat,
+
+    /// Path of the file to print the interfaces to [default: stdout]
+    #[clap(long, short = 'o')]
+    output: Option<PathBuf>,
+
+    #[clap(flatten)]
+    coupling: CouplingArgs,
+}
+
+/// Compute the ownership of entities and the bus factor of files and packages.
+///
+/// The contribution of an author to an entity is the number of lines (adds +
+/// dels) they changed in it. The share of each author is stored in the
+/// `ownership` table.
+///
+/// The bus (or truck) factor of a file or package is the smallest number of
+/// authors who together contributed more than the --coverage fraction of its
+/// lines. These are stored in the `bus_factors` table along with the dominant
+/// author. The packages with the lowest bus factors are printed.
+#[derive(Debug, clap::Args)]
+struct Ownership {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// The fraction of lines which must be covered when computing bus factors.
+    #[clap(long, default_value_t = 0.5)]
+    coverage: f64,
+
+    /// Also export the results as CSV files (`ownership.csv` and
+    /// `bus_factors.csv`) to this directory.
+    #[clap(long, value_name = "DIR")]
+    csv: Option<PathBuf>,
+
+    /// Number of packages to print.
+    #[clap(long, short = 'n', default_value_t = 20)]
+    limit: usize,
+}
+
+/// Simulate the departure of authors and find the entities left without
+/// enough knowledge.
+///
+/// The share of an author of an entity is read from the `ownership` table (see
+/// ownership). An entity is at risk if the departing authors contributed to it
+/// and the share of the remaining authors falls below --min-surviving. Such
+/// entities are candidates for handing over before the authors leave.
+///
+/// Results are stored in the `knowledge_loss` table (replacing those of the
+/// last simulation) and the entities with the lowest surviving share are
+/// printed.
+#[derive(Debug, clap::Args)]
+#[clap(name = "knowledge-loss")]
+struct KnowledgeLossCommand {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// The email or name of a departing author. May be repeated.
+    #[clap(long, required = true)]
+    author: Vec<String>,
+
+    /// Minimum share of the remaining authors for an entity to be safe.
+    #[clap(long, default_value_t = 0.5)]
+    min_surviving: f64,
+
+    /// Only consider entities of this kind (e.g. file, class, method).
+    #[clap(long)]
+    kind: Option<String>,
+
+    /// Number of entities to print.
+    #[clap(long, short = 'n', default_value_t = 20)]
+    limit: usize,
+}
+
+/// Export a network of the developers who touch the same entities.
+///
+/// Two developers are connected if they both changed the same entity (each in
+/// at least --min-touches commits). The weight of the connection is the number
+/// of such shared entities.
+///
+/// With --bipartite, a developer-entity graph is written instead. Each edge is
+/// weighted by the number of commits in which the developer changed the
+/// entity. Either graph is suitable for socio-technical congruence studies.
+#[derive(Debug, clap::Args)]
+struct DevNetwork {
+    /// Path to the database of co-change data.
+    #[clap(long)]
+    db: PathBuf,
+
+    /// Path of the file to write the graph to.
+    #[clap(long, short = 'o')]
+    output: PathBuf,
+
+    /// Format of the written graph.
+    #[clap(long, value_enum, default_value_t = GraphFormat::Graphml)]
+    format: GraphFormat,
+
+    /// Write the developer-entity graph instead of the developer-developer
+    /// graph.
+    #[clap(long)]
+    bipartite: bool,
+
+    /// Only consider entities of this kind (e.g. file, class, method).
+    #[clap(long)]
+    kind: Option<String>,
+
+    /// Minimum number of commits in which a developer must have changed an
+    /// entity for it to count as shared.
+    #[clap(long, default_value_t = 1)]
+    min_touches: usize,
+}
+
+fn parse_time_input<S: AsRef<str>>(text: S) -> Option<OffsetDateTime> {
+    // First, try to parse it as a date and time
+    if let Ok(datetime) = OffsetDateTime::parse(text.as_ref(), &Iso8601::PARSING) {
+        return Some(datetime);
+    }
+
+    // If that doesn't work, try parsing it as just a date
+    if let Ok(date) = Date::parse(text.as_ref(), &Iso8601::PARSING) {
+        return Some(PrimitiveDateTime::new(date, Time::MIDNIGHT).assume_utc());
+    }
+
+    // Finally, try to prase it as a duration and subtract
+    if let Ok(duration) = humantime::parse_duration(text.as_ref()) {
+        return Some(OffsetDateTime::now_utc() - duration);
+    }
+
+    None
+}
+
+fn validate_time_input<S: AsRef<str>>(
+    app: &mut App,
+    input: S,
+    argument: &'static str,
+) -> OffsetDateTime {
+    match parse_time_input(&input) {
+        Some(datetime) => datetime,
+        None => {
+            let msg = format!(
+                "The value ('{}') supplied to '{}' is not an ISO 8601 date or a duration.",
+                input.as_ref(),
+                &argument
+            );
+            app.error(clap::ErrorKind::ValueValidation, msg).exit();
+        }
+    }
+}
+
+fn validate_injection_input<S: AsRef<str>>(
+    app: &mut App,
+    input: S,
+    argument: &'static str,
+) -> (String, String) {
+    match input.as_ref().split_once('=') {
+        Some((language, extension)) if !language.is_empty() && extension.starts_with('.') => {
+            (language.to_string(), extension.to_string())
+        }
+        _ => {
+            let msg = format!(
+                "The value ('{}') supplied to '{}' is not of the form LANGUAGE=.EXT.",
+                input.as_ref(),
+                &argument
+            );
+            app.error(clap::ErrorKind::ValueValidation, msg).exit();
+        }
+    }
+}
+
+fn validate_duration_input<S: AsRef<str>>(
+    app: &mut App,
+    input: S,
+    argument: &'static str,
+) -> Duration {
+    match humantime::parse_duration(input.as_ref()) {
+        Ok(duration) => duration,
+        Err(_) => {
+            let msg = format!(
+                "The value ('{}') supplied to '{}' is not a duration.",
+                input.as_ref(),
+                &argument
+            );
+            app.error(clap::ErrorKind::ValueValidation, msg).exit();
+        }
+    }
+}
+
+fn validate_period_input<S: AsRef<str>>(app: &mut App, input: S, argument: &'static str) -> Window {
+    let (start, end) = match input.as_ref().split_once("..") {
+        Some(bounds) => bounds,
+        None => {
+            let msg = format!(
+                "The value ('{}') supplied to '{}' is not of the form <START>..<END>.",
+                input.as_ref(),
+                &argument
+            );
+            app.error(clap::ErrorKind::ValueValidation, msg).exit();
+        }
+    };
+
+    let start = match start {
+        "" => i64::MIN,
+        start => validate_time_input(app, start, argument).unix_timestamp(),
+    };
+
+    let end = match end {
+        "" => i64::MAX,
+        end => validate_time_input(app, end, argument).unix_timestamp(),
+    };
+
+    Window { start, end }
+}
+
+fn validate_ref_input<'r, S: AsRef<str>>(
+    app: &mut App,
+    repo: &'r Repository,
+    input: S,
+) -> Reference<'r> {
+    match repo.resolve_reference_from_short_name(input.as_ref()) {
+        Ok(reference) => reference,
+        Err(_) => {
+            let msg =
+                format!("The given ref ('{}') was not found in this repository", input.as_ref());
+            app.error(clap::ErrorKind::ValueValidation, msg).exit();
+        }
+    }
+}
+
+fn validate_rev_input<'r, S: AsRef<str>>(
+    app: &mut App,
+    repo: &'r Repository,
+    input: S,
+) -> git2::Commit<'r> {
+    match repo.revparse_single(input.as_ref()).and_then(|o| o.peel_to_commit()) {
+        Ok(commit) => commit,
+        Err(_) => {
+            let msg = format!(
+                "The given revision ('{}') was not found in this repository",
+                input.as_ref()
+            );
+            app.error(clap::ErrorKind::ValueValidation, msg).exit();
+        }
+    }
+}
+
+fn validate_sampling_input<S: AsRef<str>>(
+    app: &mut App,
+    input: S,
+    argument: &'static str,
+) -> Sampling {
+    let input = input.as_ref().trim();
+
+    if input == "tags" {
+        return Sampling::Tags;
+    }
+
+    if let Ok(n) = input.trim_end_matches("commits").trim().parse::<usize>() {
+        if n > 0 {
+            return Sampling::Commits(n);
+        }
+    }
+
+    if let Ok(duration) = humantime::parse_duration(input) {
+        return Sampling::Period(duration);
+    }
+
+    let msg = format!(
+        "The value ('{}') supplied to '{}' is not a duration, a number of commits, or 'tags'.",
+        input, &argument
+    );
+    app.error(clap::ErrorKind::ValueValidation, msg).exit();
+}
+
+fn validate_shard_input<S: AsRef<str>>(app: &mut App, input: S, argument: &'static str) -> Shard {
+    let parsed = input.as_ref().split_once('/').and_then(|(i, n)| {
+        Some((i.trim().parse::<usize>().ok()?, n.trim().parse::<usize>().ok()?))
+    });
+
+    match parsed {
+        Some((i, n)) if 1 <= i && i <= n => Shard::new(i - 1, n),
+        _ => {
+            let msg = format!(
+                "The value ('{}') supplied to '{}' is not of the form <I>/<N> where 1 <= I <= N.",
+                input.as_ref(),
+                &argument
+            );
+            app.error(clap::ErrorKind::ValueValidation, msg).exit();
+        }
+    }
+}
+
+fn validate_sample_rate(app: &mut App, cli: &CliDumpCommand) -> Option<f64> {
+    let rate = cli.sample_rate?;
+
+    if rate.is_nan() || rate <= 0.0 || rate > 1.0 {
+        let msg = "--sample-rate must be greater than 0 and at most 1";
+        app.error(clap::ErrorKind::ValueValidation, msg).exit();
+    }
+
+    Some(rate)
+}
+
+fn validate_line_args(app: &mut App, args: &LineArgs) -> LineConvention {
+    if args.line_base > 1 {
+        let msg = "--line-base must be 0 or 1";
+        app.error(clap::ErrorKind::ValueValidation, msg).exit();
+    }
+
+    LineConvention::new(args.line_base, args.end)
+}
+
+fn validate_txn_size(app: &mut App, cli: &CliDumpCommand) {
+    if cli.txn_size == Some(0) {
+        let msg = "--txn-size must be at least 1";
+        app.error(clap::ErrorKind::ValueValidation, msg).exit();
+    }
+}
+
+/// How many bytes the extracted rows may take up before they are written to
+/// the database early (i.e. the half of --max-memory not used by the cache).
+fn validate_max_memory(app: &mut App, cli: &CliDumpCommand) -> Option<usize> {
+    let max_memory = validate_size_input(app, cli.max_memory.as_ref()?, "--max-memory");
+    Some(max_memory / 2).filter(|_| get_dump_format(cli) == DumpFormat::Sqlite)
+}
+
+fn validate_jobs(app: &mut App, cli: &CliDumpCommand) {
+    if cli.jobs == 0 {
+        let msg = "--jobs must be at least 1";
+        app.error(clap::ErrorKind::ValueValidation, msg).exit();
+    }
+}
+
+fn validate_every_nth(app: &mut App, cli: &CliDumpCommand) -> Option<usize> {
+    let n = cli.every_nth?;
+
+    if n == 0 {
+        let msg = "--every-nth must be at least 1";
+        app.error(clap::ErrorKind::ValueValidation, msg).exit();
+    }
+
+    Some(n)
+}
+
+/// The hashes of the commits whose changes the database given by --resume
+/// already has.
+fn get_resumed(cmd: &mut App, cli: &CliDumpCommand) -> anyhow::Result<Option<HashSet<String>>> {
+    let path = match &cli.resume {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+
+    if !path.exists() {
+        let msg = format!("The database ('{}') does not exist.", path.to_string_lossy());
+        cmd.error(clap::ErrorKind::Io, msg).exit();
+    }
+
+    let done = load_extracted_commits(&Connection::open(path)?)?;
+    log::info!("Skipping the {} commits extracted into {}", done.len(), path.to_string_lossy());
+    Ok(Some(done))
+}
+
+/// Describes how the commits of a dump were sampled (e.g. `rate=0.1`) for the
+/// runs table.
+fn get_sampling(cli: &CliDumpCommand) -> Option<String> {
+    let mut sampling = Vec::new();
+
+    if let Some(rate) = cli.sample_rate {
+        sampling.push(format!("rate={}", rate));
+    }
+
+    if let Some(n) = cli.every_nth {
+        sampling.push(format!("every-nth={}", n));
+    }
+
+    Some(sampling.join(",")).filter(|s| !s.is_empty())
+}
+
+fn validate_size_input<S: AsRef<str>>(app: &mut App, input: S, argument: &'static str) -> usize {
+    let input = input.as_ref().trim();
+    let split = input.find(|c: char| !c.is_ascii_digit()).unwrap_or(input.len());
+    let (number, unit) = input.split_at(split);
+
+    let multiplier = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => Some(1),
+        "k" | "kb" => Some(1000),
+        "kib" => Some(1 << 10),
+        "m" | "mb" => Some(1000 * 1000),
+        "mib" => Some(1 << 20),
+        "g" | "gb" => Some(1000 * 1000 * 1000),
+        "gib" => Some(1 << 30),
+        _ => None,
+    };
+
+    match (number.parse::<usize>(), multiplier) {
+        (Ok(n), Some(multiplier)) if n > 0 => n.saturating_mul(multiplier),
+        _ => {
+            let msg = format!(
+                "The value ('{}') supplied to '{}' is not a size (e.g. 512MiB or 8GB).",
+                input, &argument
+            );
+            app.error(clap::ErrorKind::ValueValidation, msg).exit();
+        }
+    }
+}
+
+fn get_lead_refs(
+    cmd: &mut App,
+    cli: &CliDumpCommand,
+    repo: &Repository,
+) -> anyhow::Result<Vec<Ref>> {
+    if cli.all {
+        return repo.references()?.map(|r| gtl::to_ref(&r.unwrap())).collect();
+    }
+
+    let mut lead_refs = Vec::new();
+
+    for ref_name in &cli.refs {
+        lead_refs.push(gtl::to_ref(&validate_ref_input(cmd, repo, ref_name))?);
+    }
+
+    Ok(lead_refs)
+}
+
+fn get_commit_walk(
+    cmd: &mut App,
+    cli: &CliDumpCommand,
+    repo: &Repository,
+) -> anyhow::Result<CommitWalk> {
+    let mut walk = CommitWalk::new();
+    let since = cli.since.as_ref().map(|s| validate_time_input(cmd, s, "--since"));
+    let until = cli.until.as_ref().map(|s| validate_time_input(cmd, s, "--until"));
+
+    if let Some(since) = since {
+        walk.set_since(since);
+    }
+
+    if let Some(until) = until {
+        walk.set_until(until);
+    }
+
+    if let Some(n) = cli.max_count {
+        walk.set_max_count(n);
+    }
+
+    walk.set_strict_dates(cli.strict_dates);
+
+    walk.set_sort(Sort::TIME);
+
+    for ref_name in &cli.not {
+        let r#ref = validate_ref_input(cmd, repo, ref_name);
+        walk.hide_oid(r#ref.peel_to_commit()?.id());
+    }
+
+    if let Some(text) = cli.grep.clone() {
+        walk.add_filter(move |c| c.message().is_some_and(|m| m.contains(&text)));
+    }
+
+    if let Some(rate) = validate_sample_rate(cmd, cli) {
+        walk.add_filter(move |c| is_sampled(c.id(), rate));
+    }
+
+    if let Some(done) = get_resumed(cmd, cli)? {
+        walk.add_filter(move |c| !done.contains(&c.id().to_string()));
+    }
+
+    if let Some(n) = validate_every_nth(cmd, cli) {
+        walk.set_every_nth(n);
+    }
+
+    if cli.all {
+        walk.push_glob(RefGlobKind::All, None);
+        return Ok(walk);
+    }
+
+    if let Some(glob) = &cli.branches {
+        walk.push_glob(RefGlobKind::Branches, glob.clone());
+    }
+
+    if let Some(glob) = &cli.tags {
+        walk.push_glob(RefGlobKind::Tags, glob.clone());
+    }
+
+    if let Some(glob) = &cli.remotes {
+        walk.push_glob(RefGlobKind::Remotes, glob.clone());
+    }
+
+    for ref_name in &cli.refs {
+        let r#ref = validate_ref_input(cmd, repo, ref_name);
+        walk.push_start_oid(r#ref.peel_to_commit()?.id());
+    }
+
+    Ok(walk)
+}
+
+fn get_change_sets(
+    cmd: &mut App,
+    args: &CouplingArgs,
+    conn: &Connection,
+) -> anyhow::Result<Vec<ChangeSet>> {
+    let mut change_sets = load_change_sets(conn)?;
+    let thresholds = args.bulk.to_thresholds();
+
+    if !args.include_generated {
+        let mut generated = load_generated_ids(conn)?;
+        generated.extend(load_matching_files(conn, &args.generated_pattern)?);
+
+        if !generated.is_empty() {
+            let parents = load_parents(conn)?;
+            let excluded = parents
+                .keys()
+                .filter(|&&id| {
+                    ancestors_or_self(&parents, id).iter().any(|a| generated.contains(a))
+                })
+                .collect::<HashSet<_>>();
+
+            for change_set in &mut change_sets {
+                change_set.entities.retain(|(id, _)| !excluded.contains(id));
+            }
+
+            change_sets.retain(|c| !c.entities.is_empty());
+            log::info!("Excluded {} generated entities", excluded.len());
+        }
+    }
+
+    if let Some(path) = &args.aliases {
+        let aliases = to_aliases(&load_entity_paths(conn)?, &read_aliases(path)?)?;
+        roll_up(&mut change_sets, &aliases);
+        log::info!("Merged {} entities into their aliases", aliases.len());
+    }
+
+    if args.granularity != Aggregation::Entity {
+        let kinds = load_kinds(conn)?;
+
+        if args.granularity == Aggregation::Package && !kinds.values().any(|k| k == "dir") {
+            let msg = "--granularity package requires a database dumped with --dir-entities";
+            cmd.error(clap::ErrorKind::InvalidValue, msg).exit();
+        }
+
+        roll_up(&mut change_sets, &to_rollup(&load_parents(conn)?, &kinds, args.granularity));
+        log::info!("Rolled changes up to the {:?} level", args.granularity);
+    }
+
+    if args.exclude_bulk || thresholds.max_files.is_some() || thresholds.max_entities.is_some() {
+        let excluded = load_commit_shapes(conn)?
+            .iter()
+            .filter(|s| match classify_commit(s, &thresholds) {
+                Some(BulkKind::Large) => true,
+                Some(_) => args.exclude_bulk,
+                None => false,
+            })
+            .map(|s| s.commit_id)
+            .collect::<HashSet<_>>();
+        change_sets.retain(|c| !excluded.contains(&c.commit_id));
+        log::info!("Excluded {} bulk commits", excluded.len());
+    }
+
+    if args.collapse_duplicates {
+        let duplicates = load_duplicate_ids(conn)?;
+        change_sets.retain(|c| !duplicates.contains(&c.commit_id));
+        log::info!("Collapsed {} duplicate commits", duplicates.len());
+    }
+
+    if let Some(gap) = &args.session_gap {
+        let gap = validate_duration_input(cmd, gap, "--session-gap");
+        let num_commits = change_sets.len();
+        change_sets = group_sessions(change_sets, &load_commit_authors(conn)?, gap);
+        log::info!("Grouped {} commits into {} sessions", num_commits, change_sets.len());
+    }
+
+    if args.by_pull_request {
+        if !has_table(conn, "pull_request_commits")? {
+            let msg = "--by-pull-request requires pull requests imported with import-prs";
+            cmd.error(clap::ErrorKind::InvalidValue, msg).exit();
+        }
+
+        let num_commits = change_sets.len();
+        let pull_requests = load_pull_request_ids(conn)?;
+        let num_merged = change_sets.iter().filter(|c| pull_requests.contains_key(&c.commit_id));
+        let num_merged = num_merged.count();
+        change_sets = group_pull_requests(change_sets, &pull_requests);
+        log::info!(
+            "Grouped {} commits ({} of merged pull requests) into {} change sets",
+            num_commits,
+            num_merged,
+            change_sets.len()
+        );
+    }
+
+    if let Some(half_life) = &args.half_life {
+        apply_half_life(&mut change_sets, validate_duration_input(cmd, half_life, "--half-life"));
+    }
+
+    Ok(change_sets)
+}
+
+/// Gets the windows to compute results for along with a label for each.
+fn get_windows(
+    cmd: &mut App,
+    args: &WindowArgs,
+    conn: &Connection,
+    change_sets: &[ChangeSet],
+) -> anyhow::Result<Vec<(String, Window)>> {
+    if let Some(glob) = &args.releases {
+        let windows = to_release_windows(&load_releases(conn, glob)?);
+
+        if windows.is_empty() {
+            let msg = format!("Fewer than two tags match '{}' for --releases", glob);
+            cmd.error(clap::ErrorKind::InvalidValue, msg).exit();
+        }
+
+        return Ok(windows);
+    }
+
+    let size = args.window.as_ref().map(|w| validate_duration_input(cmd, w, "--window"));
+    let step = args.step.as_ref().map(|s| validate_duration_input(cmd, s, "--step"));
+    let windows = to_windows(change_sets, size, step)
+        .into_iter()
+        .map(|w| (format!("{}..{}", to_date_string(w.start), to_date_string(w.end)), w))
+        .collect();
+    Ok(windows)
+}
+
+fn to_date_string(time: i64) -> String {
+    match OffsetDateTime::from_unix_timestamp(time) {
+        Ok(datetime) => datetime.date().to_string(),
+        Err(_) => time.to_string(),
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = <Cli as clap::Parser>::parse();
+    init_logger(cli.verbose.log_level_filter(), cli.log_format);
+
+    match cli.command {
+        CliSubCommand::Dump(args) => dump(&args).map(|_| {
+            // Whatever was completed has been written by now
+            if INTERRUPTED.load(Ordering::SeqCst) {
+                std::process::exit(130);
+            }
+        }),
+        CliSubCommand::AddDeps(args) => add_deps(&args),
+        CliSubCommand::Log(args) => entity_log(&args),
+        CliSubCommand::Suggest(args) => suggest(&args),
+        CliSubCommand::Impact(args) => impact(&args),
+        CliSubCommand::Check(args) => check(&args),
+        CliSubCommand::Notes(args) => write_notes(&args),
+        CliSubCommand::Report(args) => report(&args),
+        CliSubCommand::Browse(args) => browse_db(&args),
+        CliSubCommand::Serve(args) => serve_db(&args),
+        CliSubCommand::Merge(args) => merge(&args),
+        CliSubCommand::Audit(args) => audit(&args),
+        CliSubCommand::ImportPrs(args) => import_prs(&args),
+        CliSubCommand::ImportIssues(args) => import_issues(&args),
+        CliSubCommand::Export(args) => match args.command {
+            ExportSubCommand::Features(args) => export_features(&args),
+            ExportSubCommand::Graph(args) => export_graph(&args),
+            ExportSubCommand::Tables(args) => export_tables(&args),
+            ExportSubCommand::Neo4j(args) => export_neo4j(&args),
+        },
+        CliSubCommand::Analyze(args) => match args.command {
+            AnalyzeSubCommand::HiddenDeps(args) => hidden_deps(&args),
+            AnalyzeSubCommand::DepCochange(args) => dep_cochange(&args),
+            AnalyzeSubCommand::Ownership(args) => ownership(&args),
+            AnalyzeSubCommand::DevNetwork(args) => dev_network(&args),
+            AnalyzeSubCommand::Coupling(args) => coupling(&args),
+            AnalyzeSubCommand::Hotspots(args) => hotspots(&args),
+            AnalyzeSubCommand::Compare(args) => compare(&args),
+            AnalyzeSubCommand::BulkCommits(args) => bulk_commits(&args),
+            AnalyzeSubCommand::TestLinks(args) => test_links(&args),
+            AnalyzeSubCommand::Clones(args) => clones(&args),
+            AnalyzeSubCommand::DivergentChange(args) => divergent_change(&args),
+            AnalyzeSubCommand::Clusters(args) => clusters(&args),
+            AnalyzeSubCommand::Interfaces(args) => interfaces(&args),
+            AnalyzeSubCommand::Trend(args) => trend(&args),
+            AnalyzeSubCommand::KnowledgeLoss(args) => knowledge_loss(&args),
+        },
+    }
+}
+
+/// Everything extracted by a dump, in the order it is added to the database.
+/// Under --max-memory, the rows extracted per commit are added early (see
+/// `flush_extracted`).
+#[derive(Default)]
+struct Dumped {
+    db: VirtualDb,
+    /// The database once rows were written to it early
+    conn: Option<Connection>,
+    /// Roughly how many bytes the extracted rows not yet written take up
+    buffered: usize,
+    /// The extracted rows are written early once they take up more bytes
+    max_buffered: Option<usize>,
+    changes: Vec<Change>,
+    presences: Vec<Presence>,
+    entity_sources: Vec<EntitySource>,
+    lead_refs: Vec<Ref>,
+    tags: Vec<Tag>,
+    ref_updates: Vec<RefUpdate>,
+    ref_members: Vec<RefMember>,
+    duplicates: Vec<Duplicate>,
+    reverts: Vec<Revert>,
+    submodule_updates: Vec<SubmoduleUpdate>,
+    symlink_updates: Vec<SymlinkUpdate>,
+    changed_lines: Vec<ChangedLines>,
+    doc_changes: Vec<DocChange>,
+    mapped_hunks: Vec<MappedHunk>,
+    parse_errors: Vec<ParseError>,
+    generated: Vec<GeneratedEntity>,
+    diagnostics: Vec<Diagnostic>,
+    /// Which phases of the extraction ran for each commit
+    commit_infos: Vec<(Commit, CommitInfo)>,
+    /// The settings which the phases ran with
+    coverage: Coverage,
+    /// Why (and where) the extraction stopped early (if it did)
+    stop: Option<Stop>,
+}
+
+/// Why (and where) a dump stopped before extracting every commit.
+#[derive(Clone, Debug)]
+struct Stop {
+    /// Either `commit_budget`, `time_budget`, or `interrupted`
+    reason: &'static str,
+    /// The first commit which was not extracted (if any)
+    commit: Option<Commit>,
+}
+
+/// Set once Ctrl-C is pressed during a dump (see `trap_interrupts`).
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+fn get_dump_format(cli: &CliDumpCommand) -> DumpFormat {
+    if cli.db.as_os_str() == "-" {
+        DumpFormat::Jsonl
+    } else {
+        cli.format
+    }
+}
+
+fn dump(cli: &CliDumpCommand) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+    let started_at = OffsetDateTime::now_utc();
+    let mut metrics = Metrics::new();
+    let time_budget = cli.time_budget.as_ref();
+    let deadline =
+        time_budget.map(|s| Instant::now() + validate_duration_input(&mut cmd, s, "--time-budget"));
+    validate_txn_size(&mut cmd, cli);
+    validate_jobs(&mut cmd, cli);
+    validate_line_args(&mut cmd, &cli.lines);
+    trap_interrupts();
+
+    // Check if database already exists
+    if cli.db.as_os_str() != "-" && !cli.force && Path::new(&cli.db).exists() {
+        let msg = format!(
+            "The database ('{}') already exists. Use --force to overwrite it. (Updating databases \
+             is not yet supported.)",
+            &cli.db.to_string_lossy()
+        );
+        cmd.error(clap::ErrorKind::Io, msg).exit();
+    }
+
+    if cli.vcs == VcsKind::Hg {
+        return dump_hg(&mut cmd, cli, started_at, deadline, metrics);
+    }
+
+    // Open repository
+    let repo = open_repo(cli.repo.as_deref())?;
+
+    // This is a necessary config for Windows. Even though we never touch the actual
+    // filesystem, because libgit2 emulates the behavior of the real git, it will
+    // still crash on Windows when encountering especially long paths.
+    repo.config()?.set_bool("core.longpaths", true)?;
+
+    // libgit2 reads the generation numbers of a commit-graph when walking
+    // whenever the objects directory has one (it does not look at
+    // core.commitGraph), so the config of the repository is left alone
+    if cli.write_commit_graph && !has_commit_graph(&repo) {
+        let start = Instant::now();
+        write_commit_graph(&repo)?;
+        metrics.phase("commit_graph", start, 1, "commit-graph");
+    }
+
+    if has_commit_graph(&repo) {
+        log::info!("Using the commit-graph of the repository");
+    } else {
+        log::info!("No commit-graph found (see --write-commit-graph)");
+    }
+
+    // Setup tree sitter
+    let mut cache = ExtractionCtx::new(&repo, get_java_parser()?, ".java");
+    setup_extraction(&mut cmd, cli, &mut cache)?;
+    cache.set_fetch_missing(cli.fetch_missing);
+
+    // Initial collection of commits into HashMap
+    // We walk in reverse chronological order. This is to ensure the "-n" flag works
+    // as expected. For instance, "-n 50" should fetch the 50 most recent commits.
+    let walk = get_commit_walk(&mut cmd, cli, &repo)?;
+    let start = Instant::now();
+    let (walked, skipped) = match cli.max_count_per_ref {
+        Some(n) => walk.walk_per_start(&repo, n)?,
+        None => {
+            let mut walk = walk.walk(&repo)?;
+            let walked = walk.by_ref().collect::<Result<Vec<_>, _>>()?;
+            (walked, walk.into_skipped())
+        }
+    };
+    metrics.phase("walk", start, walked.len(), "commits");
+
+    // Keep only the commits of this shard
+    let shard = cli.shard.as_ref().map(|s| validate_shard_input(&mut cmd, s, "--shard"));
+    let mut commits = match shard {
+        Some(shard) => walked.iter().filter(|c| shard.contains(c.id())).cloned().collect(),
+        None => walked.clone(),
+    };
+
+    if shard.is_some() {
+        log::info!("Kept {} of {} commits in this shard", commits.len(), walked.len());
+    }
+
+    // No commit is diffed (so no budget applies) when only presence is wanted
+    if cli.skip_changes {
+        commits.clear();
+    }
+
+    let mut stop = None;
+
+    if let Some(n) = cli.commit_budget.filter(|&n| n < commits.len()) {
+        stop = Some(Stop { reason: "commit_budget", commit: Some(gtl::to_commit(&commits[n])?) });
+        commits.truncate(n);
+    }
+
+    // Collect changed files
+    let start = Instant::now();
+    let max_buffered = validate_max_memory(&mut cmd, cli);
+    let mut dumped = Dumped { coverage: cache.coverage(), stop, max_buffered, ..Dumped::default() };
+    let rewritten =
+        if cli.rewrite_parents { Some(rewrite_parents(&repo, &commits, &skipped)?) } else { None };
+    let (mut diffed_files, submodule_updates, diagnostics) = diff_all_files(
+        &repo,
+        &commits,
+        rewritten.as_ref(),
+        cache.suffix(),
+        cli.merges,
+        cli.fetch_missing,
+    )?;
+    exclude_vendored(&cache, &mut diffed_files);
+    metrics.phase("diff", start, diffed_files.len(), "changed files");
+    dumped.submodule_updates = submodule_updates;
+    dumped.diagnostics = diagnostics;
+    let diffed = commits.iter().map(gtl::to_commit).collect::<Result<Vec<_>, _>>()?;
+    record_diffed(&mut dumped, diffed, cli.merges);
+
+    if repo.is_shallow() {
+        let message = "the repository is a shallow clone, so history beyond its boundary commits \
+                       is missing";
+        log::warn!("Note that {}", message);
+        dumped.diagnostics.push(Diagnostic::new(
+            None,
+            "shallow",
+            String::new(),
+            message.to_string(),
+        ));
+    }
+
+    if !dumped.submodule_updates.is_empty() {
+        log::info!("Found {} submodule updates", dumped.submodule_updates.len());
+    }
+
+    // Calculate changes
+    extract_changes(cli, deadline, &mut cache, &diffed_files, &mut dumped, &mut metrics)?;
+
+    // Calculate presence
+    dumped.lead_refs = get_lead_refs(&mut cmd, cli, &repo)?;
+    let mut snapshots = dumped.lead_refs.iter().map(|r| r.commit.clone()).collect::<Vec<_>>();
+
+    for rev in &cli.presence_at {
+        snapshots.push(gtl::to_commit(&validate_rev_input(&mut cmd, &repo, rev))?);
+    }
+
+    if let Some(every) = &cli.presence_every {
+        let sampling = validate_sampling_input(&mut cmd, every, "--presence-every");
+
+        for commit in sample_commits(&repo, &walked, sampling)? {
+            snapshots.push(gtl::to_commit(&commit)?);
+        }
+    }
+
+    if !is_stopped(&mut dumped) && !cli.skip_presence {
+        extract_presences(cli, &mut cache, snapshots, shard, &mut dumped, &mut metrics)?;
+    }
+
+    summarize_extraction(&mut cache, &mut dumped, &mut metrics);
+
+    // Find which processed commits belong to each lead ref
+    let start = Instant::now();
+    dumped.ref_members = get_ref_members(&repo, &dumped.lead_refs, &commits)?;
+    metrics.phase("ref_members", start, dumped.ref_members.len(), "ref memberships");
+
+    // Find tags of processed commits
+    dumped.tags = get_tags(&repo, &commits)?;
+    log::info!("Found {} tags", dumped.tags.len());
+
+    // Read reflogs
+    let mut reflog_names = Vec::new();
+
+    for ref_name in &cli.reflog {
+        let r#ref = validate_ref_input(&mut cmd, &repo, ref_name);
+        reflog_names.push(r#ref.name().context("missing ref name")?.to_string());
+    }
+
+    dumped.ref_updates = get_ref_history(&repo, &reflog_names)?;
+    log::info!("Found {} reflog entries", dumped.ref_updates.len());
+
+    // Find duplicate and reverted patches
+    if cli.patch_ids {
+        let start = Instant::now();
+        let patch_ids = get_patch_ids(&repo, &commits)?;
+        dumped.duplicates = find_duplicates(&commits, &patch_ids)?;
+        dumped.reverts = find_reverts(&commits, &patch_ids)?;
+        log::info!(
+            "Found {} duplicates and {} reverts",
+            dumped.duplicates.len(),
+            dumped.reverts.len()
+        );
+        metrics.phase("patch_ids", start, patch_ids.len(), "patch-ids");
+    }
+
+    write_dump(cli, dumped, &repo, started_at, metrics)
+}
+
+/// Like `dump`, but reads a Mercurial repository. Only what does not need git
+/// is extracted (i.e. no tags, reflogs, ref memberships, or patch-ids).
+fn dump_hg(
+    cmd: &mut App,
+    cli: &CliDumpCommand,
+    started_at: OffsetDateTime,
+    deadline: Option<Instant>,
+    mut metrics: Metrics,
+) -> anyhow::Result<()> {
+    let unsupported = [
+        (cli.all, "--all"),
+        (cli.branches.is_some(), "--branches"),
+        (cli.tags.is_some(), "--tags"),
+        (cli.remotes.is_some(), "--remotes"),
+        (cli.glob.is_some(), "--glob"),
+        (cli.presence_every.is_some(), "--presence-every"),
+        (cli.patch_ids, "--patch-ids"),
+        (!cli.reflog.is_empty(), "--reflog"),
+        (cli.fetch_missing, "--fetch-missing"),
+        (cli.write_commit_graph, "--write-commit-graph"),
+        (cli.rewrite_parents, "--rewrite-parents"),
+        (cli.max_count_per_ref.is_some(), "--max-count-per-ref"),
+    ];
+
+    if let Some((_, flag)) = unsupported.iter().find(|(given, _)| *given) {
+        let msg = format!("{} is not supported with --vcs hg", flag);
+        cmd.error(clap::ErrorKind::ArgumentConflict, msg).exit();
+    }
+
+    let hg = Mercurial::open(cli.repo.as_deref().unwrap_or(Path::new(".")))?;
+    let mut cache = ExtractionCtx::new(&hg, get_java_parser()?, ".java");
+    setup_extraction(cmd, cli, &mut cache)?;
+
+    // Walk in reverse chronological order, so that "-n 50" keeps the 50 most
+    // recent commits
+    let since = cli.since.as_ref().map(|s| validate_time_input(cmd, s, "--since"));
+    let until = cli.until.as_ref().map(|s| validate_time_input(cmd, s, "--until"));
+    let start = Instant::now();
+    let sample_rate = validate_sample_rate(cmd, cli);
+    let every_nth = validate_every_nth(cmd, cli);
+    let resumed = get_resumed(cmd, cli)?;
+    let mut walked = hg.walk(&cli.refs, &cli.not)?;
+    walked.retain(|c| {
+        since.is_none_or(|s| c.commit_date >= s)
+            && until.is_none_or(|u| c.commit_date <= u)
+            && cli.grep.as_ref().is_none_or(|text| c.message.contains(text))
+            && sample_rate.is_none_or(|rate| is_sampled(c.sha1, rate))
+            && resumed.as_ref().is_none_or(|done| !done.contains(&c.sha1.to_string()))
+    });
+
+    if let Some(n) = every_nth {
+        walked = walked.into_iter().step_by(n).collect();
+    }
+    if let Some(n) = cli.max_count {
+        walked.truncate(n);
+    }
+    metrics.phase("walk", start, walked.len(), "commits");
+
+    // Keep only the commits of this shard
+    let shard = cli.shard.as_ref().map(|s| validate_shard_input(cmd, s, "--shard"));
+    let mut commits = walked.clone();
+    if let Some(shard) = &shard {
+        commits.retain(|c| shard.contains(c.sha1));
+    }
+
+    if shard.is_some() {
+        log::info!("Kept {} of {} commits in this shard", commits.len(), walked.len());
+    }
+
+    // No commit is diffed (so no budget applies) when only presence is wanted
+    if cli.skip_changes {
+        commits.clear();
+    }
+
+    let mut stop = None;
+
+    if let Some(n) = cli.commit_budget.filter(|&n| n < commits.len()) {
+        stop = Some(Stop { reason: "commit_budget", commit: Some(commits[n].clone()) });
+        commits.truncate(n);
+    }
+
+    // Collect changed files
+    let start = Instant::now();
+    let max_buffered = validate_max_memory(cmd, cli);
+    let mut dumped = Dumped { coverage: cache.coverage(), stop, max_buffered, ..Dumped::default() };
+    let (mut diffed_files, diagnostics) = hg.diff(&commits, cache.suffix(), cli.merges)?;
+    exclude_vendored(&cache, &mut diffed_files);
+    metrics.phase("diff", start, diffed_files.len(), "changed files");
+    dumped.diagnostics = diagnostics;
+    record_diffed(&mut dumped, commits, cli.merges);
+
+    // Calculate changes
+    extract_changes(cli, deadline, &mut cache, &diffed_files, &mut dumped, &mut metrics)?;
+
+    // Calculate presence
+    for rev in &cli.refs {
+        dumped.lead_refs.push(Ref::new(hg.resolve(rev)?, rev.clone()));
+    }
+
+    let mut snapshots = dumped.lead_refs.iter().map(|r| r.commit.clone()).collect::<Vec<_>>();
+
+    for rev in &cli.presence_at {
+        snapshots.push(hg.resolve(rev)?);
+    }
+
+    if !is_stopped(&mut dumped) && !cli.skip_presence {
+        extract_presences(cli, &mut cache, snapshots, shard, &mut dumped, &mut metrics)?;
+    }
+
+    summarize_extraction(&mut cache, &mut dumped, &mut metrics);
+    write_dump(cli, dumped, &hg, started_at, metrics)
+}
+
+/// Records that the changes of the diffed commits are known, except for merges
+/// which were skipped and commits which could not be diffed (see the
+/// diagnostics).
+fn record_diffed(dumped: &mut Dumped, commits: Vec<Commit>, merges: MergeMode) {
+    let skipped = dumped.diagnostics.iter().filter_map(|d| d.commit.as_ref().map(|c| c.sha1));
+    let skipped = skipped.collect::<HashSet<_>>();
+
+    for commit in commits {
+        let is_skipped = commit.is_merge && merges == MergeMode::Skip;
+
+        if !is_skipped && !skipped.contains(&commit.sha1) {
+            dumped.commit_infos.push((commit, CommitInfo::CHANGES));
+        }
+    }
+}
+
+/// Configures how files are parsed and cached from the command line.
+fn setup_extraction(
+    cmd: &mut App,
+    cli: &CliDumpCommand,
+    cache: &mut ExtractionCtx,
+) -> anyhow::Result<()> {
+    cache.set_granularity(cli.granularity);
+
+    let fallback = match (&cli.ctags, &cli.tags_file) {
+        (Some(program), _) => Some(CtagsParser::new(program.clone())),
+        (None, Some(path)) => Some(CtagsParser::from_tags_file(path)?),
+        (None, None) => None,
+    };
+
+    if let Some(fallback) = fallback {
+        if cli.granularity != Granularity::Auto {
+            let msg = "--ctags and --tags-file require --granularity auto";
+            cmd.error(clap::ErrorKind::ArgumentConflict, msg).exit();
+        }
+
+        cache.set_fallback_parser(Box::new(fallback));
+    }
+
+    for input in &cli.inject {
+        let (language, extension) = validate_injection_input(cmd, input, "--inject");
+
+        if extension != ".java" && cli.ctags.is_none() {
+            let msg = format!("--inject {} requires --ctags", input);
+            cmd.error(clap::ErrorKind::MissingRequiredArgument, msg).exit();
+        }
+
+        cache.add_injection(language, extension);
+    }
+
+    if let Some(max_error_ratio) = cli.max_error_ratio {
+        if !(0.0..=1.0).contains(&max_error_ratio) {
+            let msg = "--max-error-ratio must be between 0 and 1";
+            cmd.error(clap::ErrorKind::ValueValidation, msg).exit();
+        }
+
+        cache.set_max_error_ratio(max_error_ratio);
+    }
+
+    cache.set_exclude_comments(cli.exclude_comments);
+
+    if !cli.include_vendored {
+        let mut vendored_dirs = VENDORED_DIRS.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+        vendored_dirs.extend(cli.vendored_dir.iter().cloned());
+        cache.set_vendored_dirs(vendored_dirs);
+    }
+
+    // Split the size of the cache between the threads
+    let cache_size = match (&cli.cache_size, &cli.max_memory) {
+        (Some(size), _) => Some(validate_size_input(cmd, size, "--cache-size")),
+        (None, Some(size)) => Some(validate_size_input(cmd, size, "--max-memory") / 2),
+        (None, None) => None,
+    };
+
+    if let Some(size) = cache_size {
+        cache.set_max_cache_size(size / cli.jobs);
+    }
+
+    if let Some(n) = cli.cache_entries {
+        cache.set_max_cache_entries(n);
+    }
+
+    Ok(())
+}
+
+/// Leaves out the changed files below vendored directories (see
+/// --vendored-dir).
+fn exclude_vendored(cache: &ExtractionCtx, diffed_files: &mut Vec<DiffedFile>) {
+    let count = diffed_files.len();
+    diffed_files.retain(|f| !cache.is_vendored(&f.filename));
+
+    if diffed_files.len() < count {
+        log::info!(
+            "Left out {} changed files below vendored directories",
+            count - diffed_files.len()
+        );
+    }
+}
+
+/// Records the changed files whose paths differ only by case as diagnostics.
+/// With `merge`, each is also inserted under the most recently changed path.
+fn record_case_collisions(mode: CaseCollisions, diffed_files: &[DiffedFile], dumped: &mut Dumped) {
+    if mode == CaseCollisions::Ignore {
+        return;
+    }
+
+    let mut aliases = HashMap::new();
+
+    for paths in find_case_collisions(diffed_files) {
+        let (path, others) = paths.split_first().unwrap();
+        let others_str = others.join(", ");
+        log::warn!("{} was also committed as {}", path, others_str);
+
+        let message = if mode == CaseCollisions::Merge {
+            aliases.extend(others.iter().map(|other| (other.clone(), path.clone())));
+            format!("merged with {}", others_str)
+        } else {
+            format!("also committed as {}", others_str)
+        };
+
+        dumped.diagnostics.push(Diagnostic::new(None, "case_collision", path.clone(), message));
+    }
+
+    dumped.db.set_path_aliases(aliases);
+}
+
+/// What is extracted from a single diffed file (see `extract_changes`).
+#[derive(Debug, Default)]
+struct ExtractedFile {
+    changes: Vec<Change>,
+    changed_lines: Vec<ChangedLines>,
+    symlink_updates: Vec<SymlinkUpdate>,
+    doc_changes: Vec<DocChange>,
+    mapped_hunks: Vec<MappedHunk>,
+    /// How long the extraction took
+    duration: Duration,
+}
+
+/// Calculates the changes (and whatever else is stored per diffed file).
+///
+/// Commits are extracted from the most to the least recent. Once the deadline
+/// has passed, the remaining commits are left out (see `stop_at`).
+fn extract_changes(
+    cli: &CliDumpCommand,
+    deadline: Option<Instant>,
+    cache: &mut ExtractionCtx,
+    diffed_files: &[DiffedFile],
+    dumped: &mut Dumped,
+    metrics: &mut Metrics,
+) -> anyhow::Result<()> {
+    let start = Instant::now();
+    dumped.db.set_dir_entities(cli.dir_entities);
+    dumped.db.set_txn_size(cli.txn_size.map(|n| n * 1000));
+    record_case_collisions(cli.case_collisions, diffed_files, dumped);
+    let mut change_count = 0;
+
+    let mut diffed_files = diffed_files.iter().collect::<Vec<_>>();
+    diffed_files.sort_by_key(|f| (to_extraction_order(&f.commit), &f.filename, f.parent));
+
+    let mut add = |dumped: &mut Dumped, i: usize, extracted: ExtractedFile| {
+        for change in extracted.changes {
+            change_count += 1;
+            dumped.buffered += change.approx_size();
+            dumped.changes.push(change);
+        }
+
+        dumped.changed_lines.extend(extracted.changed_lines);
+        dumped.symlink_updates.extend(extracted.symlink_updates);
+        dumped.doc_changes.extend(extracted.doc_changes);
+        dumped.mapped_hunks.extend(extracted.mapped_hunks);
+
+        let (sha1, filename) = (diffed_files[i].commit.sha1, &diffed_files[i].filename);
+        metrics.file("changes", sha1, filename, extracted.duration);
+
+        // The rows of a commit are all added before any is written, since
+        // repeated rows are merged
+        if diffed_files.get(i + 1).is_none_or(|next| next.commit.sha1 != sha1) {
+            flush_if_full(cli, dumped)?;
+        }
+
+        anyhow::Ok(())
+    };
+
+    let stop = if cli.jobs > 1 {
+        extract_in_parallel(cli, deadline, cache, &diffed_files, |i, e| add(dumped, i, e))?
+    } else {
+        let mut stop = None;
+
+        for i in 0..diffed_files.len() {
+            if let Some(reason) = get_stop_reason(&diffed_files, i, deadline) {
+                stop = Some((i, reason));
+                break;
+            }
+
+            let extracted = extract_file(cli, cache, diffed_files[i])?;
+            add(dumped, i, extracted)?;
+        }
+
+        stop
+    };
+
+    if let Some((i, reason)) = stop {
+        stop_at(dumped, &diffed_files[i].commit, reason);
+    }
+
+    metrics.phase("changes", start, change_count, "changes");
+    Ok(())
+}
+
+/// Extracts the diffed files on --jobs threads, each of which reads blobs from
+/// its own handle of the repository and keeps its own parse cache. A producer
+/// hands out the files in order, and each worker takes the next one whenever
+/// it is free, so that a slow file holds up no other. What is extracted is
+/// passed to `add` in the order of the files all the same. Returns the file
+/// which the extraction stopped at and why (if it stopped early).
+fn extract_in_parallel<F>(
+    cli: &CliDumpCommand,
+    deadline: Option<Instant>,
+    cache: &mut ExtractionCtx,
+    diffed_files: &[&DiffedFile],
+    mut add: F,
+) -> anyhow::Result<Option<(usize, &'static str)>>
+where
+    F: FnMut(usize, ExtractedFile) -> anyhow::Result<()>,
+{
+    let vcss = (0..cli.jobs).map(|_| cache.vcs().reopen()).collect::<Result<Vec<_>, _>>()?;
+    let (job_tx, job_rx) = mpsc::sync_channel(cli.jobs * 4);
+    let job_rx = Arc::new(Mutex::new(job_rx));
+    let (result_tx, result_rx) = mpsc::channel();
+
+    let (stop, findings) = std::thread::scope(|scope| {
+        let mut workers = Vec::new();
+
+        for vcs in vcss {
+            let (job_rx, result_tx) = (job_rx.clone(), result_tx.clone());
+            workers.push(scope.spawn(move || {
+                let mut worker_cache = ExtractionCtx::new(&*vcs, get_java_parser()?, ".java");
+                setup_extraction(&mut Cli::command(), cli, &mut worker_cache)?;
+                worker_cache.set_fetch_missing(cli.fetch_missing);
+
+                loop {
+                    // The queue is only locked while waiting for the next file
+                    let i = match job_rx.lock().unwrap().recv() {
+                        Ok(i) => i,
+                        Err(_) => break,
+                    };
+                    let extracted = extract_file(cli, &mut worker_cache, diffed_files[i]);
+
+                    if result_tx.send((i, extracted)).is_err() {
+                        break;
+                    }
+                }
+
+                anyhow::Ok(worker_cache.take_findings())
+            }));
+        }
+
+        // Only the threads hold on to the channels, so that each closes once
+        // the threads on one side are done
+        drop((job_rx, result_tx));
+
+        let producer = scope.spawn(move || {
+            for i in 0..diffed_files.len() {
+                if let Some(reason) = get_stop_reason(diffed_files, i, deadline) {
+                    return Some((i, reason));
+                }
+
+                if job_tx.send(i).is_err() {
+                    break;
+                }
+            }
+
+            None
+        });
+
+        // The files are extracted in any order but added in order
+        let (mut pending, mut next) = (HashMap::new(), 0);
+
+        for (i, extracted) in result_rx {
+            pending.insert(i, extracted);
+
+            while let Some(extracted) = pending.remove(&next) {
+                add(next, extracted?)?;
+                next += 1;
+            }
+        }
+
+        let stop = producer.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+        let findings = workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        anyhow::Ok((stop, findings))
+    })?;
+
+    for findings in findings {
+        cache.add_findings(findings);
+    }
+
+    Ok(stop)
+}
+
+/// Why the extraction should stop before the diffed file at the given index
+/// (if it should), which it only does between commits.
+fn get_stop_reason(
+    diffed_files: &[&DiffedFile],
+    i: usize,
+    deadline: Option<Instant>,
+) -> Option<&'static str> {
+    let diffed_file = diffed_files[i];
+    let is_next_commit = i == 0 || diffed_files[i - 1].commit.sha1 != diffed_file.commit.sha1;
+
+    if is_next_commit && deadline.is_some_and(|d| Instant::now() >= d) {
+        log::warn!("Ran out of time before extracting {}", diffed_file.commit.sha1);
+        return Some("time_budget");
+    }
+
+    if is_next_commit && INTERRUPTED.load(Ordering::SeqCst) {
+        return Some("interrupted");
+    }
+
+    None
+}
+
+/// Extracts the changes of a diffed file (and whatever else is stored for it).
+fn extract_file(
+    cli: &CliDumpCommand,
+    cache: &mut ExtractionCtx,
+    diffed_file: &DiffedFile,
+) -> anyhow::Result<ExtractedFile> {
+    let start = Instant::now();
+    let format = get_dump_format(cli);
+    let changes = get_changes(cache, diffed_file)?;
+    let mut extracted = ExtractedFile { changes, ..ExtractedFile::default() };
+
+    if cli.store_lines && format == DumpFormat::Sqlite {
+        extracted.changed_lines.extend(get_changed_lines(cache, diffed_file));
+    }
+
+    if format == DumpFormat::Sqlite {
+        extracted.symlink_updates.extend(get_symlink_update(cache, diffed_file)?);
+    }
+
+    if cli.store_docs && format == DumpFormat::Sqlite {
+        extracted.doc_changes.extend(get_doc_changes(cache, diffed_file));
+    }
+
+    if cli.store_hunks && format == DumpFormat::Sqlite {
+        extracted.mapped_hunks.extend(get_mapped_hunks(cache, diffed_file));
+    }
+
+    extracted.duration = start.elapsed();
+    Ok(extracted)
+}
+
+/// Commits are extracted in this order (i.e. the most recent first).
+fn to_extraction_order(commit: &Commit) -> (std::cmp::Reverse<OffsetDateTime>, Oid) {
+    (std::cmp::Reverse(commit.commit_date), commit.sha1)
+}
+
+/// Leaves the given commit and those after it (in extraction order) out of
+/// the commits whose changes are known, unless an earlier commit was already
+/// left out.
+fn stop_at(dumped: &mut Dumped, commit: &Commit, reason: &'static str) {
+    let order = to_extraction_order(commit);
+    let earlier = dumped.stop.as_ref().and_then(|s| s.commit.as_ref());
+
+    if earlier.is_none_or(|earlier| order < to_extraction_order(earlier)) {
+        dumped.stop = Some(Stop { reason, commit: Some(commit.clone()) });
+    }
+
+    dumped.commit_infos.retain(|(c, _)| to_extraction_order(c) < order);
+}
+
+/// Whether the extraction stopped early, which it also has once interrupted
+/// (even if every commit was extracted by then).
+fn is_stopped(dumped: &mut Dumped) -> bool {
+    if dumped.stop.is_none() && INTERRUPTED.load(Ordering::SeqCst) {
+        dumped.stop = Some(Stop { reason: "interrupted", commit: None });
+    }
+
+    dumped.stop.is_some()
+}
+
+/// Has Ctrl-C stop the extraction between commits (see `extract_changes`) so
+/// that whatever was completed is still written. A second Ctrl-C exits at once.
+fn trap_interrupts() {
+    let result = ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+
+        log::warn!("Interrupted. Writing what was completed (press Ctrl-C again to quit now).");
+    });
+
+    if let Err(err) = result {
+        log::warn!("Failed to trap Ctrl-C: {}", err);
+    }
+}
+
+/// Calculates the presences of the given snapshots (each once), along with the
+/// sources of the present entities if they are stored. Each snapshot is only
+/// taken by the shard its commit belongs to.
+fn extract_presences(
+    cli: &CliDumpCommand,
+    cache: &mut ExtractionCtx,
+    mut snapshots: Vec<Commit>,
+    shard: Option<Shard>,
+    dumped: &mut Dumped,
+    metrics: &mut Metrics,
+) -> anyhow::Result<()> {
+    let sources = cli.store_sources.filter(|_| get_dump_format(cli) == DumpFormat::Sqlite);
+    snapshots.sort_by_key(|c| c.sha1);
+    snapshots.dedup_by_key(|c| c.sha1);
+
+    if let Some(shard) = shard {
+        snapshots.retain(|c| shard.contains(c.sha1));
+    }
+
+    let start = Instant::now();
+    let mut presence_count = 0;
+
+    for snapshot in &snapshots {
+        let snapshot_start = Instant::now();
+
+        let (presences, entity_sources) = get_presences(cache, snapshot, sources)?;
+
+        for presence in presences {
+            presence_count += 1;
+            dumped.buffered += presence.approx_size();
+            dumped.presences.push(presence);
+        }
+
+        for source in entity_sources {
+            dumped.buffered += source.approx_size();
+            dumped.entity_sources.push(source);
+        }
+
+        metrics.commit("presence", snapshot.sha1, snapshot_start.elapsed());
+        dumped.commit_infos.push((snapshot.clone(), CommitInfo::PRESENCE));
+        flush_if_full(cli, dumped)?;
+    }
+
+    log::info!("Took {} presence snapshots", snapshots.len());
+    metrics.phase("presence", start, presence_count, "presences");
+    Ok(())
+}
+
+/// Writes what was extracted so far to the database once it takes up more than
+/// its share of --max-memory.
+fn flush_if_full(cli: &CliDumpCommand, dumped: &mut Dumped) -> anyhow::Result<()> {
+    if dumped.max_buffered.is_some_and(|max| dumped.buffered > max) {
+        flush_extracted(cli, dumped)?;
+    }
+
+    Ok(())
+}
+
+/// Adds what was extracted so far to the virtual database and writes the rows
+/// which are filled one commit at a time to the database (which is created on
+/// the first flush), so that they no longer take up memory.
+fn flush_extracted(cli: &CliDumpCommand, dumped: &mut Dumped) -> anyhow::Result<()> {
+    let start = Instant::now();
+    let count = dumped.changes.len() + dumped.presences.len();
+    insert_extracted(dumped)?;
+
+    // The rows written early refer to entities and commits, which are only
+    // written at the end
+    if dumped.conn.is_none() {
+        let conn = create_database(cli)?;
+        conn.pragma_update(None, "foreign_keys", false)?;
+        dumped.conn = Some(conn);
+    }
+
+    let conn = dumped.conn.as_mut().unwrap();
+
+    if cli.txn_size.is_some() {
+        dumped.db.flush(conn)?;
+    } else {
+        let tx = conn.transaction()?;
+        dumped.db.flush(&tx)?;
+        tx.commit()?;
+    }
+
+    dumped.buffered = 0;
+    let ms = start.elapsed().as_millis();
+    log::info!("Wrote {} changes and presences to the database early in {}ms", count, ms);
+    Ok(())
+}
+
+/// Adds the rows extracted per diffed file or snapshot to the virtual database
+/// (leaving them out of `dumped`).
+fn insert_extracted(dumped: &mut Dumped) -> anyhow::Result<()> {
+    let db = &mut dumped.db;
+
+    for change in dumped.changes.drain(..) {
+        insert_change(db, &change)?;
+    }
+
+    for presence in dumped.presences.drain(..) {
+        insert_presence(db, &presence)?;
+    }
+
+    for source in dumped.entity_sources.drain(..) {
+        insert_entity_source(db, &source)?;
+    }
+
+    for update in dumped.symlink_updates.drain(..) {
+        insert_symlink_update(db, &update)?;
+    }
+
+    for lines in dumped.changed_lines.drain(..) {
+        insert_changed_lines(db, &lines)?;
+    }
+
+    for doc_change in dumped.doc_changes.drain(..) {
+        insert_doc_change(db, &doc_change)?;
+    }
+
+    for mapped_hunk in dumped.mapped_hunks.drain(..) {
+        insert_mapped_hunk(db, &mapped_hunk)?;
+    }
+
+    Ok(())
+}
+
+/// Creates the database (replacing it under --force) and writes the schema
+/// first, so that even a partly written database describes itself.
+fn create_database(cli: &CliDumpCommand) -> anyhow::Result<Connection> {
+    if cli.force && Path::new(&cli.db).exists() {
+        log::warn!("Overwriting existing database ('{}')...", &cli.db.to_string_lossy());
+        remove_file(&cli.db)?;
+    }
+
+    let mut conn = Connection::open(cli.db.clone())?;
+    let tx = conn.transaction()?;
+    write_meta_schema(&tx)?;
+    tx.commit()?;
+    Ok(conn)
+}
+
+/// Takes the blobs which could not be fully parsed or found (and the entities
+/// marked as generated code) from the cache and summarizes them.
+fn summarize_extraction(cache: &mut ExtractionCtx, dumped: &mut Dumped, metrics: &mut Metrics) {
+    for stats in cache.take_language_stats() {
+        metrics.language(stats);
+    }
+
+    dumped.parse_errors = cache.take_parse_errors();
+    dumped.generated = cache.take_generated();
+
+    if !dumped.generated.is_empty() {
+        log::info!("Found {} entities marked as generated code", dumped.generated.len());
+    }
+
+    let parse_errors = &dumped.parse_errors;
+
+    if !parse_errors.is_empty() {
+        let files = parse_errors.iter().map(|e| &e.filename).collect::<HashSet<_>>();
+        let skipped = parse_errors.iter().filter(|e| e.skipped).count();
+        log::warn!(
+            "Found syntax errors in {} blobs of {} files ({} skipped)",
+            parse_errors.len(),
+            files.len(),
+            skipped
+        );
+
+        let mut worst = parse_errors.iter().collect::<Vec<_>>();
+        worst.sort_by_key(|e| std::cmp::Reverse(e.errors));
+
+        for e in worst.iter().take(10) {
+            log::info!(
+                "  {} ({}): {} errors in {} of {} bytes",
+                e.filename,
+                e.blob,
+                e.errors,
+                e.error_bytes,
+                e.bytes
+            );
+        }
+    }
+
+    // Record the blobs which were missing
+    dumped.diagnostics.extend(cache.take_diagnostics());
+
+    if !dumped.diagnostics.is_empty() {
+        log::warn!("Recorded {} diagnostics (see the diagnostics table)", dumped.diagnostics.len());
+    }
+}
+
+/// Writes whatever was extracted as JSON Lines or to the database.
+fn write_dump(
+    cli: &CliDumpCommand,
+    mut dumped: Dumped,
+    vcs: &dyn Vcs,
+    started_at: OffsetDateTime,
+    mut metrics: Metrics,
+) -> anyhow::Result<()> {
+    let (changes, presences) = (&dumped.changes, &dumped.presences);
+    let stop = dumped.stop.clone();
+
+    if let Some(Stop { reason, commit: Some(commit) }) = &stop {
+        log::warn!(
+            "Stopped at {} ({}). Use --resume to extract the remaining commits.",
+            commit.sha1,
+            reason
+        );
+    }
+
+    if get_dump_format(cli) == DumpFormat::Jsonl {
+        let start = Instant::now();
+        let lines = LineConvention::new(cli.lines.line_base, cli.lines.end);
+
+        if cli.db.as_os_str() == "-" {
+            let mut w = BufWriter::new(std::io::stdout().lock());
+            write_jsonl(&mut w, changes, presences, lines)?;
+        } else {
+            write_jsonl(&mut BufWriter::new(File::create(&cli.db)?), changes, presences, lines)?;
+        }
+
+        metrics.phase("jsonl", start, changes.len() + presences.len(), "records");
+        return Ok(());
+    }
+
+    // Insert the rest into the virtual database
+    let start = Instant::now();
+    insert_extracted(&mut dumped)?;
+    let mut db = dumped.db;
+
+    for (commit, info) in &dumped.commit_infos {
+        insert_commit_coverage(&mut db, commit, *info, &dumped.coverage)?;
+    }
+
+    for r#ref in &dumped.lead_refs {
+        insert_ref(&mut db, r#ref)?;
+    }
+
+    for tag in &dumped.tags {
+        insert_tag(&mut db, tag)?;
+    }
+
+    for update in &dumped.ref_updates {
+        insert_ref_update(&mut db, update)?;
+    }
+
+    for member in &dumped.ref_members {
+        insert_ref_member(&mut db, member)?;
+    }
+
+    for duplicate in &dumped.duplicates {
+        insert_duplicate(&mut db, duplicate)?;
+    }
+
+    for revert in &dumped.reverts {
+        insert_revert(&mut db, revert)?;
+    }
+
+    for update in &dumped.submodule_updates {
+        insert_submodule_update(&mut db, update)?;
+    }
+
+    for parse_error in &dumped.parse_errors {
+        insert_parse_error(&mut db, parse_error)?;
+    }
+
+    for generated in &dumped.generated {
+        insert_generated_entity(&mut db, generated)?;
+    }
+
+    for diagnostic in &dumped.diagnostics {
+        insert_diagnostic(&mut db, diagnostic)?;
+    }
+
+    metrics.phase("populate", start, db.change_vt.len() + db.presence_vt.len(), "rows");
+
+    // Write virtual database to real (on disk) database, which already exists
+    // if rows were written to it early
+    let start = Instant::now();
+    let mut conn = match dumped.conn {
+        Some(conn) => conn,
+        None => create_database(cli)?,
+    };
+    conn.pragma_update(None, "foreign_keys", true)?;
+
+    if cli.txn_size.is_some() {
+        db.write(&conn)?;
+    } else {
+        let tx = conn.transaction()?;
+        db.write(&tx)?;
+        tx.commit()?;
+    }
+
+    metrics.phase("write", start, 1, "database");
+
+    // Derive tables which are expensive to compute with SQL alone
+    let start = Instant::now();
+    let tx = conn.transaction()?;
+    write_lifespans(&tx)?;
+    write_entity_metrics(&tx)?;
+    write_commit_metrics(&tx)?;
+    tx.commit()?;
+    metrics.phase("derive", start, 3, "tables");
+
+    let sampling = get_sampling(cli);
+    write_run(&mut conn, "dump", started_at, Some(vcs), sampling, stop.as_ref(), &metrics)?;
+
+    if cli.run_metrics {
+        write_run_metrics(&mut conn, &metrics)?;
+    }
+
+    Ok(())
+}
+
+/// Records how and when the database was produced in the runs table, including
+/// how long each phase of the command took and what was parsed of each
+/// language.
+fn write_run(
+    conn: &mut Connection,
+    command: &str,
+    started_at: OffsetDateTime,
+    vcs: Option<&dyn Vcs>,
+    sampling: Option<String>,
+    stop: Option<&Stop>,
+    metrics: &Metrics,
+) -> anyhow::Result<()> {
+    let args = std::env::args().collect::<Vec<_>>();
+    let phases = metrics
+        .phases()
+        .iter()
+        .map(|(name, duration)| json!({ "name": name, "ms": duration.as_millis() as u64 }))
+        .collect::<Vec<_>>();
+    let languages = metrics
+        .languages()
+        .iter()
+        .map(|l| {
+            json!({
+                "language": l.language,
+                "files": l.files,
+                "entities": l.entities,
+                "parse_errors": l.parse_errors,
+                "ms": l.duration.as_millis() as u64,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let repo_path = vcs.map(|v| v.root().to_string_lossy().to_string());
+    let repo_url = vcs.and_then(|v| v.remote_url()).map(|url| strip_credentials(&url));
+    let head_sha1 = vcs.and_then(|v| v.head()).map(|oid| oid.to_string());
+
+    let mut vt = RunVirtualTable::new();
+    let key = RunKey::new(started_at.unix_timestamp());
+    let extra = RunExtraBuilder::default()
+        .command(command.to_string())
+        .version(env!("CARGO_PKG_VERSION").to_string())
+        .args(serde_json::to_string(&args)?)
+        .repo_path(repo_path)
+        .repo_url(repo_url)
+        .head_sha1(head_sha1)
+        .finished_at(OffsetDateTime::now_utc().unix_timestamp())
+        .phases(serde_json::to_string(&phases)?)
+        .languages(serde_json::to_string(&languages)?)
+        .sampling(sampling)
+        .stopped_at(stop.and_then(|s| s.commit.as_ref()).map(|c| c.sha1.to_string()))
+        .stop_reason(stop.map(|s| s.reason.to_string()))
+        .build()?;
+    vt.insert(key, extra);
+
+    let tx = conn.transaction()?;
+    vt.write::<RunWriter>(&tx)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Stores every timing of the run in the run_metrics table.
+fn write_run_metrics(conn: &mut Connection, metrics: &Metrics) -> anyhow::Result<()> {
+    let mut vt = RunMetricVirtualTable::new();
+
+    for m in metrics.metrics() {
+        let key = RunMetricKey::new(m.scope, m.phase, m.subject.clone());
+        vt.insert(key, RunMetricExtra::new(m.duration.as_millis() as usize, m.count));
+    }
+
+    let tx = conn.transaction()?;
+    vt.write::<RunMetricWriter>(&tx)?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn add_deps(args: &AddDeps) -> anyhow::Result<()> {
+    log::info!("Hello, world!");
+    
+    let start = Instant::now();
+    let deps = load_dep_file(&args.dep_file)?;
+    log::info!("Loaded dep file in {}ms", start.elapsed().as_millis());
+
+    let start = Instant::now();
+    let mut conn = Connection::open(args.db.clone())?;
+    let locs = load_locs(&conn, &args.commit)?;
+    log::info!("Loaded entities from database in {}ms", start.elapsed().as_millis());
+
+    let start = Instant::now();
+    let mut vt = DepVirtualTable::new();
+    let commit_id = get_commit_id(&conn, &args.commit)?;
+    insert_deps(&mut vt, &locs, &deps, commit_id)?;
+    log::info!("Wrote to virtual database in {}ms", start.elapsed().as_millis());
+
+    let start = Instant::now();
+    let tx = conn.transaction()?;
+    vt.write::<DepWriter>(&tx)?;
+    tx.commit()?;
+    log::info!("Wrote virtual table to disk in {}ms", start.elapsed().as_millis());
+
+    Ok(())
+}
+
+fn entity_log(args: &CliLogCommand) -> anyhow::Result<()> {
+    let conn = Connection::open(args.db.clone())?;
+    let paths = load_entity_paths(&conn)?;
+    let entity_id = resolve_entity(&paths, &args.entity)?;
+    let changes = load_entity_log(&conn, entity_id)?;
+
+    for change in changes.iter().take(args.limit.unwrap_or(usize::MAX)) {
+        println!(
+            "{}\t{}\t{}\t+{}\t-{}",
+            change.sha1,
+            to_date_string(change.commit_time),
+            change.kind,
+            change.adds,
+            change.dels
+        );
+    }
+
+    Ok(())
+}
+
+fn get_changed_ids(
+    args: &ChangedArgs,
+    conn: &Connection,
+    paths: &HashMap<Id, String>,
+) -> anyhow::Result<HashSet<Id>> {
+    let mut changed = HashSet::new();
+
+    for entity in &args.entities {
+        changed.insert(resolve_entity(paths, entity)?);
+    }
+
+    let uncommitted = match (args.staged, args.worktree) {
+        (true, _) => Some(Uncommitted::Index),
+        (_, true) => Some(Uncommitted::Workdir),
+        _ => None,
+    };
+
+    if let Some(uncommitted) = uncommitted {
+        let repo = open_repo(args.repo.as_deref())?;
+        let keys = load_entity_keys(conn)?;
+
+        // Files of other languages are only found if the database was dumped
+        // with a coarser granularity, so every file is diffed
+        let (diffed_files, blobs) = diff_uncommitted(&repo, uncommitted, None)?;
+        let vcs = WorkdirVcs::new(repo, blobs);
+        let mut ctx = ExtractionCtx::new(&vcs, get_java_parser()?, ".java");
+        ctx.set_granularity(Granularity::Auto);
+
+        for diffed_file in diffed_files {
+            for change in get_changes(&mut ctx, &diffed_file)? {
+                let key = change.entity.to_vec();
+                let key =
+                    key.into_iter().map(|(n, k, d)| (n, k.to_string(), d)).collect::<Vec<_>>();
+
+                match keys.get(&key) {
+                    Some(&id) => {
+                        changed.insert(id);
+                    }
+                    None => log::info!(
+                        "Skipping {} which is not in the database",
+                        key.iter().map(|(n, _, _)| n.as_str()).collect::<Vec<_>>().join("::")
+                    ),
+                }
+            }
+        }
+    }
+
+    Ok(changed)
+}
+
+fn get_suggestions(
+    cmd: &mut App,
+    conn: &Connection,
+    coupling: &CouplingArgs,
+    changed: &HashSet<Id>,
+    kind: Option<&String>,
+    min_support: f64,
+    min_confidence: f64,
+) -> anyhow::Result<Vec<Suggestion>> {
+    let start = Instant::now();
+    let change_sets = get_change_sets(cmd, coupling, conn)?;
+    let coupling = Coupling::new(&change_sets);
+    let parents = load_parents(conn)?;
+    let mut suggestions =
+        find_suggestions(&coupling, &parents, changed, min_support, min_confidence);
+
+    if let Some(kind) = kind {
+        let kinds = load_kinds(conn)?;
+        suggestions.retain(|s| kinds.get(&s.entity_id) == Some(kind));
+    }
+
+    log::info!("Found {} suggestions in {}ms", suggestions.len(), start.elapsed().as_millis());
+    Ok(suggestions)
+}
+
+fn suggest(args: &CliSuggestCommand) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+    let conn = Connection::open(args.db.clone())?;
+    let paths = load_entity_paths(&conn)?;
+    let changed = get_changed_ids(&args.changed, &conn, &paths)?;
+
+    if changed.is_empty() {
+        return Ok(());
+    }
+
+    let suggestions = get_suggestions(
+        &mut cmd,
+        &conn,
+        &args.coupling,
+        &changed,
+        args.kind.as_ref(),
+        args.min_support,
+        args.min_confidence,
+    )?;
+
+    for suggestion in suggestions.iter().take(args.limit) {
+        println!(
+            "{:.2}\t{:.2}\t{}\t{}",
+            suggestion.confidence,
+            suggestion.support,
+            paths[&suggestion.entity_id],
+            paths[&suggestion.because_id]
+        );
+    }
+
+    Ok(())
+}
+
+fn impact(args: &CliImpactCommand) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+    let start = Instant::now();
+    let conn = Connection::open(args.db.clone())?;
+    let paths = load_entity_paths(&conn)?;
+    let changed = get_changed_ids(&args.changed, &conn, &paths)?;
+
+    if changed.is_empty() {
+        return Ok(());
+    }
+
+    let parents = load_parents(&conn)?;
+    let test_links = if has_table(&conn, "test_links")? {
+        load_test_links(&conn)?
+    } else {
+        log::warn!("No tests are listed without test links (see analyze test-links)");
+        Vec::new()
+    };
+    let dependents = match &args.commit {
+        Some(sha1) => {
+            Some(DepGraph::load(&conn, get_commit_id(&conn, sha1)?, &parents)?.reversed())
+        }
+        None => None,
+    };
+
+    let change_sets = get_change_sets(&mut cmd, &args.coupling, &conn)?;
+    let coupling = Coupling::new(&change_sets);
+    let mut impacts = find_impact(
+        &coupling,
+        &parents,
+        dependents.as_ref(),
+        &test_links,
+        &changed,
+        args.transitive,
+    );
+    impacts.retain(|i| i.confidence >= args.min_confidence);
+
+    if let Some(kind) = &args.kind {
+        let kinds = load_kinds(&conn)?;
+        impacts.retain(|i| kinds.get(&i.entity_id) == Some(kind));
+    }
+
+    log::info!("Found {} impacted entities in {}ms", impacts.len(), start.elapsed().as_millis());
+
+    for impact in impacts.iter().take(args.limit) {
+        println!(
+            "{:.2}\t{:.2}\t{}\t{}\t{}",
+            impact.confidence,
+            impact.support,
+            if impact.is_test { "test" } else { "dependent" },
+            paths[&impact.entity_id],
+            paths[&impact.because_id]
+        );
+    }
+
+    Ok(())
+}
+
+fn check(args: &CliCheckCommand) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+    let conn = Connection::open(args.db.clone())?;
+    let paths = load_entity_paths(&conn)?;
+    let changed = get_changed_ids(&args.changed, &conn, &paths)?;
+
+    let suggestions = if changed.is_empty() {
+        Vec::new()
+    } else {
+        get_suggestions(
+            &mut cmd,
+            &conn,
+            &args.coupling,
+            &changed,
+            Some(&args.kind),
+            args.min_support,
+            args.min_confidence,
+        )?
+    };
+
+    let file_ids = to_file_ids(&load_parents(&conn)?, &load_kinds(&conn)?);
+    let locations = match load_latest_presence_commit(&conn)? {
+        Some(commit_id) => load_locations(&conn, commit_id)?,
+        None => HashMap::new(),
+    };
+    let findings = suggestions
+        .iter()
+        .map(|s| to_finding(&paths, &file_ids, &locations, s.entity_id, to_alert(&paths, s)))
+        .collect::<Vec<_>>();
+
+    let mut out = open_output(&args.output)?;
+
+    match args.format {
+        ReportFormat::Text => {
+            for finding in &findings {
+                writeln!(out, "{}: {}", finding.path, finding.message)?;
+            }
+        }
+        ReportFormat::Sarif => write_sarif(&mut out, MISSING_COCHANGE, &findings)?,
+    }
+
+    out.flush()?;
+
+    if !findings.is_empty() {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Opens the file to print results to (or stdout if there is none).
+fn open_output(output: &Option<PathBuf>) -> anyhow::Result<Box<dyn Write>> {
+    Ok(match output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(std::io::stdout()),
+    })
+}
+
+/// Reports a problem with an entity in the file it belongs to (or where it is
+/// if it is not in a file, e.g. a directory), at its lines if they are known.
+fn to_finding(
+    paths: &HashMap<Id, String>,
+    file_ids: &HashMap<Id, Id>,
+    locations: &HashMap<Id, (usize, usize)>,
+    entity_id: Id,
+    message: String,
+) -> Finding {
+    let file_id = file_ids.get(&entity_id).copied().unwrap_or(entity_id);
+    Finding::new(paths[&file_id].clone(), message, locations.get(&entity_id).copied())
+}
+
+/// Describes an entity which was not changed although it usually changes
+/// together with one which was.
+fn to_alert(paths: &HashMap<Id, String>, suggestion: &Suggestion) -> String {
+    format!(
+        "{} usually changes together with {} (confidence {:.2}, support {:.2}) but was not \
+         changed",
+        paths[&suggestion.entity_id],
+        paths[&suggestion.because_id],
+        suggestion.confidence,
+        suggestion.support
+    )
+}
+
+fn write_notes(args: &CliNotesCommand) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+    let start = Instant::now();
+    let conn = Connection::open(args.db.clone())?;
+    let repo = open_repo(args.repo.as_deref())?;
+    let paths = load_entity_paths(&conn)?;
+    let parents = load_parents(&conn)?;
+    let kinds = load_kinds(&conn)?;
+    let shas = load_commit_shas(&conn)?;
+
+    // Pairs below the minimum support are never alerted on, so drop them once
+    // rather than for every commit
+    let mut coupling = Coupling::new(&get_change_sets(&mut cmd, &args.coupling, &conn)?);
+    coupling.pairs.retain(|_, support| *support >= args.min_support);
+
+    let mut change_sets = load_change_sets(&conn)?;
+    change_sets.sort_by_key(|c| std::cmp::Reverse((c.commit_time, c.commit_id)));
+    change_sets.truncate(args.max_count.unwrap_or(usize::MAX));
+
+    let sig = match repo.signature() {
+        Ok(sig) => sig,
+        Err(_) => git2::Signature::now("cochange-tool", "cochange-tool@localhost")?,
+    };
+    let mut num_notes = 0;
+
+    for change_set in &change_sets {
+        let oid = Oid::from_str(&shas[&change_set.commit_id])?;
+
+        if repo.find_commit(oid).is_err() {
+            log::debug!("Skipped commit {} (not in the repository)", oid);
+            continue;
+        }
+
+        let changed = change_set.entity_ids().collect::<HashSet<_>>();
+        let mut alerts =
+            find_suggestions(&coupling, &parents, &changed, args.min_support, args.min_confidence);
+        alerts.retain(|s| kinds.get(&s.entity_id) == Some(&args.kind));
+
+        let mut entities = change_set
+            .entities
+            .iter()
+            .filter(|(id, _)| kinds.get(id) == Some(&args.kind))
+            .map(|(id, churn)| (&paths[id], churn))
+            .collect::<Vec<_>>();
+        entities.sort();
+
+        let mut note = format!(
+            "cochange: {} entities changed ({} of kind {}), {} coupling alerts\n",
+            change_set.entities.len(),
+            entities.len(),
+            args.kind,
+            alerts.len()
+        );
+
+        for (path, churn) in entities {
+            note.push_str(&format!("\n    changed {} ({} lines)", path, churn));
+        }
+
+        for alert in &alerts {
+            note.push_str(&format!("\n    alert: {}", to_alert(&paths, alert)));
+        }
+
+        note.push('\n');
+        repo.note(&sig, &sig, Some(&args.notes_ref), oid, &note, true)?;
+        num_notes += 1;
+    }
+
+    log::info!(
+        "Wrote {} notes to {} in {}ms",
+        num_notes,
+        args.notes_ref,
+        start.elapsed().as_millis()
+    );
+    Ok(())
+}
+
+fn hidden_deps(args: &HiddenDeps) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+    let start = Instant::now();
+    let mut conn = Connection::open(args.db.clone())?;
+    let commit_id = get_commit_id(&conn, &args.commit)?;
+    let change_sets = get_change_sets(&mut cmd, &args.coupling, &conn)?;
+    let hidden_deps = find_hidden_deps(
+        &conn,
+        change_sets,
+        commit_id,
+        args.min_support,
+        args.min_confidence,
+        args.transitive,
+    )?;
+    log::info!(
+        "Found {} hidden dependencies in {}ms",
+        hidden_deps.len(),
+        start.elapsed().as_millis()
+    );
+
+    let paths = load_entity_paths(&conn)?;
+    let mut out = open_output(&args.output)?;
+
+    match args.format {
+        ReportFormat::Text => {
+            for hidden_dep in hidden_deps.iter().take(args.limit) {
+                writeln!(
+                    out,
+                    "{:.2}\t{:.2}\t{}\t{}",
+                    hidden_dep.support,
+                    hidden_dep.confidence,
+                    paths[&hidden_dep.entity1_id],
+                    paths[&hidden_dep.entity2_id]
+                )?;
+            }
+        }
+        ReportFormat::Sarif => {
+            let file_ids = to_file_ids(&load_parents(&conn)?, &load_kinds(&conn)?);
+            let locations = load_locations(&conn, commit_id)?;
+            let findings = hidden_deps
+                .iter()
+                .take(args.limit)
+                .map(|h| {
+                    let message = format!(
+                        "{} usually changes together with {} (support {:.2}, confidence {:.2}) \
+                         but neither depends on the other",
+                        paths[&h.entity1_id], paths[&h.entity2_id], h.support, h.confidence
+                    );
+                    to_finding(&paths, &file_ids, &locations, h.entity1_id, message)
+                })
+                .collect::<Vec<_>>();
+            write_sarif(&mut out, HIDDEN_DEPENDENCY, &findings)?;
+        }
+    }
+
+    out.flush()?;
+
+    let start = Instant::now();
+    let mut vt = HiddenDepVirtualTable::new();
+
+    for hidden_dep in &hidden_deps {
+        let key = HiddenDepKey::new(hidden_dep.entity1_id, hidden_dep.entity2_id);
+        let extra = HiddenDepExtra::new(hidden_dep.support, hidden_dep.confidence);
+        vt.insert(key, extra);
+    }
+
+    let tx = conn.transaction()?;
+    drop_table(&tx, "hidden_deps")?;
+    vt.write::<HiddenDepWriter>(&tx)?;
+    tx.commit()?;
+    log::info!("Wrote hidden dependencies to disk in {}ms", start.elapsed().as_millis());
+
+    Ok(())
+}
+
+fn dep_cochange(args: &DepCochange) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+    let start = Instant::now();
+    let mut conn = Connection::open(args.db.clone())?;
+    let commit_id = get_commit_id(&conn, &args.commit)?;
+    let change_sets = get_change_sets(&mut cmd, &args.coupling, &conn)?;
+    let dep_cochanges =
+        find_dep_cochanges(&conn, change_sets, commit_id, args.min_fan_in, args.min_propagation)?;
+    log::info!(
+        "Analyzed {} dependencies in {}ms",
+        dep_cochanges.len(),
+        start.elapsed().as_millis()
+    );
+
+    let paths = load_entity_paths(&conn)?;
+    let mut fragile = dep_cochanges
+        .iter()
+        .filter(|d| d.class == Some(InterfaceClass::Fragile))
+        .map(|d| (d.tgt_fan_in, d.tgt_id))
+        .collect::<Vec<_>>();
+    fragile.sort();
+    fragile.dedup();
+
+    for (fan_in, tgt_id) in fragile.into_iter().rev() {
+        println!("{}\t{}", fan_in, paths[&tgt_id]);
+    }
+
+    let start = Instant::now();
+    let mut vt = DepCochangeVirtualTable::new();
+
+    for d in &dep_cochanges {
+        let key = DepCochangeKey::new(commit_id, d.src_id, d.tgt_id);
+        let extra = DepCochangeExtra::new(
+            d.src_changes,
+            d.tgt_changes,
+            d.support,
+            d.propagation(),
+            d.tgt_fan_in,
+            d.class.map(|c| c.to_str()),
+        );
+        vt.insert(key, extra);
+    }
+
+    let tx = conn.transaction()?;
+    drop_table(&tx, "dep_cochange")?;
+    vt.write::<DepCochangeWriter>(&tx)?;
+    tx.commit()?;
+    log::info!("Wrote dependency co-changes to disk in {}ms", start.elapsed().as_millis());
+
+    Ok(())
+}
+
+fn ownership(args: &Ownership) -> anyhow::Result<()> {
+    let start = Instant::now();
+    let mut conn = Connection::open(args.db.clone())?;
+    let contributions = load_contributions(&conn)?;
+    let files = load_files(&conn)?;
+    let bus_factors = find_bus_factors(&contributions, &files, args.coverage);
+    log::info!("Computed ownership in {}ms", start.elapsed().as_millis());
+
+    let mut packages = bus_factors.iter().filter(|b| b.kind == "package").collect::<Vec<_>>();
+    packages.sort_by_key(|b| (b.bus_factor, &b.name));
+
+    for package in packages.iter().take(args.limit) {
+        let (mail, share) = &package.shares[0];
+        println!("{}\t{}\t{:.2}\t{}", package.bus_factor, mail, share, package.name);
+    }
+
+    let start = Instant::now();
+    let mut ownership_vt = OwnershipVirtualTable::new();
+    let mut entity_ids = contributions.lines.keys().copied().collect::<Vec<_>>();
+    entity_ids.sort();
+
+    for entity_id in entity_ids {
+        let lines = &contributions.lines[&entity_id];
+
+        for (mail, share) in to_shares(lines) {
+            let name = contributions.names[&mail].clone();
+            let extra = OwnershipExtra::new(name, lines[&mail], share);
+            ownership_vt.insert(OwnershipKey::new(entity_id, mail), extra);
+        }
+    }
+
+    let mut bus_factor_vt = BusFactorVirtualTable::new();
+
+    for b in &bus_factors {
+        let (mail, share) = b.shares[0].clone();
+        let key = BusFactorKey::new(b.kind.to_string(), b.name.clone());
+        let extra = BusFactorExtra::new(b.entity_id, mail, share, b.shares.len(), b.bus_factor);
+        bus_factor_vt.insert(key, extra);
+    }
+
+    let tx = conn.transaction()?;
+    drop_table(&tx, "ownership")?;
+    drop_table(&tx, "bus_factors")?;
+    ownership_vt.write::<OwnershipWriter>(&tx)?;
+    bus_factor_vt.write::<BusFactorWriter>(&tx)?;
+    tx.commit()?;
+    log::info!("Wrote ownership to disk in {}ms", start.elapsed().as_millis());
+
+    if let Some(dir) = &args.csv {
+        export_ownership_csv(&conn, dir)?;
+    }
+
+    Ok(())
+}
+
+fn knowledge_loss(args: &KnowledgeLossCommand) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+    let start = Instant::now();
+    let mut conn = Connection::open(args.db.clone())?;
+    let mut ownership = load_ownership(&conn)?;
+    let mut departed = HashSet::new();
+
+    // Each author is given by either their email or their name
+    for author in &args.author {
+        let mails = ownership
+            .values()
+            .flatten()
+            .filter(|(name, mail, _)| mail.eq_ignore_ascii_case(author) || name == author)
+            .map(|(_, mail, _)| mail.clone())
+            .collect::<Vec<_>>();
+
+        if mails.is_empty() {
+            let msg = format!("No author with the email or name '{}' owns any entity", author);
+            cmd.error(clap::ErrorKind::ValueValidation, msg).exit();
+        }
+
+        departed.extend(mails);
+    }
+
+    if let Some(kind) = &args.kind {
+        let kinds = load_kinds(&conn)?;
+        ownership.retain(|id, _| kinds.get(id) == Some(kind));
+    }
+
+    let losses = find_knowledge_loss(&ownership, &departed, args.min_surviving);
+    log::info!("Found {} entities at risk in {}ms", losses.len(), start.elapsed().as_millis());
+
+    let paths = load_entity_paths(&conn)?;
+
+    for loss in losses.iter().take(args.limit) {
+        println!("{:.2}\t{:.2}\t{}", loss.surviving, loss.lost, paths[&loss.entity_id]);
+    }
+
+    let start = Instant::now();
+    let mut vt = KnowledgeLossVirtualTable::new();
+
+    for loss in &losses {
+        let extra = KnowledgeLossExtra::new(loss.lost, loss.surviving);
+        vt.insert(KnowledgeLossKey::new(loss.entity_id), extra);
+    }
+
+    let tx = conn.transaction()?;
+    drop_table(&tx, "knowledge_loss")?;
+    vt.write::<KnowledgeLossWriter>(&tx)?;
+    tx.commit()?;
+    log::info!("Wrote knowledge loss to disk in {}ms", start.elapsed().as_millis());
+
+    Ok(())
+}
+
+fn export_ownership_csv(conn: &Connection, dir: &Path) -> anyhow::Result<()> {
+    let paths = load_entity_paths(conn)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT entity_id, author_name, author_mail, lines, share FROM ownership ORDER BY id",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(vec![
+                paths[&row.get::<_, Id>(0)?].clone(),
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, usize>(3)?.to_string(),
+                row.get::<_, f64>(4)?.to_string(),
+            ])
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let header = ["entity", "author_name", "author_mail", "lines", "share"];
+    write_csv(dir.join("ownership.csv"), &header, rows)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT kind, name, dominant_mail, dominant_share, num_authors, bus_factor
+        FROM bus_factors
+        ORDER BY id",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(vec![
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, f64>(3)?.to_string(),
+                row.get::<_, usize>(4)?.to_string(),
+                row.get::<_, usize>(5)?.to_string(),
+            ])
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let header = ["kind", "name", "dominant_mail", "dominant_share", "num_authors", "bus_factor"];
+    write_csv(dir.join("bus_factors.csv"), &header, rows)?;
+
+    log::info!("Exported ownership to {}", dir.to_string_lossy());
+    Ok(())
+}
+
+fn dev_network(args: &DevNetwork) -> anyhow::Result<()> {
+    let start = Instant::now();
+    let conn = Connection::open(args.db.clone())?;
+    let touches = load_touches(&conn, args.kind.as_deref())?;
+    let mut graph = Graph::default();
+    let mut mails = touches.keys().collect::<Vec<_>>();
+    mails.sort();
+
+    for mail in &mails {
+        graph.add_node(format!("dev:{}", mail), *mail, "developer");
+    }
+
+    if args.bipartite {
+        let paths = load_entity_paths(&conn)?;
+        let mut entity_ids = touches.values().flat_map(|t| t.keys()).collect::<Vec<_>>();
+        entity_ids.sort();
+        entity_ids.dedup();
+
+        for entity_id in entity_ids {
+            graph.add_node(format!("entity:{}", entity_id), &paths[entity_id], "entity");
+        }
+
+        for mail in &mails {
+            let mut entities = touches[*mail].iter().collect::<Vec<_>>();
+            entities.sort();
+
+            for (entity_id, &n) in entities {
+                if n >= args.min_touches {
+                    let (source, target) =
+                        (format!("dev:{}", mail), format!("entity:{}", entity_id));
+                    graph.add_edge(source, target, "touches", n as f64);
+                }
+            }
+        }
+    } else {
+        let mut pairs = find_dev_pairs(&touches, args.min_touches).into_iter().collect::<Vec<_>>();
+        pairs.sort();
+
+        for ((a, b), n) in pairs {
+            graph.add_edge(format!("dev:{}", a), format!("dev:{}", b), "shares", n as f64);
+        }
+    }
+
+    log::info!(
+        "Built developer network ({} nodes, {} edges) in {}ms",
+        graph.nodes.len(),
+        graph.edges.len(),
+        start.elapsed().as_millis()
+    );
+
+    graph.write(&args.output, args.format)?;
+    log::info!("Wrote developer network to {}", args.output.to_string_lossy());
+    Ok(())
+}
+
+fn coupling(args: &CouplingCommand) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+    let start = Instant::now();
+    let mut conn = Connection::open(args.db.clone())?;
+    let change_sets = get_change_sets(&mut cmd, &args.coupling, &conn)?;
+    let windows = get_windows(&mut cmd, &args.window, &conn, &change_sets)?;
+    let parents = load_parents(&conn)?;
+    let paths = load_entity_paths(&conn)?;
+    let mut vt = CouplingVirtualTable::new();
+
+    for (label, window) in &windows {
+        let coupling = Coupling::new(&in_window(&change_sets, window));
+        let pairs = find_coupled_pairs(&coupling, &parents, args.min_support, args.min_confidence);
+
+        if windows.len() > 1 {
+            println!("# {}", label);
+        }
+
+        for pair in pairs.iter().take(args.limit) {
+            println!(
+                "{:.2}\t{:.2}\t{}\t{}",
+                pair.support, pair.confidence, paths[&pair.entity1_id], paths[&pair.entity2_id]
+            );
+        }
+
+        for pair in &pairs {
+            let key = CouplingKey::new(window.start, pair.entity1_id, pair.entity2_id);
+            let extra = CouplingExtra::new(window.end, pair.support, pair.confidence);
+            vt.insert(key, extra);
+        }
+    }
+
+    log::info!(
+        "Found coupled pairs in {} windows in {}ms",
+        windows.len(),
+        start.elapsed().as_millis()
+    );
+
+    let start = Instant::now();
+    let tx = conn.transaction()?;
+    drop_table(&tx, "coupling")?;
+    vt.write::<CouplingWriter>(&tx)?;
+    tx.commit()?;
+    log::info!("Wrote coupled pairs to disk in {}ms", start.elapsed().as_millis());
+
+    Ok(())
+}
+
+fn hotspots(args: &Hotspots) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+    let start = Instant::now();
+    let mut conn = Connection::open(args.db.clone())?;
+    let mut change_sets = get_change_sets(&mut cmd, &args.coupling, &conn)?;
+
+    if let Some(kind) = &args.kind {
+        let kinds = load_kinds(&conn)?;
+
+        for change_set in &mut change_sets {
+            change_set.entities.retain(|(id, _)| kinds.get(id) == Some(kind));
+        }
+
+        change_sets.retain(|c| !c.entities.is_empty());
+    }
+
+    let windows = get_windows(&mut cmd, &args.window, &conn, &change_sets)?;
+    let measures = load_measures(&conn)?;
+    let paths = load_entity_paths(&conn)?;
+    let mut out = open_output(&args.output)?;
+    let mut findings = Vec::new();
+    let mut vt = HotspotVirtualTable::new();
+
+    for (label, window) in &windows {
+        let hotspots = find_hotspots(&in_window(&change_sets, window), &measures, args.by);
+
+        match args.format {
+            ReportFormat::Text => {
+                if windows.len() > 1 {
+                    writeln!(out, "# {}", label)?;
+                }
+
+                for hotspot in hotspots.iter().take(args.limit) {
+                    writeln!(
+                        out,
+                        "{:.2}\t{:.2}\t{}\t{}\t{}\t{}",
+                        hotspot.score,
+                        hotspot.changes,
+                        hotspot.churn,
+                        hotspot.size,
+                        hotspot.complexity,
+                        paths[&hotspot.entity_id]
+                    )?;
+                }
+            }
+            // Only the last (i.e. most recent) window is kept
+            ReportFormat::Sarif => {
+                findings = hotspots
+                    .iter()
+                    .take(args.limit)
+                    .map(|h| {
+                        let message = format!(
+                            "{} is a hotspot (score {:.2}, changes {:.2}, churn {})",
+                            paths[&h.entity_id], h.score, h.changes, h.churn
+                        );
+                        (h.entity_id, message)
+                    })
+                    .collect::<Vec<_>>();
+            }
+        }
+
+        for h in &hotspots {
+            let key = HotspotKey::new(window.start, h.entity_id);
+            let extra =
+                HotspotExtra::new(window.end, h.changes, h.churn, h.size, h.complexity, h.score);
+            vt.insert(key, extra);
+        }
+    }
+
+    log::info!("Found hotspots in {} windows in {}ms", windows.len(), start.elapsed().as_millis());
+
+    if args.format == ReportFormat::Sarif {
+        let file_ids = to_file_ids(&load_parents(&conn)?, &load_kinds(&conn)?);
+        let locations = match load_latest_presence_commit(&conn)? {
+            Some(commit_id) => load_locations(&conn, commit_id)?,
+            None => HashMap::new(),
+        };
+        let findings = findings
+            .into_iter()
+            .map(|(id, message)| to_finding(&paths, &file_ids, &locations, id, message))
+            .collect::<Vec<_>>();
+        write_sarif(&mut out, HOTSPOT, &findings)?;
+    }
+
+    out.flush()?;
+
+    let start = Instant::now();
+    let tx = conn.transaction()?;
+    drop_table(&tx, "hotspots")?;
+    vt.write::<HotspotWriter>(&tx)?;
+    tx.commit()?;
+    log::info!("Wrote hotspots to disk in {}ms", start.elapsed().as_millis());
+
+    Ok(())
+}
+
+fn trend(args: &Trend) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+
+    if args.window.window.is_none() && args.window.releases.is_none() {
+        let msg = "Either --window or --releases is required for a trend";
+        cmd.error(clap::ErrorKind::MissingRequiredArgument, msg).exit();
+    }
+
+    let start = Instant::now();
+    let conn = Connection::open(args.db.clone())?;
+    let change_sets = get_change_sets(&mut cmd, &args.coupling, &conn)?;
+    let windows = get_windows(&mut cmd, &args.window, &conn, &change_sets)?;
+    let parents = load_parents(&conn)?;
+    let measures = load_measures(&conn)?;
+    let mut points = Vec::new();
+
+    for (label, window) in &windows {
+        let change_sets = in_window(&change_sets, window);
+        let p = to_trend_point(
+            &change_sets,
+            &parents,
+            &measures,
+            args.min_support,
+            args.min_confidence,
+        );
+        points.push(vec![
+            ("window", json!(label)),
+            ("start", json!(to_date_string(window.start))),
+            ("end", json!(to_date_string(window.end))),
+            ("commits", json!(p.commits)),
+            ("pairs", json!(p.pairs)),
+            ("total_support", json!(p.total_support)),
+            ("mean_confidence", json!(p.mean_confidence)),
+            ("top_support", json!(p.top_support)),
+            ("top_degree", json!(p.top_degree)),
+            ("top_hotspot", json!(p.top_hotspot)),
+        ]);
+    }
+
+    log::info!("Computed {} points in {}ms", points.len(), start.elapsed().as_millis());
+
+    let mut out = open_output(&args.output)?;
+    write_series(&mut out, args.format, &points)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn compare(args: &Compare) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+    let before = validate_period_input(&mut cmd, &args.before, "--before");
+    let after = validate_period_input(&mut cmd, &args.after, "--after");
+
+    let start = Instant::now();
+    let mut conn = Connection::open(args.db.clone())?;
+    let change_sets = get_change_sets(&mut cmd, &args.coupling, &conn)?;
+    let parents = load_parents(&conn)?;
+    let (min_support, min_confidence) = (args.min_support, args.min_confidence);
+    let (before_metrics, before_pairs) =
+        to_period_metrics(&in_window(&change_sets, &before), &parents, min_support, min_confidence);
+    let (after_metrics, after_pairs) =
+        to_period_metrics(&in_window(&change_sets, &after), &parents, min_support, min_confidence);
+    log::info!("Compared periods in {}ms", start.elapsed().as_millis());
+
+    println!("Strongly coupled pairs before: {}", before_pairs);
+    println!("Strongly coupled pairs after: {}", after_pairs);
+
+    let mut entity_ids = before_metrics.keys().chain(after_metrics.keys()).collect::<Vec<_>>();
+    entity_ids.sort();
+    entity_ids.dedup();
+
+    let empty = PeriodMetrics::default();
+    let mut vt = ComparisonVirtualTable::new();
+    let mut deltas = Vec::new();
+
+    for &entity_id in entity_ids {
+        let b = before_metrics.get(&entity_id).unwrap_or(&empty);
+        let a = after_metrics.get(&entity_id).unwrap_or(&empty);
+        let key = ComparisonKey::new(entity_id);
+        let extra =
+            ComparisonExtra::new(b.changes, a.changes, b.churn, a.churn, b.degree, a.degree);
+        vt.insert(key, extra);
+
+        if a.degree != b.degree {
+            deltas.push((a.degree as i64 - b.degree as i64, b.degree, a.degree, entity_id));
+        }
+    }
+
+    deltas.sort_by_key(|&(delta, _, _, entity_id)| (-delta.abs(), entity_id));
+    let paths = load_entity_paths(&conn)?;
+
+    for (delta, before_degree, after_degree, entity_id) in deltas.into_iter().take(args.limit) {
+        println!("{:+}\t{}\t{}\t{}", delta, before_degree, after_degree, paths[&entity_id]);
+    }
+
+    let start = Instant::now();
+    let tx = conn.transaction()?;
+    drop_table(&tx, "comparison")?;
+    vt.write::<ComparisonWriter>(&tx)?;
+    tx.commit()?;
+    log::info!("Wrote comparison to disk in {}ms", start.elapsed().as_millis());
+
+    Ok(())
+}
+
+fn bulk_commits(args: &BulkCommits) -> anyhow::Result<()> {
+    let start = Instant::now();
+    let mut conn = Connection::open(args.db.clone())?;
+    let thresholds = args.bulk.to_thresholds();
+    let shas = load_commit_shas(&conn)?;
+    let mut vt = BulkCommitVirtualTable::new();
+
+    for shape in load_commit_shapes(&conn)? {
+        if let Some(kind) = classify_commit(&shape, &thresholds) {
+            let (num_files, num_entities) = (shape.files.len(), shape.num_entities);
+            println!(
+                "{}\t{}\t{}\t{}",
+                shas[&shape.commit_id],
+                kind.to_str(),
+                num_files,
+                num_entities
+            );
+
+            let key = BulkCommitKey::new(shape.commit_id);
+            let extra = BulkCommitExtra::new(kind.to_str(), num_files, num_entities);
+            vt.insert(key, extra);
+        }
+    }
+
+    log::info!("Classified commits in {}ms", start.elapsed().as_millis());
+
+    let start = Instant::now();
+    let tx = conn.transaction()?;
+    drop_table(&tx, "bulk_commits")?;
+    vt.write::<BulkCommitWriter>(&tx)?;
+    tx.commit()?;
+    log::info!("Wrote bulk commits to disk in {}ms", start.elapsed().as_millis());
+
+    Ok(())
+}
+
+fn test_links(args: &TestLinks) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+    let start = Instant::now();
+    let mut conn = Connection::open(args.db.clone())?;
+    let parents = load_parents(&conn)?;
+    let file_ids = to_file_ids(&parents, &load_kinds(&conn)?);
+    let files = load_files(&conn)?;
+    let mut test_files = load_matching_files(&conn, &args.test_pattern)?;
+
+    if let Some(sha1) = &args.commit {
+        let repo = open_repo(args.repo.as_deref())?;
+        let methods = load_present_methods(&conn, get_commit_id(&conn, sha1)?)?
+            .into_iter()
+            .filter_map(|(id, name, start_row)| {
+                file_ids.get(&id).map(|file_id| (files[file_id].clone(), name, start_row))
+            })
+            .collect::<Vec<_>>();
+        let annotated = find_annotated_test_files(&repo, Oid::from_str(sha1)?, &methods)?;
+        test_files.extend(files.iter().filter(|(_, f)| annotated.contains(*f)).map(|(&id, _)| id));
+    }
+
+    log::info!("Found {} test files", test_files.len());
+
+    let change_sets = get_change_sets(&mut cmd, &args.coupling, &conn)?;
+    let coupling = Coupling::new(&change_sets);
+    let links = find_test_links(
+        &coupling,
+        &files,
+        &file_ids,
+        &test_files,
+        args.min_support,
+        args.min_confidence,
+    );
+    log::info!("Found {} test links in {}ms", links.len(), start.elapsed().as_millis());
+
+    let paths = load_entity_paths(&conn)?;
+
+    for link in links.iter().take(args.limit) {
+        println!(
+            "{:.2}\t{:.2}\t{}\t{}",
+            link.confidence, link.support, paths[&link.test_id], paths[&link.entity_id]
+        );
+    }
+
+    let start = Instant::now();
+    let mut vt = TestLinkVirtualTable::new();
+
+    for link in &links {
+        let key = TestLinkKey::new(link.test_id, link.entity_id);
+        let extra = TestLinkExtra::new(link.by_name, link.support, link.confidence);
+        vt.insert(key, extra);
+    }
+
+    let tx = conn.transaction()?;
+    drop_table(&tx, "test_links")?;
+    vt.write::<TestLinkWriter>(&tx)?;
+    tx.commit()?;
+    log::info!("Wrote test links to disk in {}ms", start.elapsed().as_millis());
+
+    Ok(())
+}
+
+fn clones(args: &Clones) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+    let start = Instant::now();
+    let mut conn = Connection::open(args.db.clone())?;
+    let hashes = load_source_hashes(&conn, get_commit_id(&conn, &args.commit)?)?;
+    let parents = load_parents(&conn)?;
+    let file_ids = to_file_ids(&parents, &load_kinds(&conn)?);
+    let coupling = Coupling::new(&get_change_sets(&mut cmd, &args.coupling, &conn)?);
+    let clones = find_clones(&coupling, &parents, &file_ids, &hashes, args.min_lines);
+    log::info!("Found {} clones in {}ms", clones.len(), start.elapsed().as_millis());
+
+    let paths = load_entity_paths(&conn)?;
+
+    for clone in clones.iter().take(args.limit) {
+        println!(
+            "{:.2}\t{:.2}\t{}\t{}\t{}",
+            clone.divergent,
+            clone.support,
+            clone.lines,
+            paths[&clone.entity1_id],
+            paths[&clone.entity2_id]
+        );
+    }
+
+    let start = Instant::now();
+    let mut vt = CloneVirtualTable::new();
+
+    for clone in &clones {
+        let key = CloneKey::new(clone.entity1_id, clone.entity2_id);
+        let extra = CloneExtra::new(clone.lines, clone.support, clone.divergent);
+        vt.insert(key, extra);
+    }
+
+    let tx = conn.transaction()?;
+    drop_table(&tx, "clones")?;
+    vt.write::<CloneWriter>(&tx)?;
+    tx.commit()?;
+    log::info!("Wrote clones to disk in {}ms", start.elapsed().as_millis());
+
+    Ok(())
+}
+
+fn divergent_change(args: &DivergentChange) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+    let start = Instant::now();
+    let mut conn = Connection::open(args.db.clone())?;
+    let coupling = Coupling::new(&get_change_sets(&mut cmd, &args.coupling, &conn)?);
+    let parents = load_parents(&conn)?;
+    let pairs = find_coupled_pairs(&coupling, &parents, args.min_support, args.min_confidence);
+    let mut divergent = find_divergent_changes(&pairs, args.min_clusters);
+
+    if let Some(kind) = &args.kind {
+        let kinds = load_kinds(&conn)?;
+        divergent.retain(|d| kinds.get(&d.entity_id) == Some(kind));
+    }
+
+    log::info!(
+        "Found {} entities with divergent changes in {}ms",
+        divergent.len(),
+        start.elapsed().as_millis()
+    );
+
+    let paths = load_entity_paths(&conn)?;
+
+    for d in divergent.iter().take(args.limit) {
+        println!("{}\t{}\t{}", d.clusters.len(), d.partners(), paths[&d.entity_id]);
+
+        for cluster in &d.clusters {
+            let names = cluster.iter().map(|id| paths[id].as_str()).collect::<Vec<_>>();
+            println!("\t{}", names.join(", "));
+        }
     }
 
-    // Write virtual database to real (on disk) database
     let start = Instant::now();
-    let mut conn = Connection::open(cli.db.clone())?;
+    let mut vt = DivergentChangeVirtualTable::new();
+
+    for d in &divergent {
+        for (i, cluster) in d.clusters.iter().enumerate() {
+            for &partner_id in cluster {
+                let key = DivergentChangeKey::new(d.entity_id, partner_id);
+                let extra = DivergentChangeExtra::new(i, coupling.support(d.entity_id, partner_id));
+                vt.insert(key, extra);
+            }
+        }
+    }
+
     let tx = conn.transaction()?;
-    db.write(&tx)?;
+    drop_table(&tx, "divergent_changes")?;
+    vt.write::<DivergentChangeWriter>(&tx)?;
     tx.commit()?;
-    log::info!("Wrote virtual database to disk in {}ms", start.elapsed().as_millis());
+    log::info!("Wrote divergent changes to disk in {}ms", start.elapsed().as_millis());
 
     Ok(())
 }
 
-fn add_deps(args: &AddDeps) -> anyhow::Result<()> {
-    log::info!("Hello, world!");
-    
+fn clusters(args: &Clusters) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
     let start = Instant::now();
-    let deps = load_dep_file(&args.dep_file)?;
-    log::info!("Loaded dep file in {}ms", start.elapsed().as_millis());
+    let mut conn = Connection::open(args.db.clone())?;
+    let coupling = Coupling::new(&get_change_sets(&mut cmd, &args.coupling, &conn)?);
+    let parents = load_parents(&conn)?;
+    let pairs = find_coupled_pairs(&coupling, &parents, args.min_support, args.min_confidence);
+    let clusters = find_clusters(&pairs, args.max_iterations);
+
+    let files = load_files(&conn)?;
+    let packages = to_file_ids(&parents, &load_kinds(&conn)?)
+        .into_iter()
+        .filter(|(id, _)| clusters.contains_key(id))
+        .map(|(id, file_id)| (id, to_package(&files[&file_id])))
+        .collect::<HashMap<_, _>>();
+    let (alignments, purity, package_purity) = align_clusters(&clusters, &packages);
+    log::info!("Found {} clusters in {}ms", alignments.len(), start.elapsed().as_millis());
+
+    let paths = load_entity_paths(&conn)?;
+    let mut members: HashMap<usize, Vec<Id>> = HashMap::new();
+
+    for (&id, &cluster) in &clusters {
+        members.entry(cluster).or_default().push(id);
+    }
+
+    println!("purity\t{:.2}\tpackage purity\t{:.2}", purity, package_purity);
+
+    for alignment in alignments.iter().take(args.limit) {
+        let package = alignment.package.as_deref().unwrap_or("-");
+        println!("{}\t{}\t{:.2}\t{}", alignment.cluster, alignment.size, alignment.purity, package);
+
+        let mut misplaced = members[&alignment.cluster]
+            .iter()
+            .filter_map(|id| packages.get(id).filter(|p| Some(*p) != alignment.package.as_ref()))
+            .collect::<Vec<_>>();
+        misplaced.sort();
+        misplaced.dedup();
+
+        for package in misplaced {
+            let ids =
+                members[&alignment.cluster].iter().filter(|id| packages.get(id) == Some(package));
+            let mut names = ids.map(|id| paths[id].as_str()).collect::<Vec<_>>();
+            names.sort();
+            println!("\t{}\t{}", package, names.join(", "));
+        }
+    }
 
     let start = Instant::now();
-    let mut conn = Connection::open(args.db.clone())?;
-    let locs = load_locs(&mut conn, &args.commit)?;
-    log::info!("Loaded entities from database in {}ms", start.elapsed().as_millis());
+    let mut vt = ClusterVirtualTable::new();
+
+    for alignment in &alignments {
+        for &id in &members[&alignment.cluster] {
+            let package = packages.get(&id).cloned();
+            let misplaced = package.as_ref().map(|p| Some(p) != alignment.package.as_ref());
+            vt.insert(
+                ClusterKey::new(id),
+                ClusterExtra::new(alignment.cluster, package, misplaced),
+            );
+        }
+    }
 
+    let tx = conn.transaction()?;
+    drop_table(&tx, "clusters")?;
+    vt.write::<ClusterWriter>(&tx)?;
+    tx.commit()?;
+    log::info!("Wrote clusters to disk in {}ms", start.elapsed().as_millis());
+
+    Ok(())
+}
+
+fn interfaces(args: &Interfaces) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
     let start = Instant::now();
-    let mut vt = DepVirtualTable::new();
+    let mut conn = Connection::open(args.db.clone())?;
     let commit_id = get_commit_id(&conn, &args.commit)?;
-    insert_deps(&mut vt, &locs, &deps, commit_id)?;
-    log::info!("Wrote to virtual database in {}ms", start.elapsed().as_millis());
+    let change_sets = get_change_sets(&mut cmd, &args.coupling, &conn)?;
+    let mut interfaces =
+        find_interfaces(&conn, &change_sets, commit_id, args.min_fan_in, args.min_changes)?;
+
+    if let Some(kind) = &args.kind {
+        let kinds = load_kinds(&conn)?;
+        interfaces.retain(|i| kinds.get(&i.entity_id) == Some(kind));
+    }
+
+    log::info!("Found {} interfaces in {}ms", interfaces.len(), start.elapsed().as_millis());
+
+    let paths = load_entity_paths(&conn)?;
+    let mut out = open_output(&args.output)?;
+
+    match args.format {
+        ReportFormat::Text => {
+            for i in interfaces.iter().take(args.limit) {
+                writeln!(
+                    out,
+                    "{}\t{}\t{:.2}\t{}\t{}",
+                    i.volatility.to_str(),
+                    i.fan_in,
+                    i.changes,
+                    i.churn,
+                    paths[&i.entity_id]
+                )?;
+            }
+        }
+        ReportFormat::Sarif => {
+            let file_ids = to_file_ids(&load_parents(&conn)?, &load_kinds(&conn)?);
+            let locations = load_locations(&conn, commit_id)?;
+            let findings = interfaces
+                .iter()
+                .filter(|i| i.volatility == Volatility::Volatile)
+                .take(args.limit)
+                .map(|i| {
+                    let message = format!(
+                        "{} has {} dependents but changed {:.2} times (churn {})",
+                        paths[&i.entity_id], i.fan_in, i.changes, i.churn
+                    );
+                    to_finding(&paths, &file_ids, &locations, i.entity_id, message)
+                })
+                .collect::<Vec<_>>();
+            write_sarif(&mut out, VOLATILE_INTERFACE, &findings)?;
+        }
+    }
+
+    out.flush()?;
 
     let start = Instant::now();
+    let mut vt = InterfaceVirtualTable::new();
+
+    for i in &interfaces {
+        let class = i.volatility.to_str().to_string();
+        let extra = InterfaceExtra::new(i.fan_in, i.changes, i.churn, class);
+        vt.insert(InterfaceKey::new(i.entity_id), extra);
+    }
+
     let tx = conn.transaction()?;
-    vt.write::<DepWriter>(&tx)?;
+    drop_table(&tx, "interfaces")?;
+    vt.write::<InterfaceWriter>(&tx)?;
     tx.commit()?;
-    log::info!("Wrote virtual table to disk in {}ms", start.elapsed().as_millis());
+    log::info!("Wrote interfaces to disk in {}ms", start.elapsed().as_millis());
+
+    Ok(())
+}
+
+fn report(args: &CliReportCommand) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+    let start = Instant::now();
+    let conn = Connection::open(args.db.clone())?;
+    let change_sets = get_change_sets(&mut cmd, &args.coupling, &conn)?;
+    let paths = load_entity_paths(&conn)?;
+
+    let mut hotspots = find_hotspots(&change_sets, &load_measures(&conn)?, HotspotMeasure::Size);
+    hotspots.truncate(args.limit);
+
+    let coupling = Coupling::new(&change_sets);
+    let parents = load_parents(&conn)?;
+    let mut pairs = find_coupled_pairs(&coupling, &parents, args.min_support, args.min_confidence);
+    pairs.truncate(args.limit);
+
+    let trend = find_churn_trend(&change_sets, &find_commit_metrics(&conn)?);
+
+    let contributions = load_contributions(&conn)?;
+    let mut packages = find_bus_factors(&contributions, &load_files(&conn)?, 0.5)
+        .into_iter()
+        .filter(|b| b.kind == "package")
+        .collect::<Vec<_>>();
+    packages.sort_by(|a, b| a.bus_factor.cmp(&b.bus_factor).then(a.name.cmp(&b.name)));
+    packages.truncate(args.limit);
+
+    let mut lines: HashMap<String, usize> = HashMap::new();
+
+    for (mail, n) in contributions.lines.values().flatten() {
+        *lines.entry(mail.clone()).or_default() += n;
+    }
+
+    let mut authors = to_shares(&lines);
+    authors.truncate(args.limit);
+    let authors = authors
+        .into_iter()
+        .map(|(mail, share)| (format!("{} <{}>", contributions.names[&mail], mail), share))
+        .collect::<Vec<_>>();
+
+    let title = match &args.title {
+        Some(title) => title.clone(),
+        None => format!("Co-Change Report: {}", args.db.to_string_lossy()),
+    };
+
+    let report = Report {
+        title,
+        paths: &paths,
+        hotspots: &hotspots,
+        pairs: &pairs,
+        trend: &trend,
+        packages: &packages,
+        authors: &authors,
+    };
+
+    let mut writer = BufWriter::new(File::create(&args.output)?);
+    write_html_report(&mut writer, &report)?;
+    writer.flush()?;
+    log::info!(
+        "Wrote report to {} in {}ms",
+        args.output.to_string_lossy(),
+        start.elapsed().as_millis()
+    );
+    Ok(())
+}
+
+fn browse_db(args: &CliBrowseCommand) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+    let conn = Connection::open(args.db.clone())?;
+    let change_sets = get_change_sets(&mut cmd, &args.coupling, &conn)?;
+    let coupling = Coupling::new(&change_sets);
+    let paths = load_entity_paths(&conn)?;
+    browse(&conn, &coupling, &paths, args.limit)
+}
+
+fn serve_db(args: &CliServeCommand) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+    let start = Instant::now();
+    let conn = Connection::open(args.db.clone())?;
+    let change_sets = get_change_sets(&mut cmd, &args.coupling, &conn)?;
+    let coupling = Coupling::new(&change_sets);
+    let parents = load_parents(&conn)?;
+    let paths = load_entity_paths(&conn)?;
+    let kinds = load_kinds(&conn)?;
+    log::info!("Loaded database in {}ms", start.elapsed().as_millis());
+
+    let ctx = serve::Context {
+        conn: &conn,
+        coupling: &coupling,
+        parents: &parents,
+        paths: &paths,
+        kinds: &kinds,
+    };
+    serve(&args.listen, args.cors_origin.as_deref(), &ctx)
+}
+
+fn import_prs(args: &CliImportPrsCommand) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+
+    if !args.db.exists() {
+        let msg = format!("The database ('{}') does not exist.", &args.db.to_string_lossy());
+        cmd.error(clap::ErrorKind::Io, msg).exit();
+    }
+
+    let mut conn = Connection::open(&args.db)?;
+    let commit_ids = load_commit_shas(&conn)?
+        .into_iter()
+        .map(|(id, sha1)| (sha1, id))
+        .collect::<HashMap<_, _>>();
+
+    let token = std::env::var(args.platform.token_var()).ok();
+
+    if token.is_none() {
+        log::warn!("{} is not set, so requests are unauthenticated", args.platform.token_var());
+    }
+
+    let start = Instant::now();
+    let client = PrClient::new(args.platform, args.api_url.clone(), &args.project, token);
+    let pull_requests = client.list(args.include_unmerged, args.max_count)?;
+    log::info!("Read {} pull requests in {}ms", pull_requests.len(), start.elapsed().as_millis());
+
+    let mut db = PullRequestDb::new();
+
+    for pull_request in &pull_requests {
+        insert_pull_request(&mut db, pull_request, &commit_ids)?;
+    }
+
+    let commits = pull_requests.iter().flat_map(|p| p.commits.iter().chain(&p.merge_commits));
+    let (known, total) = commits.fold((0, 0), |(known, total), commit| {
+        (known + commit_ids.contains_key(&commit.to_string()) as usize, total + 1)
+    });
+    log::info!("Found {} of the {} commits of these pull requests in the database", known, total);
+
+    let tx = conn.transaction()?;
+    db.write(&tx)?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn import_issues(args: &CliImportIssuesCommand) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+
+    if !args.db.exists() {
+        let msg = format!("The database ('{}') does not exist.", &args.db.to_string_lossy());
+        cmd.error(clap::ErrorKind::Io, msg).exit();
+    }
+
+    if args.tracker == Tracker::Github && args.project.len() > 1 {
+        let msg = "--project may only be given once with --tracker github";
+        cmd.error(clap::ErrorKind::ArgumentConflict, msg).exit();
+    }
+
+    if args.tracker == Tracker::Jira && args.api_url.is_none() {
+        let msg = "--tracker jira requires --api-url";
+        cmd.error(clap::ErrorKind::MissingRequiredArgument, msg).exit();
+    }
+
+    let mut conn = Connection::open(&args.db)?;
+    let mut commit_ids: HashMap<String, Vec<Id>> = HashMap::new();
+
+    for (commit_id, message) in load_commit_messages(&conn)? {
+        for key in extract_issue_keys(&message, args.tracker, &args.project) {
+            commit_ids.entry(key).or_default().push(commit_id);
+        }
+    }
 
+    log::info!("Found {} issue keys in commit messages", commit_ids.len());
+
+    let token = std::env::var(args.tracker.token_var()).ok();
+    let user = std::env::var("JIRA_USER").ok().filter(|_| args.tracker == Tracker::Jira);
+
+    if token.is_none() {
+        log::warn!("{} is not set, so requests are unauthenticated", args.tracker.token_var());
+    }
+
+    let start = Instant::now();
+    let client =
+        IssueClient::new(args.tracker, args.api_url.clone(), &args.project[0], token, user)?;
+    let mut db = IssueDb::new();
+    let mut keys = commit_ids.keys().collect::<Vec<_>>();
+    keys.sort();
+
+    for key in keys {
+        match client.get(key)? {
+            Some(issue) => {
+                log::debug!("Read issue {} ({})", key, issue.kind.as_deref().unwrap_or("issue"));
+                insert_issue(&mut db, &issue, &commit_ids[key]);
+            }
+            None => log::debug!("Skipped issue {} (not found)", key),
+        }
+    }
+
+    let num_issues = db.issue_vt.len();
+    log::info!("Read {} issues in {}ms", num_issues, start.elapsed().as_millis());
+
+    let tx = conn.transaction()?;
+    db.write(&tx)?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn merge(args: &CliMergeCommand) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+    let started_at = OffsetDateTime::now_utc();
+    let mut metrics = Metrics::new();
+
+    if !args.force && args.db.exists() {
+        let msg = format!(
+            "The database ('{}') already exists. Use --force to overwrite it.",
+            &args.db.to_string_lossy()
+        );
+        cmd.error(clap::ErrorKind::Io, msg).exit();
+    }
+
+    if args.inputs.contains(&args.db) {
+        let msg = "The merged database cannot also be one of the inputs.";
+        cmd.error(clap::ErrorKind::ArgumentConflict, msg).exit();
+    }
+
+    let start = Instant::now();
+    let mut db = VirtualDb::new();
+    let mut deps = DepVirtualTable::new();
+
+    // Keep directory entities if any of the inputs has them
+    for input in &args.inputs {
+        if has_dir_entities(&Connection::open(input)?)? {
+            db.set_dir_entities(true);
+        }
+    }
+
+    for input in &args.inputs {
+        let conn = Connection::open(input)?;
+        merge_db(&mut db, &mut deps, &conn)?;
+        log::info!("Merged '{}'", input.to_string_lossy());
+    }
+
+    metrics.phase("merge", start, args.inputs.len(), "databases");
+
+    if args.force && args.db.exists() {
+        log::warn!("Overwriting existing database ('{}')...", &args.db.to_string_lossy());
+        remove_file(&args.db)?;
+    }
+
+    let start = Instant::now();
+    let mut conn = Connection::open(args.db.clone())?;
+    let tx = conn.transaction()?;
+    db.write(&tx)?;
+
+    // Leave the deps table to add-deps unless some input already had one
+    if !deps.is_empty() {
+        deps.write::<DepWriter>(&tx)?;
+    }
+
+    write_meta_schema(&tx)?;
+    tx.commit()?;
+    metrics.phase("write", start, 1, "database");
+
+    let start = Instant::now();
+    let tx = conn.transaction()?;
+    write_lifespans(&tx)?;
+    write_entity_metrics(&tx)?;
+    write_commit_metrics(&tx)?;
+    tx.commit()?;
+    metrics.phase("derive", start, 3, "tables");
+
+    write_run(&mut conn, "merge", started_at, None, None, None, &metrics)?;
+    Ok(())
+}
+
+fn audit(args: &CliAuditCommand) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+    let repo = open_repo(args.repo.as_deref())?;
+    let sampling = validate_sampling_input(&mut cmd, &args.every, "--every");
+
+    let mut walk = CommitWalk::new();
+    walk.set_sort(Sort::TIME);
+
+    if args.refs.is_empty() {
+        walk.push_start_oid(repo.head()?.peel_to_commit()?.id());
+    }
+
+    for ref_name in &args.refs {
+        let r#ref = validate_ref_input(&mut cmd, &repo, ref_name);
+        walk.push_start_oid(r#ref.peel_to_commit()?.id());
+    }
+
+    let walked = walk.walk(&repo)?.collect::<Result<Vec<_>, _>>()?;
+    let commits = sample_commits(&repo, &walked, sampling)?
+        .into_iter()
+        .filter(|c| c.parent_count() <= 1)
+        .collect::<Vec<_>>();
+
+    let mut ctx = ExtractionCtx::new(&repo, get_java_parser()?, ".java");
+    ctx.set_granularity(args.granularity);
+
+    let (diffed_files, _, _) =
+        diff_all_files(&repo, &commits, None, ctx.suffix(), MergeMode::Skip, false)?;
+    let mut by_commit: HashMap<Oid, Vec<_>> = HashMap::new();
+
+    for diffed_file in &diffed_files {
+        by_commit.entry(diffed_file.commit.sha1).or_default().push(diffed_file);
+    }
+
+    let mut audited = 0;
+    let mut discrepancies = 0;
+
+    for commit in &commits {
+        let numstat = get_numstat(&repo, commit)?;
+        let mut diffed_files = by_commit.remove(&commit.id()).unwrap_or_default();
+        diffed_files.sort_by(|a, b| a.filename.cmp(&b.filename));
+
+        for diffed_file in diffed_files {
+            let expected = match numstat.get(&diffed_file.filename) {
+                Some(&expected) => expected,
+                None => continue,
+            };
+
+            let attributed = get_changes(&mut ctx, diffed_file)?
+                .into_iter()
+                .find(|c| c.entity.parent.is_none())
+                .map_or((0, 0), |c| (c.adds, c.dels));
+            audited += 1;
+
+            if attributed != expected {
+                discrepancies += 1;
+                println!(
+                    "{} {}: git +{} -{}, entities +{} -{}",
+                    commit.id(),
+                    diffed_file.filename,
+                    expected.0,
+                    expected.1,
+                    attributed.0,
+                    attributed.1
+                );
+            }
+        }
+    }
+
+    log::info!(
+        "Audited {} files in {} commits and found {} discrepancies",
+        audited,
+        commits.len(),
+        discrepancies
+    );
+
+    if discrepancies > 0 {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn export_features(args: &ExportFeatures) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+    let start = Instant::now();
+    let conn = Connection::open(args.db.clone())?;
+    let change_sets = get_change_sets(&mut cmd, &args.coupling, &conn)?;
+    let mut features = find_features(&conn, &change_sets, args.min_support, args.min_confidence)?;
+    let paths = load_entity_paths(&conn)?;
+    let kinds = load_kinds(&conn)?;
+
+    if let Some(kind) = &args.kind {
+        features.retain(|f| kinds.get(&f.metrics.entity_id) == Some(kind));
+    }
+
+    log::info!(
+        "Computed features of {} entities in {}ms",
+        features.len(),
+        start.elapsed().as_millis()
+    );
+
+    let columns = [
+        ("entity_id", ColumnType::Int),
+        ("path", ColumnType::Text),
+        ("kind", ColumnType::Text),
+        ("loc", ColumnType::Int),
+        ("nesting", ColumnType::Int),
+        ("branches", ColumnType::Int),
+        ("params", ColumnType::Int),
+        ("commits", ColumnType::Int),
+        ("adds", ColumnType::Int),
+        ("dels", ColumnType::Int),
+        ("authors", ColumnType::Int),
+        ("author_entropy", ColumnType::Real),
+        ("scattering", ColumnType::Real),
+        ("history_complexity", ColumnType::Real),
+        ("coupling_degree", ColumnType::Int),
+        ("age_days", ColumnType::Real),
+        ("fix_commits", ColumnType::Int),
+    ]
+    .map(|(name, ty)| Column::new(name.to_string(), ty, false));
+    let int = |n: usize| Value::Integer(n as i64);
+    let rows = features.iter().map(|f| {
+        let m = &f.metrics;
+        Ok(vec![
+            int(m.entity_id),
+            Value::Text(paths[&m.entity_id].clone()),
+            Value::Text(kinds[&m.entity_id].clone()),
+            int(m.loc),
+            int(m.nesting),
+            int(m.branches),
+            int(m.params),
+            int(m.commits),
+            int(m.adds),
+            int(m.dels),
+            int(m.authors),
+            Value::Real(f.author_entropy),
+            Value::Real(m.scattering),
+            Value::Real(m.history_complexity),
+            int(f.coupling_degree),
+            Value::Real(f.age_days),
+            int(f.fix_commits),
+        ])
+    });
+    write_table(&args.output, args.format, &columns, rows)?;
+
+    Ok(())
+}
+
+fn export_graph(args: &ExportGraph) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+    let start = Instant::now();
+    let conn = Connection::open(args.db.clone())?;
+    let mut change_sets = get_change_sets(&mut cmd, &args.coupling, &conn)?;
+    let kinds = load_kinds(&conn)?;
+    let paths = load_entity_paths(&conn)?;
+    let parents = load_parents(&conn)?;
+    let is_included = |id: &Id| args.kind.is_none() || kinds.get(id) == args.kind.as_ref();
+
+    for change_set in &mut change_sets {
+        change_set.entities.retain(|(id, _)| is_included(id));
+    }
+
+    let coupling = Coupling::new(&change_sets);
+    let pairs = find_coupled_pairs(&coupling, &parents, args.min_support, args.min_confidence);
+
+    let mut edges = pairs
+        .iter()
+        .map(|p| (p.entity1_id, p.entity2_id, "cochange".to_string(), p.support))
+        .collect::<Vec<_>>();
+
+    if let Some(sha1) = &args.deps_at {
+        let commit_id = get_commit_id(&conn, sha1)?;
+
+        for (src_id, tgt_id, kind) in load_deps(&conn, commit_id)? {
+            if is_included(&src_id) && is_included(&tgt_id) {
+                edges.push((src_id, tgt_id, kind, 1.0));
+            }
+        }
+    }
+
+    let mut entity_ids = edges.iter().flat_map(|&(a, b, _, _)| [a, b]).collect::<Vec<_>>();
+    entity_ids.sort();
+    entity_ids.dedup();
+
+    let mut graph = Graph::default();
+
+    for entity_id in entity_ids {
+        let kind = kinds.get(&entity_id).map(String::as_str).unwrap_or("entity");
+        graph.add_node(entity_id.to_string(), &paths[&entity_id], kind);
+    }
+
+    for (source, target, kind, weight) in edges {
+        graph.add_edge(source.to_string(), target.to_string(), kind, weight);
+    }
+
+    log::info!(
+        "Built co-change graph ({} nodes, {} edges) in {}ms",
+        graph.nodes.len(),
+        graph.edges.len(),
+        start.elapsed().as_millis()
+    );
+
+    graph.write(&args.output, args.format)?;
+    log::info!("Wrote co-change graph to {}", args.output.to_string_lossy());
+    Ok(())
+}
+
+fn export_tables(args: &ExportTables) -> anyhow::Result<()> {
+    let lines = validate_line_args(&mut Cli::command(), &args.lines);
+    let conn = Connection::open(args.db.clone())?;
+    let tables =
+        if args.tables.is_empty() { load_table_names(&conn)? } else { args.tables.clone() };
+
+    create_dir_all(&args.output)?;
+
+    for table in &tables {
+        let start = Instant::now();
+        let path = args.output.join(format!("{}.{}", table, args.format.extension()));
+        export_table(&conn, table, &path, args.format, lines)?;
+        log::info!(
+            "Wrote {} to {} in {}ms",
+            table,
+            path.to_string_lossy(),
+            start.elapsed().as_millis()
+        );
+    }
+
+    Ok(())
+}
+
+fn export_neo4j(args: &ExportNeo4j) -> anyhow::Result<()> {
+    let start = Instant::now();
+    let conn = Connection::open(args.db.clone())?;
+    create_dir_all(&args.output)?;
+    let files = write_neo4j_import(&conn, &args.output)?;
+    log::info!("Wrote Neo4j import files in {}ms", start.elapsed().as_millis());
+
+    let mut command = "neo4j-admin database import full --multiline-fields=true".to_string();
+
+    for (file, is_nodes) in files {
+        let flag = if is_nodes { "nodes" } else { "relationships" };
+        command.push_str(&format!(" --{}={}", flag, args.output.join(file).to_string_lossy()));
+    }
+
+    println!("{}", command);
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+use git2::Oid;
+use log::LevelFilter;
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+
+/// Log records of this target carry a metric. In the JSON format, their message
+/// is itself a JSON object whose fields are added to the record.
+const TARGET: &str = "metrics";
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+/// Sets up the logger. With [`LogFormat::Json`], each record is written to
+/// stderr as a single line of JSON.
+pub fn init_logger(level: LevelFilter, format: LogFormat) {
+    let mut builder = env_logger::Builder::new();
+    builder.filter_level(level);
+
+    if format == LogFormat::Json {
+        JSON.store(true, Ordering::Relaxed);
+        builder.format(|buf, record| {
+            let time = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default();
+
+            let mut fields = Map::new();
+            fields.insert("time".to_string(), json!(time));
+            fields.insert("level".to_string(), json!(record.level().as_str()));
+            fields.insert("target".to_string(), json!(record.target()));
+
+            let message = record.args().to_string();
+
+            match serde_json::from_str(&message) {
+                Ok(Value::Object(metric)) if record.target() == TARGET => fields.extend(metric),
+                _ => {
+                    fields.insert("message".to_string(), json!(message));
+                }
+            }
+
+            writeln!(buf, "{}", Value::Object(fields))
+        });
+    }
+
+    builder.init();
+}
+
+/// How long a phase of a run, or a commit or file within a phase, took.
+#[derive(Clone, Debug)]
+pub struct Metric {
+    /// Either "phase", "commit", or "file"
+    pub scope: &'static str,
+    pub phase: &'static str,
+    /// The commit or file which was measured (nothing for a whole phase)
+    pub subject: Option<String>,
+    pub duration: Duration,
+    /// The number of items produced by a whole phase
+    pub count: Option<usize>,
+}
+
+impl Metric {
+    fn log(&self, level: log::Level, text: String) {
+        if JSON.load(Ordering::Relaxed) {
+            let fields = json!({
+                "metric": self.scope,
+                "phase": self.phase,
+                "subject": self.subject,
+                "ms": self.duration.as_millis() as u64,
+                "count": self.count,
+            });
+            log::log!(target: TARGET, level, "{}", fields);
+        } else {
+            log::log!(target: TARGET, level, "{}", text);
+        }
+    }
+}
+
+/// What the extraction parsed of a language. A revision of a file counts once
+/// no matter how many commits it appears in.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LanguageStats {
+    /// The suffix of the files parsed by tree-sitter without the dot (e.g.
+    /// `java`), `ctags`, an injected language (e.g. `sql`), or `unparsed` for
+    /// the files recorded as a single entity
+    pub language: String,
+    /// The number of files (or injected snippets) parsed
+    pub files: usize,
+    pub entities: usize,
+    /// The number of files with syntax errors or which could not be parsed
+    pub parse_errors: usize,
+    pub duration: Duration,
+}
+
+/// Collects the timings of a run. Every timing is logged as soon as it is
+/// recorded: phases at the info level, commits at debug, and files at trace.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    metrics: Vec<Metric>,
+    /// Time spent on each commit by the files recorded since the last phase
+    pending: Vec<(Oid, Duration)>,
+    languages: Vec<LanguageStats>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn metrics(&self) -> &[Metric] {
+        &self.metrics
+    }
+
+    /// The name and duration of each phase, in the order they ran.
+    pub fn phases(&self) -> Vec<(&'static str, Duration)> {
+        self.metrics.iter().filter(|m| m.scope == "phase").map(|m| (m.phase, m.duration)).collect()
+    }
+
+    /// What was parsed of each language, in the order they were recorded.
+    pub fn languages(&self) -> &[LanguageStats] {
+        &self.languages
+    }
+
+    fn record(&mut self, metric: Metric, level: log::Level, text: String) {
+        if log::log_enabled!(target: TARGET, level) {
+            metric.log(level, text);
+        }
+
+        self.metrics.push(metric);
+    }
+
+    /// Records a phase which started at `start` and produced `count` items. The
+    /// times of any files recorded since the last phase are summed by commit.
+    pub fn phase(&mut self, phase: &'static str, start: Instant, count: usize, items: &str) {
+        let mut order = Vec::new();
+        let mut by_commit: HashMap<Oid, Duration> = HashMap::new();
+
+        for (commit, duration) in std::mem::take(&mut self.pending) {
+            if !by_commit.contains_key(&commit) {
+                order.push(commit);
+            }
+
+            *by_commit.entry(commit).or_default() += duration;
+        }
+
+        for commit in order {
+            self.commit(phase, commit, by_commit[&commit]);
+        }
+
+        let duration = start.elapsed();
+        let text = format!("{}: {} {} in {}ms", phase, count, items, duration.as_millis());
+        let metric = Metric { scope: "phase", phase, subject: None, duration, count: Some(count) };
+        self.record(metric, log::Level::Info, text);
+    }
+
+    /// Records the time spent on a single commit.
+    pub fn commit(&mut self, phase: &'static str, commit: Oid, duration: Duration) {
+        let text = format!("{}: commit {} took {}ms", phase, commit, duration.as_millis());
+        let subject = Some(commit.to_string());
+        let metric = Metric { scope: "commit", phase, subject, duration, count: None };
+        self.record(metric, log::Level::Debug, text);
+    }
+
+    /// Records the time spent on a single file of a commit.
+    pub fn file(&mut self, phase: &'static str, commit: Oid, filename: &str, duration: Duration) {
+        let text = format!("{}: {} in {} took {}ms", phase, filename, commit, duration.as_millis());
+        let subject = Some(format!("{}:{}", commit, filename));
+        let metric = Metric { scope: "file", phase, subject, duration, count: None };
+        self.record(metric, log::Level::Trace, text);
+        self.pending.push((commit, duration));
+    }
+
+    /// Records what was parsed of a language, which is logged at the info level.
+    pub fn language(&mut self, stats: LanguageStats) {
+        if JSON.load(Ordering::Relaxed) {
+            let fields = json!({
+                "metric": "language",
+                "language": stats.language,
+                "files": stats.files,
+                "entities": stats.entities,
+                "parse_errors": stats.parse_errors,
+                "ms": stats.duration.as_millis() as u64,
+            });
+            log::info!(target: TARGET, "{}", fields);
+        } else {
+            log::info!(
+                target: TARGET,
+                "{}: {} files, {} entities, {} parse errors in {}ms",
+                stats.language,
+                stats.files,
+                stats.entities,
+                stats.parse_errors,
+                stats.duration.as_millis()
+            );
+        }
+
+        self.languages.push(stats);
+    }
+}
@@ -1,19 +1,42 @@
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::sync::Arc;
 
+use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
+use tree_sitter::InputEdit;
 use tree_sitter::Language;
 use tree_sitter::Node;
 use tree_sitter::Parser;
+use tree_sitter::Point;
 use tree_sitter::Query;
 use tree_sitter::QueryCursor;
 use tree_sitter::Range;
+use tree_sitter::Tree;
+use unicode_normalization::UnicodeNormalization;
 
+use crate::ir::Complexity;
 use crate::ir::Entity;
+use crate::ir::Hunk;
 use crate::ir::Interval;
 use crate::ir::LocEntity;
 
+extern "C" {
+    fn tree_sitter_java() -> Language;
+}
+
+/// The parser of Java files, which is the only language parsed with
+/// tree-sitter.
+pub fn get_java_parser() -> Result<FileParser> {
+    let language = unsafe { tree_sitter_java() };
+    let java_query = include_str!("../queries/java/tags.scm");
+    let mut parser = FileParser::new(language, java_query)?;
+    parser.set_complexity_query(include_str!("../queries/java/complexity.scm"))?;
+    parser.set_injection_query(include_str!("../queries/java/injections.scm"))?;
+    Ok(parser)
+}
+
 #[derive(Debug, Builder)]
 struct Tag {
     id: usize,
@@ -22,6 +45,71 @@ struct Tag {
     kind: Arc<String>,
     disc: String,
     range: Range,
+    #[builder(default)]
+    complexity: Complexity,
+    /// Whether the name was set by the query rather than captured
+    #[builder(default)]
+    unnamed: bool,
+}
+
+/// A node captured by the complexity query.
+#[derive(Debug)]
+struct Mark {
+    id: usize,
+    ancestor_ids: Vec<usize>,
+    kind: MarkKind,
+    range: Range,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MarkKind {
+    Branch,
+    Nesting,
+    Parameter,
+}
+
+/// The parts of a file which tree-sitter could not make sense of.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SyntaxErrors {
+    /// The number of ERROR and MISSING nodes
+    pub count: usize,
+    /// The number of bytes within ERROR nodes
+    pub bytes: usize,
+}
+
+/// Code of another language embedded in a file (e.g. SQL in a string literal).
+#[derive(Debug, Clone)]
+pub struct Injection {
+    /// The name of the language, as given by the injection query
+    pub language: String,
+    pub start_byte: usize,
+    pub end_byte: usize,
+    /// The (zero-based) rows of the first and last line of the code
+    pub start_row: usize,
+    pub end_row: usize,
+}
+
+impl Injection {
+    /// The embedded code. Quotes around it (as around a string literal) are
+    /// blanked out so that only the code itself is parsed.
+    pub fn content(&self, source: &[u8]) -> Vec<u8> {
+        let mut content = source[self.start_byte..self.end_byte].to_vec();
+        let quotes = content.iter().take_while(|&&b| b == b'"').count();
+
+        if quotes > 0 && content.len() >= 2 * quotes && content.ends_with(&content[..quotes]) {
+            let len = content.len();
+            content[..quotes].fill(b' ');
+            content[len - quotes..].fill(b' ');
+        }
+
+        content
+    }
+}
+
+/// Anything which breaks the source code of a file into located entities. The
+/// last entity is the file itself, which is the root of every other.
+pub trait EntityParser {
+    fn parse(&mut self, source: &[u8], filename: &str) -> Result<Vec<LocEntity>>;
 }
 
 pub struct FileParser {
@@ -30,6 +118,10 @@ pub struct FileParser {
     name_ix: u32,
     disc_ix: u32,
     tag_kinds: Vec<Option<Arc<String>>>,
+    complexity_query: Option<Query>,
+    injection_query: Option<Query>,
+    /// The syntax errors of the last parsed file
+    syntax_errors: SyntaxErrors,
 }
 
 impl FileParser {
@@ -50,17 +142,98 @@ impl FileParser {
             .map(|n| n.strip_prefix("tag.").map(|n| Arc::new(n.to_string())))
             .collect::<Vec<_>>();
 
-        Ok(Self { parser, query, name_ix, disc_ix, tag_kinds })
+        Ok(Self {
+            parser,
+            query,
+            name_ix,
+            disc_ix,
+            tag_kinds,
+            complexity_query: None,
+            injection_query: None,
+            syntax_errors: SyntaxErrors::default(),
+        })
+    }
+
+    /// Sets the query used to compute the complexity of each entity. Its
+    /// captures must be named `branch`, `nesting`, or `parameter`.
+    pub fn set_complexity_query<Q: AsRef<str>>(&mut self, query: Q) -> Result<()> {
+        let language = self.parser.language().context("parser has no language")?;
+        let query =
+            Query::new(language, query.as_ref()).context("failed to parse complexity query")?;
+
+        for name in query.capture_names() {
+            if !matches!(name.as_str(), "branch" | "nesting" | "parameter") {
+                bail!("unknown capture `{}` in complexity query", name);
+            }
+        }
+
+        self.complexity_query = Some(query);
+        Ok(())
+    }
+
+    /// Sets the query used to find code of other languages embedded in a file.
+    /// Its `injection.content` capture is the embedded code, whose language is
+    /// either the text of its `injection.language` capture or is set with
+    /// `(#set! injection.language "...")`. Captures starting with `_` are only
+    /// for predicates.
+    pub fn set_injection_query<Q: AsRef<str>>(&mut self, query: Q) -> Result<()> {
+        let language = self.parser.language().context("parser has no language")?;
+        let query =
+            Query::new(language, query.as_ref()).context("failed to parse injection query")?;
+
+        if query.capture_index_for_name("injection.content").is_none() {
+            bail!("failed to find `injection.content` capture");
+        }
+
+        for name in query.capture_names() {
+            if !name.starts_with('_')
+                && !matches!(name.as_str(), "injection.content" | "injection.language")
+            {
+                bail!("unknown capture `{}` in injection query", name);
+            }
+        }
+
+        self.injection_query = Some(query);
+        Ok(())
+    }
+
+    /// The syntax errors of the last parsed file. Entities within its ERROR
+    /// nodes were left out.
+    pub fn syntax_errors(&self) -> SyntaxErrors {
+        self.syntax_errors
+    }
+
+    pub fn parse(&mut self, source: &[u8], filename: &str) -> Result<Vec<LocEntity>> {
+        Ok(self.parse_tree(source, filename, None)?.1)
+    }
+
+    /// Finds the code of other languages embedded in a parsed file with the
+    /// injection query (if there is one).
+    pub fn get_injections(&self, tree: &Tree, source: &[u8]) -> Vec<Injection> {
+        match &self.injection_query {
+            Some(injection_query) => get_injections(injection_query, &tree.root_node(), source),
+            None => Vec::new(),
+        }
     }
 
-    pub fn parse(&mut self, source: &[u8], filename: &String) -> Result<Vec<LocEntity>> {
+    /// Parses a file into its syntax tree and entities. Given the tree of an
+    /// older version of the file (edited to match this one with `Tree::edit`),
+    /// only the parts which changed are parsed again.
+    pub fn parse_tree(
+        &mut self,
+        source: &[u8],
+        filename: &str,
+        old_tree: Option<&Tree>,
+    ) -> Result<(Tree, Vec<LocEntity>)> {
         self.parser.reset();
-        let tree = self.parser.parse(source, None).context("failed to parse source code")?;
+        let tree = self.parser.parse(source, old_tree).context("failed to parse source code")?;
         let mut cursor = QueryCursor::new();
+        let has_error = tree.root_node().has_error();
+        self.syntax_errors = if has_error { get_syntax_errors(&tree) } else { Default::default() };
 
         let mut tags = Vec::new();
 
-        for r#match in cursor.matches(&self.query, tree.root_node(), source) {
+        'matches: for r#match in cursor.matches(&self.query, tree.root_node(), source) {
             let mut builder = TagBuilder::default();
 
             for capture in r#match.captures {
@@ -75,6 +248,11 @@ impl FileParser {
                 }
 
                 if let Some(tag_kind) = &self.tag_kinds[capture.index as usize] {
+                    // Skip entities within broken regions of the file
+                    if has_error && is_within_error(&capture.node) {
+                        continue 'matches;
+                    }
+
                     builder.id(capture.node.id());
                     builder.ancestor_ids(get_ancestor_ids(&capture.node));
                     builder.kind(tag_kind.clone());
@@ -85,6 +263,20 @@ impl FileParser {
                 log::warn!("Found unused capture: {:?}", capture);
             }
 
+            if builder.name.is_none() {
+                let name = self
+                    .query
+                    .property_settings(r#match.pattern_index)
+                    .iter()
+                    .find(|p| &*p.key == "name")
+                    .and_then(|p| p.value.as_ref());
+
+                if let Some(name) = name {
+                    builder.name(name.to_string());
+                    builder.unnamed(true);
+                }
+            }
+
             if builder.disc.is_none() {
                 builder.disc("".to_string());
             }
@@ -92,18 +284,404 @@ impl FileParser {
             tags.push(builder.build()?);
         }
 
+        let root = tree.root_node();
+        let mut file_complexity = Complexity::default();
+
+        if let Some(complexity_query) = &self.complexity_query {
+            let marks = get_marks(complexity_query, &root, source);
+            let mut tag_ids = tags.iter().map(|t| t.id).collect::<HashSet<_>>();
+            tag_ids.insert(root.id());
+
+            for tag in &mut tags {
+                tag.complexity = to_complexity(tag.id, &tag.range, &tag_ids, &marks);
+            }
+
+            file_complexity = to_complexity(root.id(), &root.range(), &tag_ids, &marks);
+        }
+
         // Create a "psuedo-entity" for the file to be the root entity
-        let file = Entity::new_root(filename.clone(), Arc::new("file".to_string()), String::new());
-        let file = LocEntity::new(Arc::new(file), to_interval(&tree.root_node().range()));
+        let file =
+            Entity::new_root(normalize_path(filename), Arc::new("file".to_string()), String::new());
+        let file = LocEntity::new(Arc::new(file), to_interval(&root.range()), file_complexity);
+
+        Ok((tree, to_loc_entities(file, tags)))
+    }
+}
 
-        Ok(to_loc_entities(file, tags))
+impl EntityParser for FileParser {
+    fn parse(&mut self, source: &[u8], filename: &str) -> Result<Vec<LocEntity>> {
+        FileParser::parse(self, source, filename)
     }
 }
 
+/// Creates the "psuedo-entity" of a file without parsing it. Every line of the
+/// file (and at least one line, even for binary files) belongs to it.
+pub fn to_file_entity(source: &[u8], filename: &str) -> LocEntity {
+    let file =
+        Entity::new_root(normalize_path(filename), Arc::new("file".to_string()), String::new());
+    let rows = source.iter().filter(|&&b| b == b'\n').count();
+    LocEntity::new(Arc::new(file), Interval(1, rows.max(1) + 1), Complexity::default())
+}
+
+/// Normalizes a path for use as the name of a file entity, so that a file has
+/// the same name whichever platform it was committed from. Unicode is composed
+/// (NFC), since macOS writes filenames decomposed (NFD).
+pub fn normalize_path(path: &str) -> String {
+    if path.is_ascii() {
+        path.to_string()
+    } else {
+        path.nfc().collect()
+    }
+}
+
+/// Undoes the C-style quoting of a path written by git (e.g. by `git diff
+/// --name-only` with `core.quotePath`, such as `"caf\303\251.java"`) and then
+/// normalizes it. Paths which are not quoted are only normalized.
+pub fn unquote_path(path: &str) -> String {
+    let inner = match path.strip_prefix('"').and_then(|p| p.strip_suffix('"')) {
+        Some(inner) if inner.contains('\\') => inner.as_bytes(),
+        _ => return normalize_path(path),
+    };
+
+    let mut bytes = Vec::with_capacity(inner.len());
+    let mut i = 0;
+
+    while i < inner.len() {
+        let b = inner[i];
+        i += 1;
+
+        if b != b'\\' || i == inner.len() {
+            bytes.push(b);
+            continue;
+        }
+
+        let escaped = inner[i];
+        i += 1;
+
+        bytes.push(match escaped {
+            b'a' => 0x07,
+            b'b' => 0x08,
+            b't' => b'\t',
+            b'n' => b'\n',
+            b'v' => 0x0b,
+            b'f' => 0x0c,
+            b'r' => b'\r',
+            b'0'..=b'7' => {
+                // Up to three octal digits
+                let mut n = (escaped - b'0') as u32;
+
+                for _ in 0..2 {
+                    match inner.get(i) {
+                        Some(&d @ b'0'..=b'7') => n = n * 8 + (d - b'0') as u32,
+                        _ => break,
+                    }
+                    i += 1;
+                }
+
+                n as u8
+            }
+            other => other,
+        });
+    }
+
+    normalize_path(&String::from_utf8_lossy(&bytes))
+}
+
+/// Adds the entities parsed from an injection to those of its host file. The
+/// innermost host entity around the injection takes the place of the guest
+/// file entity and the lines of the guest entities are shifted to the host's.
+pub fn inject(host: &mut Vec<LocEntity>, injection: &Injection, mut guest: Vec<LocEntity>) {
+    let guest_root = match guest.pop() {
+        Some(guest_root) => guest_root.entity,
+        None => return,
+    };
+
+    let rows = Interval(injection.start_row + 1, injection.end_row + 1);
+    let parent = host
+        .iter()
+        .filter(|e| e.loc.0 <= rows.0 && rows.1 <= e.loc.1)
+        .min_by_key(|e| e.loc.1 - e.loc.0)
+        .map(|e| e.entity.clone());
+
+    let (parent, file) = match (parent, host.pop()) {
+        (Some(parent), Some(file)) => (parent, file),
+        (_, file) => {
+            host.extend(file);
+            return;
+        }
+    };
+
+    // Parents always come before their children
+    let mut reparented: HashMap<*const Entity, Arc<Entity>> = HashMap::new();
+    reparented.insert(Arc::as_ptr(&guest_root), parent);
+
+    for e in guest {
+        let old_parent = e.entity.parent.as_ref().map_or(std::ptr::null(), Arc::as_ptr);
+        let new_parent = match reparented.get(&old_parent) {
+            Some(new_parent) => new_parent.clone(),
+            None => continue,
+        };
+
+        let (name, kind, disc) =
+            (e.entity.name.clone(), e.entity.kind.clone(), e.entity.disc.clone());
+        let entity = Arc::new(Entity::new(new_parent, name, kind, disc));
+        reparented.insert(Arc::as_ptr(&e.entity), entity.clone());
+
+        let loc = Interval(e.loc.0 + injection.start_row, e.loc.1 + injection.start_row);
+        host.push(LocEntity::new(entity, loc, e.complexity));
+    }
+
+    host.push(file);
+}
+
+/// Converts the hunks of a diff without context lines into the edits which turn
+/// the old source into the new one. The edits are in reverse order so that
+/// each can be applied to the old tree in turn.
+pub fn to_input_edits(old: &[u8], new: &[u8], hunks: &[Hunk]) -> Vec<InputEdit> {
+    let (old_starts, new_starts) = (to_line_starts(old), to_line_starts(new));
+    let mut hunks = hunks.to_vec();
+    hunks.sort();
+
+    hunks
+        .iter()
+        .rev()
+        .map(|hunk| {
+            let (old_row, old_end_row) = to_rows(&hunk.old_interval);
+            let (new_row, new_end_row) = to_rows(&hunk.new_interval);
+            let start_byte = to_byte(&old_starts, old.len(), old_row);
+            let old_end_byte = to_byte(&old_starts, old.len(), old_end_row);
+            let new_start_byte = to_byte(&new_starts, new.len(), new_row);
+            let new_end_byte = to_byte(&new_starts, new.len(), new_end_row);
+
+            // The old source still comes before the edit, so shift the rows of
+            // the new source to match
+            let new_end = to_point(&new_starts, new_end_byte);
+
+            InputEdit {
+                start_byte,
+                old_end_byte,
+                new_end_byte: start_byte + (new_end_byte - new_start_byte),
+                start_position: to_point(&old_starts, start_byte),
+                old_end_position: to_point(&old_starts, old_end_byte),
+                new_end_position: Point::new(new_end.row + old_row - new_row, new_end.column),
+            }
+        })
+        .collect()
+}
+
+/// The byte at which each line starts.
+pub fn to_line_starts(source: &[u8]) -> Vec<usize> {
+    let newlines = source.iter().enumerate().filter(|(_, &b)| b == b'\n').map(|(i, _)| i + 1);
+    std::iter::once(0).chain(newlines).collect()
+}
+
+/// The (zero-based) rows of the first line of a hunk and of the line after its
+/// last. Hunks without lines start after the line git gives.
+fn to_rows(interval: &Interval) -> (usize, usize) {
+    let lines = interval.1 - interval.0;
+    let row = if lines == 0 { interval.0 } else { interval.0.saturating_sub(1) };
+    (row, row.saturating_add(lines))
+}
+
+fn to_byte(line_starts: &[usize], len: usize, row: usize) -> usize {
+    line_starts.get(row).copied().unwrap_or(len).min(len)
+}
+
+fn to_point(line_starts: &[usize], byte: usize) -> Point {
+    let row = line_starts.partition_point(|&start| start <= byte).saturating_sub(1);
+    Point::new(row, byte - line_starts[row])
+}
+
 fn to_interval(range: &Range) -> Interval {
     Interval(range.start_point.row + 1, range.end_point.row + 1)
 }
 
+fn get_syntax_errors(tree: &Tree) -> SyntaxErrors {
+    let mut errors = SyntaxErrors::default();
+    let mut cursor = tree.walk();
+
+    loop {
+        let node = cursor.node();
+
+        if node.is_error() {
+            errors.count += 1;
+            errors.bytes += node.end_byte() - node.start_byte();
+        } else if node.is_missing() {
+            errors.count += 1;
+        }
+
+        // Only descend into nodes which contain errors (but not into errors)
+        if !node.is_error() && node.has_error() && cursor.goto_first_child() {
+            continue;
+        }
+
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return errors;
+            }
+        }
+    }
+}
+
+/// Whether each row of a tree holds any code, i.e. a token which is not (part
+/// of) a comment. The other rows are blank or only hold comments.
+pub fn get_code_rows(tree: &Tree) -> Vec<bool> {
+    let mut code_rows = vec![false; tree.root_node().end_position().row + 1];
+    let mut cursor = tree.walk();
+
+    loop {
+        let node = cursor.node();
+
+        if node.kind().contains("comment") {
+            // Comments are never code, and neither is anything inside them
+        } else if cursor.goto_first_child() {
+            continue;
+        } else if node.start_byte() < node.end_byte() {
+            let rows = node.start_position().row..=node.end_position().row;
+            code_rows[rows].fill(true);
+        }
+
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return code_rows;
+            }
+        }
+    }
+}
+
+/// The lines of each doc comment (i.e. a comment starting with `/**`) of a
+/// tree, along with the first line of the node which follows it. This is the
+/// node the comment documents.
+pub fn get_doc_comments(tree: &Tree, source: &[u8]) -> Vec<(Interval, usize)> {
+    let mut docs = Vec::new();
+    let mut cursor = tree.walk();
+
+    loop {
+        let node = cursor.node();
+
+        if node.kind().contains("comment") {
+            let text = &source[node.start_byte()..node.end_byte()];
+            let is_doc = text.starts_with(b"/**") && text != b"/**/";
+            let next = node.next_sibling().filter(|n| !n.kind().contains("comment"));
+
+            if let Some(next) = next.filter(|_| is_doc) {
+                docs.push((to_interval(&node.range()), next.start_position().row + 1));
+            }
+        } else if cursor.goto_first_child() {
+            continue;
+        }
+
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return docs;
+            }
+        }
+    }
+}
+
+fn is_within_error(node: &Node) -> bool {
+    let mut curr = Some(*node);
+
+    while let Some(curr_node) = curr {
+        if curr_node.is_error() {
+            return true;
+        }
+
+        curr = curr_node.parent();
+    }
+
+    false
+}
+
+fn get_injections(query: &Query, root: &Node, source: &[u8]) -> Vec<Injection> {
+    let content_ix = query.capture_index_for_name("injection.content");
+    let language_ix = query.capture_index_for_name("injection.language");
+    let mut cursor = QueryCursor::new();
+    let mut injections = Vec::new();
+
+    for r#match in cursor.matches(query, *root, source) {
+        let mut language = query
+            .property_settings(r#match.pattern_index)
+            .iter()
+            .find(|p| &*p.key == "injection.language")
+            .and_then(|p| p.value.as_ref())
+            .map(|v| v.to_string());
+        let mut content = None;
+
+        for capture in r#match.captures {
+            if Some(capture.index) == content_ix {
+                content = Some(capture.node);
+            } else if Some(capture.index) == language_ix {
+                // The injection is skipped if its language is not valid UTF-8
+                language = capture.node.utf8_text(source).ok().map(str::to_string);
+            }
+        }
+
+        if let (Some(language), Some(node)) = (language, content) {
+            injections.push(Injection {
+                language,
+                start_byte: node.start_byte(),
+                end_byte: node.end_byte(),
+                start_row: node.start_position().row,
+                end_row: node.end_position().row,
+            });
+        }
+    }
+
+    injections
+}
+
+fn get_marks(query: &Query, root: &Node, source: &[u8]) -> Vec<Mark> {
+    let mut cursor = QueryCursor::new();
+    let mut marks = Vec::new();
+
+    for r#match in cursor.matches(query, *root, source) {
+        for capture in r#match.captures {
+            let kind = match query.capture_names()[capture.index as usize].as_str() {
+                "branch" => MarkKind::Branch,
+                "nesting" => MarkKind::Nesting,
+                _ => MarkKind::Parameter,
+            };
+
+            marks.push(Mark {
+                id: capture.node.id(),
+                ancestor_ids: get_ancestor_ids(&capture.node),
+                kind,
+                range: capture.node.range(),
+            });
+        }
+    }
+
+    marks
+}
+
+/// Computes the complexity of the tag with the given id and range. Branches
+/// and nesting include those of nested tags while parameters do not.
+fn to_complexity(id: usize, range: &Range, tag_ids: &HashSet<usize>, marks: &[Mark]) -> Complexity {
+    let within =
+        |m: &&Mark| range.start_byte <= m.range.start_byte && m.range.end_byte <= range.end_byte;
+    let marks = marks.iter().filter(within).collect::<Vec<_>>();
+
+    let branches = marks.iter().filter(|m| m.kind == MarkKind::Branch).count();
+
+    let nesting_ids =
+        marks.iter().filter(|m| m.kind == MarkKind::Nesting).map(|m| m.id).collect::<HashSet<_>>();
+    let nesting = marks
+        .iter()
+        .filter(|m| m.kind == MarkKind::Nesting)
+        .map(|m| 1 + m.ancestor_ids.iter().filter(|a| nesting_ids.contains(a)).count())
+        .max()
+        .unwrap_or_default();
+
+    let params = marks
+        .iter()
+        .filter(|m| m.kind == MarkKind::Parameter)
+        .filter(|m| m.ancestor_ids.iter().find(|a| tag_ids.contains(a)) == Some(&id))
+        .count();
+
+    Complexity::new(nesting, branches, params)
+}
+
 fn get_ancestor_ids(node: &Node) -> Vec<usize> {
     let mut ids = Vec::new();
     let mut curr: Option<Node> = node.parent();
@@ -116,7 +694,24 @@ fn get_ancestor_ids(node: &Node) -> Vec<usize> {
     ids
 }
 
+/// Numbers the unnamed tags of each parent in the order they appear (starting
+/// with 1). The number is their disc and replaces any `{}` in their name.
+fn number_unnamed(tags: &mut [Tag]) {
+    let tag_ids = tags.iter().map(|t| t.id).collect::<HashSet<_>>();
+    let mut ordinals: HashMap<(Option<usize>, String, Arc<String>), usize> = HashMap::new();
+    tags.sort_by_key(|t| t.range.start_byte);
+
+    for tag in tags.iter_mut().filter(|t| t.unnamed) {
+        let parent_id = tag.ancestor_ids.iter().find(|id| tag_ids.contains(id)).copied();
+        let ordinal = ordinals.entry((parent_id, tag.name.clone(), tag.kind.clone())).or_default();
+        *ordinal += 1;
+        tag.name = tag.name.replace("{}", &ordinal.to_string());
+        tag.disc = ordinal.to_string();
+    }
+}
+
 fn to_loc_entities(file_tag: LocEntity, mut tags: Vec<Tag>) -> Vec<LocEntity> {
+    number_unnamed(&mut tags);
     tags.sort_by_key(|t| t.ancestor_ids.len());
 
     let mut entities: HashMap<usize, Arc<Entity>> = HashMap::new();
@@ -134,7 +729,8 @@ fn to_loc_entities(file_tag: LocEntity, mut tags: Vec<Tag>) -> Vec<LocEntity> {
         };
 
         let tag = Arc::new(tag);
-        loc_entities.push(LocEntity::new(tag.clone(), to_interval(&pre_tag.range)));
+        let loc = to_interval(&pre_tag.range);
+        loc_entities.push(LocEntity::new(tag.clone(), loc, pre_tag.complexity));
         entities.insert(pre_tag.id, tag);
     }
 
@@ -0,0 +1,341 @@
+use std::io::Write;
+use std::process::Command;
+use std::process::Stdio;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use git2::Oid;
+use serde_json::Value;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::ir;
+
+/// How many items are requested per page (the most either platform allows).
+const PER_PAGE: usize = 100;
+
+/// Where a repository and its pull requests are hosted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Platform {
+    /// GitHub (or GitHub Enterprise) pull requests
+    Github,
+    /// GitLab merge requests
+    Gitlab,
+}
+
+impl Platform {
+    pub fn default_api_url(self) -> &'static str {
+        match self {
+            Platform::Github => "https://api.github.com",
+            Platform::Gitlab => "https://gitlab.com/api/v4",
+        }
+    }
+
+    /// The environment variable which holds the access token.
+    pub fn token_var(self) -> &'static str {
+        match self {
+            Platform::Github => "GITHUB_TOKEN",
+            Platform::Gitlab => "GITLAB_TOKEN",
+        }
+    }
+
+    fn accept(self) -> &'static str {
+        match self {
+            Platform::Github => "application/vnd.github+json",
+            Platform::Gitlab => "application/json",
+        }
+    }
+}
+
+/// Reads the pull requests of a repository from the REST API of its platform.
+/// Requests are made by running curl.
+pub struct PrClient {
+    platform: Platform,
+    api_url: String,
+    /// The URL path of the repository (e.g. `repos/owner/name`)
+    project: String,
+    token: Option<String>,
+}
+
+impl PrClient {
+    /// The project is `owner/name` on GitHub and the full path of the project
+    /// (e.g. `group/subgroup/name`) on GitLab.
+    pub fn new(
+        platform: Platform,
+        api_url: Option<String>,
+        project: &str,
+        token: Option<String>,
+    ) -> Self {
+        let api_url = api_url.unwrap_or_else(|| platform.default_api_url().to_string());
+        let project = match platform {
+            Platform::Github => format!("repos/{}", project.trim_matches('/')),
+            Platform::Gitlab => format!("projects/{}", encode(project.trim_matches('/'))),
+        };
+        Self { platform, api_url: api_url.trim_end_matches('/').to_string(), project, token }
+    }
+
+    fn get(&self, url: &str) -> Result<Value> {
+        let mut headers = vec![format!("Accept: {}", self.platform.accept())];
+
+        if let Some(token) = &self.token {
+            headers.push(format!("Authorization: Bearer {}", token));
+        }
+
+        get_json(url, &headers)?.with_context(|| format!("GET {} found nothing", url))
+    }
+
+    /// Gets a page of a list (numbered from 1).
+    fn get_page(&self, path: &str, query: &str, page: usize) -> Result<Vec<Value>> {
+        let url = format!(
+            "{}/{}/{}?{}per_page={}&page={}",
+            self.api_url, self.project, path, query, PER_PAGE, page
+        );
+
+        match self.get(&url)? {
+            Value::Array(items) => Ok(items),
+            other => bail!("expected a list from GET {} but got: {}", url, other),
+        }
+    }
+
+    /// Gets every page of a list.
+    fn get_all(&self, path: &str) -> Result<Vec<Value>> {
+        let mut items = Vec::new();
+
+        for page in 1.. {
+            let page = self.get_page(path, "", page)?;
+            let is_last = page.len() < PER_PAGE;
+            items.extend(page);
+
+            if is_last {
+                break;
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// The pull requests of the repository, the most recently created first.
+    /// Unless unmerged pull requests are included, only merged ones are kept.
+    pub fn list(
+        &self,
+        include_unmerged: bool,
+        max_count: Option<usize>,
+    ) -> Result<Vec<ir::PullRequest>> {
+        let (path, query) = match (self.platform, include_unmerged) {
+            (Platform::Github, true) => ("pulls", "state=all&"),
+            (Platform::Github, false) => ("pulls", "state=closed&"),
+            (Platform::Gitlab, true) => ("merge_requests", "state=all&"),
+            (Platform::Gitlab, false) => ("merge_requests", "state=merged&"),
+        };
+        let max_count = max_count.unwrap_or(usize::MAX);
+        let mut pull_requests = Vec::new();
+
+        for page in 1.. {
+            let items = self.get_page(path, query, page)?;
+            let is_last = items.len() < PER_PAGE;
+
+            for item in &items {
+                if pull_requests.len() == max_count {
+                    break;
+                }
+
+                // Closed pull requests which were not merged are skipped before
+                // their commits and reviews are requested
+                let is_unmerged = self.platform == Platform::Github && item["merged_at"].is_null();
+
+                if !include_unmerged && is_unmerged {
+                    continue;
+                }
+
+                let pull_request = match self.platform {
+                    Platform::Github => self.to_github_pull_request(item)?,
+                    Platform::Gitlab => self.to_gitlab_pull_request(item)?,
+                };
+
+                if include_unmerged || pull_request.state == "merged" {
+                    log::debug!("Read pull request #{}", pull_request.number);
+                    pull_requests.push(pull_request);
+                }
+            }
+
+            if is_last || pull_requests.len() == max_count {
+                break;
+            }
+        }
+
+        Ok(pull_requests)
+    }
+
+    fn to_github_pull_request(&self, item: &Value) -> Result<ir::PullRequest> {
+        let number = get_number(item, "number")?;
+        let author = get_str(&item["user"], "login")?;
+        let merged_at = get_date(item, "merged_at")?;
+        let state = if merged_at.is_some() { "merged" } else { get_str(item, "state")? };
+
+        let commits = self
+            .get_all(&format!("pulls/{}/commits", number))?
+            .iter()
+            .map(|commit| get_oid(commit, "sha"))
+            .collect::<Result<Vec<_>, _>>()?;
+        let merge_commits = match merged_at {
+            Some(_) => get_opt_oid(item, "merge_commit_sha")?.into_iter().collect(),
+            None => Vec::new(),
+        };
+
+        let mut reviewers = get_strs(&item["requested_reviewers"], "login");
+        let reviews = self.get_all(&format!("pulls/{}/reviews", number))?;
+        reviewers
+            .extend(reviews.iter().filter_map(|r| r["user"]["login"].as_str().map(str::to_string)));
+
+        Ok(ir::PullRequestBuilder::default()
+            .number(number)
+            .title(get_str(item, "title")?.to_string())
+            .author(author.to_string())
+            .state(state.to_string())
+            .created_at(get_date(item, "created_at")?.context("missing created_at")?)
+            .merged_at(merged_at)
+            .url(get_str(item, "html_url")?.to_string())
+            .commits(commits)
+            .merge_commits(merge_commits)
+            .labels(get_strs(&item["labels"], "name"))
+            .reviewers(to_reviewers(reviewers, author))
+            .build()?)
+    }
+
+    fn to_gitlab_pull_request(&self, item: &Value) -> Result<ir::PullRequest> {
+        let number = get_number(item, "iid")?;
+        let author = get_str(&item["author"], "username")?;
+        let state = match get_str(item, "state")? {
+            "opened" => "open",
+            "merged" => "merged",
+            _ => "closed",
+        };
+
+        let commits = self
+            .get_all(&format!("merge_requests/{}/commits", number))?
+            .iter()
+            .map(|commit| get_oid(commit, "id"))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut merge_commits = Vec::new();
+        merge_commits.extend(get_opt_oid(item, "merge_commit_sha")?);
+        merge_commits.extend(get_opt_oid(item, "squash_commit_sha")?);
+
+        let mut reviewers = get_strs(&item["reviewers"], "username");
+        let url = format!("{}/{}/merge_requests/{}/approvals", self.api_url, self.project, number);
+        let approvals = self.get(&url)?;
+
+        let approvals = approvals["approved_by"].as_array().into_iter().flatten();
+        reviewers
+            .extend(approvals.filter_map(|a| a["user"]["username"].as_str().map(str::to_string)));
+
+        let labels = item["labels"].as_array().into_iter().flatten();
+        let labels = labels.filter_map(|label| label.as_str().map(str::to_string)).collect();
+
+        Ok(ir::PullRequestBuilder::default()
+            .number(number)
+            .title(get_str(item, "title")?.to_string())
+            .author(author.to_string())
+            .state(state.to_string())
+            .created_at(get_date(item, "created_at")?.context("missing created_at")?)
+            .merged_at(get_date(item, "merged_at")?)
+            .url(get_str(item, "web_url")?.to_string())
+            .commits(commits)
+            .merge_commits(merge_commits)
+            .labels(labels)
+            .reviewers(to_reviewers(reviewers, author))
+            .build()?)
+    }
+}
+
+/// Runs curl to GET a JSON document, which is `None` if the server responds with
+/// 404 Not Found. The headers are written to stdin so that any token is not
+/// visible in the arguments of the process.
+pub fn get_json(url: &str, headers: &[String]) -> Result<Option<Value>> {
+    let mut child = Command::new("curl")
+        .args(["--silent", "--show-error", "--location", "--header", "@-"])
+        .args(["--write-out", "\\n%{http_code}"])
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("failed to run curl (is it installed?)")?;
+
+    let headers = headers.iter().map(|h| format!("{}\n", h)).collect::<String>();
+    let mut stdin = child.stdin.take().context("failed to open stdin of curl")?;
+    stdin.write_all(headers.as_bytes())?;
+    drop(stdin);
+    let output = child.wait_with_output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("GET {} failed ({}): {}", url, output.status, stderr.trim());
+    }
+
+    // The status code is written on a line after the body
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (body, status) = stdout.rsplit_once('\n').unwrap_or(("", &stdout));
+
+    match status.trim() {
+        "404" => Ok(None),
+        status if status.starts_with('2') => serde_json::from_str(body)
+            .map(Some)
+            .with_context(|| format!("failed to parse the response to GET {}", url)),
+        status => bail!("GET {} failed (HTTP {}): {}", url, status, body.trim()),
+    }
+}
+
+/// Percent-encodes every character which may not appear in a segment of a URL
+/// path (e.g. the slashes of a GitLab project path).
+pub fn encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+pub fn get_str<'a>(item: &'a Value, field: &str) -> Result<&'a str> {
+    item[field].as_str().with_context(|| format!("missing {} in {}", field, item))
+}
+
+/// The given field of each item of a list (skipping items without it).
+pub fn get_strs(items: &Value, field: &str) -> Vec<String> {
+    let items = items.as_array().into_iter().flatten();
+    items.filter_map(|item| item[field].as_str().map(str::to_string)).collect()
+}
+
+fn get_number(item: &Value, field: &str) -> Result<u64> {
+    item[field].as_u64().with_context(|| format!("missing {} in {}", field, item))
+}
+
+fn get_date(item: &Value, field: &str) -> Result<Option<OffsetDateTime>> {
+    match item[field].as_str() {
+        Some(date) => Ok(Some(
+            OffsetDateTime::parse(date, &Rfc3339)
+                .with_context(|| format!("invalid {}: {}", field, date))?,
+        )),
+        None => Ok(None),
+    }
+}
+
+fn get_oid(item: &Value, field: &str) -> Result<Oid> {
+    get_opt_oid(item, field)?.with_context(|| format!("missing {} in {}", field, item))
+}
+
+fn get_opt_oid(item: &Value, field: &str) -> Result<Option<Oid>> {
+    item[field].as_str().map(Oid::from_str).transpose().map_err(anyhow::Error::new)
+}
+
+/// Sorts the reviewers and removes the author (who may comment on their own
+/// pull request) and any duplicates.
+fn to_reviewers(mut reviewers: Vec<String>, author: &str) -> Vec<String> {
+    reviewers.retain(|reviewer| reviewer != author);
+    reviewers.sort();
+    reviewers.dedup();
+    reviewers
+}
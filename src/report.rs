@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::analysis::BusFactor;
+use crate::analysis::Hotspot;
+use crate::analysis::Pair;
+use crate::db::Id;
+
+/// Everything shown in an HTML report.
+pub struct Report<'a> {
+    pub title: String,
+    pub paths: &'a HashMap<Id, String>,
+    pub hotspots: &'a [Hotspot],
+    pub pairs: &'a [Pair],
+    /// The number of commits and the churn of each month (oldest first)
+    pub trend: &'a [(String, usize, usize)],
+    /// The bus factors of packages (lowest first)
+    pub packages: &'a [BusFactor],
+    /// The share of all changed lines of each author (largest first)
+    pub authors: &'a [(String, f64)],
+}
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em auto; max-width: 1100px; color: #222; }
+h1 { border-bottom: 2px solid #444; }
+h2 { margin-top: 2em; }
+table { border-collapse: collapse; width: 100%; }
+th, td { padding: 4px 8px; border-bottom: 1px solid #ddd; text-align: left; }
+td.num { text-align: right; font-variant-numeric: tabular-nums; }
+td.path { font-family: monospace; word-break: break-all; }
+.bar { background: #4a7ebb; height: 12px; }
+svg rect { fill: #4a7ebb; }
+svg rect:hover { fill: #c0504d; }
+p.note { color: #666; }
+";
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Writes the report as a single HTML page without any external resources.
+pub fn write_html_report<W: Write>(w: &mut W, report: &Report) -> Result<()> {
+    let title = escape_html(&report.title);
+    writeln!(w, "<!DOCTYPE html>")?;
+    writeln!(w, r#"<html lang="en">"#)?;
+    writeln!(w, r#"<head><meta charset="utf-8"><title>{}</title>"#, title)?;
+    writeln!(w, "<style>{}</style></head>", STYLE)?;
+    writeln!(w, "<body>")?;
+    writeln!(w, "<h1>{}</h1>", title)?;
+
+    write_hotspots(w, report)?;
+    write_pairs(w, report)?;
+    write_trend(w, report)?;
+    write_ownership(w, report)?;
+
+    writeln!(w, "</body>")?;
+    writeln!(w, "</html>")?;
+    Ok(())
+}
+
+fn write_hotspots<W: Write>(w: &mut W, report: &Report) -> Result<()> {
+    writeln!(w, "<h2>Hotspots</h2>")?;
+    writeln!(w, r#"<p class="note">Entities which change often and are large.</p>"#)?;
+    writeln!(w, "<table>")?;
+    writeln!(
+        w,
+        "<tr><th>Entity</th><th>Changes</th><th>Churn</th><th>Size</th><th>Score</th></tr>"
+    )?;
+
+    for h in report.hotspots {
+        writeln!(
+            w,
+            r#"<tr><td class="path">{}</td><td class="num">{:.0}</td><td class="num">{}</td><td class="num">{}</td><td class="num">{:.0}</td></tr>"#,
+            escape_html(&report.paths[&h.entity_id]),
+            h.changes,
+            h.churn,
+            h.size,
+            h.score
+        )?;
+    }
+
+    writeln!(w, "</table>")?;
+    Ok(())
+}
+
+fn write_pairs<W: Write>(w: &mut W, report: &Report) -> Result<()> {
+    writeln!(w, "<h2>Strongest Couplings</h2>")?;
+    writeln!(w, r#"<p class="note">Pairs of entities which frequently change together.</p>"#)?;
+    writeln!(w, "<table>")?;
+    writeln!(w, "<tr><th>Entity</th><th>Entity</th><th>Support</th><th>Confidence</th></tr>")?;
+
+    for p in report.pairs {
+        writeln!(
+            w,
+            r#"<tr><td class="path">{}</td><td class="path">{}</td><td class="num">{:.0}</td><td class="num">{:.0}%</td></tr>"#,
+            escape_html(&report.paths[&p.entity1_id]),
+            escape_html(&report.paths[&p.entity2_id]),
+            p.support,
+            p.confidence * 100.0
+        )?;
+    }
+
+    writeln!(w, "</table>")?;
+    Ok(())
+}
+
+/// Writes the monthly churn as an inline SVG bar chart.
+fn write_trend<W: Write>(w: &mut W, report: &Report) -> Result<()> {
+    const WIDTH: usize = 1000;
+    const HEIGHT: usize = 200;
+
+    writeln!(w, "<h2>Churn Trend</h2>")?;
+    writeln!(w, r#"<p class="note">Lines added and deleted per month.</p>"#)?;
+
+    if report.trend.is_empty() {
+        return Ok(());
+    }
+
+    let max = report.trend.iter().map(|&(_, _, churn)| churn).max().unwrap_or_default().max(1);
+    let bar_width = WIDTH as f64 / report.trend.len() as f64;
+    writeln!(w, r#"<svg viewBox="0 0 {} {}" width="100%">"#, WIDTH, HEIGHT)?;
+
+    for (i, (month, commits, churn)) in report.trend.iter().enumerate() {
+        let height = (*churn as f64 / max as f64) * HEIGHT as f64;
+        writeln!(
+            w,
+            r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}"><title>{}: {} lines in {} commits</title></rect>"#,
+            i as f64 * bar_width,
+            HEIGHT as f64 - height,
+            (bar_width - 1.0).max(1.0),
+            height,
+            escape_html(month),
+            churn,
+            commits
+        )?;
+    }
+
+    writeln!(w, "</svg>")?;
+
+    let (first, last) = (&report.trend[0].0, &report.trend[report.trend.len() - 1].0);
+    writeln!(w, r#"<p class="note">{} to {}</p>"#, escape_html(first), escape_html(last))?;
+    Ok(())
+}
+
+fn write_ownership<W: Write>(w: &mut W, report: &Report) -> Result<()> {
+    writeln!(w, "<h2>Ownership</h2>")?;
+    writeln!(w, r#"<p class="note">Share of all changed lines by author.</p>"#)?;
+    writeln!(w, "<table>")?;
+    writeln!(w, "<tr><th>Author</th><th>Share</th><th></th></tr>")?;
+
+    for (author, share) in report.authors {
+        writeln!(
+            w,
+            r#"<tr><td>{}</td><td class="num">{:.1}%</td><td style="width: 50%"><div class="bar" style="width: {:.1}%"></div></td></tr>"#,
+            escape_html(author),
+            share * 100.0,
+            share * 100.0
+        )?;
+    }
+
+    writeln!(w, "</table>")?;
+    writeln!(
+        w,
+        r#"<p class="note">Packages with the lowest bus factor (the fewest authors who together wrote most of the package).</p>"#
+    )?;
+    writeln!(w, "<table>")?;
+    writeln!(w, "<tr><th>Package</th><th>Bus Factor</th><th>Top Author</th><th>Share</th></tr>")?;
+
+    for b in report.packages {
+        let (author, share) = &b.shares[0];
+        writeln!(
+            w,
+            r#"<tr><td class="path">{}</td><td class="num">{}</td><td>{}</td><td class="num">{:.1}%</td></tr>"#,
+            escape_html(&b.name),
+            b.bus_factor,
+            escape_html(author),
+            share * 100.0
+        )?;
+    }
+
+    writeln!(w, "</table>")?;
+    Ok(())
+}
@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use rusqlite::Connection;
+use serde_json::json;
+use serde_json::Value;
+use tiny_http::Header;
+use tiny_http::Method;
+use tiny_http::Request;
+use tiny_http::Response;
+use tiny_http::Server;
+
+use crate::analysis::find_coupled_pairs;
+use crate::analysis::find_suggestions;
+use crate::analysis::load_entity_log;
+use crate::analysis::resolve_entity;
+use crate::analysis::Coupling;
+use crate::db::Id;
+
+/// Everything needed to answer requests. The coupling is computed once when
+/// the server starts.
+pub struct Context<'a> {
+    pub conn: &'a Connection,
+    pub coupling: &'a Coupling,
+    pub parents: &'a HashMap<Id, Option<Id>>,
+    pub paths: &'a HashMap<Id, String>,
+    pub kinds: &'a HashMap<Id, String>,
+}
+
+/// An error which is reported to the client with the given status code.
+struct HttpError(u16, String);
+
+impl From<anyhow::Error> for HttpError {
+    fn from(err: anyhow::Error) -> Self {
+        HttpError(500, err.to_string())
+    }
+}
+
+type HttpResult = std::result::Result<Value, HttpError>;
+
+/// Decodes a percent-encoded component of a URL (with `+` as a space).
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// The path and query parameters of a request URL.
+struct Url {
+    segments: Vec<String>,
+    params: Vec<(String, String)>,
+}
+
+impl Url {
+    fn parse(url: &str) -> Self {
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let segments = path.split('/').filter(|s| !s.is_empty()).map(decode).collect();
+        let params = query
+            .split('&')
+            .filter(|p| !p.is_empty())
+            .map(|p| match p.split_once('=') {
+                Some((k, v)) => (decode(k), decode(v)),
+                None => (decode(p), String::new()),
+            })
+            .collect();
+        Self { segments, params }
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+
+    fn get_all(&self, name: &str) -> Vec<&str> {
+        self.params.iter().filter(|(k, _)| k == name).map(|(_, v)| v.as_str()).collect()
+    }
+
+    fn parse_or<T: FromStr>(&self, name: &str, default: T) -> std::result::Result<T, HttpError> {
+        match self.get(name) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| HttpError(400, format!("invalid value for '{}': {}", name, value))),
+            None => Ok(default),
+        }
+    }
+}
+
+impl<'a> Context<'a> {
+    fn entity(&self, id: Id) -> Value {
+        json!({ "id": id, "path": self.paths[&id], "kind": self.kinds.get(&id) })
+    }
+
+    fn parse_id(&self, text: &str) -> std::result::Result<Id, HttpError> {
+        match text.parse() {
+            Ok(id) if self.paths.contains_key(&id) => Ok(id),
+            _ => Err(HttpError(404, format!("no entity with id '{}'", text))),
+        }
+    }
+
+    /// GET /entities?q=<TEXT>&kind=<KIND>&limit=<N>
+    fn entities(&self, url: &Url) -> HttpResult {
+        let query = url.get("q").unwrap_or_default().to_lowercase();
+        let kind = url.get("kind");
+        let limit = url.parse_or("limit", 100)?;
+
+        let mut ids = self
+            .paths
+            .iter()
+            .filter(|(_, path)| path.to_lowercase().contains(&query))
+            .filter(|(id, _)| kind.is_none() || self.kinds.get(id).map(String::as_str) == kind)
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+        ids.sort_by(|a, b| self.paths[a].cmp(&self.paths[b]));
+
+        Ok(Value::Array(ids.into_iter().take(limit).map(|id| self.entity(id)).collect()))
+    }
+
+    /// GET /entities/<ID>/changes
+    fn changes(&self, id: Id) -> HttpResult {
+        let changes = load_entity_log(self.conn, id)?
+            .into_iter()
+            .map(|c| {
+                json!({
+                    "sha1": c.sha1,
+                    "commit_time": c.commit_time,
+                    "kind": c.kind,
+                    "adds": c.adds,
+                    "dels": c.dels,
+                })
+            })
+            .collect();
+        Ok(Value::Array(changes))
+    }
+
+    /// GET /entities/<ID>/partners?limit=<N>
+    fn partners(&self, id: Id, url: &Url) -> HttpResult {
+        let limit = url.parse_or("limit", 20)?;
+        let mut partners = self
+            .coupling
+            .pairs
+            .keys()
+            .filter_map(|&(a, b)| match (a == id, b == id) {
+                (true, _) => Some(b),
+                (_, true) => Some(a),
+                _ => None,
+            })
+            .map(|other| {
+                (other, self.coupling.support(id, other), self.coupling.confidence(id, other))
+            })
+            .collect::<Vec<_>>();
+        partners.sort_by(|x, y| y.2.total_cmp(&x.2).then(y.1.total_cmp(&x.1)).then(x.0.cmp(&y.0)));
+
+        let partners = partners
+            .into_iter()
+            .take(limit)
+            .map(|(other, support, confidence)| {
+                json!({ "entity": self.entity(other), "support": support, "confidence": confidence })
+            })
+            .collect();
+        Ok(Value::Array(partners))
+    }
+
+    /// GET /coupling?min_support=<N>&min_confidence=<N>&limit=<N>
+    fn coupling(&self, url: &Url) -> HttpResult {
+        let min_support = url.parse_or("min_support", 5.0)?;
+        let min_confidence = url.parse_or("min_confidence", 0.5)?;
+        let limit = url.parse_or("limit", 100)?;
+        let pairs = find_coupled_pairs(self.coupling, self.parents, min_support, min_confidence)
+            .into_iter()
+            .take(limit)
+            .map(|p| {
+                json!({
+                    "entity1": self.entity(p.entity1_id),
+                    "entity2": self.entity(p.entity2_id),
+                    "support": p.support,
+                    "confidence": p.confidence,
+                })
+            })
+            .collect();
+        Ok(Value::Array(pairs))
+    }
+
+    /// GET /suggestions?entity=<PATH>&entity=<PATH>&min_support=<N>&min_confidence=<N>
+    fn suggestions(&self, url: &Url) -> HttpResult {
+        let min_support = url.parse_or("min_support", 2.0)?;
+        let min_confidence = url.parse_or("min_confidence", 0.5)?;
+        let limit = url.parse_or("limit", 10)?;
+        let mut changed = HashSet::new();
+
+        for entity in url.get_all("entity") {
+            let id =
+                resolve_entity(self.paths, entity).map_err(|e| HttpError(404, e.to_string()))?;
+            changed.insert(id);
+        }
+
+        let suggestions =
+            find_suggestions(self.coupling, self.parents, &changed, min_support, min_confidence)
+                .into_iter()
+                .take(limit)
+                .map(|s| {
+                    json!({
+                        "entity": self.entity(s.entity_id),
+                        "because": self.entity(s.because_id),
+                        "support": s.support,
+                        "confidence": s.confidence,
+                    })
+                })
+                .collect();
+        Ok(Value::Array(suggestions))
+    }
+
+    fn route(&self, request: &Request) -> HttpResult {
+        if request.method() != &Method::Get {
+            return Err(HttpError(405, "only GET is supported".to_string()));
+        }
+
+        let url = Url::parse(request.url());
+        let segments = url.segments.iter().map(String::as_str).collect::<Vec<_>>();
+
+        match segments.as_slice() {
+            ["entities"] => self.entities(&url),
+            ["entities", id] => Ok(self.entity(self.parse_id(id)?)),
+            ["entities", id, "changes"] => self.changes(self.parse_id(id)?),
+            ["entities", id, "partners"] => self.partners(self.parse_id(id)?, &url),
+            ["coupling"] => self.coupling(&url),
+            ["suggestions"] => self.suggestions(&url),
+            _ => Err(HttpError(404, format!("no route for '{}'", request.url()))),
+        }
+    }
+}
+
+/// Answers requests on the given address (e.g. 127.0.0.1:8080) until the
+/// process is killed. Every response is JSON. Pages served from `cors_origin`
+/// (if given) may read the responses too.
+pub fn serve(addr: &str, cors_origin: Option<&str>, ctx: &Context) -> Result<()> {
+    let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+    let allow_origin = cors_origin
+        .map(|origin| Header::from_bytes("Access-Control-Allow-Origin", origin))
+        .transpose()
+        .map_err(|_| anyhow!("invalid CORS origin '{}'", cors_origin.unwrap_or_default()))?;
+
+    let server = Server::http(addr).map_err(|e| anyhow!("failed to listen on {}: {}", addr, e))?;
+    log::info!("Listening on http://{}", addr);
+
+    for request in server.incoming_requests() {
+        let (status, body) = match ctx.route(&request) {
+            Ok(body) => (200, body),
+            Err(HttpError(status, message)) => (status, json!({ "error": message })),
+        };
+
+        log::debug!("{} {} {}", request.method(), request.url(), status);
+
+        let mut response = Response::from_string(body.to_string())
+            .with_status_code(status)
+            .with_header(content_type.clone());
+
+        if let Some(allow_origin) = &allow_origin {
+            response.add_header(allow_origin.clone());
+        }
+
+        if let Err(err) = request.respond(response) {
+            log::warn!("Failed to respond: {}", err);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use git2::Oid;
+
+use crate::extraction::diff_all_files;
+use crate::extraction::fetch_missing_blob;
+use crate::extraction::CommitWalk;
+use crate::extraction::MergeMode;
+use crate::gtl;
+use crate::ir;
+
+/// The version control system of a repository.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum VcsKind {
+    Git,
+    /// Mercurial (using the hg executable)
+    Hg,
+}
+
+/// The contents of file revisions which are not in the object database, keyed
+/// by their hashes.
+pub type Blobs = HashMap<Oid, Vec<u8>>;
+
+/// What the extraction needs from a version control system. Commits and file
+/// revisions are identified by 20-byte hashes, which git and Mercurial share.
+pub trait Vcs {
+    /// The root of the repository.
+    fn root(&self) -> PathBuf;
+
+    /// The URL of the default remote (if any), which may hold credentials (see
+    /// `strip_credentials`).
+    fn remote_url(&self) -> Option<String>;
+
+    /// The commit which is checked out (if any).
+    fn head(&self) -> Option<Oid>;
+
+    /// Resolves a revision (e.g. a branch, tag, or hash) to a commit.
+    fn resolve(&self, rev: &str) -> Result<ir::Commit>;
+
+    /// The commits reachable from the given revisions but not from the hidden
+    /// ones, newest first.
+    fn walk(&self, revs: &[String], hide: &[String]) -> Result<Vec<ir::Commit>>;
+
+    /// The changed files of the given commits with the suffix (or of every file
+    /// if there is none), along with whatever was skipped.
+    fn diff(
+        &self,
+        commits: &[ir::Commit],
+        suffix: Option<&'static str>,
+        merges: MergeMode,
+    ) -> Result<(Vec<ir::DiffedFile>, Vec<ir::Diagnostic>)>;
+
+    /// Every file of a commit (but not its symlinks or submodules) along with
+    /// the revision of the file.
+    fn list_files(&self, commit: Oid) -> Result<Vec<(String, Oid)>>;
+
+    /// The tree of a commit if the system identifies directories by the hashes
+    /// of their contents (as git does), which lets unchanged directories be
+    /// recognized without reading them (see `read_tree`).
+    fn commit_tree(&self, _commit: Oid) -> Result<Option<Oid>> {
+        Ok(None)
+    }
+
+    /// The files (but not symlinks or submodules) and the subtrees directly in
+    /// a tree (see `commit_tree`) along with their hashes. The names of
+    /// subtrees end with a slash.
+    fn read_tree(&self, tree: Oid) -> Result<Vec<(String, Oid)>> {
+        bail!("cannot read tree {}", tree)
+    }
+
+    /// The content of a revision of the file at the given path. Only some
+    /// systems (e.g. Mercurial) need the path to find the revision.
+    fn read_blob(&self, path: &str, blob: Oid) -> Result<Vec<u8>>;
+
+    /// Opens the repository again, e.g. for another thread to read blobs from.
+    fn reopen(&self) -> Result<Box<dyn Vcs + Send>>;
+
+    /// Fetches a revision of a file which is missing from a partial clone.
+    fn fetch_blob(&self, blob: Oid) -> Result<()> {
+        bail!("cannot fetch missing blob {}", blob)
+    }
+}
+
+impl Vcs for git2::Repository {
+    fn root(&self) -> PathBuf {
+        self.workdir().unwrap_or(self.path()).to_path_buf()
+    }
+
+    fn remote_url(&self) -> Option<String> {
+        self.find_remote("origin").ok().and_then(|remote| remote.url().map(str::to_string))
+    }
+
+    fn head(&self) -> Option<Oid> {
+        self.head().ok().and_then(|head| head.target())
+    }
+
+    fn resolve(&self, rev: &str) -> Result<ir::Commit> {
+        gtl::to_commit(&self.revparse_single(rev)?.peel_to_commit()?)
+    }
+
+    fn walk(&self, revs: &[String], hide: &[String]) -> Result<Vec<ir::Commit>> {
+        let mut walk = CommitWalk::new();
+        walk.set_sort(git2::Sort::TIME);
+
+        for rev in revs {
+            walk.push_start_oid(self.revparse_single(rev)?.peel_to_commit()?.id());
+        }
+
+        for rev in hide {
+            walk.hide_oid(self.revparse_single(rev)?.peel_to_commit()?.id());
+        }
+
+        walk.walk(self)?.map(|commit| gtl::to_commit(&commit?)).collect()
+    }
+
+    fn diff(
+        &self,
+        commits: &[ir::Commit],
+        suffix: Option<&'static str>,
+        merges: MergeMode,
+    ) -> Result<(Vec<ir::DiffedFile>, Vec<ir::Diagnostic>)> {
+        let commits =
+            commits.iter().map(|c| self.find_commit(c.sha1)).collect::<Result<Vec<_>, _>>()?;
+        let (diffed_files, _, diagnostics) =
+            diff_all_files(self, &commits, None, suffix, merges, false)?;
+        Ok((diffed_files, diagnostics))
+    }
+
+    fn list_files(&self, commit: Oid) -> Result<Vec<(String, Oid)>> {
+        let mut files = Vec::new();
+
+        self.find_commit(commit)?.tree()?.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+            // Skip submodules, trees, and symlinks
+            if entry.kind() == Some(git2::ObjectType::Blob)
+                && entry.filemode() != i32::from(git2::FileMode::Link)
+            {
+                files.push((format!("{}{}", dir, entry.name().unwrap()), entry.id()));
+            }
+
+            git2::TreeWalkResult::Ok
+        })?;
+
+        Ok(files)
+    }
+
+    fn commit_tree(&self, commit: Oid) -> Result<Option<Oid>> {
+        Ok(Some(self.find_commit(commit)?.tree_id()))
+    }
+
+    fn read_tree(&self, tree: Oid) -> Result<Vec<(String, Oid)>> {
+        let mut entries = Vec::new();
+
+        for entry in self.find_tree(tree)?.iter() {
+            let name = entry.name().context("invalid path in tree")?;
+
+            // Skip submodules and symlinks
+            match entry.kind() {
+                Some(git2::ObjectType::Tree) => entries.push((format!("{}/", name), entry.id())),
+                Some(git2::ObjectType::Blob)
+                    if entry.filemode() != i32::from(git2::FileMode::Link) =>
+                {
+                    entries.push((name.to_string(), entry.id()))
+                }
+                _ => {}
+            }
+        }
+
+        Ok(entries)
+    }
+
+    fn read_blob(&self, _path: &str, blob: Oid) -> Result<Vec<u8>> {
+        Ok(self.find_blob(blob)?.content().to_vec())
+    }
+
+    fn reopen(&self) -> Result<Box<dyn Vcs + Send>> {
+        Ok(Box::new(git2::Repository::open(self.path())?))
+    }
+
+    fn fetch_blob(&self, blob: Oid) -> Result<()> {
+        fetch_missing_blob(self, blob)
+    }
+}
+
+/// A git repository along with the revisions of files which are only in the
+/// working tree (see `diff_uncommitted`), so that they are read from memory
+/// rather than written to the object database first.
+pub struct WorkdirVcs {
+    repo: git2::Repository,
+    blobs: Arc<Blobs>,
+}
+
+impl WorkdirVcs {
+    pub fn new(repo: git2::Repository, blobs: Blobs) -> Self {
+        Self { repo, blobs: Arc::new(blobs) }
+    }
+}
+
+impl Vcs for WorkdirVcs {
+    fn root(&self) -> PathBuf {
+        Vcs::root(&self.repo)
+    }
+
+    fn remote_url(&self) -> Option<String> {
+        self.repo.remote_url()
+    }
+
+    fn head(&self) -> Option<Oid> {
+        Vcs::head(&self.repo)
+    }
+
+    fn resolve(&self, rev: &str) -> Result<ir::Commit> {
+        self.repo.resolve(rev)
+    }
+
+    fn walk(&self, revs: &[String], hide: &[String]) -> Result<Vec<ir::Commit>> {
+        Vcs::walk(&self.repo, revs, hide)
+    }
+
+    fn diff(
+        &self,
+        commits: &[ir::Commit],
+        suffix: Option<&'static str>,
+        merges: MergeMode,
+    ) -> Result<(Vec<ir::DiffedFile>, Vec<ir::Diagnostic>)> {
+        Vcs::diff(&self.repo, commits, suffix, merges)
+    }
+
+    fn list_files(&self, commit: Oid) -> Result<Vec<(String, Oid)>> {
+        self.repo.list_files(commit)
+    }
+
+    fn commit_tree(&self, commit: Oid) -> Result<Option<Oid>> {
+        self.repo.commit_tree(commit)
+    }
+
+    fn read_tree(&self, tree: Oid) -> Result<Vec<(String, Oid)>> {
+        self.repo.read_tree(tree)
+    }
+
+    fn read_blob(&self, path: &str, blob: Oid) -> Result<Vec<u8>> {
+        match self.blobs.get(&blob) {
+            Some(content) => Ok(content.clone()),
+            None => self.repo.read_blob(path, blob),
+        }
+    }
+
+    fn reopen(&self) -> Result<Box<dyn Vcs + Send>> {
+        let repo = git2::Repository::open(self.repo.path())?;
+        Ok(Box::new(Self { repo, blobs: self.blobs.clone() }))
+    }
+
+    fn fetch_blob(&self, blob: Oid) -> Result<()> {
+        self.repo.fetch_blob(blob)
+    }
+}
+
+/// The given remote URL without the parts which may hold credentials, i.e. the
+/// user info (`user:token@`), query, and fragment. URLs without a scheme (e.g.
+/// `git@host:path` or a local path) are returned as they are.
+pub fn strip_credentials(url: &str) -> String {
+    let (scheme, rest) = match url.split_once("://") {
+        Some(parts) => parts,
+        None => return url.to_string(),
+    };
+
+    let rest = rest.split(['?', '#']).next().unwrap_or_default();
+    let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    format!("{}://{}{}", scheme, host, path)
+}
@@ -0,0 +1,158 @@
+mod common;
+
+use cochange_tool::analysis::find_churn_trend;
+use cochange_tool::analysis::find_commit_metrics;
+use cochange_tool::analysis::load_change_sets;
+use cochange_tool::analysis::load_commit_shapes;
+use cochange_tool::analysis::load_contributions;
+use cochange_tool::analysis::load_fix_counts;
+use cochange_tool::analysis::load_touches;
+use cochange_tool::db::insert_issue;
+use cochange_tool::db::Id;
+use cochange_tool::db::IssueDb;
+use cochange_tool::ir::Issue;
+use common::query_column;
+use common::TestRepo;
+use rusqlite::Connection;
+
+const CLASS_A: &str = "class A {\n    void f() {}\n}\n";
+const CLASS_B: &str = "class B {\n    void f() {}\n}\n";
+
+fn commit_id(conn: &Connection, message: &str) -> Id {
+    conn.query_row("SELECT id FROM commits WHERE message = ?", [message], |row| row.get(0)).unwrap()
+}
+
+fn entity_id(conn: &Connection, name: &str) -> Id {
+    conn.query_row("SELECT id FROM entities WHERE name = ?", [name], |row| row.get(0)).unwrap()
+}
+
+#[test]
+fn counts_fixes_linked_to_pull_requests_by_message() {
+    let mut repo = TestRepo::new("pr-fixes");
+    repo.write("A.java", CLASS_A);
+    repo.commit("Add A");
+    repo.write("A.java", &CLASS_A.replace("f()", "g()"));
+    repo.commit("Fix the name of f (#123)");
+    repo.write("A.java", &CLASS_A.replace("f()", "h()"));
+    repo.commit("Rename g (#124)");
+
+    let mut conn = repo.dump(&[]);
+    let fix_id = commit_id(&conn, "Fix the name of f (#123)");
+    let rename_id = commit_id(&conn, "Rename g (#124)");
+    let pr = |key: &str| {
+        let title = "A pull request".to_string();
+        let kind = Some("Pull Request".to_string());
+        Issue::new(key.to_string(), kind, title, "closed".to_string(), None, Vec::new(), false)
+    };
+    let mut db = IssueDb::new();
+    insert_issue(&mut db, &pr("#123"), &[fix_id]);
+    insert_issue(&mut db, &pr("#124"), &[rename_id]);
+    let tx = conn.transaction().unwrap();
+    db.write(&tx).unwrap();
+    tx.commit().unwrap();
+
+    let counts = load_fix_counts(&conn).unwrap();
+    assert_eq!(counts.get(&entity_id(&conn, "A.java")), Some(&1));
+}
+
+#[test]
+fn lets_classified_issues_override_the_message() {
+    let mut repo = TestRepo::new("issue-fixes");
+    repo.write("A.java", CLASS_A);
+    repo.commit("Add A");
+    repo.write("A.java", &CLASS_A.replace("f()", "g()"));
+    repo.commit("Fix the name of f (#123)");
+
+    let mut conn = repo.dump(&[]);
+    let fix_id = commit_id(&conn, "Fix the name of f (#123)");
+    let issue = Issue::new(
+        "#123".to_string(),
+        Some("Feature".to_string()),
+        "Rename f".to_string(),
+        "closed".to_string(),
+        None,
+        Vec::new(),
+        false,
+    );
+    let mut db = IssueDb::new();
+    insert_issue(&mut db, &issue, &[fix_id]);
+    let tx = conn.transaction().unwrap();
+    db.write(&tx).unwrap();
+    tx.commit().unwrap();
+
+    let counts = load_fix_counts(&conn).unwrap();
+    assert_eq!(counts.get(&entity_id(&conn, "A.java")), None);
+}
+
+#[test]
+fn counts_merges_once_across_parents() {
+    let mut repo = TestRepo::new("all-parents");
+    repo.write("A.java", CLASS_A);
+    repo.write("B.java", CLASS_B);
+    let base = repo.commit("Add A and B");
+    repo.write("B.java", &CLASS_B.replace("f()", "g()"));
+    let ours = repo.commit("Rename B.f");
+    repo.write("B.java", CLASS_B);
+    repo.write("A.java", &CLASS_A.replace("f()", "g()"));
+    let theirs = repo.commit_with_parents("Rename A.f", &[base]);
+    // The merge changes A against both of its parents
+    repo.write("B.java", &CLASS_B.replace("f()", "g()"));
+    repo.write("A.java", &CLASS_A.replace("f()", "h()\n    void i()"));
+    repo.commit_with_parents("Fix A while merging", &[ours, theirs]);
+
+    let conn = repo.dump(&["--merges", "all-parents"]);
+    let a = entity_id(&conn, "A.java");
+    let merge_id = commit_id(&conn, "Fix A while merging");
+    let merge_rows: usize = conn
+        .query_row(
+            "SELECT COUNT(*) FROM changes WHERE commit_id = ? AND entity_id = ?",
+            [merge_id, a],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(merge_rows, 2);
+
+    let touches = load_touches(&conn, None).unwrap();
+    assert_eq!(touches["tester@example.com"][&a], 3);
+
+    let fix_counts = load_fix_counts(&conn).unwrap();
+    assert_eq!(fix_counts.get(&a), Some(&1));
+
+    let shapes = load_commit_shapes(&conn).unwrap();
+    let merge = shapes.iter().find(|s| s.commit_id == merge_id).unwrap();
+    let files: Vec<&str> = merge.files.iter().map(|f| f.0.as_str()).collect();
+    assert_eq!(files, ["A.java", "B.java"]);
+
+    // Only the largest churn of A in the merge counts
+    let merge_lines: usize = conn
+        .query_row(
+            "SELECT MAX(adds + dels) FROM changes WHERE commit_id = ? AND entity_id = ?",
+            [merge_id, a],
+            |row| row.get(0),
+        )
+        .unwrap();
+    let contributions = load_contributions(&conn).unwrap();
+    assert_eq!(contributions.lines[&a]["tester@example.com"], 3 + 2 + merge_lines);
+}
+
+#[test]
+fn counts_the_churn_trend_in_file_lines() {
+    let mut repo = TestRepo::new("churn-trend");
+    repo.write("A.java", CLASS_A);
+    repo.commit("Add A");
+    repo.write("A.java", "class A {\n    void f() {\n        g();\n    }\n}\n");
+    repo.commit("Call g");
+
+    let conn = repo.dump(&[]);
+    let trend =
+        find_churn_trend(&load_change_sets(&conn).unwrap(), &find_commit_metrics(&conn).unwrap());
+    let file_churn: Vec<usize> = query_column(
+        &conn,
+        "SELECT SUM(CH.adds + CH.dels)
+        FROM changes CH
+        JOIN entities E ON E.id = CH.entity_id
+        WHERE E.kind = 'file'",
+    );
+    assert_eq!(trend, [("2020-09".to_string(), 2, file_churn[0])]);
+    assert_eq!(file_churn[0], 3 + 4);
+}
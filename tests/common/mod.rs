@@ -0,0 +1,122 @@
+//! Helpers for the integration tests, which build small git repositories in a
+//! temporary directory and run the cochange-tool binary against them.
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use git2::IndexAddOption;
+use git2::Oid;
+use git2::Repository;
+use git2::Signature;
+use git2::Time;
+use rusqlite::Connection;
+
+/// A git repository in a fresh temporary directory, which is removed on drop.
+pub struct TestRepo {
+    pub dir: PathBuf,
+    repo: Repository,
+    time: i64,
+}
+
+impl TestRepo {
+    pub fn new(name: &str) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("cochange-tool-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let repo = Repository::init(&dir).unwrap();
+        Self { dir, repo, time: 1_600_000_000 }
+    }
+
+    pub fn write(&self, path: &str, content: &str) {
+        let path = self.dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[cfg(unix)]
+    pub fn symlink(&self, path: &str, target: &str) {
+        let path = self.dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::os::unix::fs::symlink(target, path).unwrap();
+    }
+
+    pub fn remove(&self, path: &str) {
+        fs::remove_file(self.dir.join(path)).unwrap();
+    }
+
+    /// Commits the working tree on top of HEAD (if any).
+    pub fn commit(&mut self, message: &str) -> Oid {
+        let parents = match self.repo.head() {
+            Ok(head) => vec![head.target().unwrap()],
+            Err(_) => Vec::new(),
+        };
+
+        self.commit_with_parents(message, &parents)
+    }
+
+    /// Commits the working tree with the given parents and points HEAD at it.
+    pub fn commit_with_parents(&mut self, message: &str, parents: &[Oid]) -> Oid {
+        let mut index = self.repo.index().unwrap();
+        index.add_all(["*"], IndexAddOption::DEFAULT, None).unwrap();
+        index.update_all(["*"], None).unwrap();
+        index.write().unwrap();
+        let tree = self.repo.find_tree(index.write_tree().unwrap()).unwrap();
+
+        self.time += 60;
+        let sig = Signature::new("Tester", "tester@example.com", &Time::new(self.time, 0)).unwrap();
+        let parents =
+            parents.iter().map(|p| self.repo.find_commit(*p).unwrap()).collect::<Vec<_>>();
+        let parents = parents.iter().collect::<Vec<_>>();
+        let oid = self.repo.commit(None, &sig, &sig, message, &tree, &parents).unwrap();
+
+        self.repo.reference("refs/heads/master", oid, true, "test").unwrap();
+        self.repo.set_head("refs/heads/master").unwrap();
+        oid
+    }
+
+    /// Dumps HEAD into a new database next to the repository.
+    pub fn dump(&self, args: &[&str]) -> Connection {
+        let db = self.db_path();
+        let dir = self.dir.to_str().unwrap();
+        let db_path = db.to_str().unwrap();
+        let mut dump_args = vec!["dump", "-C", dir, "--db", db_path, "--force"];
+        dump_args.extend(args);
+        dump_args.push("HEAD");
+        run(&dump_args);
+        Connection::open(db).unwrap()
+    }
+
+    pub fn db_path(&self) -> PathBuf {
+        self.dir.with_extension("db")
+    }
+}
+
+impl Drop for TestRepo {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+        let _ = fs::remove_file(self.db_path());
+    }
+}
+
+/// Runs the binary with the given arguments and returns what it wrote to
+/// stdout, panicking if it failed.
+pub fn run(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_cochange-tool")).args(args).output().unwrap();
+
+    if !output.status.success() {
+        panic!("{:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+    }
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// The values of the first column of each row of the query.
+pub fn query_column<T: rusqlite::types::FromSql>(conn: &Connection, sql: &str) -> Vec<T> {
+    let mut stmt = conn.prepare(sql).unwrap();
+    let rows = stmt.query_map([], |row| row.get(0)).unwrap();
+    rows.collect::<Result<_, _>>().unwrap()
+}
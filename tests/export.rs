@@ -0,0 +1,27 @@
+use cochange_tool::export::LineConvention;
+use cochange_tool::export::RangeEnd;
+
+#[test]
+fn converts_line_ranges() {
+    let zero_based = LineConvention::new(0, Some(RangeEnd::Exclusive));
+    assert_eq!(zero_based.convert(3, Some(5), true), (2, Some(4)));
+    assert_eq!(zero_based.convert(3, Some(5), false), (2, Some(5)));
+
+    let inclusive = LineConvention::new(1, Some(RangeEnd::Inclusive));
+    assert_eq!(inclusive.convert(3, Some(5), true), (3, Some(4)));
+    assert_eq!(inclusive.convert(3, Some(5), false), (3, Some(5)));
+    assert_eq!(inclusive.convert(3, None, true), (3, None));
+
+    // Lines inserted after line 3 leave an empty range on the old side
+    assert_eq!(inclusive.convert(3, Some(3), true), (3, Some(2)));
+}
+
+#[test]
+fn keeps_empty_ranges_before_the_first_line_at_zero() {
+    for base in [0, 1] {
+        for end in [None, Some(RangeEnd::Exclusive), Some(RangeEnd::Inclusive)] {
+            let lines = LineConvention::new(base, end);
+            assert_eq!(lines.convert(0, Some(0), true), (0, Some(0)), "{:?}", lines);
+        }
+    }
+}
@@ -0,0 +1,18 @@
+use cochange_tool::parsing::normalize_path;
+use cochange_tool::parsing::unquote_path;
+
+#[test]
+fn normalizes_paths_to_nfc() {
+    assert_eq!(normalize_path("cafe\u{301}/Cafe\u{301}.java"), "caf\u{e9}/Caf\u{e9}.java");
+    assert_eq!(normalize_path("caf\u{e9}/Caf\u{e9}.java"), "caf\u{e9}/Caf\u{e9}.java");
+    assert_eq!(normalize_path("src/A.java"), "src/A.java");
+}
+
+#[test]
+fn unquotes_paths_written_by_git() {
+    assert_eq!(unquote_path(r#""caf\303\251.java""#), "caf\u{e9}.java");
+    assert_eq!(unquote_path(r#""cafe\314\201.java""#), "caf\u{e9}.java");
+    assert_eq!(unquote_path(r#""a\tb.java""#), "a\tb.java");
+    assert_eq!(unquote_path("cafe\u{301}.java"), "caf\u{e9}.java");
+    assert_eq!(unquote_path("\"quoted\".java"), "\"quoted\".java");
+}